@@ -1,9 +1,6 @@
-use fullscreen_texture::FullScreenTexture;
+use wgpu_app::fullscreen::FullScreenTexture;
 use wgpu_app::*;
 
-mod fullscreen_texture;
-mod screen_rect;
-
 struct App {
     fullscreen_texture: FullScreenTexture,
 }
@@ -36,17 +33,13 @@ impl WgpuApp for App {
         }
     }
 
-    fn render(
-        &mut self,
-        app_context: &AppContext,
-        surface_view: &wgpu::TextureView,
-    ) -> EventResult {
+    fn render(&mut self, app_context: &AppContext, frame: &Frame) -> EventResult {
         let mut command_encoder = app_context
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
 
         self.fullscreen_texture
-            .render(&mut command_encoder, surface_view);
+            .render(&mut command_encoder, frame.surface_view);
 
         app_context.queue.submit([command_encoder.finish()]);
 
@@ -1,30 +0,0 @@
-struct VertexOutput {
-    @location(0) tex_coord: vec2<f32>,
-    @builtin(position) position: vec4<f32>,
-};
-
-@vertex
-fn vs_main(
-    @location(0) position: vec4<f32>,
-    @location(1) tex_coord: vec2<f32>,
-) -> VertexOutput {
-    var result: VertexOutput;
-    result.position = position;
-    result.tex_coord = tex_coord;
-
-    return result;
-}
-
-
-@group(0)
-@binding(0)
-var the_sampler: sampler;
-@group(0)
-@binding(1)
-var color: texture_2d<f32>;
-
-@fragment
-fn fs_main(vertex: VertexOutput) -> @location(0) vec4<f32> {
-    let rgb = textureSample(color, the_sampler, vertex.tex_coord).rgb;
-    return vec4<f32>(rgb, 1.0);
-}
@@ -0,0 +1,83 @@
+use std::time::Instant;
+
+use wgpu_app::buffer::UniformBuffer;
+use wgpu_app::compute::ComputeTexture;
+use wgpu_app::shader::ShaderAsset;
+use wgpu_app::*;
+
+struct App {
+    compute_texture: ComputeTexture,
+    time: UniformBuffer<f32>,
+}
+
+impl App {
+    fn new(app_context: &AppContext) -> Self {
+        let time = UniformBuffer::new(&app_context.device, &0.0, wgpu::ShaderStages::COMPUTE);
+        let shader = ShaderAsset::new(
+            &app_context.device,
+            shader_source!("Examples/gpu_texture/shader.wgsl"),
+        )
+        .unwrap_or_else(|err| panic!("{}", err));
+        let compute_texture = ComputeTexture::new(
+            &app_context.device,
+            app_context.surface_config.format,
+            app_context.window_size,
+            shader,
+            "cs_main",
+            &[time.bind_group_layout()],
+        )
+        .unwrap_or_else(|err| panic!("{}", err));
+
+        Self {
+            compute_texture,
+            time,
+        }
+    }
+}
+
+impl WgpuApp for App {
+    fn window_event(&mut self, app_context: &AppContext, event: WindowEvent) -> EventResult {
+        match event {
+            WindowEvent::Resized(new_size) => {
+                self.compute_texture
+                    .resize_window(&app_context.device, new_size);
+
+                EventResult::Redraw
+            }
+
+            _ => EventResult::Continue,
+        }
+    }
+
+    fn compute(&mut self, app_context: &AppContext, encoder: &mut wgpu::CommandEncoder) -> bool {
+        let time = (Instant::now() - app_context.start_time).as_secs_f32();
+        self.time.write(&app_context.queue, &time);
+
+        self.compute_texture
+            .dispatch(&app_context.device, encoder, &[self.time.bind_group()]);
+        true
+    }
+
+    fn render(&mut self, app_context: &AppContext, frame: &Frame) -> EventResult {
+        let mut command_encoder = app_context
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+
+        self.compute_texture
+            .render(&mut command_encoder, frame.surface_view);
+
+        app_context.queue.submit([command_encoder.finish()]);
+
+        EventResult::Continue
+    }
+}
+
+fn main() {
+    let config = AppConfig {
+        control_flow: ControlFlow::Continuous,
+        ..Default::default()
+    };
+    wgpu_app::run_with_config(config, |app_context: &AppContext| {
+        Box::new(App::new(app_context))
+    });
+}
@@ -0,0 +1,21 @@
+@group(0)
+@binding(0)
+var output: texture_storage_2d<rgba8unorm, write>;
+
+@group(1)
+@binding(0)
+var<uniform> time: f32;
+
+// Same gradient as the cpu_texture example, with a moving blue channel.
+@compute
+@workgroup_size(8, 8, 1)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(output);
+    if id.x >= size.x || id.y >= size.y {
+        return;
+    }
+
+    let uv = vec2<f32>(id.xy) / vec2<f32>(size);
+    let blue = 0.5 + 0.5 * sin(time + uv.x * 6.28318);
+    textureStore(output, id.xy, vec4<f32>(uv, blue, 1.0));
+}
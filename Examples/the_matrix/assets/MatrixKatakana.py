@@ -0,0 +1,408 @@
+#!/usr/bin/env python3
+"""Builds MatrixKatakana.ttf, the half-width katakana U+FF66 to U+FF9D drawn as straight
+strokes, with no dependencies beyond the standard library.
+
+    python3 MatrixKatakana.py MatrixKatakana.ttf
+
+Strokes are polylines on a grid 8 wide and 10 high, y down, each segment becoming a
+rectangle. Overlapping rectangles all wind the same way, so they add up instead of
+cancelling. The font is dedicated to the public domain, see MatrixKatakana-LICENSE.txt.
+"""
+
+import math
+import struct
+import sys
+
+UNITS_PER_EM = 1000
+ADVANCE = 500
+ASCENDER = 800
+DESCENDER = -200
+STROKE = 80
+
+LEFT = 80
+STEP_X = 42.5
+TOP = 720
+STEP_Y = 70
+
+STROKES = {
+    "ｦ": [[(0.5, 1.5), (7.5, 1.5), (6.5, 5), (2, 10)], [(0.5, 5), (7, 5)]],
+    "ｰ": [[(0, 5), (8, 5)]],
+    "ｱ": [[(0, 1), (8, 1), (6, 3.5)], [(4, 3), (4, 6), (1, 10)]],
+    "ｲ": [[(7, 0), (4, 4), (0, 6.5)], [(5, 3), (5, 10)]],
+    "ｳ": [[(4, 0), (4, 2)], [(0.5, 2), (0.5, 4.5)], [(0.5, 2), (7.5, 2), (7.5, 5), (3, 10)]],
+    "ｴ": [[(1, 1.5), (7, 1.5)], [(4, 1.5), (4, 9)], [(0, 9), (8, 9)]],
+    "ｵ": [[(0, 3), (8, 3)], [(5, 0), (5, 10), (3.5, 9)], [(5, 3), (0, 8.5)]],
+    "ｶ": [[(0, 3), (7, 3), (6.5, 8), (5, 10), (4, 9.5)], [(3, 0), (2.5, 6), (0, 10)]],
+    "ｷ": [[(1, 2.5), (7, 1.5)], [(0, 5.5), (8, 4.5)], [(3.5, 0), (4.5, 10)]],
+    "ｸ": [[(3, 0), (0, 4.5)], [(2, 2), (7.5, 2), (6, 6), (1, 10)]],
+    "ｹ": [[(2, 0), (0, 5)], [(1.5, 2.5), (8, 2.5)], [(5.5, 2.5), (5, 7), (2, 10)]],
+    "ｺ": [[(0.5, 2), (7.5, 2), (7.5, 9)], [(0.5, 9), (7.5, 9)]],
+    "ｻ": [[(0, 3), (8, 3)], [(2, 0), (2, 6)], [(6, 0), (6, 6), (3, 10)]],
+    "ｼ": [[(1, 1), (2.5, 2)], [(0, 4), (1.5, 5)], [(0.5, 10), (4, 8), (8, 2)]],
+    "ｽ": [[(0.5, 1.5), (7.5, 1.5), (4.5, 6), (0, 10)], [(4.5, 6), (8, 10)]],
+    "ｾ": [[(0, 3.5), (8, 3.5), (5, 6)], [(2, 0), (2, 8.5), (3, 9.5), (7, 9.5)]],
+    "ｿ": [[(0.5, 1), (2, 5)], [(7.5, 1), (6.5, 5), (2, 10)]],
+    "ﾀ": [[(3, 0), (0, 4.5)], [(2, 2), (7.5, 2), (6, 6), (1, 10)], [(2, 4.5), (6, 6.5)]],
+    "ﾁ": [[(6.5, 0.5), (1.5, 2)], [(0, 4.5), (8, 4.5)], [(4, 2), (4, 7), (2, 10)]],
+    "ﾂ": [[(0.5, 1), (1.5, 3)], [(3, 0.5), (4, 2.5)], [(8, 1), (6.5, 6), (2, 10)]],
+    "ﾃ": [[(1, 1), (7, 1)], [(0, 4), (8, 4)], [(4, 4), (4, 7), (2, 10)]],
+    "ﾄ": [[(2, 0), (2, 10)], [(2, 4), (7, 6.5)]],
+    "ﾅ": [[(0, 3.5), (8, 3.5)], [(4.5, 0), (4.5, 6), (2, 10)]],
+    "ﾆ": [[(1, 2), (7, 2)], [(0, 8.5), (8, 8.5)]],
+    "ﾇ": [[(0.5, 1.5), (7.5, 1.5), (5, 7), (0, 10)], [(2.5, 4.5), (7.5, 9.5)]],
+    "ﾈ": [[(3.5, 0), (4, 1.5)], [(1, 2.5), (7, 2.5), (0, 8)], [(4, 5), (4, 10)], [(5.5, 6), (8, 8)]],
+    "ﾉ": [[(7, 0.5), (6, 5), (1, 10)]],
+    "ﾊ": [[(3, 2), (0, 8)], [(5, 2), (8, 8)]],
+    "ﾋ": [[(1, 0.5), (1, 9), (7.5, 9)], [(1, 4.5), (7, 2.5)]],
+    "ﾌ": [[(0.5, 1.5), (7.5, 1.5), (6, 6), (1.5, 10)]],
+    "ﾍ": [[(0, 6), (2.5, 3), (8, 8.5)]],
+    "ﾎ": [[(0, 3), (8, 3)], [(4, 0), (4, 10), (3, 9.5)], [(2, 5), (0, 8)], [(6, 5), (8, 8)]],
+    "ﾏ": [[(0, 2), (8, 2), (4.5, 6.5)], [(2.5, 4.5), (5.5, 9.5)]],
+    "ﾐ": [[(1.5, 0.5), (6.5, 2)], [(1, 4), (6, 5.5)], [(0.5, 7.5), (7.5, 9.5)]],
+    "ﾑ": [[(3.5, 0), (0, 9), (7, 8)], [(5.5, 5.5), (7.5, 10)]],
+    "ﾒ": [[(6.5, 0.5), (5, 5), (0.5, 10)], [(1.5, 3.5), (7.5, 9)]],
+    "ﾓ": [[(1, 1.5), (7, 1.5)], [(0, 4.5), (8, 4.5)], [(3.5, 1.5), (3.5, 9), (4.5, 9.8), (8, 9.8)]],
+    "ﾔ": [[(0, 3), (8, 2), (6.5, 5)], [(2, 0), (4.5, 10)]],
+    "ﾕ": [[(1, 2.5), (6.5, 2.5), (6.5, 9)], [(0, 9), (8, 9)]],
+    "ﾖ": [[(0.5, 1), (7.5, 1), (7.5, 9.5)], [(0.5, 5.25), (7.5, 5.25)], [(0.5, 9.5), (7.5, 9.5)]],
+    "ﾗ": [[(1, 0.5), (7, 0.5)], [(0.5, 3), (7.5, 3), (6, 7), (1.5, 10)]],
+    "ﾘ": [[(1.5, 0.5), (1.5, 6)], [(6.5, 0), (6.5, 6), (3, 10)]],
+    "ﾙ": [[(2.5, 0.5), (2.5, 5), (0, 10)], [(5, 0), (5, 10), (8, 7)]],
+    "ﾚ": [[(1.5, 0), (1.5, 10), (8, 5.5)]],
+    "ﾛ": [[(0.5, 1.5), (7.5, 1.5), (7.5, 9.5), (0.5, 9.5), (0.5, 1.5)]],
+    "ﾜ": [[(0.5, 1.5), (0.5, 5)], [(0.5, 1.5), (7.5, 1.5), (6.5, 6), (2, 10)]],
+    "ﾝ": [[(0.5, 1), (2.5, 3)], [(0.5, 10), (4, 8.5), (8, 3)]],
+}
+
+# Small kana are their full size counterparts shrunk into the lower part of the cell.
+SMALL = {
+    "ｧ": "ｱ",
+    "ｨ": "ｲ",
+    "ｩ": "ｳ",
+    "ｪ": "ｴ",
+    "ｫ": "ｵ",
+    "ｬ": "ﾔ",
+    "ｭ": "ﾕ",
+    "ｮ": "ﾖ",
+    "ｯ": "ﾂ",
+}
+
+# Pairs whose open corners leave a gap in running text.
+KERNING = [("ﾄ", "ﾉ", -60), ("ﾚ", "ﾉ", -80)]
+
+FIRST = 0xFF66
+LAST = 0xFF9D
+
+
+def small(strokes):
+    return [[(1 + x * 0.75, 4 + y * 0.6) for x, y in stroke] for stroke in strokes]
+
+
+def to_font_units(point):
+    x, y = point
+    return LEFT + x * STEP_X, TOP - y * STEP_Y
+
+
+def signed_area(contour):
+    return sum(
+        x0 * y1 - x1 * y0
+        for (x0, y0), (x1, y1) in zip(contour, contour[1:] + contour[:1])
+    )
+
+
+def segment(p, q):
+    """Rectangle around the segment, reaching half a stroke past both ends so that joints
+    are filled, wound clockwise like TrueType outer contours."""
+    half = STROKE / 2
+    dx, dy = q[0] - p[0], q[1] - p[1]
+    length = math.hypot(dx, dy)
+    dx, dy = dx / length * half, dy / length * half
+    nx, ny = -dy, dx
+    contour = [
+        (p[0] - dx + nx, p[1] - dy + ny),
+        (q[0] + dx + nx, q[1] + dy + ny),
+        (q[0] + dx - nx, q[1] + dy - ny),
+        (p[0] - dx - nx, p[1] - dy - ny),
+    ]
+    contour = [(round(x), round(y)) for x, y in contour]
+    if signed_area(contour) > 0:
+        contour.reverse()
+    return contour
+
+
+def outline(strokes):
+    contours = []
+    for stroke in strokes:
+        points = [to_font_units(point) for point in stroke]
+        contours.extend(segment(p, q) for p, q in zip(points, points[1:]))
+    return contours
+
+
+def notdef():
+    outer = [(50, 0), (50, 700), (450, 700), (450, 0)]
+    inner = [(100, 50), (400, 50), (400, 650), (100, 650)]
+    return [outer, inner]
+
+
+def glyph_data(contours):
+    if not contours:
+        return b""
+    points = [point for contour in contours for point in contour]
+    xs = [x for x, _ in points]
+    ys = [y for _, y in points]
+    data = struct.pack(">hhhhh", len(contours), min(xs), min(ys), max(xs), max(ys))
+    end = -1
+    for contour in contours:
+        end += len(contour)
+        data += struct.pack(">H", end)
+    data += struct.pack(">H", 0)
+    data += bytes([0x01] * len(points))
+    previous = 0
+    for x in xs:
+        data += struct.pack(">h", x - previous)
+        previous = x
+    previous = 0
+    for y in ys:
+        data += struct.pack(">h", y - previous)
+        previous = y
+    return data
+
+
+def checksum(data):
+    data += b"\0" * (-len(data) % 4)
+    return sum(struct.unpack(">%dI" % (len(data) // 4), data)) & 0xFFFFFFFF
+
+
+def name_table():
+    names = {
+        0: "Made for the wgpu_app examples, dedicated to the public domain",
+        1: "Matrix Katakana",
+        2: "Regular",
+        3: "Matrix Katakana Regular 1.0",
+        4: "Matrix Katakana Regular",
+        5: "Version 1.0",
+        6: "MatrixKatakana-Regular",
+        13: "CC0 1.0 Universal",
+        14: "https://creativecommons.org/publicdomain/zero/1.0/",
+    }
+    records = b""
+    strings = b""
+    for name_id, value in names.items():
+        encoded = value.encode("utf-16-be")
+        records += struct.pack(">HHHHHH", 3, 1, 0x409, name_id, len(encoded), len(strings))
+        strings += encoded
+    header = struct.pack(">HHH", 0, len(names), 6 + len(records))
+    return header + records + strings
+
+
+def cmap_table(segments):
+    """Format 4 subtable mapping `segments` of (first, last, first glyph)."""
+    segments = segments + [(0xFFFF, 0xFFFF, 0)]
+    count = len(segments)
+    search_range = 2 * 2 ** int(math.log2(count))
+    ends = b"".join(struct.pack(">H", last) for _, last, _ in segments)
+    starts = b"".join(struct.pack(">H", first) for first, _, _ in segments)
+    deltas = b"".join(
+        struct.pack(">H", (glyph - first) & 0xFFFF if glyph else 1)
+        for first, _, glyph in segments
+    )
+    offsets = b"\0\0" * count
+    body = (
+        struct.pack(
+            ">HHH", count * 2, search_range, int(math.log2(search_range // 2))
+        )
+        + struct.pack(">H", count * 2 - search_range)
+        + ends
+        + b"\0\0"
+        + starts
+        + deltas
+        + offsets
+    )
+    subtable = struct.pack(">HHH", 4, 6 + len(body), 0) + body
+    return struct.pack(">HHHHI", 0, 1, 3, 1, 12) + subtable
+
+
+def kern_table(pairs):
+    count = len(pairs)
+    search_range = 6 * 2 ** int(math.log2(count))
+    body = struct.pack(
+        ">HHHH",
+        count,
+        search_range,
+        int(math.log2(search_range // 6)),
+        count * 6 - search_range,
+    )
+    for left, right, value in sorted(pairs):
+        body += struct.pack(">HHh", left, right, value)
+    subtable = struct.pack(">HHH", 0, 6 + len(body), 0x0001) + body
+    return struct.pack(">HH", 0, 1) + subtable
+
+
+def build():
+    chars = [chr(code) for code in range(FIRST, LAST + 1)]
+    glyphs = [notdef(), []]
+    for c in chars:
+        strokes = small(STROKES[SMALL[c]]) if c in SMALL else STROKES[c]
+        glyphs.append(outline(strokes))
+    glyph_ids = {c: index + 2 for index, c in enumerate(chars)}
+
+    glyf = b""
+    loca = []
+    for contours in glyphs:
+        loca.append(len(glyf))
+        data = glyph_data(contours)
+        glyf += data + b"\0" * (-len(data) % 4)
+    loca.append(len(glyf))
+
+    points = [point for contours in glyphs for contour in contours for point in contour]
+    x_min = min(x for x, _ in points)
+    y_min = min(y for _, y in points)
+    x_max = max(x for x, _ in points)
+    y_max = max(y for _, y in points)
+    lsbs = [min((x for c in contours for x, _ in c), default=0) for contours in glyphs]
+    max_points = max(sum(len(c) for c in contours) for contours in glyphs)
+    max_contours = max(len(contours) for contours in glyphs)
+
+    head = struct.pack(
+        ">IIIIHHqqhhhhHHhhh",
+        0x00010000,
+        0x00010000,
+        0,
+        0x5F0F3CF5,
+        0x000B,
+        UNITS_PER_EM,
+        0,
+        0,
+        x_min,
+        y_min,
+        x_max,
+        y_max,
+        0,
+        8,
+        2,
+        1,
+        0,
+    )
+    hhea = struct.pack(
+        ">IhhhHhhhhhh8xhH",
+        0x00010000,
+        ASCENDER,
+        DESCENDER,
+        0,
+        ADVANCE,
+        min(lsbs),
+        min(ADVANCE - x_max, 0),
+        x_max,
+        1,
+        0,
+        0,
+        0,
+        len(glyphs),
+    )
+    maxp = struct.pack(
+        ">IHHHHHHHHHHHHHH",
+        0x00010000,
+        len(glyphs),
+        max_points,
+        max_contours,
+        0,
+        0,
+        2,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+    )
+    os2 = struct.pack(
+        ">HhHHHhhhhhhhhhhh10sIIII4sHHHhhhHHIIhhHHH",
+        4,
+        ADVANCE,
+        400,
+        5,
+        0,
+        650,
+        600,
+        0,
+        75,
+        650,
+        600,
+        0,
+        350,
+        50,
+        250,
+        0,
+        bytes(10),
+        0,
+        1 << 17,
+        0,
+        0,
+        b"NONE",
+        0x0040,
+        0x0020,
+        LAST,
+        ASCENDER,
+        DESCENDER,
+        0,
+        ASCENDER,
+        -DESCENDER,
+        1 << 17,
+        0,
+        500,
+        700,
+        0,
+        0x0020,
+        2,
+    )
+    hmtx = b"".join(struct.pack(">Hh", ADVANCE, lsb) for lsb in lsbs)
+    cmap = cmap_table([(0x20, 0x20, 1), (FIRST, LAST, 2)])
+    kern = kern_table(
+        [(glyph_ids[left], glyph_ids[right], value) for left, right, value in KERNING]
+    )
+    post = struct.pack(">IIhhIIIII", 0x00030000, 0, -100, 50, 0, 0, 0, 0, 0)
+
+    tables = {
+        b"OS/2": os2,
+        b"cmap": cmap,
+        b"glyf": glyf,
+        b"head": head,
+        b"hhea": hhea,
+        b"hmtx": hmtx,
+        b"kern": kern,
+        b"loca": b"".join(struct.pack(">I", offset) for offset in loca),
+        b"maxp": maxp,
+        b"name": name_table(),
+        b"post": post,
+    }
+
+    count = len(tables)
+    search_range = 16 * 2 ** int(math.log2(count))
+    font = struct.pack(
+        ">IHHHH",
+        0x00010000,
+        count,
+        search_range,
+        int(math.log2(search_range // 16)),
+        count * 16 - search_range,
+    )
+    offset = 12 + 16 * count
+    directory = b""
+    data = b""
+    head_offset = 0
+    for tag, table in tables.items():
+        if tag == b"head":
+            head_offset = offset + len(data)
+        directory += struct.pack(">4sIII", tag, checksum(table), offset + len(data), len(table))
+        data += table + b"\0" * (-len(table) % 4)
+    font += directory + data
+
+    adjustment = (0xB1B0AFBA - checksum(font)) & 0xFFFFFFFF
+    return font[: head_offset + 8] + struct.pack(">I", adjustment) + font[head_offset + 12 :]
+
+
+if __name__ == "__main__":
+    with open(sys.argv[1], "wb") as file:
+        file.write(build())
@@ -0,0 +1,20 @@
+// State of the GPU rain, mirroring `Thread` and `Symbol` in matrix.rs. Every thread owns
+// MAX_LENGTH consecutive symbols, of which 0..=top_symbol are alive.
+struct Thread {
+    pos: vec2<f32>,
+    decay: f32,
+    new_symbol_delta: f32,
+    size: f32,
+    top_symbol: u32,
+    length: u32,
+    last_symbol_time: f32,
+    rng: u32,
+};
+
+struct Symbol {
+    glyph: u32,
+    opacity: f32,
+    changing: u32,
+    change_time: f32,
+    change_delta: f32,
+};
@@ -0,0 +1,71 @@
+#include "wgpu_app/common.wgsl"
+#include "the_matrix/rain.wgsl"
+
+// Glyphs of threads out of focus, next to the sharp ones in `color`.
+@group(0)
+@binding(2)
+var blurred: texture_2d<f32>;
+
+struct VertexOutput {
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec2<f32>,
+    @location(2) @interpolate(flat) blurred: u32,
+    @builtin(position) position: vec4<f32>,
+};
+
+// Right after the draw constants, which take group 1 unless they are immediates.
+#ifdef WGPU_APP_IMMEDIATES
+#define RAIN_GROUP 1
+#else
+#define RAIN_GROUP 2
+#endif
+@group(RAIN_GROUP)
+@binding(0)
+var<storage, read> threads: array<Thread>;
+@group(RAIN_GROUP)
+@binding(1)
+var<storage, read> symbols: array<Symbol>;
+
+// One instance per symbol slot, drawn as a four vertex triangle strip. Slots that aren't
+// visible collapse to a point outside the viewport.
+@vertex
+fn vs_main(
+    @builtin(vertex_index) vertex_index: u32,
+    @builtin(instance_index) instance_index: u32,
+) -> VertexOutput {
+    let thread = threads[instance_index / MAX_LENGTH];
+    let index = instance_index % MAX_LENGTH;
+    let symbol = symbols[instance_index];
+
+    var result: VertexOutput;
+
+    let center = thread.pos - vec2<f32>(0.0, 0.8 * thread.size * f32(index)) - 0.5 * thread.size;
+    if index > thread.top_symbol || symbol.opacity <= 0.0 || center.y < 0.0 || center.y > 1.0 {
+        result.position = vec4<f32>(2.0, 2.0, 2.0, 1.0);
+        return result;
+    }
+
+    let corner = vec2<f32>(f32(vertex_index >> 1u), f32(vertex_index & 1u));
+    let position = center + thread.size * (corner - 0.5);
+    let glyph = vec2<f32>(f32(symbol.glyph % ATLAS_COLUMNS), f32(symbol.glyph / ATLAS_COLUMNS));
+    let grid = vec2<f32>(f32(ATLAS_COLUMNS), f32(ATLAS_ROWS));
+
+    result.position = pc.mvp * vec4(position, 0.0, 1.0);
+    result.uv = (glyph + vec2<f32>(corner.x, 1.0 - corner.y)) / grid;
+    result.color = vec2<f32>(1.0, symbol.opacity);
+    result.blurred = select(0u, 1u, thread.size < FOCUS_SIZE);
+
+    return result;
+}
+
+@fragment
+fn fs_main(vertex: VertexOutput) -> @location(0) vec4<f32> {
+    // Both are sampled so the derivatives stay in uniform control flow.
+    let sharp = textureSample(color, the_sampler, vertex.uv).r;
+    let soft = textureSample(blurred, the_sampler, vertex.uv).r;
+
+    let coverage = select(sharp, soft, vertex.blurred != 0u);
+
+    // Coverage scales alpha only, like `fs_sprite_mask` draws the CPU rain.
+    return vec4(0.05, vertex.color.r, 0.2, vertex.color.g * coverage);
+}
@@ -0,0 +1,92 @@
+#include "the_matrix/rain.wgsl"
+
+struct RainParams {
+    time: f32,
+    delta: f32,
+    _padding: vec2<f32>,
+};
+
+@group(0)
+@binding(0)
+var<uniform> params: RainParams;
+@group(0)
+@binding(1)
+var<storage, read_write> threads: array<Thread>;
+@group(0)
+@binding(2)
+var<storage, read_write> symbols: array<Symbol>;
+
+// PCG hash step, see "Hash Functions for GPU Rendering" by Jarzynski and Olano.
+fn random_u32(state: ptr<function, u32>) -> u32 {
+    let old = *state;
+    *state = old * 747796405u + 2891336453u;
+    let word = ((old >> ((old >> 28u) + 4u)) ^ old) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+fn random_f32(state: ptr<function, u32>) -> f32 {
+    return f32(random_u32(state) >> 8u) / 16777216.0;
+}
+
+fn random_symbol(rng: ptr<function, u32>) -> Symbol {
+    var symbol: Symbol;
+    symbol.glyph = random_u32(rng) % GLYPH_COUNT;
+    symbol.changing = select(0u, 1u, (random_u32(rng) & 255u) < 40u);
+    symbol.opacity = 1.0;
+    symbol.change_time = 0.0;
+    symbol.change_delta = random_f32(rng) * 0.5 + 0.5;
+    return symbol;
+}
+
+// Restarts `thread` with a new first symbol at `first`, the index of its symbols.
+fn init_thread(thread: ptr<function, Thread>, first: u32) {
+    (*thread).decay = random_f32(&(*thread).rng) * 0.3 + 0.01;
+    let x = random_f32(&(*thread).rng);
+    (*thread).pos = vec2<f32>(x, random_f32(&(*thread).rng) * 1.8 - 0.5);
+    (*thread).new_symbol_delta = random_f32(&(*thread).rng) * 0.07 + 0.02;
+    (*thread).size = random_f32(&(*thread).rng) * 0.03 + 0.002;
+    (*thread).top_symbol = 0u;
+    (*thread).length = (random_u32(&(*thread).rng) & 255u) % MAX_LENGTH;
+
+    var first_symbol: Symbol;
+    first_symbol.opacity = 1.0;
+    first_symbol.change_delta = 1.0;
+    symbols[first] = first_symbol;
+}
+
+fn update_thread(index: u32, time: f32, delta: f32) {
+    var thread = threads[index];
+    let first = index * MAX_LENGTH;
+
+    for (var i = 0u; i <= thread.top_symbol; i++) {
+        var symbol = symbols[first + i];
+        if time - symbol.change_time >= symbol.change_delta && symbol.changing != 0u {
+            symbol.glyph = random_u32(&thread.rng) % GLYPH_COUNT;
+            symbol.change_time = time;
+        }
+        symbol.opacity = max(symbol.opacity - thread.decay * delta, 0.0);
+        symbols[first + i] = symbol;
+    }
+
+    if thread.top_symbol < thread.length && time - thread.last_symbol_time >= thread.new_symbol_delta {
+        thread.last_symbol_time = time;
+        thread.top_symbol += 1u;
+        symbols[first + thread.top_symbol] = random_symbol(&thread.rng);
+    }
+
+    if symbols[first + thread.top_symbol].opacity <= 0.0 {
+        init_thread(&thread, first);
+    }
+
+    threads[index] = thread;
+}
+
+@compute
+@workgroup_size(64)
+fn cs_update(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= arrayLength(&threads) {
+        return;
+    }
+
+    update_thread(id.x, params.time, params.delta);
+}
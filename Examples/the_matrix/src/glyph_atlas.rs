@@ -0,0 +1,301 @@
+use glam::UVec2;
+use wgpu_app::post_process::{Blur, PostEffect, PostProcess, PostProcessOptions};
+use wgpu_app::render_target::RenderTargetOptions;
+use wgpu_app::text::{Font, GlyphBitmap};
+use wgpu_app::texture::{self, MipmapGenerator, Texture, TextureOptions};
+
+/// The half-width katakana of the film, `ｦ` to `ﾝ`, from the bundled Matrix Katakana, with
+/// digits, Latin capitals and symbols from the bundled DejaVu Sans Mono.
+pub(crate) const DEFAULT_CHARSET: &str = "ｦｧｨｩｪｫｬｭｮｯｰｱｲｳｴｵｶｷｸｹｺｻｼｽｾｿﾀﾁﾂﾃﾄﾅﾆﾇﾈﾉﾊﾋﾌﾍﾎﾏﾐﾑﾒﾓﾔﾕﾖﾗﾘﾙﾚﾛﾜﾝ\
+                                          0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ:.\"=*+-<>¦|";
+
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+
+/// Symbols of the rain rasterized at startup into a grid of square cells, with a copy
+/// blurred on the GPU for threads out of focus. Glyphs are indices into the character set.
+pub(crate) struct GlyphAtlas {
+    pub sharp: Texture,
+    pub blurred: Texture,
+    pub columns: u32,
+    pub rows: u32,
+    pub glyph_count: u32,
+}
+
+impl GlyphAtlas {
+    /// Each character comes from the first of `fonts` that has it; characters none of them
+    /// have are left out. Glyphs sit on one baseline, scaled to fit together in `cell_size`
+    /// pixels minus a margin wide enough for the blur.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        fonts: &[Font],
+        charset: &str,
+        cell_size: u32,
+    ) -> Self {
+        let margin = cell_size / 8;
+        let glyph_size = (cell_size - 2 * margin) as f32;
+
+        let (bitmaps, missing) = rasterize(fonts, charset, glyph_size);
+        if missing > 0 {
+            tracing::warn!("{} characters are missing from the fonts", missing);
+        }
+        assert!(
+            !bitmaps.is_empty(),
+            "None of the characters are in the fonts"
+        );
+
+        let glyph_count = bitmaps.len() as u32;
+        let columns = (glyph_count as f32).sqrt().ceil() as u32;
+        let rows = glyph_count.div_ceil(columns);
+        let size = UVec2::new(columns, rows) * cell_size;
+
+        let mut data = vec![0u8; (size.x * size.y) as usize];
+        let origins = glyph_origins(&bitmaps, cell_size);
+        for (index, (bitmap, origin)) in bitmaps.iter().zip(origins).enumerate() {
+            let cell = UVec2::new(index as u32 % columns, index as u32 / columns) * cell_size;
+            let origin = cell + origin;
+            for y in 0..bitmap.size.y {
+                let row = ((origin.y + y) * size.x + origin.x) as usize;
+                let width = bitmap.size.x as usize;
+                let source = (y * bitmap.size.x) as usize;
+                data[row..row + width].copy_from_slice(&bitmap.data[source..source + width]);
+            }
+        }
+
+        let mut mipmap_generator = MipmapGenerator::new(device);
+        let sharp = texture::from_bytes(
+            device,
+            queue,
+            &mut mipmap_generator,
+            size.x,
+            size.y,
+            &data,
+            &TextureOptions {
+                format: FORMAT,
+                usage: wgpu::TextureUsages::COPY_SRC,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        // The blur reads a copy of the sharp atlas and writes the top level of its own,
+        // the margins keeping neighbouring cells apart.
+        let mut post = PostProcess::new(
+            device,
+            FORMAT,
+            size,
+            PostProcessOptions {
+                input: RenderTargetOptions {
+                    color_format: Some(FORMAT),
+                    depth_format: None,
+                    usage: wgpu::TextureUsages::COPY_DST,
+                    ..Default::default()
+                },
+                format: FORMAT,
+            },
+        );
+        post.effects = vec![PostEffect::Blur(Blur::Gaussian {
+            radius: margin as f32,
+        })];
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Glyph Atlas"),
+        });
+        encoder.copy_texture_to_texture(
+            sharp.texture.as_image_copy(),
+            post.input().sampled().unwrap().texture.as_image_copy(),
+            sharp.size(),
+        );
+        let blurred = create_texture(device, size);
+        let blurred_top = blurred.texture.create_view(&wgpu::TextureViewDescriptor {
+            mip_level_count: Some(1),
+            ..Default::default()
+        });
+        post.render(device, queue, &mut encoder, &blurred_top);
+
+        mipmap_generator.generate(device, &mut encoder, &blurred.texture);
+        queue.submit([encoder.finish()]);
+
+        Self {
+            sharp,
+            blurred,
+            columns,
+            rows,
+            glyph_count,
+        }
+    }
+
+    /// Texture coordinates of the cell of `glyph`, `[left, top, right, bottom]`.
+    pub fn uv_rect(&self, glyph: u32) -> [f32; 4] {
+        let (columns, rows) = (self.columns as f32, self.rows as f32);
+        let left = (glyph % self.columns) as f32 / columns;
+        let top = (glyph / self.columns) as f32 / rows;
+        [left, top, left + 1.0 / columns, top + 1.0 / rows]
+    }
+}
+
+/// Glyphs of the characters of `charset` from the first of `fonts` that has each, scaled
+/// down from `glyph_size` pixels high until they fit together in a square of `glyph_size`
+/// when placed on one baseline. Also returns the number of characters none of the fonts have.
+fn rasterize(fonts: &[Font], charset: &str, glyph_size: f32) -> (Vec<GlyphBitmap>, usize) {
+    let mut size = glyph_size;
+    loop {
+        let mut missing = 0;
+        let bitmaps: Vec<_> = charset
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .filter_map(|c| {
+                let bitmap = fonts.iter().find_map(|font| font.rasterize(c, size));
+                if bitmap.is_none() {
+                    missing += 1;
+                }
+                bitmap
+            })
+            .collect();
+
+        let (top, bottom) = vertical_bounds(&bitmaps);
+        let width = bitmaps
+            .iter()
+            .map(|bitmap| bitmap.size.x)
+            .max()
+            .unwrap_or(0);
+        let extent = (bottom - top).max(width as f32);
+        if extent <= glyph_size {
+            return (bitmaps, missing);
+        }
+        size *= glyph_size / extent;
+    }
+}
+
+/// Top and bottom of `bitmaps` placed on one baseline, in pixels from it, y down.
+fn vertical_bounds(bitmaps: &[GlyphBitmap]) -> (f32, f32) {
+    bitmaps.iter().fold(
+        (f32::INFINITY, f32::NEG_INFINITY),
+        |(top, bottom), bitmap| {
+            let y = bitmap.offset.y;
+            (top.min(y), bottom.max(y + bitmap.size.y as f32))
+        },
+    )
+}
+
+/// Top-left corners of `bitmaps` in cells of `cell_size` pixels. Glyphs share a baseline,
+/// with the box around all of them centered vertically, and are centered horizontally.
+fn glyph_origins(bitmaps: &[GlyphBitmap], cell_size: u32) -> Vec<UVec2> {
+    let (top, bottom) = vertical_bounds(bitmaps);
+    let baseline = (cell_size as f32 - (bottom - top)) / 2.0 - top;
+
+    bitmaps
+        .iter()
+        .map(|bitmap| {
+            let x = (cell_size - bitmap.size.x) / 2;
+            let y = (baseline + bitmap.offset.y).round() as u32;
+            UVec2::new(x, y)
+        })
+        .collect()
+}
+
+/// Empty texture with a full mip chain, for `MipmapGenerator::generate`.
+fn create_texture(device: &wgpu::Device, size: UVec2) -> Texture {
+    let size = wgpu::Extent3d {
+        width: size.x,
+        height: size.y,
+        depth_or_array_layers: 1,
+    };
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        size,
+        mip_level_count: size.max_mips(wgpu::TextureDimension::D2),
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: FORMAT,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+        label: Some("Glyph Atlas Blurred"),
+    });
+
+    Texture::new(
+        device,
+        texture,
+        &TextureOptions {
+            format: FORMAT,
+            ..Default::default()
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bundled_fonts() -> Vec<Font> {
+        ["DejaVuSansMono.ttf", "MatrixKatakana.ttf"]
+            .iter()
+            .map(|name| {
+                Font::from_file(format!("{}/assets/{}", env!("CARGO_MANIFEST_DIR"), name)).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn bundled_fonts_have_default_charset() {
+        let fonts = bundled_fonts();
+
+        let missing: String = DEFAULT_CHARSET
+            .chars()
+            .filter(|c| !fonts.iter().any(|font| font.has_glyph(*c)))
+            .collect();
+        assert_eq!(missing, "");
+    }
+
+    #[test]
+    fn glyphs_fit_inside_margin() {
+        let fonts = bundled_fonts();
+        // Together, the ring of `Å` and the cedilla of `Ç` reach past the height of the
+        // font, which has to be scaled down.
+        let cases = [
+            (DEFAULT_CHARSET, 64),
+            ("Agjy|", 64),
+            ("ÅÇ|gj", 64),
+            ("ÅÇ|gj", 16),
+        ];
+
+        for case in cases {
+            let (charset, cell_size) = case;
+            let margin = cell_size / 8;
+            let glyph_size = cell_size - 2 * margin;
+
+            let (bitmaps, missing) = rasterize(&fonts, charset, glyph_size as f32);
+            assert_eq!(missing, 0, "{:?}", case);
+            let origins = glyph_origins(&bitmaps, cell_size);
+            for (bitmap, origin) in bitmaps.iter().zip(origins) {
+                let end = origin + bitmap.size;
+                assert!(
+                    origin.cmpge(UVec2::splat(margin)).all()
+                        && end.cmple(UVec2::splat(cell_size - margin)).all(),
+                    "{:?} {:?} {:?}",
+                    case,
+                    origin,
+                    end
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn glyphs_share_baseline() {
+        let fonts = bundled_fonts();
+
+        let (bitmaps, _) = rasterize(&fonts, "A-g", 48.0);
+        let origins = glyph_origins(&bitmaps, 64);
+        let baselines: Vec<f32> = bitmaps
+            .iter()
+            .zip(&origins)
+            .map(|(bitmap, origin)| origin.y as f32 - bitmap.offset.y)
+            .collect();
+
+        assert_eq!(baselines[0], baselines[1]);
+        assert_eq!(baselines[0], baselines[2]);
+        // The descender of `g` goes below the baseline, the `-` sits above it.
+        assert!(origins[2].y + bitmaps[2].size.y > baselines[0] as u32);
+        assert!(origins[1].y + bitmaps[1].size.y < baselines[0] as u32);
+    }
+}
@@ -0,0 +1,194 @@
+use bytemuck::{Pod, Zeroable};
+use glam::UVec3;
+use rand::random;
+use wgpu_app::buffer::{StorageBuffer, UniformBuffer};
+use wgpu_app::compute;
+use wgpu_app::preprocessor::ShaderComposer;
+use wgpu_app::shader::ShaderAsset;
+use wgpu_app::shader_source;
+
+use crate::glyph_atlas::GlyphAtlas;
+use crate::matrix::{start_rain, Symbol, Thread, FOCUS_SIZE, MAX_LENGTH};
+
+/// Rain simulated by a compute shader, with thread and symbol state in storage buffers and
+/// every symbol drawn as an instanced quad expanded in the vertex shader. Follows the same
+/// rules as `Matrix`, which stays as the CPU reference.
+pub(crate) struct GpuMatrix {
+    params: UniformBuffer<RainParams>,
+    threads: StorageBuffer<Thread>,
+    symbols: StorageBuffer<Symbol>,
+
+    shader: ShaderAsset,
+    pipeline_layout: wgpu::PipelineLayout,
+    update_pipeline: wgpu::ComputePipeline,
+    workgroup_size: UVec3,
+    update_bind_group: wgpu::BindGroup,
+    draw_group: u32,
+    draw_bind_group: wgpu::BindGroup,
+
+    prev_time: f32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct RainParams {
+    time: f32,
+    delta: f32,
+    _padding: [f32; 2],
+}
+
+/// Adds the shared rain declarations to `composer`, for both the update and draw shaders,
+/// with the glyph count and grid of `atlas`.
+pub(crate) fn shader_composer(composer: ShaderComposer, atlas: &GlyphAtlas) -> ShaderComposer {
+    composer
+        .add_module("the_matrix/rain.wgsl", include_str!("../assets/rain.wgsl"))
+        .define("MAX_LENGTH", format!("{}u", MAX_LENGTH))
+        .define("GLYPH_COUNT", format!("{}u", atlas.glyph_count))
+        .define("ATLAS_COLUMNS", format!("{}u", atlas.columns))
+        .define("ATLAS_ROWS", format!("{}u", atlas.rows))
+        .define("FOCUS_SIZE", format!("{:?}", FOCUS_SIZE))
+}
+
+impl GpuMatrix {
+    /// `draw_group` is the group of the draw shader that reads the threads and symbols, with
+    /// `draw_layout`. Symbols pick from the glyphs of `atlas`.
+    pub fn new(
+        device: &wgpu::Device,
+        atlas: &GlyphAtlas,
+        thread_count: usize,
+        draw_group: u32,
+        draw_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let params = UniformBuffer::new(device, &RainParams::zeroed(), wgpu::ShaderStages::COMPUTE);
+        let seeds: Vec<u32> = (0..thread_count).map(|_| random()).collect();
+        let (threads, symbols) = start_rain(&seeds, atlas.glyph_count);
+        let threads = StorageBuffer::new(device, &threads, wgpu::ShaderStages::COMPUTE, false);
+        let symbols = StorageBuffer::new(device, &symbols, wgpu::ShaderStages::COMPUTE, false);
+
+        let shader = ShaderAsset::with_composer(
+            device,
+            shader_source!("assets/rain_update.wgsl"),
+            shader_composer(ShaderComposer::new(), atlas),
+        )
+        .unwrap_or_else(|err| panic!("{}", err));
+        let reflection = shader.reflect().unwrap_or_else(|err| panic!("{}", err));
+        let workgroup_size = reflection
+            .workgroup_size("cs_update")
+            .unwrap_or_else(|err| panic!("{}", err));
+        let bind_group_layouts = reflection.create_bind_group_layouts(device);
+        let pipeline_layout = reflection.create_pipeline_layout(device, &bind_group_layouts);
+        let update_pipeline =
+            create_compute_pipeline(device, &pipeline_layout, shader.module(), "cs_update");
+
+        let update_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layouts[0],
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params.binding_resource(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: threads.binding_resource(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: symbols.binding_resource(),
+                },
+            ],
+            label: Some("Rain Update"),
+        });
+        let draw_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: draw_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: threads.binding_resource(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: symbols.binding_resource(),
+                },
+            ],
+            label: Some("Rain Draw"),
+        });
+
+        Self {
+            params,
+            threads,
+            symbols,
+            shader,
+            pipeline_layout,
+            update_pipeline,
+            workgroup_size,
+            update_bind_group,
+            draw_group,
+            draw_bind_group,
+            prev_time: 0.0,
+        }
+    }
+
+    /// Advances the simulation to `time`, in seconds.
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        time: f32,
+    ) {
+        let pipeline_layout = &self.pipeline_layout;
+        if let Some(pipeline) = self.shader.poll(device, |module| {
+            create_compute_pipeline(device, pipeline_layout, module, "cs_update")
+        }) {
+            self.update_pipeline = pipeline;
+        }
+
+        let delta = time - self.prev_time;
+        self.prev_time = time;
+        self.params.write(
+            queue,
+            &RainParams {
+                time,
+                delta,
+                _padding: [0.0; 2],
+            },
+        );
+
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Rain Update"),
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&self.update_pipeline);
+        pass.set_bind_group(0, &self.update_bind_group, &[]);
+        compute::dispatch(
+            &mut pass,
+            UVec3::new(self.threads.len() as u32, 1, 1),
+            self.workgroup_size,
+        );
+    }
+
+    pub fn set_draw_bind_group(&self, pass: &mut wgpu::RenderPass) {
+        pass.set_bind_group(self.draw_group, &self.draw_bind_group, &[]);
+    }
+
+    /// One instance per symbol slot, each drawn as a four vertex triangle strip.
+    pub fn instance_count(&self) -> u32 {
+        self.symbols.len() as u32
+    }
+}
+
+fn create_compute_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    module: &wgpu::ShaderModule,
+    entry_point: &str,
+) -> wgpu::ComputePipeline {
+    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some(entry_point),
+        layout: Some(layout),
+        module,
+        entry_point: Some(entry_point),
+        compilation_options: Default::default(),
+        cache: None,
+    })
+}
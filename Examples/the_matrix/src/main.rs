@@ -2,233 +2,308 @@
 
 use std::time::Instant;
 
+use wgpu_app::draw_constants::DrawConstants;
+use wgpu_app::post_process::{
+    Bloom, PostEffect, PostProcess, PostProcessOptions, ToneMap, Vignette,
+};
+use wgpu_app::profiler::GpuProfiler;
+use wgpu_app::render_target::{RenderTarget, RenderTargetOptions};
+use wgpu_app::shader::ShaderAsset;
+use wgpu_app::sprite::{self, SpriteBatch};
+use wgpu_app::text::{Font, GlyphMode, TextRenderer};
 use wgpu_app::*;
 
-use crate::matrix::Vertex;
+use crate::glyph_atlas::GlyphAtlas;
+use crate::gpu_matrix::GpuMatrix;
+use crate::matrix::Matrix;
 use crate::push_const::MvpPushConst;
 
 mod fps;
+mod glyph_atlas;
+mod gpu_matrix;
 mod matrix;
 mod push_const;
 
 struct App {
     fps_counter: fps::FpsCounter,
-
+    profiler: GpuProfiler,
+    post: PostProcess,
+    overlay: TextRenderer,
+    rain_label: String,
+
+    shader: ShaderAsset,
+    constants: DrawConstants<MvpPushConst>,
+    pipeline_layout: wgpu::PipelineLayout,
     render_pipeline: wgpu::RenderPipeline,
+    atlas: GlyphAtlas,
     bind_group: wgpu::BindGroup,
-    vertex_buffer: wgpu::Buffer,
-    index_buffer: wgpu::Buffer,
 
-    matrix: matrix::Matrix,
+    rain: Rain,
+}
 
-    vb: Vec<Vertex>,
-    ib: Vec<u16>,
+/// The rain simulated on the GPU, or on the CPU as a reference when `MATRIX_CPU` is set.
+/// `MATRIX_THREADS` sets the number of threads, `MATRIX_FONT` a font to take the symbols
+/// from before the bundled ones and `MATRIX_CHARSET` the symbols.
+enum Rain {
+    Cpu {
+        matrix: Matrix,
+        sprites: Box<SpriteBatch>,
+    },
+    Gpu(Box<GpuMatrix>),
 }
 
 impl App {
     fn new(app_context: &AppContext) -> Self {
-        let vertex_buffer_layout = [wgpu::VertexBufferLayout {
-            array_stride: Vertex::size_in_bytes() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &[
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x2,
-                    offset: 0,
-                    shader_location: 0,
-                },
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x2,
-                    offset: 4 * 2,
-                    shader_location: 1,
-                },
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x2,
-                    offset: 4 * 4,
-                    shader_location: 2,
-                },
-            ],
-        }];
-
-        let vertex_buffer = app_context.device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size: 1024 * 1024 * 5,
-            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
-
-        let index_buffer = app_context.device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("Index Buffer"),
-            size: 1024 * 1024 * 5,
-            usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
-
-        let bind_group_layout =
-            app_context
-                .device
-                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                    entries: &[
-                        wgpu::BindGroupLayoutEntry {
-                            binding: 0,
-                            visibility: wgpu::ShaderStages::FRAGMENT,
-                            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                            count: None,
-                        },
-                        wgpu::BindGroupLayoutEntry {
-                            binding: 1,
-                            visibility: wgpu::ShaderStages::FRAGMENT,
-                            ty: wgpu::BindingType::Texture {
-                                multisampled: false,
-                                sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                                view_dimension: wgpu::TextureViewDimension::D2,
-                            },
-                            count: None,
-                        },
-                    ],
-                    label: None,
-                });
+        let cpu = std::env::var_os("MATRIX_CPU").is_some();
+        let thread_count = std::env::var("MATRIX_THREADS")
+            .ok()
+            .and_then(|threads| threads.parse().ok())
+            .unwrap_or(matrix::THREAD_COUNT);
+        tracing::info!(cpu, thread_count, "Starting the rain");
+
+        let mut fonts = vec![];
+        if let Ok(path) = std::env::var("MATRIX_FONT") {
+            match Font::from_file(&path) {
+                Ok(font) => fonts.push(font),
+                Err(err) => tracing::error!("{}", err),
+            }
+        }
+        for path in [
+            "./Examples/the_matrix/assets/DejaVuSansMono.ttf",
+            "./Examples/the_matrix/assets/MatrixKatakana.ttf",
+        ] {
+            fonts.push(Font::from_file(path).unwrap_or_else(|err| panic!("{}", err)));
+        }
+        let charset = std::env::var("MATRIX_CHARSET")
+            .unwrap_or_else(|_| glyph_atlas::DEFAULT_CHARSET.to_string());
+        let atlas = GlyphAtlas::new(
+            &app_context.device,
+            &app_context.queue,
+            &fonts,
+            &charset,
+            64,
+        );
 
+        let constants = DrawConstants::new(&app_context.device, 1, wgpu::ShaderStages::VERTEX);
+        let shader = ShaderAsset::with_composer(
+            &app_context.device,
+            shader_source!("assets/rain_draw.wgsl"),
+            gpu_matrix::shader_composer(constants.shader_composer(), &atlas),
+        )
+        .unwrap_or_else(|err| panic!("{}", err));
+        let reflection = shader.reflect().unwrap_or_else(|err| panic!("{}", err));
+        let bind_group_layouts = constants.bind_group_layouts(&app_context.device, &reflection);
         let pipeline_layout =
-            app_context
-                .device
-                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                    bind_group_layouts: &[&bind_group_layout],
-                    immediate_size: MvpPushConst::size_in_bytes(),
-                    label: None,
-                });
-
-        let screen_shader = app_context
-            .device
-            .create_shader_module(wgpu::include_wgsl!("../assets/shader.wgsl"));
-
-        let render_pipeline =
-            app_context
-                .device
-                .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                    label: None,
-                    layout: Some(&pipeline_layout),
-                    vertex: wgpu::VertexState {
-                        module: &screen_shader,
-                        entry_point: Some("vs_main"),
-                        buffers: &vertex_buffer_layout,
-                        compilation_options: Default::default(),
-                    },
-                    fragment: Some(wgpu::FragmentState {
-                        module: &screen_shader,
-                        entry_point: Some("fs_main"),
-                        targets: &[Some(wgpu::ColorTargetState {
-                            format: app_context.surface_config.format,
-                            blend: Some(wgpu::BlendState {
-                                color: wgpu::BlendComponent {
-                                    operation: wgpu::BlendOperation::Add,
-                                    src_factor: wgpu::BlendFactor::SrcAlpha,
-                                    dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
-                                },
-                                alpha: wgpu::BlendComponent {
-                                    operation: wgpu::BlendOperation::Add,
-                                    src_factor: wgpu::BlendFactor::One,
-                                    dst_factor: wgpu::BlendFactor::One,
-                                },
-                            }),
-                            write_mask: wgpu::ColorWrites::ALL,
-                        })],
-                        compilation_options: Default::default(),
-                    }),
-                    primitive: wgpu::PrimitiveState {
-                        cull_mode: None,
-                        front_face: wgpu::FrontFace::Ccw,
-                        topology: wgpu::PrimitiveTopology::TriangleList,
-
-                        ..Default::default()
-                    },
-                    depth_stencil: None,
-                    multisample: wgpu::MultisampleState::default(),
-                    multiview_mask: None,
-                    cache: None,
-                });
-
-        let img =
-            imaginarium::image::Image::read_file("./Examples/the_matrix/assets/ascii_texture.png")
-                .unwrap()
-                .convert(imaginarium::color_format::ColorFormat::GRAY_U8)
-                .unwrap();
-
-        let texture_extent = wgpu::Extent3d {
-            width: img.desc.width(),
-            height: img.desc.height(),
-            depth_or_array_layers: 1,
-        };
-        let texture = app_context.device.create_texture(&wgpu::TextureDescriptor {
-            size: texture_extent,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::R8Unorm,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING
-                | wgpu::TextureUsages::RENDER_ATTACHMENT
-                | wgpu::TextureUsages::COPY_DST,
-            view_formats: &[],
-            label: None,
-        });
-        let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-
-        app_context.queue.write_texture(
-            wgpu::TexelCopyTextureInfo {
-                texture: &texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: Default::default(),
-            },
-            img.bytes.as_slice(),
-            wgpu::TexelCopyBufferLayout {
-                offset: 0,
-                bytes_per_row: Some(img.desc.stride()),
-                rows_per_image: Some(img.desc.height()),
-            },
-            wgpu::Extent3d {
-                width: img.desc.width(),
-                height: img.desc.height(),
-                depth_or_array_layers: 1,
+            reflection.create_pipeline_layout(&app_context.device, &bind_group_layouts);
+
+        // The rain is drawn into an HDR post-processing input and glows on its way to the
+        // surface.
+        let hdr = PostProcessOptions::hdr();
+        let mut post = PostProcess::new(
+            &app_context.device,
+            app_context.surface_config.format,
+            app_context.window_size,
+            PostProcessOptions {
+                input: RenderTargetOptions {
+                    depth_format: None,
+                    ..hdr.input
+                },
+                ..hdr
             },
         );
+        post.effects = vec![
+            PostEffect::Bloom(Bloom {
+                threshold: 0.5,
+                ..Default::default()
+            }),
+            PostEffect::ToneMap(ToneMap::default()),
+            PostEffect::Vignette(Vignette::default()),
+        ];
+
+        let render_pipeline = Self::create_render_pipeline(
+            &app_context.device,
+            &pipeline_layout,
+            shader.module(),
+            Self::scene_format(&post),
+        );
 
-        let sampler = app_context.device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            border_color: None,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Linear,
-            ..Default::default()
-        });
+        let rain = if cpu {
+            Rain::Cpu {
+                matrix: Matrix::new(thread_count, atlas.glyph_count),
+                sprites: Box::new(SpriteBatch::new(
+                    &app_context.device,
+                    Self::scene_format(&post),
+                    None,
+                    wgpu::MultisampleState::default(),
+                )),
+            }
+        } else {
+            let draw_group = reflection.bind_group_count() - 1;
+            Rain::Gpu(Box::new(GpuMatrix::new(
+                &app_context.device,
+                &atlas,
+                thread_count,
+                draw_group,
+                &bind_group_layouts[draw_group as usize],
+            )))
+        };
 
-        let bind_group = app_context
-            .device
-            .create_bind_group(&wgpu::BindGroupDescriptor {
-                layout: &bind_group_layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: wgpu::BindingResource::Sampler(&sampler),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: wgpu::BindingResource::TextureView(&texture_view),
-                    },
-                ],
-                label: None,
-            });
+        let overlay = TextRenderer::new(
+            &app_context.device,
+            fonts,
+            GlyphMode::Bitmap,
+            app_context.surface_config.format,
+            None,
+            wgpu::MultisampleState::default(),
+        );
+        let rain_label = format!(
+            "{} rain, {} threads",
+            if cpu { "CPU" } else { "GPU" },
+            thread_count
+        );
+
+        let bind_group =
+            Self::create_bind_group(&app_context.device, &bind_group_layouts[0], &atlas);
 
         Self {
             fps_counter: fps::FpsCounter::new(),
+            profiler: GpuProfiler::new(&app_context.device, &app_context.queue),
+            post,
+            overlay,
+            rain_label,
+            shader,
+            constants,
+            pipeline_layout,
             render_pipeline,
+            atlas,
             bind_group,
-            vertex_buffer,
-            index_buffer,
-            matrix: matrix::Matrix::new(),
-            vb: vec![],
-            ib: vec![],
+            rain,
         }
     }
+
+    /// Frame rate and rain mode over the post-processed image.
+    fn render_overlay(
+        &mut self,
+        app_context: &AppContext,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+    ) {
+        let color = [0.6, 1.0, 0.6, 1.0];
+        self.overlay.clear();
+        let line = self.overlay.draw(
+            &format!("{:.0} fps", self.fps_counter.get_fps()),
+            glam::Vec2::new(8.0, 8.0),
+            16.0,
+            color,
+        );
+        self.overlay.draw(
+            &self.rain_label,
+            glam::Vec2::new(8.0, 8.0 + line.y),
+            16.0,
+            color,
+        );
+        self.overlay.set_projection(
+            &app_context.queue,
+            sprite::pixel_projection(app_context.window_size),
+        );
+        self.overlay
+            .flush(&app_context.device, &app_context.queue, encoder);
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Overlay"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+                resolve_target: None,
+                depth_slice: None,
+            })],
+            ..Default::default()
+        });
+        self.overlay.render(&mut render_pass);
+    }
+
+    fn scene_format(post: &PostProcess) -> wgpu::TextureFormat {
+        post.input().options().color_format.unwrap()
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        atlas: &GlyphAtlas,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Sampler(&atlas.sharp.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&atlas.sharp.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&atlas.blurred.view),
+                },
+            ],
+            label: None,
+        })
+    }
+
+    fn create_render_pipeline(
+        device: &wgpu::Device,
+        pipeline_layout: &wgpu::PipelineLayout,
+        module: &wgpu::ShaderModule,
+        target_format: wgpu::TextureFormat,
+    ) -> wgpu::RenderPipeline {
+        // Instances expand into triangle strips without a vertex buffer.
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: Some(pipeline_layout),
+            vertex: wgpu::VertexState {
+                module,
+                entry_point: Some("vs_main"),
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: target_format,
+                    blend: Some(wgpu::BlendState {
+                        color: wgpu::BlendComponent {
+                            operation: wgpu::BlendOperation::Add,
+                            src_factor: wgpu::BlendFactor::SrcAlpha,
+                            dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                        },
+                        alpha: wgpu::BlendComponent {
+                            operation: wgpu::BlendOperation::Add,
+                            src_factor: wgpu::BlendFactor::One,
+                            dst_factor: wgpu::BlendFactor::One,
+                        },
+                    }),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                cull_mode: None,
+                front_face: wgpu::FrontFace::Ccw,
+                topology: wgpu::PrimitiveTopology::TriangleStrip,
+
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview_mask: None,
+            cache: None,
+        })
+    }
 }
 
 impl WgpuApp for App {
@@ -240,46 +315,53 @@ impl WgpuApp for App {
         }
     }
 
-    fn render(
-        &mut self,
-        app_context: &AppContext,
-        surface_view: &wgpu::TextureView,
-    ) -> EventResult {
-        let time = (Instant::now() - app_context.start_time).as_secs_f32();
-        self.matrix.update(time);
-
-        let mvp = if app_context.surface_config.width > app_context.surface_config.height {
-            let aspect = (app_context.surface_config.height as f32
-                / app_context.surface_config.width as f32
-                - 1.0)
-                / 2.0;
-            glam::Mat4::orthographic_rh(0.0, 1.0, 0.0 - aspect, 1.0 + aspect, 0.0, 1.0)
-        } else {
-            let aspect = (app_context.surface_config.width as f32
-                / app_context.surface_config.height as f32
-                - 1.0)
-                / 2.0;
-            glam::Mat4::orthographic_rh(0.0 - aspect, 1.0 + aspect, 0.0, 1.0, 0.0, 1.0)
+    fn compute(&mut self, app_context: &AppContext, encoder: &mut wgpu::CommandEncoder) -> bool {
+        let Rain::Gpu(matrix) = &mut self.rain else {
+            return false;
         };
-        let pc = MvpPushConst { mvp };
+        let time = (Instant::now() - app_context.start_time).as_secs_f32();
+        matrix.update(&app_context.device, &app_context.queue, encoder, time);
+        true
+    }
 
-        self.matrix.geometry(&mut self.vb, &mut self.ib);
-        app_context
-            .queue
-            .write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&self.vb));
-        app_context
-            .queue
-            .write_buffer(&self.index_buffer, 0, bytemuck::cast_slice(&self.ib));
+    fn render_targets(&mut self) -> Vec<&mut RenderTarget> {
+        self.post.render_targets().collect()
+    }
+
+    fn render(&mut self, app_context: &AppContext, frame: &Frame) -> EventResult {
+        let device = &app_context.device;
+        let pipeline_layout = &self.pipeline_layout;
+        let scene_format = Self::scene_format(&self.post);
+        if let Some(render_pipeline) = self.shader.poll(device, |module| {
+            Self::create_render_pipeline(device, pipeline_layout, module, scene_format)
+        }) {
+            self.render_pipeline = render_pipeline;
+        }
+
+        let mvp = sprite::unit_square_projection(app_context.window_size);
+        self.constants.clear();
+        let constants = self.constants.push(&MvpPushConst { mvp });
+        self.constants.flush(device, &app_context.queue);
 
         let mut encoder = app_context
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
 
+        if let Rain::Cpu { matrix, sprites } = &mut self.rain {
+            let time = (Instant::now() - app_context.start_time).as_secs_f32();
+            matrix.update(time);
+            sprites.clear();
+            matrix.sprites(sprites, &self.atlas);
+            sprites.set_projection(&app_context.queue, mvp);
+            sprites.flush(device, &mut encoder);
+        }
+
+        let scope = self.profiler.begin_scope("main pass", None);
         {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: None,
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: surface_view,
+                    view: self.post.input().color_view().unwrap(),
                     ops: wgpu::Operations {
                         load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
                         store: wgpu::StoreOp::Store,
@@ -287,28 +369,71 @@ impl WgpuApp for App {
                     resolve_target: None,
                     depth_slice: None,
                 })],
+                timestamp_writes: self.profiler.render_pass_timestamp_writes(scope),
                 ..Default::default()
             });
 
-            render_pass.set_pipeline(&self.render_pipeline);
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-
-            render_pass.set_immediates(0, pc.as_bytes());
-            render_pass.set_bind_group(0, &self.bind_group, &[]);
-            render_pass.draw_indexed(0..self.ib.len() as u32, 0, 0..1);
+            match &self.rain {
+                Rain::Cpu { sprites, .. } => sprites.render(&mut render_pass),
+                Rain::Gpu(matrix) => {
+                    render_pass.set_pipeline(&self.render_pipeline);
+                    self.constants.set(&mut render_pass, constants);
+                    render_pass.set_bind_group(0, &self.bind_group, &[]);
+                    matrix.set_draw_bind_group(&mut render_pass);
+                    render_pass.draw(0..4, 0..matrix.instance_count());
+                }
+            }
         }
 
+        self.profiler.end_scope(scope, None);
+
+        self.post
+            .render(device, &app_context.queue, &mut encoder, frame.surface_view);
+        self.render_overlay(app_context, &mut encoder, frame.surface_view);
+        self.profiler.resolve(&mut encoder);
+
         app_context.queue.submit([encoder.finish()]);
+        self.overlay.after_submit();
+        if let Rain::Cpu { sprites, .. } = &mut self.rain {
+            sprites.after_submit();
+        }
+        self.profiler.end_frame(&app_context.device);
 
         if self.fps_counter.update() {
-            println!("FPS: {}", self.fps_counter.get_fps());
+            tracing::info!("FPS: {}", self.fps_counter.get_fps());
+            for stat in self.profiler.statistics() {
+                tracing::info!(
+                    "{}: cpu {:.3} ms, gpu {:.3} ms",
+                    stat.path,
+                    stat.cpu_average * 1000.0,
+                    stat.gpu_average.unwrap_or(0.0) * 1000.0
+                );
+            }
         }
 
         EventResult::Redraw
     }
 }
 
+impl Drop for App {
+    fn drop(&mut self) {
+        if let Ok(path) = std::env::var("CHROME_TRACE") {
+            if let Err(err) = self.profiler.write_chrome_trace(&path) {
+                tracing::error!("Failed to write {}: {}", path, err);
+            }
+        }
+    }
+}
+
 fn main() {
-    wgpu_app::run(|app_context: &AppContext| Box::new(App::new(app_context)));
+    wgpu_app::init_tracing();
+
+    let config = AppConfig {
+        control_flow: ControlFlow::Continuous,
+        hdr: true,
+        ..Default::default()
+    };
+    wgpu_app::run_with_config(config, |app_context: &AppContext| {
+        Box::new(App::new(app_context))
+    });
 }
@@ -1,132 +1,62 @@
 use bytemuck::{Pod, Zeroable};
 use rand::random;
+use wgpu_app::sprite::{Sprite, SpriteBatch};
 
+use crate::glyph_atlas::GlyphAtlas;
+
+/// A column of symbols, laid out like `Thread` in rain.wgsl. Its symbols are the MAX_LENGTH
+/// after those of the previous threads, of which `0..=top_symbol` are alive.
 #[repr(C)]
 #[derive(Debug, Clone, Copy, Pod, Zeroable)]
-pub(crate) struct Vertex {
-    pos: glam::Vec2,   // xy
-    uv: glam::Vec2,    // uv
-    color: glam::Vec2, // green, alpha
-}
-
-struct Symbol {
-    char: u8,
-    opacity: f32,
-
-    changing: bool,
-    change_time: f32,
-    change_delta: f32,
-}
-
 pub(crate) struct Thread {
-    decay: f32,
     pos: glam::Vec2,
+    decay: f32,
     new_symbol_delta: f32,
     size: f32,
-    symbols: Vec<Symbol>,
-    top_symbol: usize,
-    length: usize,
+    top_symbol: u32,
+    length: u32,
     last_symbol_time: f32,
+    rng: u32,
+    _padding: u32,
 }
 
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub(crate) struct Symbol {
+    glyph: u32,
+    opacity: f32,
+    changing: u32,
+    change_time: f32,
+    change_delta: f32,
+}
+
+/// Rain simulated on the CPU and drawn as sprites, the reference for `GpuMatrix`.
 pub(crate) struct Matrix {
     threads: Vec<Thread>,
+    symbols: Vec<Symbol>,
+    glyph_count: u32,
     prev_time: f32,
 }
 
-const MAX_LENGTH: u8 = 60;
-const THREAD_COUNT: usize = 300;
-const CHAR_ALTLAS_SIZE: u8 = 16;
-const CHAR_ALTLAS_SIZE_F32: f32 = CHAR_ALTLAS_SIZE as f32;
+pub(crate) const MAX_LENGTH: u8 = 60;
+pub(crate) const THREAD_COUNT: usize = 300;
+/// Threads smaller than this are out of focus and drawn with the blurred glyphs.
+pub(crate) const FOCUS_SIZE: f32 = 0.008;
 
-impl Default for Symbol {
-    fn default() -> Self {
-        Self {
-            char: 0,
-            changing: false,
-            opacity: 1.0,
-            change_time: 0.0,
-            change_delta: 1.0,
-        }
-    }
-}
-
-impl Symbol {
-    fn new_rand() -> Self {
-        Self {
-            char: random::<u8>(),
-            changing: random::<u8>() < 40,
-            opacity: 1.0,
-            change_time: 0.0,
-            change_delta: random::<f32>() * 0.5 + 0.5,
-        }
-    }
-}
-
-impl Thread {
-    fn new() -> Self {
-        let symbols = Vec::with_capacity(MAX_LENGTH as usize);
-        let mut result = Self {
-            decay: 0.0,
-            pos: glam::Vec2::new(0.0, 0.0),
-            new_symbol_delta: 0.0,
-            size: 0.0,
-            symbols,
-            top_symbol: 0,
-            length: 0,
-            last_symbol_time: 0.0,
-        };
-        result.init();
-
-        result
-    }
-    fn init(&mut self) {
-        self.decay = random::<f32>() * 0.3 + 0.01;
-        self.pos = glam::Vec2::new(random::<f32>(), random::<f32>() * 1.8 - 0.5);
-        self.new_symbol_delta = random::<f32>() * 0.07 + 0.02;
-        self.size = random::<f32>() * 0.03 + 0.002;
-        self.top_symbol = 0;
-        self.length = (random::<u8>() % MAX_LENGTH) as usize;
-
-        self.symbols.resize_with(1, Symbol::default);
-    }
-
-    fn update(&mut self, time: f32, delta: f32) {
-        for symbol in self.symbols[0..self.top_symbol + 1].iter_mut() {
-            if time - symbol.change_time >= symbol.change_delta && symbol.changing {
-                symbol.char = random::<u8>();
-                symbol.change_time = time;
-            }
-            symbol.opacity = (symbol.opacity - self.decay * delta).max(0.0);
-        }
-
-        if self.top_symbol < self.length && time - self.last_symbol_time >= self.new_symbol_delta {
-            self.last_symbol_time = time;
-            self.top_symbol += 1;
-            self.symbols.push(Symbol::new_rand());
-        }
-
-        let finished = self.symbols[self.top_symbol].opacity <= 0.0;
-        if finished {
-            self.init();
-        }
+impl Matrix {
+    pub fn new(thread_count: usize, glyph_count: u32) -> Self {
+        let seeds: Vec<u32> = (0..thread_count).map(|_| random()).collect();
+        Self::from_seeds(&seeds, glyph_count)
     }
-}
 
-impl Matrix {
-    pub fn new() -> Self {
-        let mut threads: Vec<Thread> = vec![];
-        for _ in 0..THREAD_COUNT {
-            threads.push(Thread::new());
-        }
-        for thread in threads.iter_mut() {
-            for i in -999..0 {
-                thread.update((i as f32) * 0.016, 0.016);
-            }
-        }
+    /// A thread per seed of its random number generator.
+    fn from_seeds(seeds: &[u32], glyph_count: u32) -> Self {
+        let (threads, symbols) = start_rain(seeds, glyph_count);
 
         Self {
             threads,
+            symbols,
+            glyph_count,
             prev_time: 0.0,
         }
     }
@@ -135,16 +65,30 @@ impl Matrix {
         let delta = time - self.prev_time;
         self.prev_time = time;
 
-        for thread in self.threads.iter_mut() {
-            thread.update(time, delta);
+        for (thread, symbols) in self
+            .threads
+            .iter_mut()
+            .zip(self.symbols.chunks_exact_mut(MAX_LENGTH as usize))
+        {
+            update_thread(thread, symbols, time, delta, self.glyph_count);
         }
     }
-    pub fn geometry(&self, vb: &mut Vec<Vertex>, ib: &mut Vec<u16>) {
-        vb.clear();
-        ib.clear();
 
-        for thread in self.threads.iter() {
-            let iter = thread.symbols[0..thread.top_symbol + 1]
+    /// Pushes a sprite per visible symbol, with glyphs from `atlas`, blurred for threads
+    /// out of focus.
+    pub fn sprites(&self, batch: &mut SpriteBatch, atlas: &GlyphAtlas) {
+        for (thread, symbols) in self
+            .threads
+            .iter()
+            .zip(self.symbols.chunks_exact(MAX_LENGTH as usize))
+        {
+            let texture = if thread.size < FOCUS_SIZE {
+                &atlas.blurred
+            } else {
+                &atlas.sharp
+            };
+
+            let iter = symbols[..=thread.top_symbol as usize]
                 .iter()
                 .enumerate()
                 .filter_map(|(idx, symbol)| {
@@ -159,54 +103,296 @@ impl Matrix {
                 });
 
             for (symbol, pos) in iter {
-                let uv_offset = glam::vec2(
-                    (symbol.char % CHAR_ALTLAS_SIZE) as f32 / CHAR_ALTLAS_SIZE_F32,
-                    (symbol.char / CHAR_ALTLAS_SIZE) as f32 / CHAR_ALTLAS_SIZE_F32,
+                batch.push(
+                    texture,
+                    Sprite {
+                        center: pos,
+                        size: glam::Vec2::splat(thread.size),
+                        uv_rect: atlas.uv_rect(symbol.glyph),
+                        color: [0.05, 1.0, 0.2, symbol.opacity],
+                        ..Default::default()
+                    },
                 );
+            }
+        }
+    }
+}
 
-                vb.push(Vertex {
-                    pos: pos + thread.size * glam::Vec2::new(-0.5, -0.5),
-                    uv: uv_offset
-                        + glam::Vec2::new(0.0, 1.0)
-                            * glam::vec2(1.0 / CHAR_ALTLAS_SIZE_F32, 1.0 / CHAR_ALTLAS_SIZE_F32),
-                    color: glam::Vec2::new(1.0, symbol.opacity),
-                });
-                vb.push(Vertex {
-                    pos: pos + thread.size * glam::Vec2::new(-0.5, 0.5),
-                    uv: uv_offset
-                        + glam::Vec2::new(0.0, 0.0)
-                            * glam::vec2(1.0 / CHAR_ALTLAS_SIZE_F32, 1.0 / CHAR_ALTLAS_SIZE_F32),
-                    color: glam::Vec2::new(1.0, symbol.opacity),
-                });
-                vb.push(Vertex {
-                    pos: pos + thread.size * glam::Vec2::new(0.5, -0.5),
-                    uv: uv_offset
-                        + glam::Vec2::new(1.0, 1.0)
-                            * glam::vec2(1.0 / CHAR_ALTLAS_SIZE_F32, 1.0 / CHAR_ALTLAS_SIZE_F32),
-                    color: glam::Vec2::new(1.0, symbol.opacity),
-                });
-                vb.push(Vertex {
-                    pos: pos + thread.size * glam::Vec2::new(0.5, 0.5),
-                    uv: uv_offset
-                        + glam::Vec2::new(1.0, 0.0)
-                            * glam::vec2(1.0 / CHAR_ALTLAS_SIZE_F32, 1.0 / CHAR_ALTLAS_SIZE_F32),
-                    color: glam::Vec2::new(1.0, symbol.opacity),
-                });
+/// Threads seeded with `seeds`, and their symbols, run for a while so the screen starts
+/// full. The GPU rain starts from it too, as that many steps in one dispatch can outlast
+/// the GPU's timeout.
+pub(crate) fn start_rain(seeds: &[u32], glyph_count: u32) -> (Vec<Thread>, Vec<Symbol>) {
+    let mut threads: Vec<Thread> = seeds
+        .iter()
+        .map(|&rng| Thread {
+            rng,
+            last_symbol_time: -999.0 * 0.016,
+            ..Thread::zeroed()
+        })
+        .collect();
+    let mut symbols = vec![Symbol::zeroed(); seeds.len() * MAX_LENGTH as usize];
 
-                ib.push((vb.len() - 2) as u16);
-                ib.push((vb.len() - 3) as u16);
-                ib.push((vb.len() - 4) as u16);
+    for (thread, symbols) in threads
+        .iter_mut()
+        .zip(symbols.chunks_exact_mut(MAX_LENGTH as usize))
+    {
+        init_thread(thread, symbols);
+        for step in -999..0 {
+            update_thread(thread, symbols, step as f32 * 0.016, 0.016, glyph_count);
+        }
+    }
 
-                ib.push((vb.len() - 1) as u16);
-                ib.push((vb.len() - 3) as u16);
-                ib.push((vb.len() - 2) as u16);
-            }
+    (threads, symbols)
+}
+
+// The rules of the rain, mirrored by rain_update.wgsl, which draws the same random numbers in
+// the same order. `symbols` are the MAX_LENGTH symbols of a thread.
+
+fn random_u32(state: &mut u32) -> u32 {
+    let old = *state;
+    *state = old.wrapping_mul(747796405).wrapping_add(2891336453);
+    let word = ((old >> ((old >> 28) + 4)) ^ old).wrapping_mul(277803737);
+    (word >> 22) ^ word
+}
+
+fn random_f32(state: &mut u32) -> f32 {
+    (random_u32(state) >> 8) as f32 / 16777216.0
+}
+
+fn random_symbol(rng: &mut u32, glyph_count: u32) -> Symbol {
+    Symbol {
+        glyph: random_u32(rng) % glyph_count,
+        changing: ((random_u32(rng) & 255) < 40) as u32,
+        opacity: 1.0,
+        change_time: 0.0,
+        change_delta: random_f32(rng) * 0.5 + 0.5,
+    }
+}
+
+fn init_thread(thread: &mut Thread, symbols: &mut [Symbol]) {
+    thread.decay = random_f32(&mut thread.rng) * 0.3 + 0.01;
+    let x = random_f32(&mut thread.rng);
+    thread.pos = glam::Vec2::new(x, random_f32(&mut thread.rng) * 1.8 - 0.5);
+    thread.new_symbol_delta = random_f32(&mut thread.rng) * 0.07 + 0.02;
+    thread.size = random_f32(&mut thread.rng) * 0.03 + 0.002;
+    thread.top_symbol = 0;
+    thread.length = (random_u32(&mut thread.rng) & 255) % MAX_LENGTH as u32;
+
+    symbols[0] = Symbol {
+        opacity: 1.0,
+        change_delta: 1.0,
+        ..Symbol::zeroed()
+    };
+}
+
+fn update_thread(
+    thread: &mut Thread,
+    symbols: &mut [Symbol],
+    time: f32,
+    delta: f32,
+    glyph_count: u32,
+) {
+    for symbol in symbols[..=thread.top_symbol as usize].iter_mut() {
+        if time - symbol.change_time >= symbol.change_delta && symbol.changing != 0 {
+            symbol.glyph = random_u32(&mut thread.rng) % glyph_count;
+            symbol.change_time = time;
         }
+        symbol.opacity = (symbol.opacity - thread.decay * delta).max(0.0);
+    }
+
+    if thread.top_symbol < thread.length
+        && time - thread.last_symbol_time >= thread.new_symbol_delta
+    {
+        thread.last_symbol_time = time;
+        thread.top_symbol += 1;
+        symbols[thread.top_symbol as usize] = random_symbol(&mut thread.rng, glyph_count);
+    }
+
+    if symbols[thread.top_symbol as usize].opacity <= 0.0 {
+        init_thread(thread, symbols);
     }
 }
 
-impl Vertex {
-    pub fn size_in_bytes() -> usize {
-        std::mem::size_of::<Self>()
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GLYPH_COUNT: u32 = 100;
+
+    #[test]
+    fn random_u32_is_pcg_hash() {
+        // Each draw hashes the state before the LCG step, so seed 0 gives 0 and then
+        // `pcg_hash(0)`.
+        let cases: [(u32, [u32; 3], u32); 2] = [
+            (0, [0, 129708002, 582399676], 568162667),
+            (0xdeadbeef, [4130710537, 1730779506, 930691114], 4160156558),
+        ];
+
+        for case in cases {
+            let (seed, expected, expected_state) = case;
+            let mut state = seed;
+            let values = [(); 3].map(|_| random_u32(&mut state));
+            assert_eq!(values, expected, "{:?}", case);
+            assert_eq!(state, expected_state, "{:?}", case);
+        }
+    }
+
+    #[test]
+    fn random_f32_is_in_unit_range() {
+        let mut state = 1;
+        let values: Vec<f32> = (0..10_000).map(|_| random_f32(&mut state)).collect();
+
+        assert!(values.iter().all(|value| (0.0..1.0).contains(value)));
+        let mean = values.iter().sum::<f32>() / values.len() as f32;
+        assert!((mean - 0.5).abs() < 0.02, "{}", mean);
+    }
+
+    #[test]
+    fn init_thread_restarts_with_one_symbol() {
+        let mut thread = Thread {
+            rng: 7,
+            top_symbol: 5,
+            ..Thread::zeroed()
+        };
+        let mut symbols = vec![Symbol::zeroed(); MAX_LENGTH as usize];
+
+        init_thread(&mut thread, &mut symbols);
+
+        assert_eq!(thread.top_symbol, 0);
+        assert!(thread.length < MAX_LENGTH as u32);
+        assert!((0.01..0.31).contains(&thread.decay), "{:?}", thread);
+        assert!(
+            (0.02..0.09).contains(&thread.new_symbol_delta),
+            "{:?}",
+            thread
+        );
+        assert!((0.002..0.032).contains(&thread.size), "{:?}", thread);
+        assert_eq!(symbols[0].opacity, 1.0);
+        assert_eq!(symbols[0].changing, 0);
+    }
+
+    #[test]
+    fn update_thread_decays_and_adds_symbols() {
+        let mut thread = Thread {
+            decay: 0.5,
+            new_symbol_delta: 0.5,
+            length: 2,
+            rng: 7,
+            ..Thread::zeroed()
+        };
+        let mut symbols = vec![Symbol::zeroed(); MAX_LENGTH as usize];
+        symbols[0] = Symbol {
+            opacity: 1.0,
+            change_delta: 1.0,
+            ..Symbol::zeroed()
+        };
+
+        // Not yet time for a new symbol.
+        update_thread(&mut thread, &mut symbols, 0.25, 0.25, GLYPH_COUNT);
+        assert_eq!(thread.top_symbol, 0);
+        assert_eq!(symbols[0].opacity, 0.875);
+
+        update_thread(&mut thread, &mut symbols, 0.5, 0.25, GLYPH_COUNT);
+        assert_eq!(thread.top_symbol, 1);
+        assert_eq!(thread.last_symbol_time, 0.5);
+        assert_eq!(symbols[0].opacity, 0.75);
+        assert_eq!(symbols[1].opacity, 1.0);
+        assert!(symbols[1].glyph < GLYPH_COUNT);
+
+        // Stops growing at its length.
+        for time in [0.75, 1.0, 1.25, 1.5] {
+            update_thread(&mut thread, &mut symbols, time, 0.25, GLYPH_COUNT);
+        }
+        assert_eq!(thread.top_symbol, 2);
+    }
+
+    #[test]
+    fn update_thread_restarts_when_top_symbol_fades() {
+        let mut thread = Thread {
+            decay: 1.0,
+            new_symbol_delta: 1.0,
+            top_symbol: 1,
+            length: 1,
+            rng: 7,
+            ..Thread::zeroed()
+        };
+        let mut symbols = vec![Symbol::zeroed(); MAX_LENGTH as usize];
+        symbols[1].opacity = 0.5;
+
+        update_thread(&mut thread, &mut symbols, 0.5, 0.5, GLYPH_COUNT);
+
+        assert_eq!(thread.top_symbol, 0);
+        assert_ne!(thread.decay, 1.0);
+        assert_eq!(symbols[0].opacity, 1.0);
+    }
+
+    #[test]
+    fn start_rain() {
+        let seeds: Vec<u32> = (0..50).collect();
+        let (threads, symbols) = super::start_rain(&seeds, GLYPH_COUNT);
+
+        assert_eq!(threads.len(), seeds.len());
+        assert_eq!(symbols.len(), seeds.len() * MAX_LENGTH as usize);
+        for (thread, symbols) in threads
+            .iter()
+            .zip(symbols.chunks_exact(MAX_LENGTH as usize))
+        {
+            assert!(thread.top_symbol <= thread.length, "{:?}", thread);
+            assert!(thread.length < MAX_LENGTH as u32, "{:?}", thread);
+            for symbol in &symbols[..=thread.top_symbol as usize] {
+                assert!(symbol.glyph < GLYPH_COUNT, "{:?}", symbol);
+                assert!((0.0..=1.0).contains(&symbol.opacity), "{:?}", symbol);
+            }
+        }
+        // The screen starts full rather than with every thread at its first symbol.
+        assert!(
+            threads
+                .iter()
+                .filter(|thread| thread.top_symbol > 0)
+                .count()
+                > 25
+        );
+
+        let (same_threads, same_symbols) = super::start_rain(&seeds, GLYPH_COUNT);
+        assert_eq!(
+            bytemuck::cast_slice::<_, u8>(&threads),
+            bytemuck::cast_slice(&same_threads)
+        );
+        assert_eq!(
+            bytemuck::cast_slice::<_, u8>(&symbols),
+            bytemuck::cast_slice(&same_symbols)
+        );
+    }
+
+    #[test]
+    fn matrix_runs_the_same_rain() {
+        let seeds: Vec<u32> = (0..50).map(|seed| seed * 7919).collect();
+        let (mut threads, mut symbols) = super::start_rain(&seeds, GLYPH_COUNT);
+        let mut matrix = Matrix::from_seeds(&seeds, GLYPH_COUNT);
+
+        let mut prev_time = 0.0;
+        for time in [0.0, 0.016, 0.032, 0.5, 3.0] {
+            assert_eq!(
+                bytemuck::cast_slice::<_, u8>(&matrix.threads),
+                bytemuck::cast_slice(&threads),
+                "{}",
+                time
+            );
+            assert_eq!(
+                bytemuck::cast_slice::<_, u8>(&matrix.symbols),
+                bytemuck::cast_slice(&symbols),
+                "{}",
+                time
+            );
+
+            matrix.update(time);
+            for (thread, symbols) in threads
+                .iter_mut()
+                .zip(symbols.chunks_exact_mut(MAX_LENGTH as usize))
+            {
+                update_thread(thread, symbols, time, time - prev_time, GLYPH_COUNT);
+            }
+            prev_time = time;
+        }
     }
 }
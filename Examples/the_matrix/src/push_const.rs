@@ -1,14 +1,8 @@
+use wgpu_app::layout::GpuBlock;
+
 #[repr(C)]
-#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable, Default)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable, Default, GpuBlock)]
+#[gpu_block(immediate)]
 pub struct MvpPushConst {
     pub mvp: glam::Mat4,
 }
-
-impl MvpPushConst {
-    pub fn size_in_bytes() -> u32 {
-        std::mem::size_of::<Self>() as u32
-    }
-    pub fn as_bytes(&self) -> &[u8] {
-        bytemuck::bytes_of(self)
-    }
-}
@@ -1,14 +1,11 @@
+#include "wgpu_app/common.wgsl"
+
 struct VertexOutput {
     @location(0) tex_coord: vec2<f32>,
     @builtin(position) position: vec4<f32>,
 };
 
 
-struct PushConstant {
-    mvp: mat4x4<f32>,
-};
-var<immediate> pc: PushConstant;
-
 @vertex
 fn vs_main(
     @location(0) position: vec4<f32>,
@@ -22,13 +19,6 @@ fn vs_main(
 }
 
 
-@group(0)
-@binding(0)
-var the_sampler: sampler;
-@group(0)
-@binding(1)
-var color: texture_2d<f32>;
-
 @fragment
 fn fs_main(vertex: VertexOutput) -> @location(0) vec4<f32> {
     let v_color: vec4<f32> = textureSample(color, the_sampler, vertex.tex_coord);
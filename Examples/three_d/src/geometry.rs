@@ -1,8 +1,9 @@
 use bytemuck::{Pod, Zeroable};
+use wgpu_app::layout::VertexLayout;
 
 #[repr(C)]
-#[derive(Copy, Clone, Debug, Pod, Zeroable)]
-struct Vert3D {
+#[derive(Copy, Clone, Debug, Pod, Zeroable, VertexLayout)]
+pub(crate) struct Vert3D {
     pos: [f32; 4],
     uw: [f32; 2],
 }
@@ -45,9 +46,6 @@ impl Circle {
         Self::from_radius_segments(radius, segments)
     }
 
-    pub fn vertex_size() -> usize {
-        std::mem::size_of::<Vert3D>()
-    }
     pub fn vertex_count(&self) -> u32 {
         self.vertices.len() as u32
     }
@@ -114,9 +112,6 @@ impl Default for Cube {
 }
 
 impl Cube {
-    pub fn vertex_size() -> usize {
-        std::mem::size_of::<Vert3D>()
-    }
     pub fn vertex_count() -> u32 {
         24
     }
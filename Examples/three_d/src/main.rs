@@ -1,13 +1,21 @@
 #![allow(dead_code)]
 
+use std::time::Duration;
+
 use wgpu::util::DeviceExt;
-use wgpu::DepthStencilState;
 
+use wgpu_app::assets::{AssetManager, Handle};
+use wgpu_app::draw_constants::DrawConstants;
+use wgpu_app::layout::VertexLayout;
+use wgpu_app::profiler::GpuProfiler;
+use wgpu_app::render_graph::{RenderGraph, RenderGraphBuilder};
+use wgpu_app::shader::ShaderAsset;
+use wgpu_app::texture::{Texture, TextureOptions};
 use wgpu_app::EventResult;
 use wgpu_app::WindowEvent::{self};
 use wgpu_app::*;
 
-use crate::geometry::Cube;
+use crate::geometry::{Cube, Vert3D};
 use crate::push_const::MvpPushConst;
 
 mod fps;
@@ -16,13 +24,19 @@ mod push_const;
 
 struct App {
     fps_counter: fps::FpsCounter,
+    profiler: GpuProfiler,
+    assets: AssetManager,
+    render_graph: RenderGraph,
 
+    shader: ShaderAsset,
+    constants: DrawConstants<MvpPushConst>,
+    pipeline_layout: wgpu::PipelineLayout,
     render_pipeline: wgpu::RenderPipeline,
+    texture: Handle<Texture>,
+    bind_group_layout: wgpu::BindGroupLayout,
     bind_group: wgpu::BindGroup,
     vertex_buffer: wgpu::Buffer,
     index_buffer: wgpu::Buffer,
-    depth_texture: Option<wgpu::Texture>,
-    depth_texture_view: Option<wgpu::TextureView>,
 
     // Quaternion-based rotation state
     rotation: glam::Quat,
@@ -34,23 +48,6 @@ impl App {
     fn new(app_context: &AppContext) -> Self {
         let cube_geometry = Cube::default();
 
-        let vertex_buffer_layout = [wgpu::VertexBufferLayout {
-            array_stride: Cube::vertex_size() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &[
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x4,
-                    offset: 0,
-                    shader_location: 0,
-                },
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x2,
-                    offset: 4 * 4,
-                    shader_location: 1,
-                },
-            ],
-        }];
-
         let vertex_buffer =
             app_context
                 .device
@@ -69,160 +66,51 @@ impl App {
                     usage: wgpu::BufferUsages::INDEX,
                 });
 
-        let bind_group_layout =
-            app_context
-                .device
-                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                    entries: &[
-                        wgpu::BindGroupLayoutEntry {
-                            binding: 0,
-                            visibility: wgpu::ShaderStages::FRAGMENT,
-                            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                            count: None,
-                        },
-                        wgpu::BindGroupLayoutEntry {
-                            binding: 1,
-                            visibility: wgpu::ShaderStages::FRAGMENT,
-                            ty: wgpu::BindingType::Texture {
-                                multisampled: false,
-                                sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                                view_dimension: wgpu::TextureViewDimension::D2,
-                            },
-                            count: None,
-                        },
-                    ],
-                    label: None,
-                });
-
+        let constants = DrawConstants::new(&app_context.device, 1, wgpu::ShaderStages::VERTEX);
+        let shader = ShaderAsset::with_composer(
+            &app_context.device,
+            shader_source!("assets/shader.wgsl"),
+            constants.shader_composer(),
+        )
+        .unwrap_or_else(|err| panic!("{}", err));
+        let reflection = shader.reflect().unwrap_or_else(|err| panic!("{}", err));
+        let bind_group_layouts = constants.bind_group_layouts(&app_context.device, &reflection);
         let pipeline_layout =
-            app_context
-                .device
-                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                    bind_group_layouts: &[&bind_group_layout],
-                    immediate_size: MvpPushConst::size_in_bytes(),
-                    label: None,
-                });
-
-        let screen_shader = app_context
-            .device
-            .create_shader_module(wgpu::include_wgsl!("../assets/shader.wgsl"));
+            reflection.create_pipeline_layout(&app_context.device, &bind_group_layouts);
+        reflection
+            .validate_vertex_layout("vs_main", &Vert3D::layout())
+            .unwrap_or_else(|err| panic!("{}", err));
 
         let render_pipeline =
-            app_context
-                .device
-                .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                    label: None,
-                    layout: Some(&pipeline_layout),
-                    vertex: wgpu::VertexState {
-                        module: &screen_shader,
-                        entry_point: Some("vs_main"),
-                        buffers: &vertex_buffer_layout,
-                        compilation_options: Default::default(),
-                    },
-                    fragment: Some(wgpu::FragmentState {
-                        module: &screen_shader,
-                        entry_point: Some("fs_main"),
-                        targets: &[Some(app_context.surface_config.format.into())],
-                        compilation_options: Default::default(),
-                    }),
-                    primitive: wgpu::PrimitiveState {
-                        cull_mode: None,
-                        front_face: wgpu::FrontFace::Ccw,
-                        topology: wgpu::PrimitiveTopology::TriangleList,
-
-                        ..Default::default()
-                    },
-                    depth_stencil: Some(DepthStencilState {
-                        format: wgpu::TextureFormat::Depth32Float,
-                        depth_write_enabled: true,
-                        depth_compare: wgpu::CompareFunction::Less,
-                        stencil: Default::default(),
-                        bias: Default::default(),
-                    }),
-                    multisample: wgpu::MultisampleState::default(),
-                    multiview_mask: None,
-                    cache: None,
-                });
-
-        let img =
-            imaginarium::image::Image::read_file("./Examples/three_d/assets/Screenshot_01.png")
-                .unwrap()
-                .convert(imaginarium::color_format::ColorFormat::RGBA_U8)
-                .unwrap();
+            Self::create_render_pipeline(app_context, &pipeline_layout, shader.module());
 
-        let texture_extent = wgpu::Extent3d {
-            width: img.desc.width(),
-            height: img.desc.height(),
-            depth_or_array_layers: 1,
-        };
-        let texture = app_context.device.create_texture(&wgpu::TextureDescriptor {
-            size: texture_extent,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING
-                | wgpu::TextureUsages::RENDER_ATTACHMENT
-                | wgpu::TextureUsages::COPY_DST,
-            view_formats: &[],
-            label: None,
-        });
-        let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-
-        app_context.queue.write_texture(
-            wgpu::TexelCopyTextureInfo {
-                texture: &texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: Default::default(),
-            },
-            img.bytes.as_slice(),
-            wgpu::TexelCopyBufferLayout {
-                offset: 0,
-                bytes_per_row: Some(img.desc.stride()),
-                rows_per_image: Some(img.desc.height()),
-            },
-            wgpu::Extent3d {
-                width: img.desc.width(),
-                height: img.desc.height(),
-                depth_or_array_layers: 1,
-            },
+        // Drawn with a placeholder until the worker threads have decoded the image.
+        let mut assets = AssetManager::new(app_context);
+        let texture = assets.load_texture(
+            "./Examples/three_d/assets/Screenshot_01.png",
+            &TextureOptions::default(),
+        );
+        let bind_group_layout = bind_group_layouts[0].clone();
+        let bind_group = Self::create_bind_group(
+            &app_context.device,
+            &bind_group_layout,
+            assets.texture(&texture),
         );
-
-        let sampler = app_context.device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            border_color: None,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Linear,
-            ..Default::default()
-        });
-
-        let bind_group = app_context
-            .device
-            .create_bind_group(&wgpu::BindGroupDescriptor {
-                layout: &bind_group_layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: wgpu::BindingResource::Sampler(&sampler),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: wgpu::BindingResource::TextureView(&texture_view),
-                    },
-                ],
-                label: None,
-            });
 
         Self {
             fps_counter: fps::FpsCounter::new(),
+            profiler: GpuProfiler::new(&app_context.device, &app_context.queue),
+            assets,
+            render_graph: RenderGraph::new(),
+            shader,
+            constants,
+            pipeline_layout,
             render_pipeline,
+            texture,
+            bind_group_layout,
             bind_group,
             vertex_buffer,
             index_buffer,
-            depth_texture: None,
-            depth_texture_view: None,
 
             // Initialize quaternion rotation state
             rotation: glam::Quat::IDENTITY,
@@ -231,6 +119,64 @@ impl App {
         }
     }
 
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        texture: &Texture,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+            ],
+            label: None,
+        })
+    }
+
+    fn create_render_pipeline(
+        app_context: &AppContext,
+        pipeline_layout: &wgpu::PipelineLayout,
+        module: &wgpu::ShaderModule,
+    ) -> wgpu::RenderPipeline {
+        let surface_format = app_context.surface_config.format;
+        app_context
+            .device
+            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: None,
+                layout: Some(pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module,
+                    entry_point: Some("vs_main"),
+                    buffers: &[Vert3D::layout()],
+                    compilation_options: Default::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module,
+                    entry_point: Some("fs_main"),
+                    targets: &[Some(surface_format.into())],
+                    compilation_options: Default::default(),
+                }),
+                primitive: wgpu::PrimitiveState {
+                    cull_mode: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+
+                    ..Default::default()
+                },
+                depth_stencil: app_context.depth_stencil_state(true, wgpu::CompareFunction::Less),
+                multisample: app_context.multisample_state(),
+                multiview_mask: None,
+                cache: None,
+            })
+    }
+
     // Helper method to perform arcball rotation from screen coordinates
     fn arcball_rotation(
         &self,
@@ -375,11 +321,7 @@ impl App {
 impl WgpuApp for App {
     fn window_event(&mut self, app_context: &AppContext, event: WindowEvent) -> EventResult {
         match event {
-            WindowEvent::Resized(_new_size) => {
-                self.depth_texture = None;
-                self.depth_texture_view = None;
-                EventResult::Redraw
-            }
+            WindowEvent::Resized(_new_size) => EventResult::Redraw,
 
             // Use index 0 to check for left mouse button
             WindowEvent::MouseButton(ref _button, ref state, position) => {
@@ -432,104 +374,132 @@ impl WgpuApp for App {
         }
     }
 
-    fn render(
-        &mut self,
-        app_context: &AppContext,
-        surface_view: &wgpu::TextureView,
-    ) -> EventResult {
-        if self.depth_texture_view.is_none() {
-            let depth_texture_extent = wgpu::Extent3d {
-                width: app_context.window_size.x,
-                height: app_context.window_size.y,
-                depth_or_array_layers: 1,
-            };
-            let depth_texture = app_context.device.create_texture(&wgpu::TextureDescriptor {
-                size: depth_texture_extent,
-                mip_level_count: 1,
-                sample_count: 1,
-                dimension: wgpu::TextureDimension::D2,
-                format: wgpu::TextureFormat::Depth32Float,
-                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-                view_formats: &[],
-                label: None,
-            });
-            let depth_texture_view =
-                depth_texture.create_view(&wgpu::TextureViewDescriptor::default());
+    fn render(&mut self, app_context: &AppContext, frame: &Frame) -> EventResult {
+        let device = &app_context.device;
+        let pipeline_layout = &self.pipeline_layout;
+        if let Some(render_pipeline) = self.shader.poll(device, |module| {
+            Self::create_render_pipeline(app_context, pipeline_layout, module)
+        }) {
+            self.render_pipeline = render_pipeline;
+        }
 
-            self.depth_texture = Some(depth_texture);
-            self.depth_texture_view = Some(depth_texture_view);
+        let loaded = self.assets.update(device, &app_context.queue);
+        if loaded.contains(&self.texture.id()) {
+            self.bind_group = Self::create_bind_group(
+                device,
+                &self.bind_group_layout,
+                self.assets.texture(&self.texture),
+            );
         }
-        let depth_texture_view = self.depth_texture_view.as_ref().unwrap();
+
+        // Convert quaternion to rotation matrix
+        let rotation_matrix = glam::Mat4::from_quat(self.rotation);
+
+        let mvp = glam::Mat4::perspective_rh_gl(
+            45.0_f32.to_radians(),
+            app_context.window_size.x as f32 / app_context.window_size.y as f32,
+            0.1,
+            100.0,
+        ) * glam::Mat4::look_at_rh(
+            glam::Vec3::new(0.0, 0.0, 5.0),
+            glam::Vec3::new(0.0, 0.0, 0.0),
+            glam::Vec3::new(0.0, 1.0, 0.0),
+        ) * rotation_matrix;
+
+        self.constants.clear();
+        let constants = self.constants.push(&MvpPushConst { mvp });
+        self.constants.flush(device, &app_context.queue);
 
         let mut encoder = app_context
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
 
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: None,
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: surface_view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.1,
-                            g: 0.2,
-                            b: 0.3,
-                            a: 1.0,
-                        }),
-                        store: wgpu::StoreOp::Store,
-                    },
-                    depth_slice: None,
-                })],
-                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                    view: depth_texture_view,
-                    depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
-                        store: wgpu::StoreOp::Discard,
-                    }),
-                    stencil_ops: None,
+        let scope = self.profiler.begin_scope("main pass", None);
+        let mut graph = RenderGraphBuilder::new();
+        let frame_resources = graph.import_frame(frame);
+        let mut pass = graph
+            .add_pass("main pass")
+            .frame_color(
+                &frame_resources,
+                wgpu::LoadOp::Clear(wgpu::Color {
+                    r: 0.1,
+                    g: 0.2,
+                    b: 0.3,
+                    a: 1.0,
                 }),
-                timestamp_writes: None,
-                occlusion_query_set: None,
-                multiview_mask: None,
-            });
-
-            // Convert quaternion to rotation matrix
-            let rotation_matrix = glam::Mat4::from_quat(self.rotation);
-
-            let mvp = glam::Mat4::perspective_rh_gl(
-                45.0_f32.to_radians(),
-                app_context.window_size.x as f32 / app_context.window_size.y as f32,
-                0.1,
-                100.0,
-            ) * glam::Mat4::look_at_rh(
-                glam::Vec3::new(0.0, 0.0, 5.0),
-                glam::Vec3::new(0.0, 0.0, 0.0),
-                glam::Vec3::new(0.0, 1.0, 0.0),
-            ) * rotation_matrix;
-
-            let pc = MvpPushConst { mvp };
-
+            )
+            .timestamp_writes(self.profiler.render_pass_timestamp_writes(scope));
+        if let Some(depth) = frame_resources.depth {
+            pass = pass.depth(
+                depth,
+                wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: wgpu::StoreOp::Discard,
+                },
+            );
+        }
+        pass.render(|_, render_pass| {
             render_pass.set_pipeline(&self.render_pipeline);
             render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
             render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
 
-            render_pass.set_immediates(0, pc.as_bytes());
+            self.constants.set(render_pass, constants);
             render_pass.set_bind_group(0, &self.bind_group, &[]);
             render_pass.draw_indexed(0..Cube::index_count(), 0, 0..1);
-        }
+        });
+        self.render_graph
+            .execute(device, &mut encoder, graph)
+            .unwrap_or_else(|err| panic!("{}", err));
+
+        self.profiler.end_scope(scope, None);
+        self.profiler.resolve(&mut encoder);
 
         app_context.queue.submit([encoder.finish()]);
+        self.profiler.end_frame(&app_context.device);
 
         if self.fps_counter.update() {
-            println!("FPS: {}", self.fps_counter.get_fps());
+            tracing::info!("FPS: {}", self.fps_counter.get_fps());
+            for stat in self.profiler.statistics() {
+                tracing::info!(
+                    "{}: cpu {:.3} ms, gpu {:.3} ms",
+                    stat.path,
+                    stat.cpu_average * 1000.0,
+                    stat.gpu_average.unwrap_or(0.0) * 1000.0
+                );
+            }
         }
 
-        EventResult::Redraw
+        EventResult::Continue
+    }
+}
+
+impl Drop for App {
+    fn drop(&mut self) {
+        if let Ok(path) = std::env::var("CHROME_TRACE") {
+            if let Err(err) = self.profiler.write_chrome_trace(&path) {
+                tracing::error!("Failed to write {}: {}", path, err);
+            }
+        }
     }
 }
 
 fn main() {
-    wgpu_app::run(|app_context: &AppContext| Box::new(App::new(app_context)));
+    wgpu_app::init_tracing();
+
+    // Wake up periodically in debug builds so that shader and texture edits show up without
+    // input.
+    let control_flow = if cfg!(debug_assertions) {
+        ControlFlow::WaitUntil(Duration::from_millis(500))
+    } else {
+        ControlFlow::Reactive
+    };
+    let config = AppConfig {
+        control_flow,
+        depth_format: Some(wgpu::TextureFormat::Depth32Float),
+        sample_count: 4,
+        ..Default::default()
+    };
+    wgpu_app::run_with_config(config, |app_context: &AppContext| {
+        Box::new(App::new(app_context))
+    });
 }
@@ -0,0 +1,179 @@
+use pollster::FutureExt;
+
+/// Environment variable that overrides `AppConfig::adapter`, parsed by `AdapterSelector::parse`.
+pub const ADAPTER_ENV_VAR: &str = "WGPU_APP_ADAPTER";
+
+/// Command line flag that makes `run` print the available adapters and exit.
+pub const LIST_ADAPTERS_ARG: &str = "--list-adapters";
+
+#[derive(PartialEq, Debug, Clone, Default)]
+pub enum AdapterSelector {
+    /// Let wgpu pick a low power adapter compatible with the surface.
+    #[default]
+    Default,
+    /// First adapter whose name contains the string, case-insensitive.
+    Name(String),
+    Backend(wgpu::Backend),
+    DeviceType(wgpu::DeviceType),
+    /// Position in the list printed by `print_adapters`.
+    Index(usize),
+}
+
+impl AdapterSelector {
+    /// Parses an index (`1`), a backend (`vulkan`, `gl`, ...), a device type
+    /// (`integrated`, `discrete`, `virtual`, `cpu`) or falls back to a name substring.
+    pub fn parse(value: &str) -> Self {
+        let value = value.trim();
+        if let Ok(index) = value.parse::<usize>() {
+            return AdapterSelector::Index(index);
+        }
+
+        match value.to_lowercase().as_str() {
+            "vulkan" | "vk" => AdapterSelector::Backend(wgpu::Backend::Vulkan),
+            "metal" | "mtl" => AdapterSelector::Backend(wgpu::Backend::Metal),
+            "dx12" | "d3d12" => AdapterSelector::Backend(wgpu::Backend::Dx12),
+            "gl" | "gles" | "opengl" => AdapterSelector::Backend(wgpu::Backend::Gl),
+            "integrated" => AdapterSelector::DeviceType(wgpu::DeviceType::IntegratedGpu),
+            "discrete" => AdapterSelector::DeviceType(wgpu::DeviceType::DiscreteGpu),
+            "virtual" => AdapterSelector::DeviceType(wgpu::DeviceType::VirtualGpu),
+            "cpu" => AdapterSelector::DeviceType(wgpu::DeviceType::Cpu),
+            _ => AdapterSelector::Name(value.to_string()),
+        }
+    }
+
+    pub fn from_env() -> Option<Self> {
+        std::env::var(ADAPTER_ENV_VAR)
+            .ok()
+            .filter(|value| !value.trim().is_empty())
+            .map(|value| Self::parse(&value))
+    }
+
+    fn matches(&self, info: &wgpu::AdapterInfo) -> bool {
+        match self {
+            AdapterSelector::Default | AdapterSelector::Index(_) => true,
+            AdapterSelector::Name(name) => info.name.to_lowercase().contains(&name.to_lowercase()),
+            AdapterSelector::Backend(backend) => info.backend == *backend,
+            AdapterSelector::DeviceType(device_type) => info.device_type == *device_type,
+        }
+    }
+}
+
+pub fn create_instance(backends: wgpu::Backends) -> wgpu::Instance {
+    wgpu::Instance::new(&wgpu::InstanceDescriptor {
+        backends,
+        ..Default::default()
+    })
+}
+
+pub fn enumerate_adapters(instance: &wgpu::Instance) -> Vec<wgpu::Adapter> {
+    instance
+        .enumerate_adapters(wgpu::Backends::all())
+        .block_on()
+}
+
+pub fn list_adapters(backends: wgpu::Backends) -> Vec<wgpu::AdapterInfo> {
+    let instance = create_instance(backends);
+    enumerate_adapters(&instance)
+        .iter()
+        .map(|adapter| adapter.get_info())
+        .collect()
+}
+
+pub fn print_adapters(backends: wgpu::Backends) {
+    for (index, info) in list_adapters(backends).iter().enumerate() {
+        println!(
+            "{}: {} ({:?}, {}, driver: {} {})",
+            index, info.name, info.device_type, info.backend, info.driver, info.driver_info
+        );
+    }
+}
+
+pub(crate) fn select_adapter(
+    instance: &wgpu::Instance,
+    surface: &wgpu::Surface,
+    selector: &AdapterSelector,
+) -> wgpu::Adapter {
+    if *selector == AdapterSelector::Default {
+        return instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::LowPower,
+                force_fallback_adapter: false,
+                compatible_surface: Some(surface),
+            })
+            .block_on()
+            .expect("No suitable GPU adapters found on the system.");
+    }
+
+    let adapters = enumerate_adapters(instance);
+    let infos = adapters
+        .iter()
+        .map(|adapter| adapter.get_info())
+        .collect::<Vec<_>>();
+
+    let adapter = match selector {
+        AdapterSelector::Index(index) => adapters.into_iter().nth(*index),
+        _ => adapters.into_iter().find(|adapter| {
+            selector.matches(&adapter.get_info()) && adapter.is_surface_supported(surface)
+        }),
+    };
+
+    let adapter = adapter.unwrap_or_else(|| {
+        panic!(
+            "No adapter matches {:?}. Available adapters: {:?}",
+            selector,
+            infos.iter().map(|info| &info.name).collect::<Vec<_>>()
+        )
+    });
+    assert!(
+        adapter.is_surface_supported(surface),
+        "Adapter {} can't present to the window surface.",
+        adapter.get_info().name
+    );
+
+    adapter
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let cases = [
+            ("0", AdapterSelector::Index(0)),
+            (" 2 ", AdapterSelector::Index(2)),
+            ("vulkan", AdapterSelector::Backend(wgpu::Backend::Vulkan)),
+            ("VK", AdapterSelector::Backend(wgpu::Backend::Vulkan)),
+            ("metal", AdapterSelector::Backend(wgpu::Backend::Metal)),
+            ("mtl", AdapterSelector::Backend(wgpu::Backend::Metal)),
+            ("dx12", AdapterSelector::Backend(wgpu::Backend::Dx12)),
+            ("D3D12", AdapterSelector::Backend(wgpu::Backend::Dx12)),
+            ("gl", AdapterSelector::Backend(wgpu::Backend::Gl)),
+            ("gles", AdapterSelector::Backend(wgpu::Backend::Gl)),
+            ("OpenGL", AdapterSelector::Backend(wgpu::Backend::Gl)),
+            (
+                "integrated",
+                AdapterSelector::DeviceType(wgpu::DeviceType::IntegratedGpu),
+            ),
+            (
+                "Discrete",
+                AdapterSelector::DeviceType(wgpu::DeviceType::DiscreteGpu),
+            ),
+            (
+                "virtual",
+                AdapterSelector::DeviceType(wgpu::DeviceType::VirtualGpu),
+            ),
+            ("cpu", AdapterSelector::DeviceType(wgpu::DeviceType::Cpu)),
+            ("llvmpipe", AdapterSelector::Name("llvmpipe".to_string())),
+            (
+                " Intel(R) UHD ",
+                AdapterSelector::Name("Intel(R) UHD".to_string()),
+            ),
+            ("-1", AdapterSelector::Name("-1".to_string())),
+        ];
+
+        for (value, expected) in cases {
+            assert_eq!(AdapterSelector::parse(value), expected, "{:?}", value);
+        }
+    }
+}
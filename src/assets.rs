@@ -0,0 +1,498 @@
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex, Weak};
+use std::thread::JoinHandle;
+use std::time::{Instant, SystemTime};
+
+use winit::event_loop::EventLoopProxy;
+
+use crate::mesh::{Mesh, MeshData, MeshError};
+use crate::preprocessor::{ComposedShader, ShaderComposer};
+use crate::shader::{self, ShaderError, POLL_INTERVAL};
+use crate::texture::{self, MipmapGenerator, Texture, TextureData, TextureError, TextureOptions};
+use crate::{AppContext, UserEventType};
+
+const MAX_WORKERS: usize = 4;
+
+/// Identifies an asset for as long as any of its handles is alive.
+#[derive(PartialEq, Eq, Hash, Debug, Copy, Clone)]
+pub struct AssetId(u64);
+
+/// Reference counted handle to an asset of an `AssetManager`. The asset is freed in the
+/// `update` after its last handle is dropped.
+pub struct Handle<T> {
+    id: AssetId,
+    refs: Arc<()>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+/// Decodes textures, meshes and shaders on worker threads and uploads them on the render
+/// thread. Loads return a handle at once and the same handle for paths that are already
+/// loaded. When data is decoded, the event loop gets `UserEventType::AssetsReady`; the app
+/// then calls `update`, typically at the start of `render`, and rebuilds the bind groups of
+/// the assets it returns. Textures read as a placeholder until then, in the format they
+/// were loaded with if it's one of `texture::DECODED_FORMATS` and `Rgba8UnormSrgb`
+/// otherwise.
+///
+/// In debug builds `update` also checks the files of all assets for changes every half a
+/// second and reloads the changed ones behind the same handles.
+#[derive(Debug)]
+pub struct AssetManager {
+    jobs: Option<mpsc::Sender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+    decoded_sender: mpsc::Sender<Decoded>,
+    decoded: mpsc::Receiver<Decoded>,
+    event_loop_proxy: EventLoopProxy<UserEventType>,
+    features: wgpu::Features,
+
+    next_id: u64,
+    paths: HashMap<(AssetKind, PathBuf), AssetId>,
+    entries: HashMap<AssetId, Entry>,
+    placeholders: HashMap<wgpu::TextureFormat, Texture>,
+    mipmap_generator: MipmapGenerator,
+
+    watch: bool,
+    last_poll: Instant,
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Decodes an asset from its files. Kept to decode it again when the files change.
+#[derive(Clone)]
+struct Decoder(Arc<dyn Fn() -> DecodedData + Send + Sync>);
+
+#[derive(PartialEq, Eq, Hash, Debug, Copy, Clone)]
+enum AssetKind {
+    Texture,
+    Mesh,
+    Shader,
+}
+
+#[derive(Debug)]
+enum Asset {
+    Texture(Texture),
+    Mesh(Mesh),
+    Shader(wgpu::ShaderModule),
+}
+
+#[derive(Debug)]
+struct Entry {
+    kind: AssetKind,
+    path: PathBuf,
+    refs: Weak<()>,
+    decoder: Decoder,
+    /// Incremented on every reload, so that older results still in flight are dropped.
+    generation: u64,
+    watched_files: Vec<(PathBuf, Option<SystemTime>)>,
+    value: Option<Asset>,
+    /// Format of the placeholder of a texture.
+    placeholder: Option<wgpu::TextureFormat>,
+}
+
+#[derive(Debug)]
+struct Decoded {
+    id: AssetId,
+    generation: u64,
+    data: DecodedData,
+}
+
+#[derive(Debug)]
+enum DecodedData {
+    Texture(Result<TextureData, TextureError>, TextureOptions),
+    Mesh(Result<MeshData, MeshError>),
+    Shader(Result<ComposedShader, ShaderError>),
+}
+
+impl AssetManager {
+    pub fn new(app_context: &AppContext) -> Self {
+        let (job_sender, job_receiver) = mpsc::channel::<Job>();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        let worker_count = std::thread::available_parallelism()
+            .map_or(1, |count| count.get())
+            .clamp(1, MAX_WORKERS);
+        let workers = (0..worker_count)
+            .map(|index| {
+                let job_receiver = job_receiver.clone();
+                std::thread::Builder::new()
+                    .name(format!("asset worker {}", index))
+                    .spawn(move || loop {
+                        let job = job_receiver.lock().unwrap().recv();
+                        match job {
+                            Ok(job) => job(),
+                            Err(_) => break,
+                        }
+                    })
+                    .expect("Failed to spawn asset worker thread")
+            })
+            .collect();
+
+        let (decoded_sender, decoded) = mpsc::channel();
+        let mut mipmap_generator = MipmapGenerator::new(&app_context.device);
+        let placeholders = texture::DECODED_FORMATS
+            .into_iter()
+            .map(|format| {
+                let placeholder = create_placeholder(
+                    &app_context.device,
+                    &app_context.queue,
+                    &mut mipmap_generator,
+                    format,
+                );
+                (format, placeholder)
+            })
+            .collect();
+
+        Self {
+            jobs: Some(job_sender),
+            workers,
+            decoded_sender,
+            decoded,
+            event_loop_proxy: app_context.event_loop_proxy.clone(),
+            features: app_context.device.features(),
+            next_id: 0,
+            paths: HashMap::new(),
+            entries: HashMap::new(),
+            placeholders,
+            mipmap_generator,
+            watch: cfg!(debug_assertions),
+            last_poll: Instant::now(),
+        }
+    }
+
+    /// Enables or disables reloading changed files, which is on in debug builds.
+    pub fn set_watch(&mut self, watch: bool) {
+        self.watch = watch;
+    }
+
+    /// Loads an image, KTX2 or DDS file like `texture::load`. A path that is already loaded
+    /// keeps the options it was first loaded with.
+    pub fn load_texture(
+        &mut self,
+        path: impl AsRef<Path>,
+        options: &TextureOptions,
+    ) -> Handle<Texture> {
+        let format = options.format;
+        let options = options.clone();
+        let features = self.features;
+        let handle = self.load(AssetKind::Texture, path.as_ref(), move |path| {
+            let data = texture::decode(path, &options, features);
+            DecodedData::Texture(data, options.clone())
+        });
+
+        if let Some(entry) = self.entries.get_mut(&handle.id) {
+            entry.placeholder.get_or_insert(format);
+        }
+        handle
+    }
+
+    /// Loads a Wavefront OBJ file, see `MeshData::load_obj`.
+    pub fn load_mesh(&mut self, path: impl AsRef<Path>) -> Handle<Mesh> {
+        self.load(AssetKind::Mesh, path.as_ref(), |path| {
+            DecodedData::Mesh(MeshData::load_obj(path))
+        })
+    }
+
+    /// Reads and preprocesses a WGSL file with `composer`. A path that is already loaded keeps
+    /// the composer it was first loaded with.
+    pub fn load_shader(
+        &mut self,
+        path: impl AsRef<Path>,
+        composer: ShaderComposer,
+    ) -> Handle<wgpu::ShaderModule> {
+        self.load(AssetKind::Shader, path.as_ref(), move |path| {
+            let composed = std::fs::read_to_string(path)
+                .map_err(|err| ShaderError {
+                    path: path.to_path_buf(),
+                    messages: vec![err.to_string()],
+                })
+                .and_then(|code| composer.compose(path, &code));
+            DecodedData::Shader(composed)
+        })
+    }
+
+    /// Uploads the assets decoded since the last call, starts reloading the ones whose files
+    /// changed and frees the ones without handles. Returns the assets that were loaded or
+    /// reloaded, whose bind groups need to be recreated. Failed loads are logged; the asset
+    /// keeps its placeholder or previous data.
+    pub fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<AssetId> {
+        if self.watch && self.last_poll.elapsed() >= POLL_INTERVAL {
+            self.last_poll = Instant::now();
+            self.reload_changed();
+        }
+
+        let mut loaded = vec![];
+        while let Ok(decoded) = self.decoded.try_recv() {
+            let Some(entry) = self.entries.get_mut(&decoded.id) else {
+                continue;
+            };
+            if entry.generation != decoded.generation || entry.refs.strong_count() == 0 {
+                continue;
+            }
+
+            let path = &entry.path;
+            let uploaded = match decoded.data {
+                DecodedData::Texture(data, options) => data
+                    .and_then(|data| {
+                        data.upload(device, queue, &mut self.mipmap_generator, &options)
+                    })
+                    .map(Asset::Texture)
+                    .map_err(|err| match err {
+                        TextureError::Read { .. } => err.to_string(),
+                        _ => format!("Failed to load {}: {}", path.display(), err),
+                    }),
+                DecodedData::Mesh(data) => data
+                    .map(|data| Asset::Mesh(Mesh::new(device, &data)))
+                    .map_err(|err| err.to_string()),
+                DecodedData::Shader(composed) => composed
+                    .and_then(|composed| {
+                        entry.watched_files = watched_files(&composed.files);
+                        shader::compile(device, &composed)
+                    })
+                    .map(Asset::Shader)
+                    .map_err(|err| err.to_string()),
+            };
+
+            match uploaded {
+                Ok(asset) => {
+                    if entry.value.is_some() {
+                        tracing::info!("Reloaded {}", path.display());
+                    } else {
+                        tracing::debug!("Loaded {}", path.display());
+                    }
+                    entry.value = Some(asset);
+                    loaded.push(decoded.id);
+                }
+                Err(message) => tracing::error!("{}", message),
+            }
+        }
+
+        let paths = &mut self.paths;
+        self.entries.retain(|id, entry| {
+            let used = entry.refs.strong_count() > 0;
+            if !used {
+                tracing::debug!("Released {}", entry.path.display());
+                let key = (entry.kind, std::mem::take(&mut entry.path));
+                if paths.get(&key) == Some(id) {
+                    paths.remove(&key);
+                }
+            }
+            used
+        });
+
+        loaded
+    }
+
+    /// The loaded texture, or a placeholder while it's loading or if it failed to load.
+    pub fn texture(&self, handle: &Handle<Texture>) -> &Texture {
+        match self.entries.get(&handle.id) {
+            Some(Entry {
+                value: Some(Asset::Texture(texture)),
+                ..
+            }) => texture,
+            entry => {
+                let format = entry.and_then(|entry| entry.placeholder);
+                self.placeholder(format.unwrap_or(wgpu::TextureFormat::Rgba8UnormSrgb))
+            }
+        }
+    }
+
+    pub fn mesh(&self, handle: &Handle<Mesh>) -> Option<&Mesh> {
+        match self.value(handle.id) {
+            Some(Asset::Mesh(mesh)) => Some(mesh),
+            _ => None,
+        }
+    }
+
+    pub fn shader(&self, handle: &Handle<wgpu::ShaderModule>) -> Option<&wgpu::ShaderModule> {
+        match self.value(handle.id) {
+            Some(Asset::Shader(module)) => Some(module),
+            _ => None,
+        }
+    }
+
+    /// Placeholder of `Rgba8UnormSrgb` textures.
+    pub fn placeholder_texture(&self) -> &Texture {
+        self.placeholder(wgpu::TextureFormat::Rgba8UnormSrgb)
+    }
+
+    fn placeholder(&self, format: wgpu::TextureFormat) -> &Texture {
+        self.placeholders
+            .get(&format)
+            .unwrap_or(&self.placeholders[&wgpu::TextureFormat::Rgba8UnormSrgb])
+    }
+
+    fn value(&self, id: AssetId) -> Option<&Asset> {
+        self.entries.get(&id).and_then(|entry| entry.value.as_ref())
+    }
+
+    /// Returns the handle of the asset at `path` if it's already known, or else registers it
+    /// and starts decoding it with `decode`.
+    fn load<T>(
+        &mut self,
+        kind: AssetKind,
+        path: &Path,
+        decode: impl Fn(&Path) -> DecodedData + Send + Sync + 'static,
+    ) -> Handle<T> {
+        let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let key = (kind, path.clone());
+
+        let existing = self.paths.get(&key).and_then(|id| {
+            let refs = self.entries.get(id)?.refs.upgrade()?;
+            Some(Handle::new(*id, refs))
+        });
+        if let Some(handle) = existing {
+            return handle;
+        }
+
+        let id = AssetId(self.next_id);
+        self.next_id += 1;
+        let refs = Arc::new(());
+
+        let decode_path = path.clone();
+        let decoder = Decoder(Arc::new(move || decode(&decode_path)));
+        self.spawn(id, 0, &decoder);
+
+        self.entries.insert(
+            id,
+            Entry {
+                kind,
+                watched_files: watched_files(std::slice::from_ref(&path)),
+                path: path.clone(),
+                refs: Arc::downgrade(&refs),
+                decoder,
+                generation: 0,
+                value: None,
+                placeholder: None,
+            },
+        );
+        self.paths.insert(key, id);
+
+        Handle::new(id, refs)
+    }
+
+    fn reload_changed(&mut self) {
+        let mut changed = vec![];
+        for (id, entry) in self.entries.iter_mut() {
+            let modified = entry
+                .watched_files
+                .iter()
+                .any(|(path, modified)| shader::modified_time(path) != *modified);
+            if !modified {
+                continue;
+            }
+
+            for (path, modified) in entry.watched_files.iter_mut() {
+                *modified = shader::modified_time(path);
+            }
+            entry.generation += 1;
+            changed.push((*id, entry.generation, entry.decoder.clone()));
+        }
+
+        for (id, generation, decoder) in changed {
+            self.spawn(id, generation, &decoder);
+        }
+    }
+
+    fn spawn(&self, id: AssetId, generation: u64, decoder: &Decoder) {
+        let decoder = decoder.clone();
+        let decoded_sender = self.decoded_sender.clone();
+        let event_loop_proxy = self.event_loop_proxy.clone();
+        let job = Box::new(move || {
+            let data = (decoder.0)();
+            let decoded = Decoded {
+                id,
+                generation,
+                data,
+            };
+            // The manager or the event loop may be gone when the app is shutting down.
+            if decoded_sender.send(decoded).is_ok() {
+                let _ = event_loop_proxy.send_event(UserEventType::AssetsReady);
+            }
+        });
+
+        self.jobs
+            .as_ref()
+            .unwrap()
+            .send(job)
+            .expect("Asset workers stopped");
+    }
+}
+
+impl Drop for AssetManager {
+    fn drop(&mut self) {
+        // Closing the channel stops the workers after their current job.
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl<T> Handle<T> {
+    fn new(id: AssetId, refs: Arc<()>) -> Self {
+        Self {
+            id,
+            refs,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn id(&self) -> AssetId {
+        self.id
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.id, self.refs.clone())
+    }
+}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> std::fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Handle").field(&self.id.0).finish()
+    }
+}
+
+impl std::fmt::Debug for Decoder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Decoder")
+    }
+}
+
+fn watched_files(paths: &[PathBuf]) -> Vec<(PathBuf, Option<SystemTime>)> {
+    paths
+        .iter()
+        .map(|path| (path.clone(), shader::modified_time(path)))
+        .collect()
+}
+
+/// Grey checkerboard shown in place of textures of `format` that aren't loaded.
+fn create_placeholder(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    mipmap_generator: &mut MipmapGenerator,
+    format: wgpu::TextureFormat,
+) -> Texture {
+    let (light, dark) = ([160u8, 160, 160, 255], [96u8, 96, 96, 255]);
+    let pixels = texture::convert_rgba8(&[light, dark, dark, light].concat(), format)
+        .expect("Placeholders are created in decoded formats");
+    let options = TextureOptions {
+        format,
+        mipmaps: false,
+        address_mode: wgpu::AddressMode::Repeat,
+        filter: wgpu::FilterMode::Nearest,
+        ..Default::default()
+    };
+
+    texture::from_bytes(device, queue, mipmap_generator, 2, 2, &pixels, &options)
+        .expect("Failed to create placeholder texture")
+}
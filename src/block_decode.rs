@@ -0,0 +1,834 @@
+use wgpu::{AstcChannel, TextureFormat};
+
+mod astc;
+mod bptc;
+
+const ETC1_MODIFIERS: [[i32; 2]; 8] = [
+    [2, 8],
+    [5, 17],
+    [9, 29],
+    [13, 42],
+    [18, 60],
+    [24, 80],
+    [33, 106],
+    [47, 183],
+];
+
+const ETC2_DISTANCES: [i32; 8] = [3, 6, 11, 16, 23, 32, 41, 64];
+
+const EAC_MODIFIERS: [[i32; 8]; 16] = [
+    [-3, -6, -9, -15, 2, 5, 8, 14],
+    [-3, -7, -10, -13, 2, 6, 9, 12],
+    [-2, -5, -8, -13, 1, 4, 7, 12],
+    [-2, -4, -6, -13, 1, 3, 5, 12],
+    [-3, -6, -8, -12, 2, 5, 7, 11],
+    [-3, -7, -9, -11, 2, 6, 8, 10],
+    [-4, -7, -8, -11, 3, 6, 7, 10],
+    [-3, -5, -8, -11, 2, 4, 7, 10],
+    [-2, -6, -8, -10, 1, 5, 7, 9],
+    [-2, -5, -8, -10, 1, 4, 7, 9],
+    [-2, -4, -8, -10, 1, 3, 7, 9],
+    [-2, -5, -7, -10, 1, 4, 6, 9],
+    [-3, -4, -7, -10, 2, 3, 6, 9],
+    [-1, -2, -3, -10, 0, 1, 2, 9],
+    [-4, -6, -8, -9, 3, 5, 7, 8],
+    [-3, -5, -7, -9, 2, 4, 6, 8],
+];
+
+type Block = [[u8; 4]; 16];
+
+/// Decodes a block into its texels, row by row, in the decompressed format.
+type DecodeBlock = Box<dyn Fn(&[u8], &mut [u8])>;
+
+/// Format `decompress` converts `format` to, `None` if there is no CPU decoder for it.
+/// Covers BC1-BC7, ETC2/EAC and ASTC LDR, signed formats going to `Rgba8Snorm` and BC6H
+/// to `Rgba16Float`.
+pub(crate) fn decompressed_format(format: TextureFormat) -> Option<TextureFormat> {
+    match format {
+        TextureFormat::Bc4RSnorm
+        | TextureFormat::Bc5RgSnorm
+        | TextureFormat::EacR11Snorm
+        | TextureFormat::EacRg11Snorm => Some(TextureFormat::Rgba8Snorm),
+        TextureFormat::Bc6hRgbUfloat | TextureFormat::Bc6hRgbFloat => {
+            Some(TextureFormat::Rgba16Float)
+        }
+        TextureFormat::Astc {
+            channel: AstcChannel::Hdr,
+            ..
+        } => None,
+        _ if format.is_compressed() && format.is_srgb() => Some(TextureFormat::Rgba8UnormSrgb),
+        _ if format.is_compressed() => Some(TextureFormat::Rgba8Unorm),
+        _ => None,
+    }
+}
+
+/// Decompresses one mip level of a single layer to `decompressed_format(format)`, or `None`
+/// if there is no CPU decoder for `format` or `data` is too short.
+pub(crate) fn decompress(
+    format: TextureFormat,
+    width: u32,
+    height: u32,
+    data: &[u8],
+) -> Option<Vec<u8>> {
+    let (block_width, block_height) = format.block_dimensions();
+    let (block_width, block_height) = (block_width as usize, block_height as usize);
+    let decode_block: DecodeBlock = match format {
+        TextureFormat::Bc1RgbaUnorm | TextureFormat::Bc1RgbaUnormSrgb => {
+            rgba8(|block| bc1(block, true))
+        }
+        TextureFormat::Bc2RgbaUnorm | TextureFormat::Bc2RgbaUnormSrgb => rgba8(bc2),
+        TextureFormat::Bc3RgbaUnorm | TextureFormat::Bc3RgbaUnormSrgb => rgba8(bc3),
+        TextureFormat::Bc4RUnorm => rgba8(bc4),
+        TextureFormat::Bc4RSnorm => rgba8(bc4_snorm),
+        TextureFormat::Bc5RgUnorm => rgba8(bc5),
+        TextureFormat::Bc5RgSnorm => rgba8(bc5_snorm),
+        TextureFormat::Bc6hRgbUfloat => rgba16(|block| bptc::bc6h(block, false)),
+        TextureFormat::Bc6hRgbFloat => rgba16(|block| bptc::bc6h(block, true)),
+        TextureFormat::Bc7RgbaUnorm | TextureFormat::Bc7RgbaUnormSrgb => rgba8(bptc::bc7),
+        TextureFormat::Etc2Rgb8Unorm | TextureFormat::Etc2Rgb8UnormSrgb => {
+            rgba8(|block| etc2_rgb(block, false))
+        }
+        TextureFormat::Etc2Rgb8A1Unorm | TextureFormat::Etc2Rgb8A1UnormSrgb => {
+            rgba8(|block| etc2_rgb(block, true))
+        }
+        TextureFormat::Etc2Rgba8Unorm | TextureFormat::Etc2Rgba8UnormSrgb => rgba8(etc2_rgba),
+        TextureFormat::EacR11Unorm => rgba8(eac_r11),
+        TextureFormat::EacR11Snorm => rgba8(eac_r11_snorm),
+        TextureFormat::EacRg11Unorm => rgba8(eac_rg11),
+        TextureFormat::EacRg11Snorm => rgba8(eac_rg11_snorm),
+        TextureFormat::Astc {
+            channel: AstcChannel::Unorm | AstcChannel::UnormSrgb,
+            ..
+        } => {
+            let srgb = format.is_srgb();
+            Box::new(move |block, texels| {
+                let texels = texels.as_chunks_mut().0;
+                astc::decode_block(block, block_width, block_height, srgb, texels)
+            })
+        }
+        _ => return None,
+    };
+    let block_size = format.block_copy_size(None)? as usize;
+    let texel_size = decompressed_format(format)?.block_copy_size(None)? as usize;
+
+    let blocks_x = (width as usize).div_ceil(block_width);
+    let blocks_y = (height as usize).div_ceil(block_height);
+    if data.len() < blocks_x * blocks_y * block_size {
+        return None;
+    }
+
+    let (width, height) = (width as usize, height as usize);
+    let mut pixels = vec![0u8; width * height * texel_size];
+    let mut texels = vec![0u8; block_width * block_height * texel_size];
+    for (index, block) in data
+        .chunks_exact(block_size)
+        .take(blocks_x * blocks_y)
+        .enumerate()
+    {
+        let (block_x, block_y) = (
+            index % blocks_x * block_width,
+            index / blocks_x * block_height,
+        );
+        decode_block(block, &mut texels);
+        let row_size = block_width.min(width - block_x) * texel_size;
+        for y in 0..block_height.min(height - block_y) {
+            let offset = ((block_y + y) * width + block_x) * texel_size;
+            let source = y * block_width * texel_size;
+            pixels[offset..offset + row_size].copy_from_slice(&texels[source..source + row_size]);
+        }
+    }
+
+    Some(pixels)
+}
+
+fn rgba8(decode: fn(&[u8]) -> Block) -> DecodeBlock {
+    Box::new(move |block, texels| texels.copy_from_slice(decode(block).as_flattened()))
+}
+
+fn rgba16(decode: fn(&[u8]) -> [[u16; 4]; 16]) -> DecodeBlock {
+    Box::new(move |block, texels| {
+        for (texel, value) in texels.chunks_exact_mut(2).zip(decode(block).as_flattened()) {
+            texel.copy_from_slice(&value.to_ne_bytes());
+        }
+    })
+}
+
+/// Reads fields of a 128-bit block from its least significant bit, bits from `end` on
+/// reading as zero.
+struct BitReader {
+    bits: u128,
+    position: u32,
+    end: u32,
+}
+
+impl BitReader {
+    fn new(block: &[u8]) -> Self {
+        Self {
+            bits: u128::from_le_bytes(block.try_into().unwrap()),
+            position: 0,
+            end: 128,
+        }
+    }
+
+    fn read(&mut self, count: u32) -> u32 {
+        let available = count.min(self.end.saturating_sub(self.position));
+        let value = if available == 0 {
+            0
+        } else {
+            (self.bits >> self.position) as u32 & (u32::MAX >> (32 - available))
+        };
+        self.position += count;
+        value
+    }
+}
+
+fn rgb565(color: u16) -> [i32; 3] {
+    let r = (color >> 11) as i32 & 31;
+    let g = (color >> 5) as i32 & 63;
+    let b = color as i32 & 31;
+    [
+        (r << 3) | (r >> 2),
+        (g << 2) | (g >> 4),
+        (b << 3) | (b >> 2),
+    ]
+}
+
+/// BC1 color block. BC2 and BC3 always use the four color mode.
+fn bc1(block: &[u8], allow_transparent: bool) -> Block {
+    let color0 = u16::from_le_bytes([block[0], block[1]]);
+    let color1 = u16::from_le_bytes([block[2], block[3]]);
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    let (c0, c1) = (rgb565(color0), rgb565(color1));
+
+    let mix = |w0: i32, w1: i32, d: i32| -> [u8; 4] {
+        let channel = |i: usize| ((c0[i] * w0 + c1[i] * w1) / d) as u8;
+        [channel(0), channel(1), channel(2), 255]
+    };
+    let palette = if color0 > color1 || !allow_transparent {
+        [mix(1, 0, 1), mix(0, 1, 1), mix(2, 1, 3), mix(1, 2, 3)]
+    } else {
+        [mix(1, 0, 1), mix(0, 1, 1), mix(1, 1, 2), [0, 0, 0, 0]]
+    };
+
+    std::array::from_fn(|i| palette[(indices >> (2 * i)) as usize & 3])
+}
+
+fn bc2(block: &[u8]) -> Block {
+    let alpha = u64::from_le_bytes(block[..8].try_into().unwrap());
+    let mut texels = bc1(&block[8..], false);
+    for (i, texel) in texels.iter_mut().enumerate() {
+        texel[3] = ((alpha >> (4 * i)) & 15) as u8 * 17;
+    }
+    texels
+}
+
+fn bc3(block: &[u8]) -> Block {
+    let alpha = bc_alpha(&block[..8]);
+    let mut texels = bc1(&block[8..], false);
+    for (texel, alpha) in texels.iter_mut().zip(alpha) {
+        texel[3] = alpha;
+    }
+    texels
+}
+
+fn bc4(block: &[u8]) -> Block {
+    bc_alpha(block).map(|red| [red, 0, 0, 255])
+}
+
+fn bc5(block: &[u8]) -> Block {
+    let red = bc_alpha(&block[..8]);
+    let green = bc_alpha(&block[8..]);
+    std::array::from_fn(|i| [red[i], green[i], 0, 255])
+}
+
+fn bc4_snorm(block: &[u8]) -> Block {
+    bc_alpha_snorm(block).map(|red| [red, 0, 0, 127])
+}
+
+fn bc5_snorm(block: &[u8]) -> Block {
+    let red = bc_alpha_snorm(&block[..8]);
+    let green = bc_alpha_snorm(&block[8..]);
+    std::array::from_fn(|i| [red[i], green[i], 0, 127])
+}
+
+/// Interpolated 8-bit channel shared by BC3 alpha, BC4 and BC5.
+fn bc_alpha(block: &[u8]) -> [u8; 16] {
+    let (a0, a1) = (block[0] as u32, block[1] as u32);
+    let mut bits = [0u8; 8];
+    bits[..6].copy_from_slice(&block[2..8]);
+    let indices = u64::from_le_bytes(bits);
+
+    let mut palette = [0u8; 8];
+    palette[0] = a0 as u8;
+    palette[1] = a1 as u8;
+    if a0 > a1 {
+        for k in 1..7 {
+            palette[k + 1] = (((7 - k as u32) * a0 + k as u32 * a1) / 7) as u8;
+        }
+    } else {
+        for k in 1..5 {
+            palette[k + 1] = (((5 - k as u32) * a0 + k as u32 * a1) / 5) as u8;
+        }
+        palette[6] = 0;
+        palette[7] = 255;
+    }
+
+    std::array::from_fn(|i| palette[(indices >> (3 * i)) as usize & 7])
+}
+
+/// Signed BC4 and BC5 channel, as the bytes of 8-bit snorm values.
+fn bc_alpha_snorm(block: &[u8]) -> [u8; 16] {
+    let a0 = (block[0] as i8).max(-127) as i32;
+    let a1 = (block[1] as i8).max(-127) as i32;
+    let mut bits = [0u8; 8];
+    bits[..6].copy_from_slice(&block[2..8]);
+    let indices = u64::from_le_bytes(bits);
+
+    let mut palette = [0i32; 8];
+    palette[0] = a0;
+    palette[1] = a1;
+    if a0 > a1 {
+        for k in 1..7 {
+            palette[k + 1] = ((7 - k as i32) * a0 + k as i32 * a1) / 7;
+        }
+    } else {
+        for k in 1..5 {
+            palette[k + 1] = ((5 - k as i32) * a0 + k as i32 * a1) / 5;
+        }
+        palette[6] = -127;
+        palette[7] = 127;
+    }
+
+    std::array::from_fn(|i| palette[(indices >> (3 * i)) as usize & 7] as i8 as u8)
+}
+
+fn expand4(value: u64) -> i32 {
+    let value = (value & 15) as i32;
+    (value << 4) | value
+}
+
+fn expand5(value: i32) -> i32 {
+    (value << 3) | (value >> 2)
+}
+
+/// ETC2 RGB block, with `punchthrough` for RGB8A1 where the differential bit marks
+/// opaque blocks.
+fn etc2_rgb(block: &[u8], punchthrough: bool) -> Block {
+    let word = u64::from_be_bytes(block[..8].try_into().unwrap());
+    let differential = punchthrough || (word >> 33) & 1 == 1;
+    let opaque = !punchthrough || (word >> 33) & 1 == 1;
+    let flip = (word >> 32) & 1 == 1;
+
+    // Pixel indices are stored column by column, with the high bits in the upper half.
+    let index = |x: usize, y: usize| {
+        let p = x * 4 + y;
+        (((word >> (16 + p)) & 1) << 1 | ((word >> p) & 1)) as usize
+    };
+    let clamp = |value: i32| value.clamp(0, 255) as u8;
+    let mut texels = [[0u8; 4]; 16];
+
+    if !differential {
+        let base = [
+            [
+                expand4(word >> 60),
+                expand4(word >> 52),
+                expand4(word >> 44),
+            ],
+            [
+                expand4(word >> 56),
+                expand4(word >> 48),
+                expand4(word >> 40),
+            ],
+        ];
+        let tables = [(word >> 37) as usize & 7, (word >> 34) as usize & 7];
+        etc1_subblocks(&mut texels, base, tables, flip, opaque, &index);
+        return texels;
+    }
+
+    let r = (word >> 59) as i32 & 31;
+    let g = (word >> 51) as i32 & 31;
+    let b = (word >> 43) as i32 & 31;
+    let delta = |shift: u32| (((word >> shift) as i32 & 7) << 29) >> 29;
+    let (r2, g2, b2) = (r + delta(56), g + delta(48), b + delta(40));
+
+    let paint = if !(0..32).contains(&r2) {
+        // T mode.
+        let c1 = [
+            expand4((word >> 57) & 12 | (word >> 56) & 3),
+            expand4(word >> 52),
+            expand4(word >> 48),
+        ];
+        let c2 = [
+            expand4(word >> 44),
+            expand4(word >> 40),
+            expand4(word >> 36),
+        ];
+        let distance = ETC2_DISTANCES[((word >> 33) & 6 | (word >> 32) & 1) as usize];
+        [
+            c1.map(clamp),
+            c2.map(|c| clamp(c + distance)),
+            c2.map(clamp),
+            c2.map(|c| clamp(c - distance)),
+        ]
+    } else if !(0..32).contains(&g2) {
+        // H mode.
+        let c1 = [
+            expand4(word >> 59),
+            expand4((word >> 55) & 14 | (word >> 52) & 1),
+            expand4((word >> 48) & 8 | (word >> 47) & 7),
+        ];
+        let c2 = [
+            expand4(word >> 43),
+            expand4(word >> 39),
+            expand4(word >> 35),
+        ];
+        let value = |c: [i32; 3]| (c[0] << 16) | (c[1] << 8) | c[2];
+        let distance_index = (word >> 32) & 4 | (word >> 31) & 2 | (value(c1) >= value(c2)) as u64;
+        let distance = ETC2_DISTANCES[distance_index as usize];
+        [
+            c1.map(|c| clamp(c + distance)),
+            c1.map(|c| clamp(c - distance)),
+            c2.map(|c| clamp(c + distance)),
+            c2.map(|c| clamp(c - distance)),
+        ]
+    } else if !(0..32).contains(&b2) {
+        etc2_planar(&mut texels, word);
+        return texels;
+    } else {
+        let base = [
+            [expand5(r), expand5(g), expand5(b)],
+            [expand5(r2), expand5(g2), expand5(b2)],
+        ];
+        let tables = [(word >> 37) as usize & 7, (word >> 34) as usize & 7];
+        etc1_subblocks(&mut texels, base, tables, flip, opaque, &index);
+        return texels;
+    };
+
+    for y in 0..4 {
+        for x in 0..4 {
+            let index = index(x, y);
+            texels[y * 4 + x] = if !opaque && index == 2 {
+                [0, 0, 0, 0]
+            } else {
+                let [r, g, b] = paint[index];
+                [r, g, b, 255]
+            };
+        }
+    }
+
+    texels
+}
+
+fn etc1_subblocks(
+    texels: &mut Block,
+    base: [[i32; 3]; 2],
+    tables: [usize; 2],
+    flip: bool,
+    opaque: bool,
+    index: &impl Fn(usize, usize) -> usize,
+) {
+    for y in 0..4 {
+        for x in 0..4 {
+            let subblock = if flip { y / 2 } else { x / 2 };
+            let [small, large] = ETC1_MODIFIERS[tables[subblock]];
+            let modifier = match index(x, y) {
+                0 if !opaque => 0,
+                0 => small,
+                1 => large,
+                2 if !opaque => {
+                    texels[y * 4 + x] = [0, 0, 0, 0];
+                    continue;
+                }
+                2 => -small,
+                _ => -large,
+            };
+            let color = base[subblock].map(|c| (c + modifier).clamp(0, 255) as u8);
+            texels[y * 4 + x] = [color[0], color[1], color[2], 255];
+        }
+    }
+}
+
+fn etc2_planar(texels: &mut Block, word: u64) {
+    let bits = |shift: u32, count: u32| ((word >> shift) & ((1 << count) - 1)) as i32;
+    let expand6 = |value: i32| (value << 2) | (value >> 4);
+    let expand7 = |value: i32| (value << 1) | (value >> 6);
+
+    let origin = [
+        expand6(bits(57, 6)),
+        expand7(bits(56, 1) << 6 | bits(49, 6)),
+        expand6(bits(48, 1) << 5 | bits(43, 2) << 3 | bits(39, 3)),
+    ];
+    let horizontal = [
+        expand6(bits(34, 5) << 1 | bits(32, 1)),
+        expand7(bits(25, 7)),
+        expand6(bits(19, 6)),
+    ];
+    let vertical = [
+        expand6(bits(13, 6)),
+        expand7(bits(6, 7)),
+        expand6(bits(0, 6)),
+    ];
+
+    for y in 0..4 {
+        for x in 0..4 {
+            let channel = |i: usize| {
+                let value = x as i32 * (horizontal[i] - origin[i])
+                    + y as i32 * (vertical[i] - origin[i])
+                    + 4 * origin[i]
+                    + 2;
+                (value >> 2).clamp(0, 255) as u8
+            };
+            texels[y * 4 + x] = [channel(0), channel(1), channel(2), 255];
+        }
+    }
+}
+
+fn etc2_rgba(block: &[u8]) -> Block {
+    let alpha = eac(&block[..8], false);
+    let mut texels = etc2_rgb(&block[8..], false);
+    for (texel, alpha) in texels.iter_mut().zip(alpha) {
+        texel[3] = alpha;
+    }
+    texels
+}
+
+fn eac_r11(block: &[u8]) -> Block {
+    eac(block, true).map(|red| [red, 0, 0, 255])
+}
+
+fn eac_rg11(block: &[u8]) -> Block {
+    let red = eac(&block[..8], true);
+    let green = eac(&block[8..], true);
+    std::array::from_fn(|i| [red[i], green[i], 0, 255])
+}
+
+fn eac_r11_snorm(block: &[u8]) -> Block {
+    eac_snorm(block).map(|red| [red, 0, 0, 127])
+}
+
+fn eac_rg11_snorm(block: &[u8]) -> Block {
+    let red = eac_snorm(&block[..8]);
+    let green = eac_snorm(&block[8..]);
+    std::array::from_fn(|i| [red[i], green[i], 0, 127])
+}
+
+/// EAC channel in row-major order. `eleven_bit` decodes R11/RG11 at full precision
+/// before reducing to 8 bits, otherwise it's the ETC2 alpha channel.
+fn eac(block: &[u8], eleven_bit: bool) -> [u8; 16] {
+    let word = u64::from_be_bytes(block[..8].try_into().unwrap());
+    let base = (word >> 56) as i32;
+    let multiplier = (word >> 52) as i32 & 15;
+    let modifiers = EAC_MODIFIERS[(word >> 48) as usize & 15];
+
+    std::array::from_fn(|i| {
+        let (x, y) = (i % 4, i / 4);
+        let modifier = modifiers[(word >> (45 - 3 * (x * 4 + y))) as usize & 7];
+        if eleven_bit {
+            let value = if multiplier == 0 {
+                base * 8 + 4 + modifier
+            } else {
+                base * 8 + 4 + modifier * multiplier * 8
+            };
+            (value.clamp(0, 2047) * 255 / 2047) as u8
+        } else {
+            (base + modifier * multiplier).clamp(0, 255) as u8
+        }
+    })
+}
+
+/// Signed R11/RG11 EAC channel, as the bytes of 8-bit snorm values.
+fn eac_snorm(block: &[u8]) -> [u8; 16] {
+    let word = u64::from_be_bytes(block[..8].try_into().unwrap());
+    let base = ((word >> 56) as i8).max(-127) as i32;
+    let multiplier = (word >> 52) as i32 & 15;
+    let modifiers = EAC_MODIFIERS[(word >> 48) as usize & 15];
+
+    std::array::from_fn(|i| {
+        let (x, y) = (i % 4, i / 4);
+        let modifier = modifiers[(word >> (45 - 3 * (x * 4 + y))) as usize & 7];
+        let value = if multiplier == 0 {
+            base * 8 + modifier
+        } else {
+            base * 8 + modifier * multiplier * 8
+        };
+        (value.clamp(-1023, 1023) * 127 / 1023) as i8 as u8
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Row-major texels of a gray block.
+    fn gray(values: [u8; 16]) -> Block {
+        values.map(|value| [value, value, value, 255])
+    }
+
+    /// ETC2 indices picking modifier `y`, the texel's row: +small, +large, -small, -large.
+    const ETC_ROW_INDICES: [u8; 4] = [0xCC, 0xCC, 0xAA, 0xAA];
+
+    /// ETC2 indices picking modifier `x`, the texel's column.
+    const ETC_COLUMN_INDICES: [u8; 4] = [0xFF, 0x00, 0xF0, 0xF0];
+
+    /// EAC indices where texel `(x, y)` picks modifier `(x * 4 + y) % 8`.
+    const EAC_INDICES: [u8; 6] = [0x05, 0x39, 0x77, 0x05, 0x39, 0x77];
+
+    #[test]
+    fn bc1_four_colors() {
+        // Red and blue endpoints, texel `x` in every row picks palette entry `x`.
+        let block = [0x00, 0xF8, 0x1F, 0x00, 0xE4, 0xE4, 0xE4, 0xE4];
+        let row = [
+            [255, 0, 0, 255],
+            [0, 0, 255, 255],
+            [170, 0, 85, 255],
+            [85, 0, 170, 255],
+        ];
+        assert_eq!(bc1(&block, true), [row; 4].as_flattened());
+    }
+
+    #[test]
+    fn bc1_three_colors() {
+        let block = [0x1F, 0x00, 0x00, 0xF8, 0xE4, 0xE4, 0xE4, 0xE4];
+        let row = [
+            [0, 0, 255, 255],
+            [255, 0, 0, 255],
+            [127, 0, 127, 255],
+            [0, 0, 0, 0],
+        ];
+        assert_eq!(bc1(&block, true), [row; 4].as_flattened());
+
+        // BC2 and BC3 color blocks ignore the endpoint order.
+        let row = [
+            [0, 0, 255, 255],
+            [255, 0, 0, 255],
+            [85, 0, 170, 255],
+            [170, 0, 85, 255],
+        ];
+        assert_eq!(bc1(&block, false), [row; 4].as_flattened());
+    }
+
+    #[test]
+    fn bc4() {
+        // Six interpolated values between 240 and 30, then 0 and 255 for the five value
+        // mode, texel `i` picking index `i % 8`.
+        let indices = [0x88, 0xC6, 0xFA, 0x88, 0xC6, 0xFA];
+        let texels = super::bc4(&[[240, 30].as_slice(), &indices].concat());
+        let palette = [240, 30, 210, 180, 150, 120, 90, 60];
+        for (i, texel) in texels.iter().enumerate() {
+            assert_eq!(*texel, [palette[i % 8], 0, 0, 255]);
+        }
+
+        let texels = super::bc4(&[[30, 240].as_slice(), &indices].concat());
+        let palette = [30, 240, 72, 114, 156, 198, 0, 255];
+        for (i, texel) in texels.iter().enumerate() {
+            assert_eq!(*texel, [palette[i % 8], 0, 0, 255]);
+        }
+    }
+
+    #[test]
+    fn bc4_snorm() {
+        // -128 decodes as -127.
+        let indices = [0x88, 0xC6, 0xFA, 0x88, 0xC6, 0xFA];
+        let texels = super::bc4_snorm(&[[0x80, 100].as_slice(), &indices].concat());
+        let palette: [i8; 8] = [-127, 100, -81, -36, 9, 54, -127, 127];
+        for (i, texel) in texels.iter().enumerate() {
+            assert_eq!(*texel, [palette[i % 8] as u8, 0, 0, 127]);
+        }
+    }
+
+    #[test]
+    fn etc1_individual() {
+        // Left half 0x88 with modifiers [2, 8], right half 0x44 with [47, 183].
+        let block = [[0x84, 0x84, 0x84, 0x1C], ETC_ROW_INDICES].concat();
+        let expected = gray([
+            138, 138, 115, 115, //
+            144, 144, 251, 251, //
+            134, 134, 21, 21, //
+            128, 128, 0, 0,
+        ]);
+        assert_eq!(etc2_rgb(&block, false), expected);
+    }
+
+    #[test]
+    fn etc1_differential() {
+        // Flipped, top half 132 with modifiers [5, 17], bottom half 132 - 4 * 8 -> 99
+        // with [9, 29].
+        let block = [[0x84, 0x84, 0x84, 0x2B], ETC_COLUMN_INDICES].concat();
+        let expected = gray([
+            137, 149, 127, 115, //
+            137, 149, 127, 115, //
+            108, 128, 90, 70, //
+            108, 128, 90, 70,
+        ]);
+        assert_eq!(etc2_rgb(&block, false), expected);
+    }
+
+    #[test]
+    fn etc2_t_mode() {
+        // Red overflows: colors (170, 85, 0) and (51, 102, 153) with distance 32, rows
+        // picking paint colors 0 to 3.
+        let block = [[0xF2, 0x50, 0x36, 0x9B], ETC_ROW_INDICES].concat();
+        let rows = [
+            [170, 85, 0, 255],
+            [83, 134, 185, 255],
+            [51, 102, 153, 255],
+            [19, 70, 121, 255],
+        ];
+        let expected: Block = std::array::from_fn(|i| rows[i / 4]);
+        assert_eq!(etc2_rgb(&block, false), expected);
+    }
+
+    #[test]
+    fn etc2_h_mode() {
+        // Green overflows: colors (34, 153, 102) and (119, 204, 17) with distance 23, the
+        // first color being smaller.
+        let block = [[0x94, 0xF3, 0x3E, 0x0E], ETC_ROW_INDICES].concat();
+        let rows = [
+            [57, 176, 125, 255],
+            [11, 130, 79, 255],
+            [142, 227, 40, 255],
+            [96, 181, 0, 255],
+        ];
+        let expected: Block = std::array::from_fn(|i| rows[i / 4]);
+        assert_eq!(etc2_rgb(&block, false), expected);
+    }
+
+    #[test]
+    fn etc2_planar() {
+        // Blue overflows: origin (130, 129, 0), horizontal (255, 0, 0), vertical (0, 255, 255).
+        let block = [0x41, 0x00, 0x04, 0x7F, 0x00, 0x00, 0x1F, 0xFF];
+        let expected = [
+            [130, 129, 0, 255],
+            [161, 97, 0, 255],
+            [193, 65, 0, 255],
+            [224, 32, 0, 255],
+            [98, 161, 64, 255],
+            [129, 128, 64, 255],
+            [160, 96, 64, 255],
+            [191, 64, 64, 255],
+            [65, 192, 128, 255],
+            [96, 160, 128, 255],
+            [128, 128, 128, 255],
+            [159, 95, 128, 255],
+            [33, 224, 191, 255],
+            [64, 191, 191, 255],
+            [95, 159, 191, 255],
+            [126, 127, 191, 255],
+        ];
+        assert_eq!(etc2_rgb(&block, false), expected);
+    }
+
+    #[test]
+    fn etc2_punchthrough() {
+        // The differential block with the opaque bit cleared: index 0 is the base color
+        // and index 2 is transparent.
+        let block = [[0x84, 0x84, 0x84, 0x29], ETC_COLUMN_INDICES].concat();
+        let row = |base, large, small| {
+            [
+                [base, base, base, 255],
+                [large, large, large, 255],
+                [0, 0, 0, 0],
+                [small, small, small, 255],
+            ]
+        };
+        let (top, bottom) = (row(132, 149, 115), row(99, 128, 70));
+        let expected = [top, top, bottom, bottom];
+        assert_eq!(etc2_rgb(&block, true), expected.as_flattened());
+
+        // With the opaque bit set it decodes like ETC2 RGB.
+        let block = [[0x84, 0x84, 0x84, 0x2B], ETC_COLUMN_INDICES].concat();
+        assert_eq!(etc2_rgb(&block, true), etc2_rgb(&block, false));
+    }
+
+    #[test]
+    fn eac_r11() {
+        // Base 128, multiplier 2, modifiers [-1, -2, -3, -10, 0, 1, 2, 9].
+        let block = [[128, 0x2D].as_slice(), &EAC_INDICES].concat();
+        let red = [
+            126, 128, 126, 128, //
+            124, 130, 124, 130, //
+            122, 132, 122, 132, //
+            108, 145, 108, 145,
+        ];
+        assert_eq!(super::eac_r11(&block), red.map(|red| [red, 0, 0, 255]));
+    }
+
+    #[test]
+    fn eac_rg11() {
+        // Green has base 255 and multiplier 15, positive modifiers clamping to 2047.
+        let red = [[128, 0x2D].as_slice(), &EAC_INDICES].concat();
+        let green = [[255, 0xF0].as_slice(), &EAC_INDICES].concat();
+        let red_values = [
+            126, 128, 126, 128, //
+            124, 130, 124, 130, //
+            122, 132, 122, 132, //
+            108, 145, 108, 145,
+        ];
+        let green_values = [
+            209, 255, 209, 255, //
+            164, 255, 164, 255, //
+            120, 255, 120, 255, //
+            30, 255, 30, 255,
+        ];
+        let expected: Block = std::array::from_fn(|i| [red_values[i], green_values[i], 0, 255]);
+        assert_eq!(super::eac_rg11(&[red, green].concat()), expected);
+    }
+
+    #[test]
+    fn eac_r11_snorm() {
+        // Base -100, multiplier 3, modifiers [-3, -6, -9, -15, 2, 5, 8, 14].
+        let block = [[0x9C, 0x30].as_slice(), &EAC_INDICES].concat();
+        let red: [i8; 16] = [
+            -108, -93, -108, -93, //
+            -117, -84, -117, -84, //
+            -126, -75, -126, -75, //
+            -127, -57, -127, -57,
+        ];
+        assert_eq!(
+            super::eac_r11_snorm(&block),
+            red.map(|red| [red as u8, 0, 0, 127])
+        );
+    }
+
+    #[test]
+    fn decompress_crops_partial_blocks() {
+        // 5x3 texels need two BC1 blocks, the second only contributing its first column.
+        let blue = [0x1F, 0x00, 0x1F, 0x00, 0, 0, 0, 0];
+        let red = [0x00, 0xF8, 0x00, 0xF8, 0, 0, 0, 0];
+        let pixels = decompress(TextureFormat::Bc1RgbaUnorm, 5, 3, &[blue, red].concat()).unwrap();
+        let row = [[0, 0, 255, 255]; 4]
+            .into_iter()
+            .chain([[255, 0, 0, 255]])
+            .collect::<Vec<_>>();
+        assert_eq!(pixels, row.repeat(3).as_flattened());
+
+        assert_eq!(decompress(TextureFormat::Bc1RgbaUnorm, 5, 3, &blue), None);
+    }
+
+    #[test]
+    fn decompressed_formats() {
+        let cases = [
+            (
+                TextureFormat::Bc1RgbaUnormSrgb,
+                Some(TextureFormat::Rgba8UnormSrgb),
+            ),
+            (TextureFormat::Bc5RgSnorm, Some(TextureFormat::Rgba8Snorm)),
+            (
+                TextureFormat::Bc6hRgbFloat,
+                Some(TextureFormat::Rgba16Float),
+            ),
+            (TextureFormat::EacR11Unorm, Some(TextureFormat::Rgba8Unorm)),
+            (
+                TextureFormat::Astc {
+                    block: wgpu::AstcBlock::B6x6,
+                    channel: AstcChannel::Hdr,
+                },
+                None,
+            ),
+            (TextureFormat::Rgba8Unorm, None),
+        ];
+        for (format, expected) in cases {
+            assert_eq!(decompressed_format(format), expected, "{:?}", format);
+        }
+    }
+}
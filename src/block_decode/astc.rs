@@ -0,0 +1,799 @@
+//! ASTC blocks of the LDR profile, following the Khronos Data Format Specification.
+
+use super::BitReader;
+
+/// Texels of blocks that are invalid or use HDR endpoints.
+const ERROR_COLOR: [u8; 4] = [255, 0, 255, 255];
+
+/// Levels of the integer sequence encodings and their bits per value, the rest of the
+/// levels being a trit (3) or a quint (5).
+const RANGES: [(u32, u32); 21] = [
+    (2, 1),
+    (3, 0),
+    (4, 2),
+    (5, 0),
+    (6, 1),
+    (8, 3),
+    (10, 1),
+    (12, 2),
+    (16, 4),
+    (20, 2),
+    (24, 3),
+    (32, 5),
+    (40, 3),
+    (48, 4),
+    (64, 6),
+    (80, 4),
+    (96, 5),
+    (128, 7),
+    (160, 5),
+    (192, 6),
+    (256, 8),
+];
+
+/// Weight levels by the precision bit and the 3-bit range of the block mode.
+const WEIGHT_LEVELS: [[u32; 8]; 2] = [[0, 0, 2, 3, 4, 5, 6, 8], [0, 0, 10, 12, 16, 20, 24, 32]];
+
+struct BlockMode {
+    grid_width: usize,
+    grid_height: usize,
+    dual_plane: bool,
+    weight_levels: u32,
+}
+
+/// Decodes a block of `block_width` by `block_height` texels to RGBA8, row by row.
+pub(super) fn decode_block(
+    block: &[u8],
+    block_width: usize,
+    block_height: usize,
+    srgb: bool,
+    texels: &mut [[u8; 4]],
+) {
+    if decode(block, block_width, block_height, srgb, texels).is_none() {
+        texels.fill(ERROR_COLOR);
+    }
+}
+
+fn decode(
+    block: &[u8],
+    block_width: usize,
+    block_height: usize,
+    srgb: bool,
+    texels: &mut [[u8; 4]],
+) -> Option<()> {
+    let bits = u128::from_le_bytes(block.try_into().unwrap());
+    let field = |shift: u32, count: u32| (bits >> shift) as u32 & ((1 << count) - 1);
+
+    if field(0, 9) == 0x1FC {
+        return void_extent(bits, texels);
+    }
+
+    let mode = block_mode(field(0, 11))?;
+    let partitions = field(11, 2) as usize + 1;
+    let planes = 1 + mode.dual_plane as usize;
+    let weight_count = mode.grid_width * mode.grid_height * planes;
+    let weight_bits = sequence_bits(mode.weight_levels, weight_count);
+    if mode.grid_width > block_width
+        || mode.grid_height > block_height
+        || weight_count > 64
+        || !(24..=96).contains(&weight_bits)
+        || (mode.dual_plane && partitions == 4)
+    {
+        return None;
+    }
+    let weights_start = 128 - weight_bits;
+
+    // Multiple partitions either share an endpoint mode or each pick one from two
+    // neighbouring classes, with the bits that don't fit stored below the weights.
+    let mut modes = [field(13, 4); 4];
+    let mut partition_index = 0;
+    let mut extra_bits = 0;
+    let mut colors_start = 17;
+    if partitions > 1 {
+        partition_index = field(13, 10);
+        colors_start = 29;
+        let selector = field(23, 2);
+        modes = [field(25, 4); 4];
+        if selector != 0 {
+            extra_bits = 3 * partitions as u32 - 4;
+            let encoded = field(25, 4) | field(weights_start - extra_bits, extra_bits) << 4;
+            for (i, mode) in modes.iter_mut().take(partitions).enumerate() {
+                let class = selector - 1 + (encoded >> i & 1);
+                *mode = class << 2 | (encoded >> (partitions + 2 * i) & 3);
+            }
+        }
+    }
+    let colors_end = weights_start - extra_bits - 2 * mode.dual_plane as u32;
+    let dual_plane_channel = field(colors_end, 2) as usize;
+
+    let value_counts = modes.map(|mode| 2 * (mode as usize >> 2) + 2);
+    let value_count = value_counts[..partitions].iter().sum::<usize>();
+    if value_count > 18 || colors_end <= colors_start {
+        return None;
+    }
+    let available = colors_end - colors_start;
+    let &(color_levels, _) = RANGES
+        .iter()
+        .rev()
+        .take_while(|&&(levels, _)| levels >= 6)
+        .find(|&&(levels, _)| sequence_bits(levels, value_count) <= available)?;
+    // Values of a partial trit or quint block past the end of the sequence are zero.
+    let mut colors = BitReader {
+        bits,
+        position: colors_start,
+        end: colors_start + sequence_bits(color_levels, value_count),
+    };
+    let values = decode_sequence(&mut colors, color_levels, value_count)
+        .into_iter()
+        .map(|value| unquantize_color(value, color_levels))
+        .collect::<Vec<_>>();
+
+    let mut endpoints = [[[0; 4]; 2]; 4];
+    let mut offset = 0;
+    for partition in 0..partitions {
+        let count = value_counts[partition];
+        endpoints[partition] = color_endpoints(modes[partition], &values[offset..offset + count])?;
+        offset += count;
+    }
+
+    // Weights are stored from the last bit backwards.
+    let mut weight_reader = BitReader {
+        bits: bits.reverse_bits(),
+        position: 0,
+        end: weight_bits,
+    };
+    let weights = decode_sequence(&mut weight_reader, mode.weight_levels, weight_count)
+        .into_iter()
+        .map(|value| unquantize_weight(value, mode.weight_levels))
+        .collect::<Vec<_>>();
+
+    let small_block = block_width * block_height < 31;
+    for (index, texel) in texels.iter_mut().enumerate() {
+        let (x, y) = (index % block_width, index / block_width);
+        let partition = if partitions > 1 {
+            select_partition(partition_index, x, y, partitions, small_block)
+        } else {
+            0
+        };
+        let plane_weights: [u32; 2] = std::array::from_fn(|plane| {
+            infill(&weights, &mode, plane, (x, y), (block_width, block_height))
+        });
+        let [e0, e1] = endpoints[partition];
+        *texel = std::array::from_fn(|channel| {
+            let plane = (mode.dual_plane && channel == dual_plane_channel) as usize;
+            let weight = plane_weights[plane];
+            let expand = |value: u32| {
+                if srgb {
+                    value << 8 | 0x80
+                } else {
+                    value << 8 | value
+                }
+            };
+            let value =
+                (expand(e0[channel]) * (64 - weight) + expand(e1[channel]) * weight + 32) / 64;
+            (value >> 8) as u8
+        });
+    }
+
+    Some(())
+}
+
+/// Block with a single color, stored as 16-bit values. The extent it covers is only an
+/// optimization hint.
+fn void_extent(bits: u128, texels: &mut [[u8; 4]]) -> Option<()> {
+    if (bits >> 9) & 1 == 1 {
+        return None;
+    }
+    let color = std::array::from_fn(|channel| (bits >> (64 + 16 * channel + 8)) as u8);
+    texels.fill(color);
+    Some(())
+}
+
+fn block_mode(mode: u32) -> Option<BlockMode> {
+    let bit = |i: u32| (mode >> i) & 1;
+    let field = |shift: u32, count: u32| (mode >> shift) & ((1 << count) - 1);
+
+    let (width, height, dual_plane, high_precision, range);
+    if field(0, 2) != 0 {
+        range = bit(4) | field(0, 2) << 1;
+        let (a, b) = (field(5, 2), field(7, 2));
+        (width, height) = match field(2, 2) {
+            0 => (b + 4, a + 2),
+            1 => (b + 8, a + 2),
+            2 => (a + 2, b + 8),
+            _ if bit(8) == 0 => (a + 2, bit(7) + 6),
+            _ => (bit(7) + 2, a + 2),
+        };
+        dual_plane = bit(10);
+        high_precision = bit(9);
+    } else {
+        range = bit(4) | field(2, 2) << 1;
+        let (a, b) = (field(5, 2), field(9, 2));
+        (width, height, dual_plane, high_precision) = match field(7, 2) {
+            0 => (12, a + 2, bit(10), bit(9)),
+            1 => (a + 2, 12, bit(10), bit(9)),
+            2 => (a + 6, b + 6, 0, 0),
+            _ => match a {
+                0 => (6, 10, bit(10), bit(9)),
+                1 => (10, 6, bit(10), bit(9)),
+                _ => return None,
+            },
+        };
+    }
+
+    let weight_levels = WEIGHT_LEVELS[high_precision as usize][range as usize];
+    (weight_levels > 0).then_some(BlockMode {
+        grid_width: width as usize,
+        grid_height: height as usize,
+        dual_plane: dual_plane == 1,
+        weight_levels,
+    })
+}
+
+fn range_bits(levels: u32) -> u32 {
+    RANGES
+        .iter()
+        .find(|&&(range_levels, _)| range_levels == levels)
+        .map_or(0, |&(_, bits)| bits)
+}
+
+/// Size in bits of `count` values with `levels` levels.
+fn sequence_bits(levels: u32, count: usize) -> u32 {
+    let bits = range_bits(levels);
+    let count = count as u32;
+    match levels >> bits {
+        3 => (8 * count).div_ceil(5) + count * bits,
+        5 => (7 * count).div_ceil(3) + count * bits,
+        _ => count * bits,
+    }
+}
+
+/// Reads an integer sequence. Trits are packed by five and quints by three, with their
+/// bits interleaved between the low bits of the values.
+fn decode_sequence(bits: &mut BitReader, levels: u32, count: usize) -> Vec<u32> {
+    let low_bits = range_bits(levels);
+    let digit_bits: &[u32] = match levels >> low_bits {
+        3 => &[2, 2, 1, 2, 1],
+        5 => &[3, 2, 2],
+        _ => &[0],
+    };
+    let mut values = Vec::with_capacity(count);
+    while values.len() < count {
+        let mut low = [0; 5];
+        let mut packed = 0;
+        let mut shift = 0;
+        for (low, &count) in low.iter_mut().zip(digit_bits) {
+            *low = bits.read(low_bits);
+            packed |= bits.read(count) << shift;
+            shift += count;
+        }
+        let digits = match levels >> low_bits {
+            3 => trits(packed),
+            5 => {
+                let [q0, q1, q2] = quints(packed);
+                [q0, q1, q2, 0, 0]
+            }
+            _ => [0; 5],
+        };
+        let group = digit_bits.len().min(count - values.len());
+        values.extend((0..group).map(|i| digits[i] << low_bits | low[i]));
+    }
+    values
+}
+
+fn trits(packed: u32) -> [u32; 5] {
+    let bit = |value: u32, i: u32| (value >> i) & 1;
+    let (c, t3, t4);
+    if (packed >> 2) & 7 == 7 {
+        c = (packed >> 5 & 7) << 2 | (packed & 3);
+        (t3, t4) = (2, 2);
+    } else {
+        c = packed & 0x1F;
+        (t3, t4) = if (packed >> 5) & 3 == 3 {
+            (bit(packed, 7), 2)
+        } else {
+            ((packed >> 5) & 3, bit(packed, 7))
+        };
+    }
+    let (t0, t1, t2) = if c & 3 == 3 {
+        (bit(c, 3) << 1 | (bit(c, 2) & !bit(c, 3) & 1), bit(c, 4), 2)
+    } else if (c >> 2) & 3 == 3 {
+        (c & 3, 2, 2)
+    } else {
+        (
+            bit(c, 1) << 1 | (bit(c, 0) & !bit(c, 1) & 1),
+            (c >> 2) & 3,
+            bit(c, 4),
+        )
+    };
+    [t0, t1, t2, t3, t4]
+}
+
+fn quints(packed: u32) -> [u32; 3] {
+    let bit = |value: u32, i: u32| (value >> i) & 1;
+    if (packed >> 1) & 3 == 3 && (packed >> 5) & 3 == 0 {
+        let q0 = bit(packed, 0);
+        let q2 = q0 << 2 | (bit(packed, 4) & !q0 & 1) << 1 | (bit(packed, 3) & !q0 & 1);
+        return [4, 4, q2];
+    }
+    let (q2, c) = if (packed >> 1) & 3 == 3 {
+        (
+            4,
+            (packed >> 3 & 3) << 3 | (!packed >> 5 & 3) << 1 | (packed & 1),
+        )
+    } else {
+        ((packed >> 5) & 3, packed & 0x1F)
+    };
+    let (q0, q1) = if c & 7 == 5 {
+        ((c >> 3) & 3, 4)
+    } else {
+        (c & 7, (c >> 3) & 3)
+    };
+    [q0, q1, q2]
+}
+
+/// Repeats the `bits` bits of `value` to fill `target` bits.
+fn replicate(value: u32, bits: u32, target: u32) -> u32 {
+    let mut result = 0;
+    let mut shift = target as i32;
+    while shift > 0 {
+        shift -= bits as i32;
+        result |= if shift >= 0 {
+            value << shift
+        } else {
+            value >> -shift
+        };
+    }
+    result
+}
+
+/// Scales an endpoint value to 8 bits.
+fn unquantize_color(value: u32, levels: u32) -> u32 {
+    let bits = range_bits(levels);
+    let digits = levels >> bits;
+    if digits == 1 {
+        return replicate(value, bits, 8);
+    }
+
+    let (low, digit) = (value & ((1 << bits) - 1), value >> bits);
+    let a = if low & 1 == 1 { 0x1FF } else { 0 };
+    let high = low >> 1;
+    let (b, c) = match (digits, bits) {
+        (3, 1) => (0, 204),
+        (5, 1) => (0, 113),
+        (3, 2) => (high * 0x116, 93),
+        (5, 2) => (high * 0x10C, 54),
+        (3, 3) => (high << 7 | high << 2 | high, 44),
+        (5, 3) => (high << 7 | high << 1 | high >> 1, 26),
+        (3, 4) => (high << 6 | high, 22),
+        (5, 4) => (high << 6 | high >> 1, 13),
+        (3, 5) => (high << 5 | high >> 2, 11),
+        (5, 5) => (high << 5 | high >> 3, 6),
+        _ => (high << 4 | high >> 4, 5),
+    };
+    let t = (digit * c + b) ^ a;
+    (a & 0x80) | (t >> 2)
+}
+
+/// Scales a weight to 0..=64.
+fn unquantize_weight(value: u32, levels: u32) -> u32 {
+    let bits = range_bits(levels);
+    let digits = levels >> bits;
+    let weight = match (digits, bits) {
+        (1, _) => replicate(value, bits, 6),
+        (3, 0) => return value * 32,
+        (5, 0) => return value * 16,
+        _ => {
+            let (low, digit) = (value & ((1 << bits) - 1), value >> bits);
+            let a = if low & 1 == 1 { 0x7F } else { 0 };
+            let high = low >> 1;
+            let (b, c) = match (digits, bits) {
+                (3, 1) => (0, 50),
+                (5, 1) => (0, 28),
+                (3, 2) => (high * 0x45, 23),
+                (5, 2) => (high * 0x42, 13),
+                _ => (high << 5 | high, 11),
+            };
+            let t = (digit * c + b) ^ a;
+            (a & 0x20) | (t >> 2)
+        }
+    };
+    if weight > 32 {
+        weight + 1
+    } else {
+        weight
+    }
+}
+
+/// Endpoints of an LDR color endpoint mode, `None` for HDR modes.
+fn color_endpoints(mode: u32, values: &[u32]) -> Option<[[u32; 4]; 2]> {
+    let v = |i: usize| values[i] as i32;
+    let endpoints = match mode {
+        0 => [[v(0), v(0), v(0), 255], [v(1), v(1), v(1), 255]],
+        1 => {
+            let l0 = (v(0) >> 2) | (v(1) & 0xC0);
+            let l1 = (l0 + (v(1) & 0x3F)).min(255);
+            [[l0, l0, l0, 255], [l1, l1, l1, 255]]
+        }
+        4 => [[v(0), v(0), v(0), v(2)], [v(1), v(1), v(1), v(3)]],
+        5 => {
+            let (l, dl) = transfer(v(0), v(1));
+            let (a, da) = transfer(v(2), v(3));
+            [[l, l, l, a], [l + dl, l + dl, l + dl, a + da]]
+        }
+        6 => [
+            [
+                (v(0) * v(3)) >> 8,
+                (v(1) * v(3)) >> 8,
+                (v(2) * v(3)) >> 8,
+                255,
+            ],
+            [v(0), v(1), v(2), 255],
+        ],
+        8 | 12 => {
+            let alpha = if mode == 12 { [v(6), v(7)] } else { [255, 255] };
+            if v(1) + v(3) + v(5) >= v(0) + v(2) + v(4) {
+                [[v(0), v(2), v(4), alpha[0]], [v(1), v(3), v(5), alpha[1]]]
+            } else {
+                [
+                    blue_contract([v(1), v(3), v(5), alpha[1]]),
+                    blue_contract([v(0), v(2), v(4), alpha[0]]),
+                ]
+            }
+        }
+        9 | 13 => {
+            let (r, dr) = transfer(v(0), v(1));
+            let (g, dg) = transfer(v(2), v(3));
+            let (b, db) = transfer(v(4), v(5));
+            let (a, da) = if mode == 13 {
+                transfer(v(6), v(7))
+            } else {
+                (255, 0)
+            };
+            if dr + dg + db >= 0 {
+                [[r, g, b, a], [r + dr, g + dg, b + db, a + da]]
+            } else {
+                [
+                    blue_contract([r + dr, g + dg, b + db, a + da]),
+                    blue_contract([r, g, b, a]),
+                ]
+            }
+        }
+        10 => [
+            [
+                (v(0) * v(3)) >> 8,
+                (v(1) * v(3)) >> 8,
+                (v(2) * v(3)) >> 8,
+                v(4),
+            ],
+            [v(0), v(1), v(2), v(5)],
+        ],
+        _ => return None,
+    };
+
+    Some(endpoints.map(|endpoint| endpoint.map(|value| value.clamp(0, 255) as u32)))
+}
+
+/// Moves the top bit of `offset` to `base` and sign extends the remaining 6 bits.
+fn transfer(base: i32, offset: i32) -> (i32, i32) {
+    let base = (base >> 1) | (offset & 0x80);
+    let offset = (offset >> 1) & 0x3F;
+    (
+        base,
+        if offset & 0x20 != 0 {
+            offset - 0x40
+        } else {
+            offset
+        },
+    )
+}
+
+fn blue_contract([r, g, b, a]: [i32; 4]) -> [i32; 4] {
+    [(r + b) >> 1, (g + b) >> 1, b, a]
+}
+
+/// Weight of `plane` at a texel, bilinearly interpolated from the weight grid.
+fn infill(
+    weights: &[u32],
+    mode: &BlockMode,
+    plane: usize,
+    (x, y): (usize, usize),
+    (block_width, block_height): (usize, usize),
+) -> u32 {
+    let planes = 1 + mode.dual_plane as usize;
+    if plane >= planes {
+        return 0;
+    }
+    let (grid_width, grid_height) = (mode.grid_width, mode.grid_height);
+    let scale_x = (1024 + block_width / 2) / (block_width - 1);
+    let scale_y = (1024 + block_height / 2) / (block_height - 1);
+    let grid_x = (scale_x * x * (grid_width - 1) + 32) >> 6;
+    let grid_y = (scale_y * y * (grid_height - 1) + 32) >> 6;
+    let (x, fraction_x) = (grid_x >> 4, (grid_x & 15) as u32);
+    let (y, fraction_y) = (grid_y >> 4, (grid_y & 15) as u32);
+
+    let w11 = (fraction_x * fraction_y + 8) >> 4;
+    let w10 = fraction_y - w11;
+    let w01 = fraction_x - w11;
+    let w00 = 16 + w11 - fraction_x - fraction_y;
+    // Neighbours past the edge of the grid always have a zero factor.
+    let weight = |x: usize, y: usize| {
+        let index = (y * grid_width + x) * planes + plane;
+        if x < grid_width && y < grid_height {
+            weights[index]
+        } else {
+            0
+        }
+    };
+
+    (weight(x, y) * w00
+        + weight(x + 1, y) * w01
+        + weight(x, y + 1) * w10
+        + weight(x + 1, y + 1) * w11
+        + 8)
+        >> 4
+}
+
+/// Partition of a texel, from a hash of the partition index and the texel position.
+fn select_partition(index: u32, x: usize, y: usize, partitions: usize, small_block: bool) -> usize {
+    let (x, y) = if small_block {
+        (x as u32 * 2, y as u32 * 2)
+    } else {
+        (x as u32, y as u32)
+    };
+    let seed = index + (partitions as u32 - 1) * 1024;
+    let random = hash52(seed);
+
+    let seeds: [u32; 8] = std::array::from_fn(|i| {
+        let seed = (random >> (4 * i)) & 15;
+        seed * seed
+    });
+    let (shift1, shift2) = if seed & 1 == 1 {
+        (
+            if seed & 2 != 0 { 4 } else { 5 },
+            if partitions == 3 { 6 } else { 5 },
+        )
+    } else {
+        (
+            if partitions == 3 { 6 } else { 5 },
+            if seed & 2 != 0 { 4 } else { 5 },
+        )
+    };
+    let seeds: [u32; 8] =
+        std::array::from_fn(|i| seeds[i] >> if i % 2 == 0 { shift1 } else { shift2 });
+
+    // The z terms of 3D blocks drop out for 2D textures.
+    let a = (seeds[0] * x + seeds[1] * y + (random >> 14)) & 0x3F;
+    let b = (seeds[2] * x + seeds[3] * y + (random >> 10)) & 0x3F;
+    let c = if partitions >= 3 {
+        (seeds[4] * x + seeds[5] * y + (random >> 6)) & 0x3F
+    } else {
+        0
+    };
+    let d = if partitions >= 4 {
+        (seeds[6] * x + seeds[7] * y + (random >> 2)) & 0x3F
+    } else {
+        0
+    };
+
+    if a >= b && a >= c && a >= d {
+        0
+    } else if b >= c && b >= d {
+        1
+    } else if c >= d {
+        2
+    } else {
+        3
+    }
+}
+
+fn hash52(mut value: u32) -> u32 {
+    value ^= value >> 15;
+    value = value.wrapping_sub(value << 17);
+    value = value.wrapping_add(value << 7);
+    value = value.wrapping_add(value << 4);
+    value ^= value >> 5;
+    value = value.wrapping_add(value << 16);
+    value ^= value >> 7;
+    value ^= value >> 3;
+    value ^= value << 6;
+    value ^= value >> 17;
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Block mode of a 4x4 grid of 2-bit weights.
+    const GRID_4X4: u32 = 0x42;
+
+    /// Packs `(value, bits)` fields from bit 0 and `weights` of `weight_bits` bits each
+    /// from bit 127 backwards.
+    fn pack(fields: &[(u32, u32)], weights: &[u32], weight_bits: u32) -> [u8; 16] {
+        let mut block = 0u128;
+        let mut position = 0;
+        for &(value, bits) in fields {
+            block |= (value as u128) << position;
+            position += bits;
+        }
+        for (i, weight) in weights.iter().enumerate() {
+            for bit in 0..weight_bits {
+                block |= ((weight >> bit) as u128 & 1) << (127 - i as u32 * weight_bits - bit);
+            }
+        }
+        block.to_le_bytes()
+    }
+
+    fn decode_4x4(block: &[u8; 16]) -> [[u8; 4]; 16] {
+        let mut texels = [[0; 4]; 16];
+        decode_block(block, 4, 4, false, &mut texels);
+        texels
+    }
+
+    #[test]
+    fn trits_and_quints_cover_every_digit_combination() {
+        let mut seen = (0..256).map(trits).collect::<Vec<_>>();
+        assert!(seen.iter().flatten().all(|&trit| trit < 3));
+        seen.sort();
+        seen.dedup();
+        assert_eq!(seen.len(), 243);
+
+        let mut seen = (0..128).map(quints).collect::<Vec<_>>();
+        assert!(seen.iter().flatten().all(|&quint| quint < 5));
+        seen.sort();
+        seen.dedup();
+        assert_eq!(seen.len(), 125);
+    }
+
+    #[test]
+    fn unquantize() {
+        let color = |levels| (0..levels).map(move |value| unquantize_color(value, levels));
+        assert!(color(6).eq([0, 255, 51, 204, 102, 153]));
+        assert!(color(12).eq([0, 255, 69, 186, 23, 232, 92, 163, 46, 209, 116, 139]));
+        let weight = |levels| (0..levels).map(move |value| unquantize_weight(value, levels));
+        assert!(weight(6).eq([0, 64, 12, 52, 25, 39]));
+        assert!(weight(12).eq([0, 64, 17, 47, 5, 59, 23, 41, 11, 53, 28, 36]));
+
+        // Every range spans the full scale with distinct values.
+        let check = |values: &mut Vec<u32>, levels: u32, max: u32| {
+            values.sort();
+            values.dedup();
+            assert_eq!(values.len(), levels as usize, "{}", levels);
+            assert_eq!(
+                (values[0], values[values.len() - 1]),
+                (0, max),
+                "{}",
+                levels
+            );
+        };
+        for (levels, _) in RANGES.into_iter().filter(|&(levels, _)| levels >= 6) {
+            check(&mut color(levels).collect(), levels, 255);
+        }
+        for levels in WEIGHT_LEVELS
+            .into_iter()
+            .flatten()
+            .filter(|&levels| levels > 0)
+        {
+            check(&mut weight(levels).collect(), levels, 64);
+        }
+    }
+
+    #[test]
+    fn void_extent() {
+        let color = [0x1234u128, 0x8000, 0xFFFF, 0x0000];
+        let extent = (1u128 << 52) - 1;
+        let block = 0x1FC | 0b11 << 10 | extent << 12 | color[0] << 64;
+        let block = block | color[1] << 80 | color[2] << 96 | color[3] << 112;
+        assert_eq!(
+            decode_4x4(&block.to_le_bytes()),
+            [[0x12, 0x80, 0xFF, 0x00]; 16]
+        );
+
+        // HDR void extent blocks need the HDR profile.
+        let block = block | 1 << 9;
+        assert_eq!(decode_4x4(&block.to_le_bytes()), [ERROR_COLOR; 16]);
+    }
+
+    #[test]
+    fn rgb_direct() {
+        // Endpoint mode 8 with 8-bit values, texel `x` in every row taking weight `x`.
+        let values = [10, 200, 20, 150, 30, 100].map(|value| (value, 8));
+        let block = pack(
+            &[&[(GRID_4X4, 11), (0, 2), (8, 4)], values.as_slice()].concat(),
+            &std::array::from_fn::<_, 16, _>(|i| i as u32 % 4),
+            2,
+        );
+        let row = [
+            [10, 20, 30, 255],
+            [72, 62, 53, 255],
+            [138, 107, 77, 255],
+            [200, 150, 100, 255],
+        ];
+        assert_eq!(decode_4x4(&block), [row; 4].as_flattened());
+    }
+
+    #[test]
+    fn rgb_direct_blue_contract() {
+        // The second endpoint sums lower than the first, so they swap and average red and
+        // green with blue.
+        let values = [200, 10, 150, 20, 100, 30].map(|value| (value, 8));
+        let block = pack(
+            &[&[(GRID_4X4, 11), (0, 2), (8, 4)], values.as_slice()].concat(),
+            &std::array::from_fn::<_, 16, _>(|i| i as u32 % 4),
+            2,
+        );
+        let row = [
+            [20, 25, 30, 255],
+            [62, 58, 53, 255],
+            [107, 92, 77, 255],
+            [150, 125, 100, 255],
+        ];
+        assert_eq!(decode_4x4(&block), [row; 4].as_flattened());
+    }
+
+    #[test]
+    fn dual_plane() {
+        // 2x2 grid of 3-bit weights for each plane, the second plane driving alpha.
+        let values = [10, 200, 20, 150, 30, 100, 40, 250].map(|value| (value, 8));
+        let fields = [
+            &[(0x51F, 11), (0, 2), (12, 4)],
+            values.as_slice(),
+            &[(0, 21), (3, 2)],
+        ]
+        .concat();
+        let block = pack(&fields, &[0, 7, 0, 7, 0, 7, 0, 7], 3);
+        assert_eq!(decode_4x4(&block), [[10, 20, 30, 250]; 16]);
+    }
+
+    #[test]
+    fn weight_infill() {
+        // Luminance from 0 to 255 on a 6x6 block, with only the last of the 4x4 weights set.
+        let mut weights = [0; 16];
+        weights[15] = 3;
+        let block = pack(
+            &[(GRID_4X4, 11), (0, 2), (0, 4), (0, 8), (255, 8)],
+            &weights,
+            2,
+        );
+        let mut texels = [[0; 4]; 36];
+        decode_block(&block, 6, 6, false, &mut texels);
+
+        assert_eq!(texels[35], [255, 255, 255, 255]);
+        assert_eq!(texels[4 * 6 + 4], [32, 32, 32, 255]);
+        for (i, texel) in texels.iter().enumerate() {
+            if i % 6 < 4 || i / 6 < 4 {
+                assert_eq!(*texel, [0, 0, 0, 255], "{}", i);
+            }
+        }
+    }
+
+    #[test]
+    fn two_partitions() {
+        // Luminance 50 and 200 with zero weights, so texels show their partition.
+        let values = [50, 0, 200, 0].map(|value| (value, 8));
+        let fields = [
+            &[(GRID_4X4, 11), (1, 2), (5, 10), (0, 2), (0, 4)],
+            values.as_slice(),
+        ];
+        let texels = decode_4x4(&pack(&fields.concat(), &[0; 16], 2));
+
+        let partitions = (0..16)
+            .map(|i| select_partition(5, i % 4, i / 4, 2, true))
+            .collect::<Vec<_>>();
+        assert!(partitions.contains(&0) && partitions.contains(&1));
+        for (texel, partition) in texels.iter().zip(partitions) {
+            let luminance = [50, 200][partition];
+            assert_eq!(*texel, [luminance, luminance, luminance, 255]);
+        }
+    }
+
+    #[test]
+    fn invalid_blocks() {
+        // Reserved block mode.
+        assert_eq!(decode_4x4(&[0; 16]), [ERROR_COLOR; 16]);
+        // HDR endpoint mode 15.
+        let block = pack(&[(GRID_4X4, 11), (0, 2), (15, 4)], &[0; 16], 2);
+        assert_eq!(decode_4x4(&block), [ERROR_COLOR; 16]);
+    }
+}
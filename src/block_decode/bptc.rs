@@ -0,0 +1,661 @@
+//! BC6H and BC7, which share their partitions and interpolation weights.
+
+use super::{BitReader, Block};
+
+/// Subset of each texel for the two subset partitions, one bit per texel.
+#[rustfmt::skip]
+const PARTITIONS_2: [u16; 64] = [
+    0xCCCC, 0x8888, 0xEEEE, 0xECC8, 0xC880, 0xFEEC, 0xFEC8, 0xEC80,
+    0xC800, 0xFFEC, 0xFE80, 0xE800, 0xFFE8, 0xFF00, 0xFFF0, 0xF000,
+    0xF710, 0x008E, 0x7100, 0x08CE, 0x008C, 0x7310, 0x3100, 0x8CCE,
+    0x088C, 0x3110, 0x6666, 0x366C, 0x17E8, 0x0FF0, 0x718E, 0x399C,
+    0xAAAA, 0xF0F0, 0x5A5A, 0x33CC, 0x3C3C, 0x55AA, 0x9696, 0xA55A,
+    0x73CE, 0x13C8, 0x324C, 0x3BDC, 0x6996, 0xC33C, 0x9966, 0x0660,
+    0x0272, 0x04E4, 0x4E40, 0x2720, 0xC936, 0x936C, 0x39C6, 0x639C,
+    0x9336, 0x9CC6, 0x817E, 0xE718, 0xCCF0, 0x0FCC, 0x7744, 0xEE22,
+];
+
+/// Subset of each texel for the three subset partitions, two bits per texel.
+const PARTITIONS_3: [u32; 64] = [
+    0xAA685050, 0x6A5A5040, 0x5A5A4200, 0x5450A0A8, 0xA5A50000, 0xA0A05050, 0x5555A0A0, 0x5A5A5050,
+    0xAA550000, 0xAA555500, 0xAAAA5500, 0x90909090, 0x94949494, 0xA4A4A4A4, 0xA9A59450, 0x2A0A4250,
+    0xA5945040, 0x0A425054, 0xA5A5A500, 0x55A0A0A0, 0xA8A85454, 0x6A6A4040, 0xA4A45000, 0x1A1A0500,
+    0x0050A4A4, 0xAAA59090, 0x14696914, 0x69691400, 0xA08585A0, 0xAA821414, 0x50A4A450, 0x6A5A0200,
+    0xA9A58000, 0x5090A0A8, 0xA8A09050, 0x24242424, 0x00AA5500, 0x24924924, 0x24499224, 0x50A50A50,
+    0x500AA550, 0xAAAA4444, 0x66660000, 0xA5A0A5A0, 0x50A050A0, 0x69286928, 0x44AAAA44, 0x66666600,
+    0xAA444444, 0x54A854A8, 0x95809580, 0x96969600, 0xA85454A8, 0x80959580, 0xAA141414, 0x96960000,
+    0xAAAA1414, 0xA05050A0, 0xA0A5A5A0, 0x96000000, 0x40804080, 0xA9A8A9A8, 0xAAAAAA44, 0x2A4A5254,
+];
+
+/// Texel whose index has one bit less, for the second subset of two subset partitions.
+#[rustfmt::skip]
+const ANCHORS_2: [u8; 64] = [
+    15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15,
+    15, 2, 8, 2, 2, 8, 8, 15, 2, 8, 2, 2, 8, 8, 2, 2,
+    15, 15, 6, 8, 2, 8, 15, 15, 2, 8, 2, 2, 2, 15, 15, 6,
+    6, 2, 6, 8, 15, 15, 2, 2, 15, 15, 15, 15, 15, 2, 2, 15,
+];
+
+/// Anchors of the second and third subsets of three subset partitions.
+#[rustfmt::skip]
+const ANCHORS_3: [[u8; 2]; 64] = [
+    [3, 15], [3, 8], [15, 8], [15, 3], [8, 15], [3, 15], [15, 3], [15, 8],
+    [8, 15], [8, 15], [6, 15], [6, 15], [6, 15], [5, 15], [3, 15], [3, 8],
+    [3, 15], [3, 8], [8, 15], [15, 3], [3, 15], [3, 8], [6, 15], [10, 8],
+    [5, 3], [8, 15], [8, 6], [6, 10], [8, 15], [5, 15], [15, 10], [15, 8],
+    [8, 15], [15, 3], [3, 15], [5, 10], [6, 10], [10, 8], [8, 9], [15, 10],
+    [15, 6], [3, 15], [15, 8], [5, 15], [15, 3], [15, 6], [15, 6], [15, 8],
+    [3, 15], [15, 3], [5, 15], [5, 15], [5, 15], [8, 15], [5, 15], [10, 15],
+    [5, 15], [10, 15], [8, 15], [13, 15], [15, 3], [12, 15], [3, 15], [3, 8],
+];
+
+const WEIGHTS_2: [i32; 4] = [0, 21, 43, 64];
+const WEIGHTS_3: [i32; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+const WEIGHTS_4: [i32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+fn subset(subsets: usize, partition: usize, texel: usize) -> usize {
+    match subsets {
+        1 => 0,
+        2 => (PARTITIONS_2[partition] >> texel) as usize & 1,
+        _ => (PARTITIONS_3[partition] >> (2 * texel)) as usize & 3,
+    }
+}
+
+fn is_anchor(subsets: usize, partition: usize, texel: usize) -> bool {
+    texel == 0
+        || match subsets {
+            1 => false,
+            2 => ANCHORS_2[partition] as usize == texel,
+            _ => ANCHORS_3[partition].contains(&(texel as u8)),
+        }
+}
+
+/// Indices of the 16 texels, anchors having one bit less.
+fn read_indices(
+    bits: &mut BitReader,
+    index_bits: u32,
+    anchor: impl Fn(usize) -> bool,
+) -> [usize; 16] {
+    std::array::from_fn(|texel| bits.read(index_bits - anchor(texel) as u32) as usize)
+}
+
+fn weight(index_bits: u32, index: usize) -> i32 {
+    match index_bits {
+        2 => WEIGHTS_2[index],
+        3 => WEIGHTS_3[index],
+        _ => WEIGHTS_4[index],
+    }
+}
+
+fn interpolate(e0: i32, e1: i32, weight: i32) -> i32 {
+    ((64 - weight) * e0 + weight * e1 + 32) >> 6
+}
+
+struct Bc7Mode {
+    subsets: usize,
+    partition_bits: u32,
+    rotation_bits: u32,
+    index_selection_bits: u32,
+    color_bits: u32,
+    alpha_bits: u32,
+    pbits: PBits,
+    index_bits: u32,
+    /// Bits of the separate alpha or color indices of modes 4 and 5, 0 for the others.
+    index_bits_2: u32,
+}
+
+#[derive(PartialEq)]
+enum PBits {
+    None,
+    /// One least significant bit per endpoint.
+    Endpoint,
+    /// One least significant bit shared by both endpoints of a subset.
+    Shared,
+}
+
+#[rustfmt::skip]
+const BC7_MODES: [Bc7Mode; 8] = [
+    Bc7Mode { subsets: 3, partition_bits: 4, rotation_bits: 0, index_selection_bits: 0, color_bits: 4, alpha_bits: 0, pbits: PBits::Endpoint, index_bits: 3, index_bits_2: 0 },
+    Bc7Mode { subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 6, alpha_bits: 0, pbits: PBits::Shared, index_bits: 3, index_bits_2: 0 },
+    Bc7Mode { subsets: 3, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 5, alpha_bits: 0, pbits: PBits::None, index_bits: 2, index_bits_2: 0 },
+    Bc7Mode { subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 7, alpha_bits: 0, pbits: PBits::Endpoint, index_bits: 2, index_bits_2: 0 },
+    Bc7Mode { subsets: 1, partition_bits: 0, rotation_bits: 2, index_selection_bits: 1, color_bits: 5, alpha_bits: 6, pbits: PBits::None, index_bits: 2, index_bits_2: 3 },
+    Bc7Mode { subsets: 1, partition_bits: 0, rotation_bits: 2, index_selection_bits: 0, color_bits: 7, alpha_bits: 8, pbits: PBits::None, index_bits: 2, index_bits_2: 2 },
+    Bc7Mode { subsets: 1, partition_bits: 0, rotation_bits: 0, index_selection_bits: 0, color_bits: 7, alpha_bits: 7, pbits: PBits::Endpoint, index_bits: 4, index_bits_2: 0 },
+    Bc7Mode { subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 5, alpha_bits: 5, pbits: PBits::Endpoint, index_bits: 2, index_bits_2: 0 },
+];
+
+pub(super) fn bc7(block: &[u8]) -> Block {
+    // The mode is the number of zeros before the first set bit, blocks without one are
+    // reserved and decode to transparent black.
+    let mode_index = block[0].trailing_zeros();
+    let Some(mode) = BC7_MODES.get(mode_index as usize) else {
+        return [[0; 4]; 16];
+    };
+    let mut bits = BitReader::new(block);
+    bits.read(mode_index + 1);
+    let partition = bits.read(mode.partition_bits) as usize;
+    let rotation = bits.read(mode.rotation_bits);
+    let index_selection = bits.read(mode.index_selection_bits);
+
+    // Endpoints are stored channel by channel, then the least significant bits.
+    let endpoint_count = mode.subsets * 2;
+    let mut endpoints = [[0i32; 4]; 6];
+    for channel in 0..4 {
+        let channel_bits = if channel < 3 {
+            mode.color_bits
+        } else {
+            mode.alpha_bits
+        };
+        for endpoint in &mut endpoints[..endpoint_count] {
+            endpoint[channel] = bits.read(channel_bits) as i32;
+        }
+    }
+    let pbits: [i32; 6] = match mode.pbits {
+        PBits::None => [0; 6],
+        PBits::Endpoint => std::array::from_fn(|i| {
+            if i < endpoint_count {
+                bits.read(1) as i32
+            } else {
+                0
+            }
+        }),
+        PBits::Shared => {
+            let shared: [i32; 3] = std::array::from_fn(|i| {
+                if i < mode.subsets {
+                    bits.read(1) as i32
+                } else {
+                    0
+                }
+            });
+            std::array::from_fn(|i| shared[i / 2])
+        }
+    };
+    let pbit_count = (mode.pbits != PBits::None) as u32;
+    for (endpoint, pbit) in endpoints.iter_mut().zip(pbits) {
+        for (channel, value) in endpoint.iter_mut().enumerate() {
+            let channel_bits = if channel < 3 {
+                mode.color_bits
+            } else {
+                mode.alpha_bits
+            };
+            *value = if channel_bits == 0 {
+                255
+            } else {
+                expand((*value << pbit_count) | pbit, channel_bits + pbit_count)
+            };
+        }
+    }
+
+    let indices = read_indices(&mut bits, mode.index_bits, |texel| {
+        is_anchor(mode.subsets, partition, texel)
+    });
+    let indices_2 = if mode.index_bits_2 > 0 {
+        read_indices(&mut bits, mode.index_bits_2, |texel| texel == 0)
+    } else {
+        indices
+    };
+    // Mode 4 can swap which index set colors and alpha use.
+    let (color_bits, alpha_bits) = if mode.index_bits_2 == 0 {
+        (mode.index_bits, mode.index_bits)
+    } else if index_selection == 0 {
+        (mode.index_bits, mode.index_bits_2)
+    } else {
+        (mode.index_bits_2, mode.index_bits)
+    };
+    let (color_indices, alpha_indices) = if index_selection == 0 {
+        (indices, indices_2)
+    } else {
+        (indices_2, indices)
+    };
+
+    std::array::from_fn(|texel| {
+        let subset = subset(mode.subsets, partition, texel);
+        let [e0, e1] = [endpoints[subset * 2], endpoints[subset * 2 + 1]];
+        let color_weight = weight(color_bits, color_indices[texel]);
+        let alpha_weight = weight(alpha_bits, alpha_indices[texel]);
+        let mut texel: [u8; 4] = std::array::from_fn(|channel| {
+            let weight = if channel < 3 {
+                color_weight
+            } else {
+                alpha_weight
+            };
+            interpolate(e0[channel], e1[channel], weight) as u8
+        });
+        if rotation > 0 {
+            texel.swap(rotation as usize - 1, 3);
+        }
+        texel
+    })
+}
+
+/// Expands `value` of `bits` bits to 8 bits.
+fn expand(value: i32, bits: u32) -> i32 {
+    let value = value << (8 - bits);
+    value | (value >> bits)
+}
+
+const W: usize = 0;
+const X: usize = 1;
+const Y: usize = 2;
+const Z: usize = 3;
+const R: usize = 0;
+const G: usize = 1;
+const B: usize = 2;
+
+/// `count` bits of `channel` of endpoint `W`, `X`, `Y` or `Z` starting at bit `shift`.
+/// The first region interpolates between `W` and `X`, the second between `Y` and `Z`.
+type Field = (usize, usize, u32, u32);
+
+struct Bc6hMode {
+    /// Mode bits, 2 bits for the first two modes and 5 for the others.
+    value: u32,
+    /// Whether the endpoints after `W` are deltas from it.
+    transformed: bool,
+    endpoint_bits: u32,
+    delta_bits: [u32; 3],
+    /// Endpoint bits in the order they are stored after the mode.
+    fields: &'static [Field],
+}
+
+#[rustfmt::skip]
+const BC6H_MODES: [Bc6hMode; 14] = [
+    Bc6hMode { value: 0b00, transformed: true, endpoint_bits: 10, delta_bits: [5, 5, 5], fields: &[
+        (Y, G, 4, 1), (Y, B, 4, 1), (Z, B, 4, 1), (W, R, 0, 10), (W, G, 0, 10), (W, B, 0, 10),
+        (X, R, 0, 5), (Z, G, 4, 1), (Y, G, 0, 4), (X, G, 0, 5), (Z, B, 0, 1), (Z, G, 0, 4),
+        (X, B, 0, 5), (Z, B, 1, 1), (Y, B, 0, 4), (Y, R, 0, 5), (Z, B, 2, 1), (Z, R, 0, 5),
+        (Z, B, 3, 1),
+    ] },
+    Bc6hMode { value: 0b01, transformed: true, endpoint_bits: 7, delta_bits: [6, 6, 6], fields: &[
+        (Y, G, 5, 1), (Z, G, 4, 1), (Z, G, 5, 1), (W, R, 0, 7), (Z, B, 0, 1), (Z, B, 1, 1),
+        (Y, B, 4, 1), (W, G, 0, 7), (Y, B, 5, 1), (Z, B, 2, 1), (Y, G, 4, 1), (W, B, 0, 7),
+        (Z, B, 3, 1), (Z, B, 5, 1), (Z, B, 4, 1), (X, R, 0, 6), (Y, G, 0, 4), (X, G, 0, 6),
+        (Z, G, 0, 4), (X, B, 0, 6), (Y, B, 0, 4), (Y, R, 0, 6), (Z, R, 0, 6),
+    ] },
+    Bc6hMode { value: 0b00010, transformed: true, endpoint_bits: 11, delta_bits: [5, 4, 4], fields: &[
+        (W, R, 0, 10), (W, G, 0, 10), (W, B, 0, 10), (X, R, 0, 5), (W, R, 10, 1), (Y, G, 0, 4),
+        (X, G, 0, 4), (W, G, 10, 1), (Z, B, 0, 1), (Z, G, 0, 4), (X, B, 0, 4), (W, B, 10, 1),
+        (Z, B, 1, 1), (Y, B, 0, 4), (Y, R, 0, 5), (Z, B, 2, 1), (Z, R, 0, 5), (Z, B, 3, 1),
+    ] },
+    Bc6hMode { value: 0b00110, transformed: true, endpoint_bits: 11, delta_bits: [4, 5, 4], fields: &[
+        (W, R, 0, 10), (W, G, 0, 10), (W, B, 0, 10), (X, R, 0, 4), (W, R, 10, 1), (Z, G, 4, 1),
+        (Y, G, 0, 4), (X, G, 0, 5), (W, G, 10, 1), (Z, G, 0, 4), (X, B, 0, 4), (W, B, 10, 1),
+        (Z, B, 1, 1), (Y, B, 0, 4), (Y, R, 0, 4), (Z, B, 0, 1), (Z, B, 2, 1), (Z, R, 0, 4),
+        (Y, G, 4, 1), (Z, B, 3, 1),
+    ] },
+    Bc6hMode { value: 0b01010, transformed: true, endpoint_bits: 11, delta_bits: [4, 4, 5], fields: &[
+        (W, R, 0, 10), (W, G, 0, 10), (W, B, 0, 10), (X, R, 0, 4), (W, R, 10, 1), (Y, B, 4, 1),
+        (Y, G, 0, 4), (X, G, 0, 4), (W, G, 10, 1), (Z, B, 0, 1), (Z, G, 0, 4), (X, B, 0, 5),
+        (W, B, 10, 1), (Y, B, 0, 4), (Y, R, 0, 4), (Z, B, 1, 1), (Z, B, 2, 1), (Z, R, 0, 4),
+        (Z, B, 4, 1), (Z, B, 3, 1),
+    ] },
+    Bc6hMode { value: 0b01110, transformed: true, endpoint_bits: 9, delta_bits: [5, 5, 5], fields: &[
+        (W, R, 0, 9), (Y, B, 4, 1), (W, G, 0, 9), (Y, G, 4, 1), (W, B, 0, 9), (Z, B, 4, 1),
+        (X, R, 0, 5), (Z, G, 4, 1), (Y, G, 0, 4), (X, G, 0, 5), (Z, B, 0, 1), (Z, G, 0, 4),
+        (X, B, 0, 5), (Z, B, 1, 1), (Y, B, 0, 4), (Y, R, 0, 5), (Z, B, 2, 1), (Z, R, 0, 5),
+        (Z, B, 3, 1),
+    ] },
+    Bc6hMode { value: 0b10010, transformed: true, endpoint_bits: 8, delta_bits: [6, 5, 5], fields: &[
+        (W, R, 0, 8), (Z, G, 4, 1), (Y, B, 4, 1), (W, G, 0, 8), (Z, B, 2, 1), (Y, G, 4, 1),
+        (W, B, 0, 8), (Z, B, 3, 1), (Z, B, 4, 1), (X, R, 0, 6), (Y, G, 0, 4), (X, G, 0, 5),
+        (Z, B, 0, 1), (Z, G, 0, 4), (X, B, 0, 5), (Z, B, 1, 1), (Y, B, 0, 4), (Y, R, 0, 6),
+        (Z, R, 0, 6),
+    ] },
+    Bc6hMode { value: 0b10110, transformed: true, endpoint_bits: 8, delta_bits: [5, 6, 5], fields: &[
+        (W, R, 0, 8), (Z, B, 0, 1), (Y, B, 4, 1), (W, G, 0, 8), (Y, G, 5, 1), (Y, G, 4, 1),
+        (W, B, 0, 8), (Z, G, 5, 1), (Z, B, 4, 1), (X, R, 0, 5), (Z, G, 4, 1), (Y, G, 0, 4),
+        (X, G, 0, 6), (Z, G, 0, 4), (X, B, 0, 5), (Z, B, 1, 1), (Y, B, 0, 4), (Y, R, 0, 5),
+        (Z, B, 2, 1), (Z, R, 0, 5), (Z, B, 3, 1),
+    ] },
+    Bc6hMode { value: 0b11010, transformed: true, endpoint_bits: 8, delta_bits: [5, 5, 6], fields: &[
+        (W, R, 0, 8), (Z, B, 1, 1), (Y, B, 4, 1), (W, G, 0, 8), (Y, B, 5, 1), (Y, G, 4, 1),
+        (W, B, 0, 8), (Z, B, 5, 1), (Z, B, 4, 1), (X, R, 0, 5), (Z, G, 4, 1), (Y, G, 0, 4),
+        (X, G, 0, 5), (Z, B, 0, 1), (Z, G, 0, 4), (X, B, 0, 6), (Y, B, 0, 4), (Y, R, 0, 5),
+        (Z, B, 2, 1), (Z, R, 0, 5), (Z, B, 3, 1),
+    ] },
+    Bc6hMode { value: 0b11110, transformed: false, endpoint_bits: 6, delta_bits: [6, 6, 6], fields: &[
+        (W, R, 0, 6), (Z, G, 4, 1), (Z, B, 0, 1), (Z, B, 1, 1), (Y, B, 4, 1), (W, G, 0, 6),
+        (Y, G, 5, 1), (Y, B, 5, 1), (Z, B, 2, 1), (Y, G, 4, 1), (W, B, 0, 6), (Z, G, 5, 1),
+        (Z, B, 3, 1), (Z, B, 5, 1), (Z, B, 4, 1), (X, R, 0, 6), (Y, G, 0, 4), (X, G, 0, 6),
+        (Z, G, 0, 4), (X, B, 0, 6), (Y, B, 0, 4), (Y, R, 0, 6), (Z, R, 0, 6),
+    ] },
+    Bc6hMode { value: 0b00011, transformed: false, endpoint_bits: 10, delta_bits: [10, 10, 10], fields: &[
+        (W, R, 0, 10), (W, G, 0, 10), (W, B, 0, 10), (X, R, 0, 10), (X, G, 0, 10), (X, B, 0, 10),
+    ] },
+    Bc6hMode { value: 0b00111, transformed: true, endpoint_bits: 11, delta_bits: [9, 9, 9], fields: &[
+        (W, R, 0, 10), (W, G, 0, 10), (W, B, 0, 10), (X, R, 0, 9), (W, R, 10, 1), (X, G, 0, 9),
+        (W, G, 10, 1), (X, B, 0, 9), (W, B, 10, 1),
+    ] },
+    // The high bits of `W` are stored in reverse order in the last two modes.
+    Bc6hMode { value: 0b01011, transformed: true, endpoint_bits: 12, delta_bits: [8, 8, 8], fields: &[
+        (W, R, 0, 10), (W, G, 0, 10), (W, B, 0, 10), (X, R, 0, 8), (W, R, 11, 1), (W, R, 10, 1),
+        (X, G, 0, 8), (W, G, 11, 1), (W, G, 10, 1), (X, B, 0, 8), (W, B, 11, 1), (W, B, 10, 1),
+    ] },
+    Bc6hMode { value: 0b01111, transformed: true, endpoint_bits: 16, delta_bits: [4, 4, 4], fields: &[
+        (W, R, 0, 10), (W, G, 0, 10), (W, B, 0, 10),
+        (X, R, 0, 4), (W, R, 15, 1), (W, R, 14, 1), (W, R, 13, 1), (W, R, 12, 1), (W, R, 11, 1), (W, R, 10, 1),
+        (X, G, 0, 4), (W, G, 15, 1), (W, G, 14, 1), (W, G, 13, 1), (W, G, 12, 1), (W, G, 11, 1), (W, G, 10, 1),
+        (X, B, 0, 4), (W, B, 15, 1), (W, B, 14, 1), (W, B, 13, 1), (W, B, 12, 1), (W, B, 11, 1), (W, B, 10, 1),
+    ] },
+];
+
+const HALF_ONE: u16 = 0x3C00;
+
+/// BC6H block as half float RGBA, `signed` for `Bc6hRgbFloat`.
+pub(super) fn bc6h(block: &[u8], signed: bool) -> [[u16; 4]; 16] {
+    let mut bits = BitReader::new(block);
+    let mut value = bits.read(2);
+    if value > 1 {
+        value |= bits.read(3) << 2;
+    }
+    // Reserved modes decode to black.
+    let Some(mode) = BC6H_MODES.iter().find(|mode| mode.value == value) else {
+        return [[0, 0, 0, HALF_ONE]; 16];
+    };
+
+    let mut endpoints = [[0i32; 3]; 4];
+    for &(endpoint, channel, shift, count) in mode.fields {
+        endpoints[endpoint][channel] |= (bits.read(count) as i32) << shift;
+    }
+    let regions = if value & 3 == 3 { 1 } else { 2 };
+    let partition = if regions == 2 {
+        bits.read(5) as usize
+    } else {
+        0
+    };
+
+    let endpoint_bits = mode.endpoint_bits;
+    let endpoints = &mut endpoints[..regions * 2];
+    if signed {
+        for value in &mut endpoints[0] {
+            *value = sign_extend(*value, endpoint_bits);
+        }
+    }
+    if signed || mode.transformed {
+        for endpoint in &mut endpoints[1..] {
+            for (value, bits) in endpoint.iter_mut().zip(mode.delta_bits) {
+                *value = sign_extend(*value, bits);
+            }
+        }
+    }
+    if mode.transformed {
+        let base = endpoints[0];
+        for endpoint in &mut endpoints[1..] {
+            for (value, base) in endpoint.iter_mut().zip(base) {
+                *value = (*value + base) & ((1 << endpoint_bits) - 1);
+                if signed {
+                    *value = sign_extend(*value, endpoint_bits);
+                }
+            }
+        }
+    }
+    for value in endpoints.iter_mut().flatten() {
+        *value = unquantize(*value, endpoint_bits, signed);
+    }
+
+    let index_bits = if regions == 2 { 3 } else { 4 };
+    let indices = read_indices(&mut bits, index_bits, |texel| {
+        is_anchor(regions, partition, texel)
+    });
+
+    std::array::from_fn(|texel| {
+        let region = subset(regions, partition, texel);
+        let [e0, e1] = [endpoints[region * 2], endpoints[region * 2 + 1]];
+        let weight = weight(index_bits, indices[texel]);
+        let [r, g, b] = std::array::from_fn(|i| finish(interpolate(e0[i], e1[i], weight), signed));
+        [r, g, b, HALF_ONE]
+    })
+}
+
+fn sign_extend(value: i32, bits: u32) -> i32 {
+    let shift = 32 - bits;
+    (value << shift) >> shift
+}
+
+/// Scales an endpoint to 16 bits, or to 15 bits and a sign when `signed`.
+fn unquantize(value: i32, bits: u32, signed: bool) -> i32 {
+    if !signed {
+        if bits >= 15 {
+            value
+        } else if value == 0 {
+            0
+        } else if value == (1 << bits) - 1 {
+            0xFFFF
+        } else {
+            ((value << 16) + 0x8000) >> bits
+        }
+    } else if bits >= 16 {
+        value
+    } else {
+        let magnitude = value.abs();
+        let unquantized = if magnitude == 0 {
+            0
+        } else if magnitude >= (1 << (bits - 1)) - 1 {
+            0x7FFF
+        } else {
+            ((magnitude << 15) + 0x4000) >> (bits - 1)
+        };
+        unquantized * value.signum()
+    }
+}
+
+/// Scales an interpolated value to the bits of a half float.
+fn finish(value: i32, signed: bool) -> u16 {
+    if !signed {
+        ((value * 31) >> 6) as u16
+    } else if value < 0 {
+        (((-value * 31) >> 5) as u16) | 0x8000
+    } else {
+        ((value * 31) >> 5) as u16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Packs `(value, bits)` fields from the least significant bit, masking negative values.
+    fn pack(fields: &[(i32, u32)]) -> [u8; 16] {
+        let mut block = 0u128;
+        let mut position = 0;
+        for &(value, bits) in fields {
+            block |= ((value as u128) & ((1 << bits) - 1)) << position;
+            position += bits;
+        }
+        assert!(position <= 128);
+        block.to_le_bytes()
+    }
+
+    /// Index fields of the 16 texels, anchors having one bit less.
+    fn indices(values: [i32; 16], bits: u32, anchors: &[usize]) -> Vec<(i32, u32)> {
+        (0..16)
+            .map(|texel| (values[texel], bits - anchors.contains(&texel) as u32))
+            .collect()
+    }
+
+    #[test]
+    fn partition_tables() {
+        for partition in 0..64 {
+            let anchor = ANCHORS_2[partition] as usize;
+            assert_eq!(subset(2, partition, 0), 0);
+            assert_eq!(subset(2, partition, anchor), 1, "{}", partition);
+
+            let [anchor_1, anchor_2] = ANCHORS_3[partition].map(usize::from);
+            assert_eq!(subset(3, partition, 0), 0);
+            assert_eq!(subset(3, partition, anchor_1), 1, "{}", partition);
+            assert_eq!(subset(3, partition, anchor_2), 2, "{}", partition);
+            assert!((0..16).all(|texel| subset(3, partition, texel) < 3));
+        }
+    }
+
+    #[test]
+    fn bc7_mode_6() {
+        // One subset, 7-bit RGBA endpoints with a least significant bit each.
+        let mut fields = vec![(1 << 6, 7), (0, 7), (127, 7), (64, 7), (64, 7)];
+        fields.extend([(127, 7), (0, 7), (127, 7), (127, 7), (0, 1), (1, 1)]);
+        fields.extend(indices(std::array::from_fn(|i| i as i32), 4, &[0]));
+        let expected = [
+            [0, 128, 254, 254],
+            [16, 128, 238, 254],
+            [36, 128, 218, 254],
+            [52, 128, 203, 254],
+            [68, 128, 187, 254],
+            [84, 128, 171, 254],
+            [104, 128, 151, 254],
+            [120, 128, 135, 254],
+            [135, 129, 120, 255],
+            [151, 129, 104, 255],
+            [171, 129, 84, 255],
+            [187, 129, 68, 255],
+            [203, 129, 52, 255],
+            [219, 129, 37, 255],
+            [239, 129, 17, 255],
+            [255, 129, 1, 255],
+        ];
+        assert_eq!(bc7(&pack(&fields)), expected);
+    }
+
+    #[test]
+    fn bc7_mode_1() {
+        // Partition 17 puts texels 1, 2, 3 and 7 in the second subset, anchored at texel 2.
+        // Black to white, then red to blue, with shared bits 1 and 0.
+        let mut fields = vec![(0b10, 2), (17, 6)];
+        fields.extend([0, 63, 63, 0].map(|red| (red, 6)));
+        fields.extend([0, 63, 0, 0].map(|green| (green, 6)));
+        fields.extend([0, 63, 0, 63].map(|blue| (blue, 6)));
+        fields.extend([(1, 1), (0, 1)]);
+        fields.extend(indices(std::array::from_fn(|i| i as i32 % 8), 3, &[0, 2]));
+        let expected = [
+            [2, 2, 2, 255],
+            [217, 0, 36, 255],
+            [182, 0, 71, 255],
+            [146, 0, 107, 255],
+            [148, 148, 148, 255],
+            [184, 184, 184, 255],
+            [219, 219, 219, 255],
+            [0, 0, 253, 255],
+            [2, 2, 2, 255],
+            [38, 38, 38, 255],
+            [73, 73, 73, 255],
+            [109, 109, 109, 255],
+            [148, 148, 148, 255],
+            [184, 184, 184, 255],
+            [219, 219, 219, 255],
+            [255, 255, 255, 255],
+        ];
+        assert_eq!(bc7(&pack(&fields)), expected);
+    }
+
+    #[test]
+    fn bc7_mode_4_rotation_and_index_selection() {
+        // Green to white and transparent to opaque. The color takes the 3-bit indices,
+        // then red and alpha are swapped.
+        let mut fields = vec![(1 << 4, 5), (1, 2), (1, 1)];
+        fields.extend([(0, 5), (31, 5), (31, 5), (31, 5), (0, 5), (31, 5)]);
+        fields.extend([(0, 6), (63, 6)]);
+        fields.extend(indices(std::array::from_fn(|i| i as i32 % 4), 2, &[0]));
+        fields.extend(indices(std::array::from_fn(|i| i as i32 % 8), 3, &[0]));
+        let expected = [
+            [0, 255, 0, 0],
+            [84, 255, 36, 36],
+            [171, 255, 72, 72],
+            [255, 255, 108, 108],
+            [0, 255, 147, 147],
+            [84, 255, 183, 183],
+            [171, 255, 219, 219],
+            [255, 255, 255, 255],
+        ];
+        assert_eq!(bc7(&pack(&fields)), [expected; 2].as_flattened());
+    }
+
+    #[test]
+    fn bc7_reserved_mode() {
+        assert_eq!(bc7(&[0; 16]), [[0; 4]; 16]);
+    }
+
+    #[test]
+    fn bc6h_single_region() {
+        // Mode 11, (0, 512, 1023) to (1023, 512, 0).
+        let mut fields = vec![(0b00011, 5), (0, 10), (512, 10), (1023, 10)];
+        fields.extend([(1023, 10), (512, 10), (0, 10)]);
+        fields.extend(indices(std::array::from_fn(|i| i as i32), 4, &[0]));
+        let expected = [
+            [0x0000, 0x3E0F, 0x7BFF, HALF_ONE],
+            [0x07C0, 0x3E0F, 0x743F, HALF_ONE],
+            [0x1170, 0x3E0F, 0x6A8F, HALF_ONE],
+            [0x1930, 0x3E0F, 0x62CF, HALF_ONE],
+            [0x20F0, 0x3E0F, 0x5B0F, HALF_ONE],
+            [0x28B0, 0x3E0F, 0x534F, HALF_ONE],
+            [0x3260, 0x3E0F, 0x499F, HALF_ONE],
+            [0x3A20, 0x3E0F, 0x41DF, HALF_ONE],
+            [0x41DF, 0x3E0F, 0x3A20, HALF_ONE],
+            [0x499F, 0x3E0F, 0x3260, HALF_ONE],
+            [0x534F, 0x3E0F, 0x28B0, HALF_ONE],
+            [0x5B0F, 0x3E0F, 0x20F0, HALF_ONE],
+            [0x62CF, 0x3E0F, 0x1930, HALF_ONE],
+            [0x6A8F, 0x3E0F, 0x1170, HALF_ONE],
+            [0x743F, 0x3E0F, 0x07C0, HALF_ONE],
+            [0x7BFF, 0x3E0F, 0x0000, HALF_ONE],
+        ];
+        assert_eq!(bc6h(&pack(&fields), false), expected);
+    }
+
+    #[test]
+    fn bc6h_signed() {
+        // Mode 11, (-300, 0, 511) to (300, -511, 100), 511 and -511 saturating.
+        let mut fields = vec![(0b00011, 5), (-300, 10), (0, 10), (511, 10)];
+        fields.extend([(300, 10), (-511, 10), (100, 10)]);
+        fields.extend(indices(std::array::from_fn(|i| i as i32), 4, &[0]));
+        let texels = bc6h(&pack(&fields), true);
+        assert_eq!(texels[0], [0xC8C7, 0x0000, 0x7BFF, HALF_ONE]);
+        assert_eq!(texels[5], [0x9904, 0xA8B0, 0x5B4C, HALF_ONE]);
+        assert_eq!(texels[15], [0x48C7, 0xFBFF, 0x1857, HALF_ONE]);
+    }
+
+    #[test]
+    fn bc6h_two_regions() {
+        // Mode 0 with 10-bit (100, 200, 300) and 5-bit deltas from it, partition 13 putting
+        // the bottom two rows in the second region.
+        let (y, z) = ([-16, 15, 1], [7, -8, -1]);
+        let bit = |value: i32, shift: u32| ((value >> shift) & 1, 1);
+        let mut fields = vec![(0b00, 2), bit(y[G], 4), bit(y[B], 4), bit(z[B], 4)];
+        fields.extend([(100, 10), (200, 10), (300, 10)]);
+        fields.extend([(5, 5), bit(z[G], 4), (y[G], 4), (-3, 5), bit(z[B], 0)]);
+        fields.extend([(z[G], 4), (0, 5), bit(z[B], 1), (y[B], 4), (y[R], 5)]);
+        fields.extend([bit(z[B], 2), (z[R], 5), bit(z[B], 3), (13, 5)]);
+        let index = |i: i32| if i < 8 { i } else { 15 - i };
+        fields.extend(indices(
+            std::array::from_fn(|i| index(i as i32)),
+            3,
+            &[0, 15],
+        ));
+        let expected = [
+            [0x0C2B, 0x1847, 0x2463],
+            [0x0C41, 0x183A, 0x2463],
+            [0x0C57, 0x182D, 0x2463],
+            [0x0C6C, 0x1820, 0x2463],
+            [0x0C85, 0x1811, 0x2463],
+            [0x0C9A, 0x1804, 0x2463],
+            [0x0CB0, 0x17F7, 0x2463],
+            [0x0CC6, 0x17EA, 0x2463],
+            [0x0D04, 0x174F, 0x2444],
+            [0x0CA0, 0x17B3, 0x244D],
+            [0x0C3B, 0x1818, 0x2455],
+            [0x0BD7, 0x187C, 0x245E],
+            [0x0B68, 0x18EB, 0x2468],
+            [0x0B04, 0x194F, 0x2471],
+            [0x0A9F, 0x19B4, 0x2479],
+            [0x0A3B, 0x1A18, 0x2482],
+        ]
+        .map(|[r, g, b]| [r, g, b, HALF_ONE]);
+        assert_eq!(bc6h(&pack(&fields), false), expected);
+    }
+
+    #[test]
+    fn bc6h_reserved_mode() {
+        assert_eq!(
+            bc6h(&pack(&[(0b10011, 5)]), false),
+            [[0, 0, 0, HALF_ONE]; 16]
+        );
+    }
+}
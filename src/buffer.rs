@@ -0,0 +1,622 @@
+use std::marker::PhantomData;
+use std::num::NonZeroU64;
+
+use bytemuck::Pod;
+use wgpu::util::DeviceExt;
+
+const DYNAMIC_INITIAL_CAPACITY: usize = 64;
+const STAGING_CHUNK_SIZE: wgpu::BufferAddress = 64 * 1024;
+
+/// Uniform buffer holding one `T`, with a bind group that has it at binding 0.
+#[derive(Debug)]
+pub struct UniformBuffer<T: Pod> {
+    buffer: wgpu::Buffer,
+    visibility: wgpu::ShaderStages,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    _marker: PhantomData<T>,
+}
+
+/// Storage buffer holding `len` elements of `T`, with a bind group that has it at binding 0.
+#[derive(Debug)]
+pub struct StorageBuffer<T: Pod> {
+    buffer: wgpu::Buffer,
+    len: usize,
+    read_only: bool,
+    visibility: wgpu::ShaderStages,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    _marker: PhantomData<T>,
+}
+
+/// Uniform buffer for many `T` values per frame, each bound with its own dynamic offset.
+/// `clear` at the start of the frame, `push` a value per draw, `flush` before submitting
+/// and pass the offset returned by `push` to `set_bind_group`.
+/// The buffer grows on `flush` when needed, which recreates the bind group.
+#[derive(Debug)]
+pub struct DynamicUniformBuffer<T: Pod> {
+    buffer: wgpu::Buffer,
+    stride: wgpu::BufferAddress,
+    capacity: usize,
+    staging: Vec<u8>,
+    visibility: wgpu::ShaderStages,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    _marker: PhantomData<T>,
+}
+
+/// Buffer for data whose size changes between frames, such as geometry built on the CPU.
+/// `write` uploads through a staging belt owned by the buffer and grows the buffer to the
+/// next power of two when the data doesn't fit. It can be called once per submission,
+/// followed by `after_submit` once the encoder it recorded into is submitted.
+#[derive(Debug)]
+pub struct DynamicBuffer<T: Pod> {
+    buffer: wgpu::Buffer,
+    usage: wgpu::BufferUsages,
+    capacity: usize,
+    len: usize,
+    belt: wgpu::util::StagingBelt,
+    /// Written into an encoder that may not be submitted yet.
+    pending: bool,
+    _marker: PhantomData<T>,
+}
+
+/// Index buffer holding `u16` indices while the vertex count fits them, and `u32` once it
+/// passes 65 535.
+#[derive(Debug)]
+pub struct DynamicIndexBuffer {
+    buffer: DynamicBuffer<u8>,
+    format: wgpu::IndexFormat,
+    len: usize,
+    narrowed: Vec<u16>,
+}
+
+impl<T: Pod> UniformBuffer<T> {
+    pub fn new(device: &wgpu::Device, value: &T, visibility: wgpu::ShaderStages) -> Self {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(std::any::type_name::<T>()),
+            contents: bytemuck::bytes_of(value),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let bind_group_layout =
+            create_bind_group_layout(device, uniform_layout_entry::<T>(0, visibility, false));
+        let bind_group = create_bind_group(device, &bind_group_layout, &buffer, None);
+
+        Self {
+            buffer,
+            visibility,
+            bind_group_layout,
+            bind_group,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn write(&self, queue: &wgpu::Queue, value: &T) {
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(value));
+    }
+
+    /// Writes part of the value, e.g. a single field at `std::mem::offset_of!(T, field)`.
+    pub fn write_at<U: Pod>(&self, queue: &wgpu::Queue, offset: usize, value: &U) {
+        assert!(
+            offset + size_of::<U>() <= size_of::<T>(),
+            "Write of {} bytes at {} is out of bounds of {}",
+            size_of::<U>(),
+            offset,
+            std::any::type_name::<T>()
+        );
+        queue.write_buffer(
+            &self.buffer,
+            offset as wgpu::BufferAddress,
+            bytemuck::bytes_of(value),
+        );
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    /// Entry for building a larger bind group layout that includes this buffer.
+    pub fn layout_entry(&self, binding: u32) -> wgpu::BindGroupLayoutEntry {
+        uniform_layout_entry::<T>(binding, self.visibility, false)
+    }
+    pub fn binding_resource(&self) -> wgpu::BindingResource<'_> {
+        self.buffer.as_entire_binding()
+    }
+}
+
+impl<T: Pod> StorageBuffer<T> {
+    /// `read_only` must be set for buffers visible to the vertex stage.
+    pub fn new(
+        device: &wgpu::Device,
+        data: &[T],
+        visibility: wgpu::ShaderStages,
+        read_only: bool,
+    ) -> Self {
+        assert!(!data.is_empty(), "Storage buffers can't be empty");
+
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(std::any::type_name::<T>()),
+            contents: bytemuck::cast_slice(data),
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
+        });
+        let bind_group_layout =
+            create_bind_group_layout(device, storage_layout_entry::<T>(0, visibility, read_only));
+        let bind_group = create_bind_group(device, &bind_group_layout, &buffer, None);
+
+        Self {
+            buffer,
+            len: data.len(),
+            read_only,
+            visibility,
+            bind_group_layout,
+            bind_group,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn zeroed(
+        device: &wgpu::Device,
+        len: usize,
+        visibility: wgpu::ShaderStages,
+        read_only: bool,
+    ) -> Self {
+        Self::new(device, &vec![T::zeroed(); len], visibility, read_only)
+    }
+
+    /// Overwrites the elements starting at `first`.
+    pub fn write(&self, queue: &wgpu::Queue, first: usize, data: &[T]) {
+        assert!(
+            first + data.len() <= self.len,
+            "Write of {} elements at {} is out of bounds of {}",
+            data.len(),
+            first,
+            self.len
+        );
+        queue.write_buffer(
+            &self.buffer,
+            (first * size_of::<T>()) as wgpu::BufferAddress,
+            bytemuck::cast_slice(data),
+        );
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    /// Entry for building a larger bind group layout that includes this buffer.
+    pub fn layout_entry(&self, binding: u32) -> wgpu::BindGroupLayoutEntry {
+        storage_layout_entry::<T>(binding, self.visibility, self.read_only)
+    }
+    pub fn binding_resource(&self) -> wgpu::BindingResource<'_> {
+        self.buffer.as_entire_binding()
+    }
+}
+
+impl<T: Pod> DynamicUniformBuffer<T> {
+    pub fn new(device: &wgpu::Device, visibility: wgpu::ShaderStages) -> Self {
+        let alignment = device.limits().min_uniform_buffer_offset_alignment as wgpu::BufferAddress;
+        let stride = (size_of::<T>() as wgpu::BufferAddress).div_ceil(alignment) * alignment;
+
+        let buffer = create_dynamic_buffer::<T>(device, stride, DYNAMIC_INITIAL_CAPACITY);
+        let bind_group_layout =
+            create_bind_group_layout(device, uniform_layout_entry::<T>(0, visibility, true));
+        let bind_group = create_bind_group(
+            device,
+            &bind_group_layout,
+            &buffer,
+            NonZeroU64::new(size_of::<T>() as u64),
+        );
+
+        Self {
+            buffer,
+            stride,
+            capacity: DYNAMIC_INITIAL_CAPACITY,
+            staging: vec![],
+            visibility,
+            bind_group_layout,
+            bind_group,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.staging.clear();
+    }
+
+    /// Appends a value and returns its dynamic offset.
+    pub fn push(&mut self, value: &T) -> u32 {
+        let offset = self.staging.len();
+        self.staging.extend_from_slice(bytemuck::bytes_of(value));
+        self.staging.resize(offset + self.stride as usize, 0);
+
+        offset as u32
+    }
+
+    /// Uploads the values pushed since `clear`, growing the buffer if they don't fit.
+    pub fn flush(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        if self.len() > self.capacity {
+            self.capacity = self.len().next_power_of_two();
+            self.buffer = create_dynamic_buffer::<T>(device, self.stride, self.capacity);
+            self.bind_group = create_bind_group(
+                device,
+                &self.bind_group_layout,
+                &self.buffer,
+                NonZeroU64::new(size_of::<T>() as u64),
+            );
+        }
+        if !self.staging.is_empty() {
+            queue.write_buffer(&self.buffer, 0, &self.staging);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.staging.len() / self.stride as usize
+    }
+    pub fn is_empty(&self) -> bool {
+        self.staging.is_empty()
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    /// Entry for building a larger bind group layout that includes this buffer.
+    pub fn layout_entry(&self, binding: u32) -> wgpu::BindGroupLayoutEntry {
+        uniform_layout_entry::<T>(binding, self.visibility, true)
+    }
+    pub fn binding_resource(&self) -> wgpu::BindingResource<'_> {
+        wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+            buffer: &self.buffer,
+            offset: 0,
+            size: NonZeroU64::new(size_of::<T>() as u64),
+        })
+    }
+}
+
+impl<T: Pod> DynamicBuffer<T> {
+    /// `usage` is added to `COPY_DST`.
+    pub fn new(device: &wgpu::Device, usage: wgpu::BufferUsages, capacity: usize) -> Self {
+        let usage = wgpu::BufferUsages::COPY_DST | usage;
+        let capacity = capacity.max(1);
+
+        Self {
+            buffer: create_growable_buffer::<T>(device, usage, capacity),
+            usage,
+            capacity,
+            len: 0,
+            belt: wgpu::util::StagingBelt::new(device.clone(), STAGING_CHUNK_SIZE),
+            pending: false,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Replaces the contents with `data`, recording the copy into `encoder`. Returns whether
+    /// the buffer was recreated to fit it, in which case bind groups using it need to be
+    /// recreated too. Panics if the previous `write` wasn't followed by `after_submit`.
+    pub fn write(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        data: &[T],
+    ) -> bool {
+        assert!(
+            !self.pending,
+            "DynamicBuffer written again before after_submit"
+        );
+        self.pending = true;
+
+        let grew = data.len() > self.capacity;
+        if grew {
+            self.capacity = data.len().next_power_of_two();
+            self.buffer = create_growable_buffer::<T>(device, self.usage, self.capacity);
+        }
+
+        self.len = data.len();
+        let bytes: &[u8] = bytemuck::cast_slice(data);
+        if let Some(size) = NonZeroU64::new(padded_size(bytes.len())) {
+            let mut view = self.belt.write_buffer(encoder, &self.buffer, 0, size);
+            view[..bytes.len()].copy_from_slice(bytes);
+        }
+        self.belt.finish();
+
+        grew
+    }
+
+    /// Reclaims the staging memory of the last `write`, to be called once the encoder it
+    /// recorded into is submitted.
+    pub fn after_submit(&mut self) {
+        self.belt.recall();
+        self.pending = false;
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+    /// The elements of the last `write`. Panics if it was empty.
+    pub fn slice(&self) -> wgpu::BufferSlice<'_> {
+        self.buffer
+            .slice(..(self.len * size_of::<T>()) as wgpu::BufferAddress)
+    }
+    pub fn binding_resource(&self) -> wgpu::BindingResource<'_> {
+        self.buffer.as_entire_binding()
+    }
+}
+
+impl DynamicIndexBuffer {
+    pub fn new(device: &wgpu::Device) -> Self {
+        Self {
+            buffer: DynamicBuffer::new(
+                device,
+                wgpu::BufferUsages::INDEX,
+                DYNAMIC_INITIAL_CAPACITY * size_of::<u32>(),
+            ),
+            format: wgpu::IndexFormat::Uint16,
+            len: 0,
+            narrowed: vec![],
+        }
+    }
+
+    /// Replaces the indices, stored as `u16` if `vertex_count` allows, see
+    /// `DynamicBuffer::write`.
+    pub fn write(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        indices: &[u32],
+        vertex_count: usize,
+    ) {
+        self.len = indices.len();
+        // 0xFFFF stays free, as it restarts strips.
+        if vertex_count <= u16::MAX as usize {
+            self.format = wgpu::IndexFormat::Uint16;
+            self.narrowed.clear();
+            self.narrowed
+                .extend(indices.iter().map(|&index| index as u16));
+            self.buffer
+                .write(device, encoder, bytemuck::cast_slice(&self.narrowed));
+        } else {
+            self.format = wgpu::IndexFormat::Uint32;
+            self.buffer
+                .write(device, encoder, bytemuck::cast_slice(indices));
+        }
+    }
+
+    /// See `DynamicBuffer::after_submit`.
+    pub fn after_submit(&mut self) {
+        self.buffer.after_submit();
+    }
+
+    /// Binds the indices of the last `write`, which must not be empty.
+    pub fn set(&self, pass: &mut wgpu::RenderPass) {
+        pass.set_index_buffer(self.buffer.slice(), self.format);
+    }
+
+    pub fn format(&self) -> wgpu::IndexFormat {
+        self.format
+    }
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+fn uniform_layout_entry<T>(
+    binding: u32,
+    visibility: wgpu::ShaderStages,
+    has_dynamic_offset: bool,
+) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset,
+            min_binding_size: NonZeroU64::new(size_of::<T>() as u64),
+        },
+        count: None,
+    }
+}
+
+fn storage_layout_entry<T>(
+    binding: u32,
+    visibility: wgpu::ShaderStages,
+    read_only: bool,
+) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only },
+            has_dynamic_offset: false,
+            min_binding_size: NonZeroU64::new(size_of::<T>() as u64),
+        },
+        count: None,
+    }
+}
+
+fn create_bind_group_layout(
+    device: &wgpu::Device,
+    entry: wgpu::BindGroupLayoutEntry,
+) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &[entry],
+        label: None,
+    })
+}
+
+fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    buffer: &wgpu::Buffer,
+    size: Option<NonZeroU64>,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                buffer,
+                offset: 0,
+                size,
+            }),
+        }],
+        label: None,
+    })
+}
+
+fn create_dynamic_buffer<T>(
+    device: &wgpu::Device,
+    stride: wgpu::BufferAddress,
+    capacity: usize,
+) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(std::any::type_name::<T>()),
+        size: stride * capacity as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+/// Sized for `capacity` elements, rounded up so that padded writes fit.
+fn create_growable_buffer<T>(
+    device: &wgpu::Device,
+    usage: wgpu::BufferUsages,
+    capacity: usize,
+) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(std::any::type_name::<T>()),
+        size: padded_size(capacity * size_of::<T>()),
+        usage,
+        mapped_at_creation: false,
+    })
+}
+
+/// Buffer copies work in multiples of `COPY_BUFFER_ALIGNMENT`.
+fn padded_size(size: usize) -> wgpu::BufferAddress {
+    (size as wgpu::BufferAddress).next_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn submit(queue: &wgpu::Queue, encoder: wgpu::CommandEncoder) {
+        queue.submit([encoder.finish()]);
+    }
+
+    #[test]
+    fn dynamic_buffer_grows_to_powers_of_two() {
+        let (device, queue) = wgpu::Device::noop(&wgpu::DeviceDescriptor::default());
+        let mut buffer = DynamicBuffer::<u32>::new(&device, wgpu::BufferUsages::VERTEX, 4);
+        // Length written, whether the buffer grew and the capacity after.
+        let cases = [
+            (3, false, 4),
+            (4, false, 4),
+            (5, true, 8),
+            (5, false, 8),
+            (2, false, 8),
+            (100, true, 128),
+            (0, false, 128),
+        ];
+
+        for case in cases {
+            let (len, grew, capacity) = case;
+            let data = vec![7u32; len];
+            let mut encoder = device.create_command_encoder(&Default::default());
+
+            assert_eq!(
+                buffer.write(&device, &mut encoder, &data),
+                grew,
+                "{:?}",
+                case
+            );
+            submit(&queue, encoder);
+            buffer.after_submit();
+
+            assert_eq!(buffer.len(), len, "{:?}", case);
+            assert_eq!(buffer.capacity(), capacity, "{:?}", case);
+            assert_eq!(
+                buffer.buffer().size(),
+                padded_size(capacity * size_of::<u32>()),
+                "{:?}",
+                case
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "before after_submit")]
+    fn dynamic_buffer_needs_after_submit() {
+        let (device, _queue) = wgpu::Device::noop(&wgpu::DeviceDescriptor::default());
+        let mut buffer = DynamicBuffer::<u32>::new(&device, wgpu::BufferUsages::VERTEX, 4);
+        let mut encoder = device.create_command_encoder(&Default::default());
+
+        buffer.write(&device, &mut encoder, &[1, 2]);
+        buffer.write(&device, &mut encoder, &[3, 4]);
+    }
+
+    #[test]
+    fn index_format_follows_vertex_count() {
+        let (device, queue) = wgpu::Device::noop(&wgpu::DeviceDescriptor::default());
+        let mut buffer = DynamicIndexBuffer::new(&device);
+        let indices = [0, 1, 2, 2, 1, 3];
+        let cases = [
+            (4, wgpu::IndexFormat::Uint16, 12),
+            (65_535, wgpu::IndexFormat::Uint16, 12),
+            (65_536, wgpu::IndexFormat::Uint32, 24),
+            (3, wgpu::IndexFormat::Uint16, 12),
+        ];
+
+        for case in cases {
+            let (vertex_count, format, byte_len) = case;
+            let mut encoder = device.create_command_encoder(&Default::default());
+
+            buffer.write(&device, &mut encoder, &indices, vertex_count);
+            submit(&queue, encoder);
+            buffer.after_submit();
+
+            assert_eq!(buffer.format(), format, "{:?}", case);
+            assert_eq!(buffer.len(), indices.len(), "{:?}", case);
+            assert_eq!(buffer.buffer.len(), byte_len, "{:?}", case);
+        }
+    }
+}
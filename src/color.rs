@@ -0,0 +1,37 @@
+/// How the values stored in a texture map to colors. Shaders read and write linear values
+/// in all of them; the format decides the encoding in memory and the range it can hold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorSpace {
+    /// sRGB-encoded values in 0..1, for `*Srgb` formats. The GPU encodes on write and decodes
+    /// when sampling.
+    Srgb,
+    /// Linear values with sRGB primaries that may exceed 1, 1 being SDR white (scRGB). Float
+    /// formats; on a surface, values above 1 are shown brighter on HDR displays.
+    ExtendedLinearSrgb,
+    /// Values stored as written, for the other formats: data, or colors the shader encodes.
+    Linear,
+}
+
+impl ColorSpace {
+    pub fn of(format: wgpu::TextureFormat) -> Self {
+        use wgpu::TextureFormat as Tf;
+
+        match format {
+            format if format.is_srgb() => Self::Srgb,
+            Tf::R16Float
+            | Tf::Rg16Float
+            | Tf::Rgba16Float
+            | Tf::R32Float
+            | Tf::Rg32Float
+            | Tf::Rgba32Float
+            | Tf::Rg11b10Ufloat
+            | Tf::Rgb9e5Ufloat => Self::ExtendedLinearSrgb,
+            _ => Self::Linear,
+        }
+    }
+
+    /// Whether values above 1 survive, so colors need tone mapping before an SDR output.
+    pub fn is_hdr(self) -> bool {
+        self == Self::ExtendedLinearSrgb
+    }
+}
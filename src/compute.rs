@@ -0,0 +1,265 @@
+use glam::{UVec2, UVec3};
+
+use crate::fullscreen::FullScreenTexture;
+use crate::reflection::ReflectionError;
+use crate::shader::ShaderAsset;
+
+/// 2D texture that shaders read or write as a storage texture and can also sample.
+#[derive(Debug)]
+pub struct StorageTexture {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    usage: wgpu::TextureUsages,
+}
+
+/// Texture a compute shader fills each frame, drawn over the whole target by a
+/// `FullScreenTexture`. The shader writes
+/// `@group(0) @binding(0) var output: texture_storage_2d<rgba8unorm, write>`, sized like the
+/// window, with one invocation per texel; groups from 1 on are the ones passed to `new`.
+#[derive(Debug)]
+pub struct ComputeTexture {
+    fullscreen: FullScreenTexture,
+    shader: ShaderAsset,
+    entry_point: String,
+    workgroup_size: UVec3,
+    output_layout: wgpu::BindGroupLayout,
+    output_bind_group: wgpu::BindGroup,
+    pipeline_layout: wgpu::PipelineLayout,
+    pipeline: wgpu::ComputePipeline,
+}
+
+const COMPUTE_TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+/// Workgroups of `workgroup_size` needed for at least one invocation per element of `size`.
+pub fn workgroup_count(size: UVec3, workgroup_size: UVec3) -> UVec3 {
+    UVec3::new(
+        size.x.div_ceil(workgroup_size.x),
+        size.y.div_ceil(workgroup_size.y),
+        size.z.div_ceil(workgroup_size.z),
+    )
+}
+
+/// Dispatches enough workgroups to cover `size`. Shaders must skip the invocations past it.
+pub fn dispatch(pass: &mut wgpu::ComputePass, size: UVec3, workgroup_size: UVec3) {
+    let count = workgroup_count(size, workgroup_size);
+    pass.dispatch_workgroups(count.x, count.y, count.z);
+}
+
+impl StorageTexture {
+    /// `usage` is added to `STORAGE_BINDING | TEXTURE_BINDING`.
+    pub fn new(
+        device: &wgpu::Device,
+        size: UVec2,
+        format: wgpu::TextureFormat,
+        usage: wgpu::TextureUsages,
+    ) -> Self {
+        let usage =
+            wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING | usage;
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage,
+            view_formats: &[],
+            label: None,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        Self {
+            texture,
+            view,
+            usage,
+        }
+    }
+
+    /// Recreates the texture if `size` changed and returns whether it did, in which case bind
+    /// groups using it need to be recreated too.
+    pub fn resize(&mut self, device: &wgpu::Device, size: UVec2) -> bool {
+        if size == self.size() {
+            return false;
+        }
+
+        *self = Self::new(device, size, self.texture.format(), self.usage);
+        true
+    }
+
+    pub fn size(&self) -> UVec2 {
+        UVec2::new(self.texture.width(), self.texture.height())
+    }
+
+    /// Entry for a bind group layout that has this texture as a storage texture.
+    pub fn layout_entry(
+        &self,
+        binding: u32,
+        visibility: wgpu::ShaderStages,
+        access: wgpu::StorageTextureAccess,
+    ) -> wgpu::BindGroupLayoutEntry {
+        storage_texture_layout_entry(binding, visibility, access, self.texture.format())
+    }
+
+    pub fn binding_resource(&self) -> wgpu::BindingResource<'_> {
+        wgpu::BindingResource::TextureView(&self.view)
+    }
+}
+
+impl ComputeTexture {
+    pub fn new(
+        device: &wgpu::Device,
+        surface_format: wgpu::TextureFormat,
+        window_size: UVec2,
+        shader: ShaderAsset,
+        entry_point: &str,
+        bind_group_layouts: &[&wgpu::BindGroupLayout],
+    ) -> Result<Self, ReflectionError> {
+        let reflection = shader.reflect()?;
+        let workgroup_size = reflection.workgroup_size(entry_point)?;
+
+        let fullscreen = FullScreenTexture::with_texture(
+            device,
+            surface_format,
+            window_size,
+            COMPUTE_TEXTURE_FORMAT,
+            wgpu::TextureUsages::STORAGE_BINDING,
+        );
+        let output_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[storage_texture_layout_entry(
+                0,
+                wgpu::ShaderStages::COMPUTE,
+                wgpu::StorageTextureAccess::WriteOnly,
+                COMPUTE_TEXTURE_FORMAT,
+            )],
+            label: None,
+        });
+        let output_bind_group = create_output_bind_group(device, &output_layout, &fullscreen);
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            bind_group_layouts: &[&[&output_layout], bind_group_layouts].concat(),
+            immediate_size: 0,
+            label: None,
+        });
+        let pipeline =
+            create_compute_pipeline(device, &pipeline_layout, shader.module(), entry_point);
+
+        Ok(Self {
+            fullscreen,
+            shader,
+            entry_point: entry_point.to_string(),
+            workgroup_size,
+            output_layout,
+            output_bind_group,
+            pipeline_layout,
+            pipeline,
+        })
+    }
+
+    /// Runs the shader over the texture, reloading it first if its file changed.
+    /// `bind_groups` are set from group 1 on.
+    pub fn dispatch(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        bind_groups: &[&wgpu::BindGroup],
+    ) {
+        let (pipeline_layout, entry_point) = (&self.pipeline_layout, self.entry_point.as_str());
+        if let Some(pipeline) = self.shader.poll(device, |module| {
+            create_compute_pipeline(device, pipeline_layout, module, entry_point)
+        }) {
+            self.pipeline = pipeline;
+            match self.shader.reflect() {
+                Ok(reflection) => {
+                    self.workgroup_size = reflection
+                        .workgroup_size(entry_point)
+                        .unwrap_or(self.workgroup_size);
+                }
+                Err(err) => tracing::error!("{}", err),
+            }
+        }
+
+        let size = self.fullscreen.get_texture().size();
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some(entry_point),
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.output_bind_group, &[]);
+        for (index, bind_group) in bind_groups.iter().enumerate() {
+            pass.set_bind_group(index as u32 + 1, *bind_group, &[]);
+        }
+        dispatch(
+            &mut pass,
+            UVec3::new(size.width, size.height, 1),
+            self.workgroup_size,
+        );
+    }
+
+    /// Draws the texture written by the last `dispatch`, see `FullScreenTexture::render`.
+    pub fn render(&self, command_encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        self.fullscreen.render(command_encoder, view);
+    }
+
+    pub fn resize_window(&mut self, device: &wgpu::Device, window_size: UVec2) {
+        if self.fullscreen.resize_window(device, window_size) {
+            self.output_bind_group =
+                create_output_bind_group(device, &self.output_layout, &self.fullscreen);
+        }
+    }
+
+    pub fn get_texture(&self) -> &wgpu::Texture {
+        self.fullscreen.get_texture()
+    }
+}
+
+fn storage_texture_layout_entry(
+    binding: u32,
+    visibility: wgpu::ShaderStages,
+    access: wgpu::StorageTextureAccess,
+    format: wgpu::TextureFormat,
+) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility,
+        ty: wgpu::BindingType::StorageTexture {
+            access,
+            format,
+            view_dimension: wgpu::TextureViewDimension::D2,
+        },
+        count: None,
+    }
+}
+
+fn create_output_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    fullscreen: &FullScreenTexture,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::TextureView(fullscreen.get_texture_view()),
+        }],
+        label: None,
+    })
+}
+
+fn create_compute_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    module: &wgpu::ShaderModule,
+    entry_point: &str,
+) -> wgpu::ComputePipeline {
+    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some(entry_point),
+        layout: Some(layout),
+        module,
+        entry_point: Some(entry_point),
+        compilation_options: Default::default(),
+        cache: None,
+    })
+}
@@ -0,0 +1,116 @@
+use bytemuck::Pod;
+
+use crate::buffer::DynamicUniformBuffer;
+use crate::preprocessor::ShaderComposer;
+use crate::reflection::ShaderReflection;
+
+/// Defined by `DrawConstants::shader_composer` when the device supports immediates.
+pub const IMMEDIATES_DEFINE: &str = "WGPU_APP_IMMEDIATES";
+
+/// Defined by `DrawConstants::shader_composer` to the group of the uniform buffer fallback.
+pub const GROUP_DEFINE: &str = "WGPU_APP_DRAW_CONSTANTS_GROUP";
+
+/// Handle returned by `DrawConstants::push`, valid until the next `clear`.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub struct DrawConstantsId(u32);
+
+/// Small per-draw values such as a model-view-projection matrix. Uses immediates when the
+/// device has `Features::IMMEDIATES` and otherwise a uniform buffer bound with a dynamic
+/// offset at `group`, binding 0. Shaders select the matching declaration with
+/// `#ifdef WGPU_APP_IMMEDIATES` and place the buffer at `WGPU_APP_DRAW_CONSTANTS_GROUP`, see
+/// `wgpu_app/common.wgsl`.
+///
+/// Per frame: `clear`, `push` the values of every draw, `flush`, then `set` in the pass.
+#[derive(Debug)]
+pub struct DrawConstants<T: Pod> {
+    group: u32,
+    storage: Storage<T>,
+}
+
+#[derive(Debug)]
+enum Storage<T: Pod> {
+    Immediates(Vec<T>),
+    Uniform(DynamicUniformBuffer<T>),
+}
+
+impl<T: Pod> DrawConstants<T> {
+    pub fn new(device: &wgpu::Device, group: u32, visibility: wgpu::ShaderStages) -> Self {
+        let storage = if device.features().contains(wgpu::Features::IMMEDIATES) {
+            Storage::Immediates(vec![])
+        } else {
+            tracing::info!(
+                "Immediates are not supported, passing draw constants in a uniform buffer"
+            );
+            Storage::Uniform(DynamicUniformBuffer::new(device, visibility))
+        };
+
+        Self { group, storage }
+    }
+
+    pub fn uses_immediates(&self) -> bool {
+        matches!(self.storage, Storage::Immediates(_))
+    }
+
+    /// Composer with `WGPU_APP_DRAW_CONSTANTS_GROUP` defined to `group`, and
+    /// `WGPU_APP_IMMEDIATES` when immediates are used.
+    pub fn shader_composer(&self) -> ShaderComposer {
+        let composer = ShaderComposer::new().define(GROUP_DEFINE, self.group);
+        if self.uses_immediates() {
+            composer.define(IMMEDIATES_DEFINE, "")
+        } else {
+            composer
+        }
+    }
+
+    /// Bind group layouts of `reflection` with the dynamic offset uniform buffer layout at
+    /// `group` when immediates aren't used.
+    pub fn bind_group_layouts(
+        &self,
+        device: &wgpu::Device,
+        reflection: &ShaderReflection,
+    ) -> Vec<wgpu::BindGroupLayout> {
+        let mut layouts = reflection.create_bind_group_layouts(device);
+        if let Storage::Uniform(buffer) = &self.storage {
+            if let Some(layout) = layouts.get_mut(self.group as usize) {
+                *layout = buffer.bind_group_layout().clone();
+            }
+        }
+
+        layouts
+    }
+
+    pub fn clear(&mut self) {
+        match &mut self.storage {
+            Storage::Immediates(values) => values.clear(),
+            Storage::Uniform(buffer) => buffer.clear(),
+        }
+    }
+
+    pub fn push(&mut self, value: &T) -> DrawConstantsId {
+        match &mut self.storage {
+            Storage::Immediates(values) => {
+                values.push(*value);
+                DrawConstantsId(values.len() as u32 - 1)
+            }
+            Storage::Uniform(buffer) => DrawConstantsId(buffer.push(value)),
+        }
+    }
+
+    /// Uploads the pushed values. Must be called before recording the passes that use them.
+    pub fn flush(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        if let Storage::Uniform(buffer) = &mut self.storage {
+            buffer.flush(device, queue);
+        }
+    }
+
+    pub fn set(&self, pass: &mut wgpu::RenderPass, id: DrawConstantsId) {
+        match &self.storage {
+            Storage::Immediates(values) => {
+                pass.set_immediates(0, bytemuck::bytes_of(&values[id.0 as usize]));
+            }
+            Storage::Uniform(buffer) => {
+                pass.set_bind_group(self.group, buffer.bind_group(), &[id.0]);
+            }
+        }
+    }
+}
@@ -0,0 +1,277 @@
+use bytemuck::{Pod, Zeroable};
+use glam::UVec2;
+use wgpu::util::DeviceExt;
+
+use crate::layout::VertexLayout;
+use crate::render_target::{RenderTarget, RenderTargetOptions};
+use crate::shader;
+
+/// Vertex of the fullscreen quad, at locations 0 (clip position) and 1 (uv with a top-left
+/// origin). `vs_fullscreen` in `wgpu_app/fullscreen.wgsl` passes both on.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Pod, Zeroable)]
+pub struct ScreenVertex {
+    pub position: [f32; 4],
+    pub uv: [f32; 2],
+}
+
+/// Triangle strip covering the viewport, shared by fullscreen pipelines.
+#[derive(Debug)]
+pub struct FullscreenQuad {
+    vertex_buffer: wgpu::Buffer,
+}
+
+/// Draws a texture over the whole target, e.g. one the CPU writes into.
+#[derive(Debug)]
+pub struct FullScreenTexture {
+    quad: FullscreenQuad,
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    target: RenderTarget,
+    bind_group: wgpu::BindGroup,
+}
+
+const SCREEN_RECT: [ScreenVertex; 4] = [
+    ScreenVertex {
+        position: [-1.0, -1.0, 0.0, 1.0],
+        uv: [0.0, 1.0],
+    },
+    ScreenVertex {
+        position: [-1.0, 1.0, 0.0, 1.0],
+        uv: [0.0, 0.0],
+    },
+    ScreenVertex {
+        position: [1.0, -1.0, 0.0, 1.0],
+        uv: [1.0, 1.0],
+    },
+    ScreenVertex {
+        position: [1.0, 1.0, 0.0, 1.0],
+        uv: [1.0, 0.0],
+    },
+];
+
+impl VertexLayout for ScreenVertex {
+    const ATTRIBUTES: &'static [wgpu::VertexAttribute] =
+        &wgpu::vertex_attr_array![0 => Float32x4, 1 => Float32x2];
+}
+
+impl FullscreenQuad {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Fullscreen Quad"),
+            contents: bytemuck::cast_slice(&SCREEN_RECT),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        Self { vertex_buffer }
+    }
+
+    /// Pipeline running `vs_fullscreen` and `fragment_entry` from `module`, which includes
+    /// `wgpu_app/fullscreen.wgsl`.
+    pub fn create_pipeline(
+        &self,
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        module: &wgpu::ShaderModule,
+        fragment_entry: &str,
+        target: wgpu::ColorTargetState,
+    ) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(fragment_entry),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module,
+                entry_point: Some("vs_fullscreen"),
+                buffers: &[ScreenVertex::layout()],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module,
+                entry_point: Some(fragment_entry),
+                targets: &[Some(target)],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                cull_mode: None,
+                front_face: wgpu::FrontFace::Cw,
+                topology: wgpu::PrimitiveTopology::TriangleStrip,
+
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            cache: None,
+            multiview_mask: None,
+        })
+    }
+
+    pub fn draw(&self, pass: &mut wgpu::RenderPass) {
+        pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        pass.draw(0..SCREEN_RECT.len() as u32, 0..1);
+    }
+}
+
+impl FullScreenTexture {
+    pub fn new(
+        device: &wgpu::Device,
+        surface_format: wgpu::TextureFormat,
+        window_size: UVec2,
+    ) -> Self {
+        Self::with_texture(
+            device,
+            surface_format,
+            window_size,
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+            wgpu::TextureUsages::COPY_DST,
+        )
+    }
+
+    /// Draws a texture of `format` with `usage` on top of `TEXTURE_BINDING`, e.g. a
+    /// `STORAGE_BINDING` one a compute shader writes. Storage textures can't be sRGB.
+    pub fn with_texture(
+        device: &wgpu::Device,
+        surface_format: wgpu::TextureFormat,
+        window_size: UVec2,
+        format: wgpu::TextureFormat,
+        usage: wgpu::TextureUsages,
+    ) -> Self {
+        let quad = FullscreenQuad::new(device);
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            border_color: None,
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::NonFiltering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+            ],
+            label: None,
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            bind_group_layouts: &[&bind_group_layout],
+            label: None,
+            immediate_size: 0,
+        });
+
+        let module = shader::compile_builtin(
+            device,
+            "wgpu_app/fullscreen_texture.wgsl",
+            include_str!("shaders/fullscreen_texture.wgsl"),
+        );
+        let pipeline = quad.create_pipeline(
+            device,
+            &pipeline_layout,
+            &module,
+            "fs_main",
+            surface_format.into(),
+        );
+
+        let target = RenderTarget::new(
+            device,
+            window_size,
+            RenderTargetOptions {
+                color_format: Some(format),
+                depth_format: None,
+                usage,
+                ..Default::default()
+            },
+        );
+        let bind_group = create_bind_group(device, &bind_group_layout, &sampler, &target);
+
+        Self {
+            quad,
+            pipeline,
+            bind_group_layout,
+            sampler,
+            target,
+            bind_group,
+        }
+    }
+
+    pub fn render(&self, command_encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+                depth_slice: None,
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+            multiview_mask: None,
+        });
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        self.quad.draw(&mut render_pass);
+    }
+
+    /// Recreates the texture if the window size changed and returns whether it did.
+    pub fn resize_window(&mut self, device: &wgpu::Device, window_size: UVec2) -> bool {
+        if !self.target.resize(device, window_size) {
+            return false;
+        }
+
+        self.bind_group =
+            create_bind_group(device, &self.bind_group_layout, &self.sampler, &self.target);
+        true
+    }
+
+    /// The texture that gets drawn, sized like the window.
+    pub fn get_texture(&self) -> &wgpu::Texture {
+        &self.target.sampled().unwrap().texture
+    }
+
+    pub fn get_texture_view(&self) -> &wgpu::TextureView {
+        self.target.sampled_view().unwrap()
+    }
+}
+
+fn create_bind_group(
+    device: &wgpu::Device,
+    bind_group_layout: &wgpu::BindGroupLayout,
+    sampler: &wgpu::Sampler,
+    target: &RenderTarget,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout: bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(target.sampled_view().unwrap()),
+            },
+        ],
+        label: None,
+    })
+}
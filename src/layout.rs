@@ -0,0 +1,147 @@
+pub use wgpu_app_derive::{GpuBlock, VertexLayout};
+
+/// Immediate data size the runner requests from the device.
+pub const MAX_IMMEDIATE_SIZE: u32 = 256;
+
+/// Vertex struct whose fields map to shader locations, usually derived.
+pub trait VertexLayout: bytemuck::Pod {
+    const ATTRIBUTES: &'static [wgpu::VertexAttribute];
+    const STEP_MODE: wgpu::VertexStepMode = wgpu::VertexStepMode::Vertex;
+
+    fn layout() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: Self::STEP_MODE,
+            attributes: Self::ATTRIBUTES,
+        }
+    }
+
+    fn size_in_bytes() -> usize {
+        size_of::<Self>()
+    }
+}
+
+/// Field type of a `VertexLayout` struct.
+pub trait VertexAttributeType {
+    const FORMAT: wgpu::VertexFormat;
+}
+
+/// Alignment and size of the WGSL type a Rust type stands for.
+pub trait WgslType {
+    const ALIGN: usize;
+    const SIZE: usize;
+    /// Uniform buffers align structs and arrays to 16 bytes.
+    const UNIFORM_ALIGN: usize = Self::ALIGN;
+    /// Space taken in a uniform buffer, where a member after a struct starts 16-byte aligned.
+    const UNIFORM_SIZE: usize = Self::SIZE;
+    /// False if an array stride isn't a multiple of 16.
+    const UNIFORM_COMPATIBLE: bool = true;
+}
+
+/// Struct shared with WGSL as a uniform, storage or immediate block, usually derived.
+pub trait GpuBlock: bytemuck::Pod + WgslType {
+    fn size_in_bytes() -> u32 {
+        size_of::<Self>() as u32
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        bytemuck::bytes_of(self)
+    }
+}
+
+#[doc(hidden)]
+pub const fn round_up(value: usize, align: usize) -> usize {
+    value.div_ceil(align) * align
+}
+
+#[doc(hidden)]
+pub const fn max_align(aligns: &[usize]) -> usize {
+    let mut max = 1;
+    let mut index = 0;
+    while index < aligns.len() {
+        if aligns[index] > max {
+            max = aligns[index];
+        }
+        index += 1;
+    }
+    max
+}
+
+macro_rules! vertex_attribute_types {
+    ($($ty:ty => $format:ident),* $(,)?) => {
+        $(impl VertexAttributeType for $ty {
+            const FORMAT: wgpu::VertexFormat = wgpu::VertexFormat::$format;
+        })*
+    };
+}
+
+vertex_attribute_types! {
+    f32 => Float32,
+    [f32; 1] => Float32,
+    [f32; 2] => Float32x2,
+    [f32; 3] => Float32x3,
+    [f32; 4] => Float32x4,
+    u32 => Uint32,
+    [u32; 1] => Uint32,
+    [u32; 2] => Uint32x2,
+    [u32; 3] => Uint32x3,
+    [u32; 4] => Uint32x4,
+    i32 => Sint32,
+    [i32; 1] => Sint32,
+    [i32; 2] => Sint32x2,
+    [i32; 3] => Sint32x3,
+    [i32; 4] => Sint32x4,
+    [u16; 2] => Uint16x2,
+    [u16; 4] => Uint16x4,
+    [i16; 2] => Sint16x2,
+    [i16; 4] => Sint16x4,
+    [u8; 2] => Uint8x2,
+    [u8; 4] => Uint8x4,
+    [i8; 2] => Sint8x2,
+    [i8; 4] => Sint8x4,
+    glam::Vec2 => Float32x2,
+    glam::Vec3 => Float32x3,
+    glam::Vec4 => Float32x4,
+    glam::UVec2 => Uint32x2,
+    glam::UVec3 => Uint32x3,
+    glam::UVec4 => Uint32x4,
+    glam::IVec2 => Sint32x2,
+    glam::IVec3 => Sint32x3,
+    glam::IVec4 => Sint32x4,
+}
+
+macro_rules! wgsl_types {
+    ($($ty:ty => ($align:literal, $size:literal)),* $(,)?) => {
+        $(impl WgslType for $ty {
+            const ALIGN: usize = $align;
+            const SIZE: usize = $size;
+        })*
+    };
+}
+
+// glam::Mat3 is 36 bytes while WGSL's mat3x3 is 48, use glam::Mat3A instead.
+wgsl_types! {
+    f32 => (4, 4),
+    u32 => (4, 4),
+    i32 => (4, 4),
+    glam::Vec2 => (8, 8),
+    glam::Vec3 => (16, 12),
+    glam::Vec4 => (16, 16),
+    glam::UVec2 => (8, 8),
+    glam::UVec3 => (16, 12),
+    glam::UVec4 => (16, 16),
+    glam::IVec2 => (8, 8),
+    glam::IVec3 => (16, 12),
+    glam::IVec4 => (16, 16),
+    glam::Mat2 => (8, 16),
+    glam::Mat3A => (16, 48),
+    glam::Mat4 => (16, 64),
+}
+
+impl<T: WgslType, const N: usize> WgslType for [T; N] {
+    const ALIGN: usize = T::ALIGN;
+    const SIZE: usize = N * round_up(T::SIZE, T::ALIGN);
+    const UNIFORM_ALIGN: usize = round_up(T::UNIFORM_ALIGN, 16);
+    const UNIFORM_COMPATIBLE: bool =
+        T::UNIFORM_COMPATIBLE && round_up(T::SIZE, T::ALIGN).is_multiple_of(16);
+}
@@ -1,8 +1,36 @@
 #![allow(dead_code)]
 
+pub use adapter::AdapterSelector;
+pub use color::ColorSpace;
 pub use events::{EventResult, WindowEvent};
-pub use wgpu_app::run;
-pub use wgpu_app::{AppContext, UserEventType, WgpuApp};
+#[cfg(feature = "tracing-subscriber")]
+pub use logging::init_tracing;
+pub use wgpu_app::{run, run_with_config};
+pub use wgpu_app::{AppConfig, AppContext, ControlFlow, Frame, UserEventType, WgpuApp};
 
+pub use wgpu;
+
+pub mod adapter;
+pub mod assets;
+mod block_decode;
+pub mod buffer;
+pub mod color;
+pub mod compute;
+pub mod draw_constants;
 mod events;
+pub mod fullscreen;
+pub mod layout;
+mod logging;
+pub mod mesh;
+pub mod post_process;
+pub mod preprocessor;
+pub mod profiler;
+pub mod reflection;
+pub mod render_graph;
+pub mod render_target;
+pub mod shader;
+pub mod sprite;
+pub mod text;
+pub mod texture;
+mod texture_container;
 mod wgpu_app;
@@ -0,0 +1,11 @@
+/// Installs a `tracing` subscriber printing to stderr. Honors `RUST_LOG` and otherwise keeps
+/// wgpu's own crates at `warn` so that app and `wgpu_app` messages stay readable.
+#[cfg(feature = "tracing-subscriber")]
+pub fn init_tracing() {
+    use tracing_subscriber::EnvFilter;
+
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new("info,wgpu_core=warn,wgpu_hal=warn,naga=warn"));
+
+    let _ = tracing_subscriber::fmt().with_env_filter(filter).try_init();
+}
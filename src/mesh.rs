@@ -0,0 +1,306 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+use crate::layout::VertexLayout;
+
+/// Vertex of meshes loaded from files, at locations 0 (position), 1 (normal) and 2 (uv).
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Pod, Zeroable)]
+pub struct MeshVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub uv: [f32; 2],
+}
+
+/// Indexed triangle list on the CPU.
+#[derive(Debug, Clone, Default)]
+pub struct MeshData {
+    pub vertices: Vec<MeshVertex>,
+    pub indices: Vec<u32>,
+}
+
+/// Vertex and `Uint32` index buffers of a `MeshData`.
+#[derive(Debug)]
+pub struct Mesh {
+    pub vertex_buffer: wgpu::Buffer,
+    pub index_buffer: wgpu::Buffer,
+    pub index_count: u32,
+}
+
+#[derive(Debug)]
+pub struct MeshError {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+impl VertexLayout for MeshVertex {
+    const ATTRIBUTES: &'static [wgpu::VertexAttribute] =
+        &wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3, 2 => Float32x2];
+}
+
+impl MeshData {
+    /// Reads a Wavefront OBJ file. Faces are triangulated as fans and all objects and groups
+    /// are merged. Texture coordinates are flipped to a top-left origin; normals default to
+    /// zero when the file has none.
+    pub fn load_obj(path: impl AsRef<Path>) -> Result<Self, MeshError> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path).map_err(|err| MeshError {
+            path: path.to_path_buf(),
+            message: err.to_string(),
+        })?;
+
+        Self::parse_obj(&source).map_err(|message| MeshError {
+            path: path.to_path_buf(),
+            message,
+        })
+    }
+
+    fn parse_obj(source: &str) -> Result<Self, String> {
+        let mut positions: Vec<[f32; 3]> = vec![];
+        let mut normals: Vec<[f32; 3]> = vec![];
+        let mut uvs: Vec<[f32; 2]> = vec![];
+        let mut mesh = MeshData::default();
+        let mut vertex_indices: HashMap<[usize; 3], u32> = HashMap::new();
+
+        for (line_index, line) in source.lines().enumerate() {
+            let line_error = |message: &str| format!("Line {}: {}", line_index + 1, message);
+            let mut tokens = line.split_whitespace();
+            let floats = |tokens: std::str::SplitWhitespace| {
+                tokens
+                    .map(|token| token.parse::<f32>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|err| line_error(&err.to_string()))
+            };
+
+            match tokens.next() {
+                Some("v") => {
+                    let values = floats(tokens)?;
+                    if values.len() < 3 {
+                        return Err(line_error("Expected 3 position components"));
+                    }
+                    positions.push([values[0], values[1], values[2]]);
+                }
+                Some("vn") => {
+                    let values = floats(tokens)?;
+                    if values.len() < 3 {
+                        return Err(line_error("Expected 3 normal components"));
+                    }
+                    normals.push([values[0], values[1], values[2]]);
+                }
+                Some("vt") => {
+                    let values = floats(tokens)?;
+                    if values.is_empty() {
+                        return Err(line_error("Expected texture coordinates"));
+                    }
+                    uvs.push([values[0], 1.0 - values.get(1).copied().unwrap_or(0.0)]);
+                }
+                Some("f") => {
+                    let mut face = vec![];
+                    for token in tokens {
+                        // `v`, `v/vt`, `v//vn` or `v/vt/vn`, 1-based or negative from the end.
+                        let mut key = [0usize; 3];
+                        let counts = [positions.len(), uvs.len(), normals.len()];
+                        for (i, index) in token.split('/').enumerate().take(3) {
+                            if index.is_empty() {
+                                continue;
+                            }
+                            let index = index
+                                .parse::<isize>()
+                                .map_err(|err| line_error(&err.to_string()))?;
+                            let resolved = if index < 0 {
+                                counts[i] as isize + index + 1
+                            } else {
+                                index
+                            };
+                            if resolved < 1 || resolved > counts[i] as isize {
+                                return Err(line_error("Index out of range"));
+                            }
+                            key[i] = resolved as usize;
+                        }
+                        if key[0] == 0 {
+                            return Err(line_error("Face vertex without a position"));
+                        }
+
+                        let index = *vertex_indices.entry(key).or_insert_with(|| {
+                            let uv = key[1].checked_sub(1).map_or([0.0; 2], |i| uvs[i]);
+                            let normal = key[2].checked_sub(1).map_or([0.0; 3], |i| normals[i]);
+                            mesh.vertices.push(MeshVertex {
+                                position: positions[key[0] - 1],
+                                normal,
+                                uv,
+                            });
+                            mesh.vertices.len() as u32 - 1
+                        });
+                        face.push(index);
+                    }
+                    if face.len() < 3 {
+                        return Err(line_error("Face with less than 3 vertices"));
+                    }
+                    for i in 1..face.len() - 1 {
+                        mesh.indices
+                            .extend_from_slice(&[face[0], face[i], face[i + 1]]);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(mesh)
+    }
+}
+
+impl Mesh {
+    pub fn new(device: &wgpu::Device, data: &MeshData) -> Self {
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Mesh Vertex Buffer"),
+            contents: bytemuck::cast_slice(&data.vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Mesh Index Buffer"),
+            contents: bytemuck::cast_slice(&data.indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        Self {
+            vertex_buffer,
+            index_buffer,
+            index_count: data.indices.len() as u32,
+        }
+    }
+
+    /// Binds the buffers, the vertex buffer at `slot`, and draws all triangles.
+    pub fn draw(&self, pass: &mut wgpu::RenderPass, slot: u32, instances: std::ops::Range<u32>) {
+        pass.set_vertex_buffer(slot, self.vertex_buffer.slice(..));
+        pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        pass.draw_indexed(0..self.index_count, 0, instances);
+    }
+}
+
+impl std::fmt::Display for MeshError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Failed to load {}: {}",
+            self.path.display(),
+            self.message
+        )
+    }
+}
+
+impl std::error::Error for MeshError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUAD: &str = "\
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn 0 0 1
+";
+
+    fn vertex(position: [f32; 3], normal: [f32; 3], uv: [f32; 2]) -> MeshVertex {
+        MeshVertex {
+            position,
+            normal,
+            uv,
+        }
+    }
+
+    #[test]
+    fn face_formats() {
+        let up = [0.0, 0.0, 1.0];
+        let cases = [
+            (
+                "f 1/1/1 2/2/1 3/3/1",
+                vec![
+                    vertex([0.0, 0.0, 0.0], up, [0.0, 1.0]),
+                    vertex([1.0, 0.0, 0.0], up, [1.0, 1.0]),
+                    vertex([1.0, 1.0, 0.0], up, [1.0, 0.0]),
+                ],
+            ),
+            (
+                "f 1//1 2//1 3//1",
+                vec![
+                    vertex([0.0, 0.0, 0.0], up, [0.0; 2]),
+                    vertex([1.0, 0.0, 0.0], up, [0.0; 2]),
+                    vertex([1.0, 1.0, 0.0], up, [0.0; 2]),
+                ],
+            ),
+            (
+                "f 1/4 2/3 3",
+                vec![
+                    vertex([0.0, 0.0, 0.0], [0.0; 3], [0.0, 0.0]),
+                    vertex([1.0, 0.0, 0.0], [0.0; 3], [1.0, 0.0]),
+                    vertex([1.0, 1.0, 0.0], [0.0; 3], [0.0; 2]),
+                ],
+            ),
+            // Negative indices count back from the last element read so far.
+            (
+                "f -4/-4/-1 -3/-3/-1 -2/-2/-1",
+                vec![
+                    vertex([0.0, 0.0, 0.0], up, [0.0, 1.0]),
+                    vertex([1.0, 0.0, 0.0], up, [1.0, 1.0]),
+                    vertex([1.0, 1.0, 0.0], up, [1.0, 0.0]),
+                ],
+            ),
+        ];
+
+        for case in cases {
+            let (face, expected) = &case;
+            let mesh = MeshData::parse_obj(&format!("{}{}\n", QUAD, face)).unwrap();
+
+            assert_eq!(&mesh.vertices, expected, "{:?}", case);
+            assert_eq!(mesh.indices, [0, 1, 2], "{:?}", case);
+        }
+    }
+
+    #[test]
+    fn polygons_are_fans() {
+        let mesh = MeshData::parse_obj(&format!("{}v 0.5 2 0\nf 1 2 3 5 4\n", QUAD)).unwrap();
+
+        assert_eq!(mesh.vertices.len(), 5);
+        assert_eq!(mesh.indices, [0, 1, 2, 0, 2, 3, 0, 3, 4]);
+    }
+
+    #[test]
+    fn shared_vertices_are_deduplicated() {
+        let mesh = MeshData::parse_obj(&format!("{}f 1/1 2/2 3/3\nf 1/1 3/3 4/4\nf 1 2 3\n", QUAD))
+            .unwrap();
+
+        // `1` and `1/1` differ in their texture coordinates.
+        assert_eq!(mesh.vertices.len(), 7);
+        assert_eq!(mesh.indices, [0, 1, 2, 0, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn invalid_faces() {
+        let cases = [
+            ("f 1 2 5", "Line 10: Index out of range"),
+            ("f 0 1 2", "Line 10: Index out of range"),
+            ("f -5 1 2", "Line 10: Index out of range"),
+            ("f 1/5 2 3", "Line 10: Index out of range"),
+            ("f 1//2 2 3", "Line 10: Index out of range"),
+            ("f /1 2 3", "Line 10: Face vertex without a position"),
+            ("f 1 2", "Line 10: Face with less than 3 vertices"),
+            ("f 1 x 3", "Line 10: invalid digit found in string"),
+        ];
+
+        for case in cases {
+            let (face, expected) = case;
+            let result = MeshData::parse_obj(&format!("{}{}\n", QUAD, face));
+
+            assert_eq!(result.unwrap_err(), expected, "{:?}", case);
+        }
+    }
+}
@@ -0,0 +1,872 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::PathBuf;
+
+use bytemuck::{Pod, Zeroable};
+use glam::UVec2;
+
+use crate::color::ColorSpace;
+use crate::fullscreen::FullscreenQuad;
+use crate::render_target::{RenderTarget, RenderTargetOptions};
+use crate::shader::{self, ShaderAsset, ShaderSource};
+
+/// Effect of a `PostProcess` chain together with its parameters.
+#[derive(Debug, Clone)]
+pub enum PostEffect {
+    Blur(Blur),
+    Bloom(Bloom),
+    ToneMap(ToneMap),
+    Vignette(Vignette),
+    ChromaticAberration(ChromaticAberration),
+    Fxaa(Fxaa),
+    Custom(CustomEffect),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Blur {
+    /// Separable Gaussian, with the radius in pixels at three standard deviations.
+    Gaussian { radius: f32 },
+    /// Passes of four diagonal bilinear taps at 0.5, 1.5, 2.5, ... texels from the center.
+    /// Cheaper than a Gaussian for wide blurs.
+    Kawase { iterations: u32 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bloom {
+    /// Brightness above which colors glow.
+    pub threshold: f32,
+    /// Width of the soft transition below the threshold.
+    pub knee: f32,
+    pub intensity: f32,
+    /// Number of half-resolution levels; more levels spread the glow further.
+    pub levels: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ToneMapOperator {
+    Reinhard,
+    /// Narkowicz's fit of the ACES filmic curve.
+    #[default]
+    Aces,
+    /// Troy Sobotka's AgX with the default look, which desaturates very bright colors
+    /// towards white instead of shifting their hue.
+    AgX,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ToneMap {
+    pub operator: ToneMapOperator,
+    /// Multiplies the color before the operator.
+    pub exposure: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vignette {
+    /// Darkening in the corners, from 0 to 1.
+    pub intensity: f32,
+    /// Distance from the center where the darkening starts, 1 being a corner.
+    pub radius: f32,
+    /// Distance over which the darkening fades in.
+    pub smoothness: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChromaticAberration {
+    /// Offset in pixels of the red and blue channels at the edges of the screen.
+    pub intensity: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fxaa {
+    /// Minimum local contrast, relative to the brightest neighbor, to be treated as an edge.
+    pub edge_threshold: f32,
+    /// Contrast below which dark areas are skipped.
+    pub edge_threshold_min: f32,
+    /// Amount of subpixel aliasing removal, from 0 to 1.
+    pub subpixel: f32,
+}
+
+/// Effect from a WGSL file that includes `wgpu_app/post_process.wgsl` and has an `fs_main`
+/// entry point taking a `FullscreenOutput`. Parameters, if any, are read from a uniform at
+/// `@group(0) @binding(2)` and follow the file in debug builds like `ShaderAsset`.
+#[derive(Debug, Clone)]
+pub struct CustomEffect {
+    pub source: ShaderSource,
+    params: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PostProcessOptions {
+    /// Target the app renders the scene into, the input of the chain. Needs a color format.
+    pub input: RenderTargetOptions,
+    /// Format of the targets between effects.
+    pub format: wgpu::TextureFormat,
+}
+
+/// Chain of fullscreen effects. Apps render into `input`, then `render` runs `effects` in
+/// order, ping-ponging between intermediate targets, and the last effect writes the output.
+/// `effects` can be edited between frames; pipelines and targets are created on first use.
+#[derive(Debug)]
+pub struct PostProcess {
+    pub effects: Vec<PostEffect>,
+
+    output_format: wgpu::TextureFormat,
+    format: wgpu::TextureFormat,
+
+    quad: FullscreenQuad,
+    sampler: wgpu::Sampler,
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline_layout: wgpu::PipelineLayout,
+
+    input: RenderTarget,
+    /// Two targets the effects ping-pong between and two for passes within an effect.
+    targets: [Option<RenderTarget>; 4],
+    /// Bloom mip chain, each level half the size of the previous one.
+    bloom_levels: Vec<RenderTarget>,
+
+    modules: HashMap<&'static str, wgpu::ShaderModule>,
+    /// `None` for shaders that failed to compile, which are drawn as a copy.
+    custom_shaders: HashMap<PathBuf, Option<ShaderAsset>>,
+    pipelines: HashMap<PipelineKey, wgpu::RenderPipeline>,
+
+    params: Vec<u8>,
+    params_buffer: wgpu::Buffer,
+}
+
+const COPY: &str = "wgpu_app/post/copy.wgsl";
+const BLUR: &str = "wgpu_app/post/blur.wgsl";
+const BLOOM: &str = "wgpu_app/post/bloom.wgsl";
+const TONE_MAP: &str = "wgpu_app/post/tone_map.wgsl";
+const VIGNETTE: &str = "wgpu_app/post/vignette.wgsl";
+const CHROMATIC_ABERRATION: &str = "wgpu_app/post/chromatic_aberration.wgsl";
+const FXAA: &str = "wgpu_app/post/fxaa.wgsl";
+
+const BUILTIN_SHADERS: &[(&str, &str)] = &[
+    (COPY, include_str!("shaders/post/copy.wgsl")),
+    (BLUR, include_str!("shaders/post/blur.wgsl")),
+    (BLOOM, include_str!("shaders/post/bloom.wgsl")),
+    (TONE_MAP, include_str!("shaders/post/tone_map.wgsl")),
+    (VIGNETTE, include_str!("shaders/post/vignette.wgsl")),
+    (
+        CHROMATIC_ABERRATION,
+        include_str!("shaders/post/chromatic_aberration.wgsl"),
+    ),
+    (FXAA, include_str!("shaders/post/fxaa.wgsl")),
+];
+
+/// Uniform bindings are at least this large, so parameter structs are padded to it.
+const MIN_PARAMS_SIZE: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum ShaderId {
+    Builtin(&'static str),
+    Custom(PathBuf),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct PipelineKey {
+    shader: ShaderId,
+    entry: &'static str,
+    format: wgpu::TextureFormat,
+    additive: bool,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum View {
+    Input,
+    Target(usize),
+    Bloom(usize),
+    Output,
+}
+
+struct PassDesc {
+    shader: ShaderId,
+    entry: &'static str,
+    source: View,
+    /// The effect input, bound as `original`.
+    original: View,
+    target: View,
+    /// Added to the target instead of replacing it.
+    additive: bool,
+}
+
+struct Pass {
+    pipeline: PipelineKey,
+    source: View,
+    original: View,
+    target: View,
+    params: Range<u64>,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+struct BlurParams {
+    direction: [f32; 2],
+    radius: f32,
+    offset: f32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+struct BloomParams {
+    threshold: f32,
+    knee: f32,
+    intensity: f32,
+    _padding: f32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+struct ToneMapParams {
+    exposure: f32,
+    tone_operator: u32,
+    _padding: [f32; 2],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+struct VignetteParams {
+    intensity: f32,
+    radius: f32,
+    smoothness: f32,
+    _padding: f32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+struct ChromaticAberrationParams {
+    intensity: f32,
+    _padding: [f32; 3],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+struct FxaaParams {
+    edge_threshold: f32,
+    edge_threshold_min: f32,
+    subpixel: f32,
+    _padding: f32,
+}
+
+impl Default for Bloom {
+    fn default() -> Self {
+        Self {
+            threshold: 0.8,
+            knee: 0.2,
+            intensity: 0.5,
+            levels: 5,
+        }
+    }
+}
+
+impl Default for ToneMap {
+    fn default() -> Self {
+        Self {
+            operator: ToneMapOperator::default(),
+            exposure: 1.0,
+        }
+    }
+}
+
+impl Default for Vignette {
+    fn default() -> Self {
+        Self {
+            intensity: 0.4,
+            radius: 0.5,
+            smoothness: 0.5,
+        }
+    }
+}
+
+impl Default for ChromaticAberration {
+    fn default() -> Self {
+        Self { intensity: 2.0 }
+    }
+}
+
+impl Default for Fxaa {
+    fn default() -> Self {
+        Self {
+            edge_threshold: 0.125,
+            edge_threshold_min: 0.0312,
+            subpixel: 0.75,
+        }
+    }
+}
+
+impl Default for PostProcessOptions {
+    fn default() -> Self {
+        Self {
+            input: RenderTargetOptions::default(),
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+        }
+    }
+}
+
+impl PostProcessOptions {
+    /// `Rgba16Float` input and intermediate targets, which keep colors above 1 until a
+    /// `ToneMap` effect maps them to the output.
+    pub fn hdr() -> Self {
+        let format = wgpu::TextureFormat::Rgba16Float;
+        Self {
+            input: RenderTargetOptions {
+                color_format: Some(format),
+                ..Default::default()
+            },
+            format,
+        }
+    }
+}
+
+impl CustomEffect {
+    pub fn new(source: ShaderSource) -> Self {
+        Self {
+            source,
+            params: vec![],
+        }
+    }
+
+    pub fn with_params<T: Pod>(mut self, params: &T) -> Self {
+        self.set_params(params);
+        self
+    }
+
+    /// `params` must be laid out like the WGSL uniform struct.
+    pub fn set_params<T: Pod>(&mut self, params: &T) {
+        self.params = bytemuck::bytes_of(params).to_vec();
+    }
+}
+
+impl PassDesc {
+    fn new(shader: ShaderId, entry: &'static str, source: View, target: View) -> Self {
+        Self {
+            shader,
+            entry,
+            source,
+            original: source,
+            target,
+            additive: false,
+        }
+    }
+}
+
+impl PostProcess {
+    pub fn new(
+        device: &wgpu::Device,
+        output_format: wgpu::TextureFormat,
+        window_size: UVec2,
+        options: PostProcessOptions,
+    ) -> Self {
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            label: Some("Post Process"),
+            ..Default::default()
+        });
+
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                texture_entry(1),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                texture_entry(3),
+            ],
+            label: Some("Post Process"),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            bind_group_layouts: &[&bind_group_layout],
+            immediate_size: 0,
+            label: Some("Post Process"),
+        });
+
+        Self {
+            effects: vec![],
+            output_format,
+            format: options.format,
+            quad: FullscreenQuad::new(device),
+            sampler,
+            bind_group_layout,
+            pipeline_layout,
+            input: RenderTarget::new(device, window_size, options.input),
+            targets: Default::default(),
+            bloom_levels: vec![],
+            modules: HashMap::new(),
+            custom_shaders: HashMap::new(),
+            pipelines: HashMap::new(),
+            params: vec![],
+            params_buffer: create_params_buffer(device, 1024),
+        }
+    }
+
+    /// Target to render the scene into.
+    pub fn input(&self) -> &RenderTarget {
+        &self.input
+    }
+
+    pub fn output_format(&self) -> wgpu::TextureFormat {
+        self.output_format
+    }
+
+    /// Colors above 1 are clipped in SDR outputs unless a `ToneMap` effect maps them first.
+    pub fn output_color_space(&self) -> ColorSpace {
+        ColorSpace::of(self.output_format)
+    }
+
+    /// Resizes the input and intermediate targets, see `RenderTarget::resize`.
+    pub fn resize(&mut self, device: &wgpu::Device, window_size: UVec2) {
+        for target in self.render_targets() {
+            target.resize(device, window_size);
+        }
+    }
+
+    /// The input and intermediate targets, for `WgpuApp::render_targets`. Targets created
+    /// later take the window size of the input.
+    pub fn render_targets(&mut self) -> impl Iterator<Item = &mut RenderTarget> {
+        std::iter::once(&mut self.input)
+            .chain(self.targets.iter_mut().flatten())
+            .chain(self.bloom_levels.iter_mut())
+    }
+
+    /// Records the effects into `encoder`, reading the input and writing `output`, which has
+    /// the output format. Without effects the input is copied.
+    pub fn render(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        output: &wgpu::TextureView,
+    ) {
+        let _span = tracing::info_span!("post_process").entered();
+
+        self.reload_custom_shaders(device);
+        let passes = self.plan(device);
+
+        if self.params_buffer.size() < self.params.len() as u64 {
+            self.params_buffer =
+                create_params_buffer(device, self.params.len().next_power_of_two() as u64);
+        }
+        queue.write_buffer(&self.params_buffer, 0, &self.params);
+
+        for pass in passes.iter() {
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &self.bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::Sampler(&self.sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(
+                            self.view(pass.source, output),
+                        ),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                            buffer: &self.params_buffer,
+                            offset: pass.params.start,
+                            size: wgpu::BufferSize::new(pass.params.end - pass.params.start),
+                        }),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: wgpu::BindingResource::TextureView(
+                            self.view(pass.original, output),
+                        ),
+                    },
+                ],
+                label: None,
+            });
+
+            let load = if pass.pipeline.additive {
+                wgpu::LoadOp::Load
+            } else {
+                wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT)
+            };
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some(pass.pipeline.entry),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: self.view(pass.target, output),
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                    depth_slice: None,
+                })],
+                ..Default::default()
+            });
+
+            render_pass.set_pipeline(&self.pipelines[&pass.pipeline]);
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            self.quad.draw(&mut render_pass);
+        }
+    }
+
+    /// Turns the effects into passes, creating the pipelines and targets they need and
+    /// collecting their parameters.
+    fn plan(&mut self, device: &wgpu::Device) -> Vec<Pass> {
+        self.params.clear();
+        let effects = std::mem::take(&mut self.effects);
+        let mut passes = vec![];
+
+        if effects.is_empty() {
+            let desc = PassDesc::new(
+                ShaderId::Builtin(COPY),
+                "fs_main",
+                View::Input,
+                View::Output,
+            );
+            self.add_pass(device, &mut passes, desc, &[]);
+        }
+
+        let mut source = View::Input;
+        for (index, effect) in effects.iter().enumerate() {
+            let target = if index + 1 == effects.len() {
+                View::Output
+            } else if source == View::Target(0) {
+                View::Target(1)
+            } else {
+                View::Target(0)
+            };
+            self.plan_effect(device, &mut passes, effect, source, target);
+            source = target;
+        }
+
+        self.effects = effects;
+        passes
+    }
+
+    fn plan_effect(
+        &mut self,
+        device: &wgpu::Device,
+        passes: &mut Vec<Pass>,
+        effect: &PostEffect,
+        source: View,
+        target: View,
+    ) {
+        let single_pass =
+            |shader| PassDesc::new(ShaderId::Builtin(shader), "fs_main", source, target);
+
+        match effect {
+            PostEffect::Blur(Blur::Gaussian { radius }) => {
+                let scratch = View::Target(2);
+                let horizontal = BlurParams {
+                    direction: [1.0, 0.0],
+                    radius: *radius,
+                    offset: 0.0,
+                };
+                let vertical = BlurParams {
+                    direction: [0.0, 1.0],
+                    ..horizontal
+                };
+                let shader = ShaderId::Builtin(BLUR);
+                let desc = PassDesc::new(shader.clone(), "fs_gaussian", source, scratch);
+                self.add_pass(device, passes, desc, bytemuck::bytes_of(&horizontal));
+                let desc = PassDesc {
+                    original: source,
+                    ..PassDesc::new(shader, "fs_gaussian", scratch, target)
+                };
+                self.add_pass(device, passes, desc, bytemuck::bytes_of(&vertical));
+            }
+            PostEffect::Blur(Blur::Kawase { iterations }) => {
+                let iterations = (*iterations).max(1);
+                let mut current = source;
+                for iteration in 0..iterations {
+                    let next = if iteration + 1 == iterations {
+                        target
+                    } else {
+                        View::Target(2 + iteration as usize % 2)
+                    };
+                    let params = BlurParams {
+                        direction: [0.0, 0.0],
+                        radius: 0.0,
+                        offset: iteration as f32 + 0.5,
+                    };
+                    let desc = PassDesc {
+                        original: source,
+                        ..PassDesc::new(ShaderId::Builtin(BLUR), "fs_kawase", current, next)
+                    };
+                    self.add_pass(device, passes, desc, bytemuck::bytes_of(&params));
+                    current = next;
+                }
+            }
+            PostEffect::Bloom(bloom) => {
+                let levels = bloom.levels.max(1) as usize;
+                let params = BloomParams {
+                    threshold: bloom.threshold,
+                    knee: bloom.knee,
+                    intensity: bloom.intensity,
+                    _padding: 0.0,
+                };
+                let params = bytemuck::bytes_of(&params);
+                let shader = ShaderId::Builtin(BLOOM);
+                let level_pass = |entry, level_source, level_target| PassDesc {
+                    original: source,
+                    ..PassDesc::new(shader.clone(), entry, level_source, level_target)
+                };
+
+                let desc = level_pass("fs_prefilter", source, View::Bloom(0));
+                self.add_pass(device, passes, desc, params);
+                for level in 1..levels {
+                    let desc =
+                        level_pass("fs_downsample", View::Bloom(level - 1), View::Bloom(level));
+                    self.add_pass(device, passes, desc, params);
+                }
+                for level in (0..levels - 1).rev() {
+                    let desc = PassDesc {
+                        additive: true,
+                        ..level_pass("fs_upsample", View::Bloom(level + 1), View::Bloom(level))
+                    };
+                    self.add_pass(device, passes, desc, params);
+                }
+                let desc = level_pass("fs_composite", View::Bloom(0), target);
+                self.add_pass(device, passes, desc, params);
+            }
+            PostEffect::ToneMap(tone_map) => {
+                let params = ToneMapParams {
+                    exposure: tone_map.exposure,
+                    tone_operator: match tone_map.operator {
+                        ToneMapOperator::Reinhard => 0,
+                        ToneMapOperator::Aces => 1,
+                        ToneMapOperator::AgX => 2,
+                    },
+                    _padding: [0.0; 2],
+                };
+                self.add_pass(
+                    device,
+                    passes,
+                    single_pass(TONE_MAP),
+                    bytemuck::bytes_of(&params),
+                );
+            }
+            PostEffect::Vignette(vignette) => {
+                let params = VignetteParams {
+                    intensity: vignette.intensity,
+                    radius: vignette.radius,
+                    smoothness: vignette.smoothness,
+                    _padding: 0.0,
+                };
+                self.add_pass(
+                    device,
+                    passes,
+                    single_pass(VIGNETTE),
+                    bytemuck::bytes_of(&params),
+                );
+            }
+            PostEffect::ChromaticAberration(chromatic_aberration) => {
+                let params = ChromaticAberrationParams {
+                    intensity: chromatic_aberration.intensity,
+                    _padding: [0.0; 3],
+                };
+                self.add_pass(
+                    device,
+                    passes,
+                    single_pass(CHROMATIC_ABERRATION),
+                    bytemuck::bytes_of(&params),
+                );
+            }
+            PostEffect::Fxaa(fxaa) => {
+                let params = FxaaParams {
+                    edge_threshold: fxaa.edge_threshold,
+                    edge_threshold_min: fxaa.edge_threshold_min,
+                    subpixel: fxaa.subpixel,
+                    _padding: 0.0,
+                };
+                self.add_pass(
+                    device,
+                    passes,
+                    single_pass(FXAA),
+                    bytemuck::bytes_of(&params),
+                );
+            }
+            PostEffect::Custom(custom) => {
+                let compiled = self
+                    .custom_shaders
+                    .entry(custom.source.path.clone())
+                    .or_insert_with(|| {
+                        ShaderAsset::new(device, custom.source.clone())
+                            .map_err(|err| tracing::error!("{}", err))
+                            .ok()
+                    })
+                    .is_some();
+                let desc = if compiled {
+                    let shader = ShaderId::Custom(custom.source.path.clone());
+                    PassDesc::new(shader, "fs_main", source, target)
+                } else {
+                    single_pass(COPY)
+                };
+                self.add_pass(device, passes, desc, &custom.params);
+            }
+        }
+    }
+
+    fn add_pass(
+        &mut self,
+        device: &wgpu::Device,
+        passes: &mut Vec<Pass>,
+        desc: PassDesc,
+        params: &[u8],
+    ) {
+        for view in [desc.source, desc.original, desc.target] {
+            self.create_view(device, view);
+        }
+
+        let alignment = device.limits().min_uniform_buffer_offset_alignment as usize;
+        let start = self.params.len().next_multiple_of(alignment);
+        let size = params
+            .len()
+            .max(MIN_PARAMS_SIZE)
+            .next_multiple_of(MIN_PARAMS_SIZE);
+        self.params.resize(start, 0);
+        self.params.extend_from_slice(params);
+        self.params.resize(start + size, 0);
+
+        let format = if desc.target == View::Output {
+            self.output_format
+        } else {
+            self.format
+        };
+        let pipeline = PipelineKey {
+            shader: desc.shader,
+            entry: desc.entry,
+            format,
+            additive: desc.additive,
+        };
+        self.create_pipeline(device, &pipeline);
+
+        passes.push(Pass {
+            pipeline,
+            source: desc.source,
+            original: desc.original,
+            target: desc.target,
+            params: start as u64..(start + size) as u64,
+        });
+    }
+
+    fn create_view(&mut self, device: &wgpu::Device, view: View) {
+        let window_size = self.input.window_size();
+        let options = |scale| RenderTargetOptions {
+            color_format: Some(self.format),
+            depth_format: None,
+            sample_count: 1,
+            scale,
+            usage: wgpu::TextureUsages::empty(),
+        };
+
+        match view {
+            View::Target(index) if self.targets[index].is_none() => {
+                let target = RenderTarget::new(device, window_size, options(1.0));
+                self.targets[index] = Some(target);
+            }
+            View::Bloom(level) => {
+                while self.bloom_levels.len() <= level {
+                    let scale = 0.5f32.powi(self.bloom_levels.len() as i32 + 1);
+                    let target = RenderTarget::new(device, window_size, options(scale));
+                    self.bloom_levels.push(target);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn create_pipeline(&mut self, device: &wgpu::Device, key: &PipelineKey) {
+        if self.pipelines.contains_key(key) {
+            return;
+        }
+
+        let module = match &key.shader {
+            ShaderId::Builtin(name) => self.modules.entry(*name).or_insert_with(|| {
+                let (_, source) = BUILTIN_SHADERS
+                    .iter()
+                    .find(|(builtin, _)| builtin == name)
+                    .unwrap();
+                shader::compile_builtin(device, name, source)
+            }),
+            ShaderId::Custom(path) => self.custom_shaders[path].as_ref().unwrap().module(),
+        };
+        let additive = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        };
+        let target = wgpu::ColorTargetState {
+            format: key.format,
+            blend: key.additive.then_some(wgpu::BlendState {
+                color: additive,
+                alpha: additive,
+            }),
+            write_mask: wgpu::ColorWrites::ALL,
+        };
+
+        let pipeline =
+            self.quad
+                .create_pipeline(device, &self.pipeline_layout, module, key.entry, target);
+        self.pipelines.insert(key.clone(), pipeline);
+    }
+
+    /// Drops the pipelines of custom shaders whose files changed and compiled.
+    fn reload_custom_shaders(&mut self, device: &wgpu::Device) {
+        for (path, shader) in self.custom_shaders.iter_mut() {
+            let Some(shader) = shader else {
+                continue;
+            };
+            if shader.poll(device, |_| ()).is_some() {
+                self.pipelines
+                    .retain(|key, _| key.shader != ShaderId::Custom(path.clone()));
+            }
+        }
+    }
+
+    fn view<'a>(&'a self, view: View, output: &'a wgpu::TextureView) -> &'a wgpu::TextureView {
+        match view {
+            View::Input => self
+                .input
+                .sampled_view()
+                .expect("Post-processing input needs a color format"),
+            View::Target(index) => self.targets[index]
+                .as_ref()
+                .unwrap()
+                .sampled_view()
+                .unwrap(),
+            View::Bloom(level) => self.bloom_levels[level].sampled_view().unwrap(),
+            View::Output => output,
+        }
+    }
+}
+
+fn create_params_buffer(device: &wgpu::Device, size: u64) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        size,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+        label: Some("Post Process Params"),
+    })
+}
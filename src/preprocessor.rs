@@ -0,0 +1,459 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::shader::ShaderError;
+
+/// Shared WGSL snippets shipped with the library, available to `#include`.
+const BUILTIN_MODULES: &[(&str, &str)] = &[
+    ("wgpu_app/common.wgsl", include_str!("shaders/common.wgsl")),
+    (
+        "wgpu_app/fullscreen.wgsl",
+        include_str!("shaders/fullscreen.wgsl"),
+    ),
+    (
+        "wgpu_app/post_process.wgsl",
+        include_str!("shaders/post_process.wgsl"),
+    ),
+];
+
+/// Expands `#include "file"`, `#define NAME [value]`, `#undef NAME`, `#ifdef NAME`,
+/// `#ifndef NAME`, `#else` and `#endif` in WGSL sources. Defines with a value are substituted
+/// wherever their name appears as a whole identifier. Every file is included at most once.
+#[derive(Debug, Clone)]
+pub struct ShaderComposer {
+    defines: HashMap<String, String>,
+    modules: HashMap<String, Cow<'static, str>>,
+}
+
+/// Preprocessed WGSL together with the original location of every line.
+#[derive(Debug, Clone)]
+pub struct ComposedShader {
+    pub code: String,
+    /// Files read from disk, to be watched for changes.
+    pub files: Vec<PathBuf>,
+
+    sources: Vec<PathBuf>,
+    line_map: Vec<(usize, u32)>,
+}
+
+struct Conditional {
+    active: bool,
+    parent_active: bool,
+    has_else: bool,
+    line: u32,
+}
+
+struct ComposeState {
+    defines: HashMap<String, String>,
+    lines: Vec<String>,
+    composed: ComposedShader,
+    include_stack: Vec<PathBuf>,
+}
+
+impl Default for ShaderComposer {
+    fn default() -> Self {
+        Self {
+            defines: HashMap::new(),
+            modules: BUILTIN_MODULES
+                .iter()
+                .map(|(name, source)| (name.to_string(), Cow::Borrowed(*source)))
+                .collect(),
+        }
+    }
+}
+
+impl ShaderComposer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Defines `name` for `#ifdef`. A non-empty `value` also replaces the name in the code.
+    pub fn define(mut self, name: &str, value: impl ToString) -> Self {
+        self.defines.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    pub fn undefine(mut self, name: &str) -> Self {
+        self.defines.remove(name);
+        self
+    }
+
+    /// Registers source that `#include "name"` resolves to before looking on disk.
+    pub fn add_module(mut self, name: &str, source: impl Into<Cow<'static, str>>) -> Self {
+        self.modules.insert(name.to_string(), source.into());
+        self
+    }
+
+    pub fn compose(&self, path: &Path, source: &str) -> Result<ComposedShader, ShaderError> {
+        let mut state = ComposeState {
+            defines: self.defines.clone(),
+            lines: vec![],
+            composed: ComposedShader {
+                code: String::new(),
+                files: vec![path.to_path_buf()],
+                sources: vec![],
+                line_map: vec![],
+            },
+            include_stack: vec![],
+        };
+
+        self.compose_source(path, source, &mut state)?;
+
+        let mut composed = state.composed;
+        composed.code = state.lines.join("\n");
+        Ok(composed)
+    }
+
+    fn compose_source(
+        &self,
+        path: &Path,
+        source: &str,
+        state: &mut ComposeState,
+    ) -> Result<(), ShaderError> {
+        state.composed.sources.push(path.to_path_buf());
+        state.include_stack.push(path.to_path_buf());
+        let source_index = state.composed.sources.len() - 1;
+
+        let mut conditionals: Vec<Conditional> = vec![];
+
+        for (index, line) in source.lines().enumerate() {
+            let line_number = index as u32 + 1;
+            let error = |message: String| ShaderError {
+                path: path.to_path_buf(),
+                messages: vec![format!("{}:{}: {}", path.display(), line_number, message)],
+            };
+            let active = conditionals.last().is_none_or(|c| c.active);
+
+            let Some(directive) = line.trim_start().strip_prefix('#') else {
+                if active {
+                    state.lines.push(substitute(line, &state.defines));
+                    state.composed.line_map.push((source_index, line_number));
+                }
+                continue;
+            };
+
+            let mut parts = directive.trim().splitn(2, char::is_whitespace);
+            let keyword = parts.next().unwrap_or_default();
+            let argument = parts.next().unwrap_or_default().trim();
+
+            match keyword {
+                "ifdef" | "ifndef" => {
+                    let defined = state.defines.contains_key(argument);
+                    conditionals.push(Conditional {
+                        active: active && (defined == (keyword == "ifdef")),
+                        parent_active: active,
+                        has_else: false,
+                        line: line_number,
+                    });
+                }
+                "else" => {
+                    let conditional = conditionals
+                        .last_mut()
+                        .filter(|c| !c.has_else)
+                        .ok_or_else(|| error("Unexpected #else".to_string()))?;
+                    conditional.has_else = true;
+                    conditional.active = conditional.parent_active && !conditional.active;
+                }
+                "endif" => {
+                    conditionals
+                        .pop()
+                        .ok_or_else(|| error("#endif without #ifdef".to_string()))?;
+                }
+                _ if !active => {}
+                "define" => {
+                    let mut parts = argument.splitn(2, char::is_whitespace);
+                    let name = parts.next().unwrap_or_default();
+                    if name.is_empty() {
+                        return Err(error("#define without a name".to_string()));
+                    }
+                    let value = parts.next().unwrap_or_default().trim();
+                    state.defines.insert(name.to_string(), value.to_string());
+                }
+                "undef" => {
+                    state.defines.remove(argument);
+                }
+                "include" => {
+                    let name = argument
+                        .strip_prefix('"')
+                        .and_then(|name| name.strip_suffix('"'))
+                        .ok_or_else(|| error(format!("Malformed #include {}", argument)))?;
+                    self.include(path, name, state).map_err(|err| {
+                        let mut messages = vec![format!(
+                            "{}:{}: in file included from here",
+                            path.display(),
+                            line_number
+                        )];
+                        messages.extend(err.messages);
+                        ShaderError {
+                            path: err.path,
+                            messages,
+                        }
+                    })?;
+                }
+                _ => return Err(error(format!("Unknown directive #{}", keyword))),
+            }
+        }
+
+        if let Some(conditional) = conditionals.last() {
+            return Err(ShaderError {
+                path: path.to_path_buf(),
+                messages: vec![format!(
+                    "{}:{}: #ifdef without #endif",
+                    path.display(),
+                    conditional.line
+                )],
+            });
+        }
+
+        state.include_stack.pop();
+        Ok(())
+    }
+
+    fn include(
+        &self,
+        parent: &Path,
+        name: &str,
+        state: &mut ComposeState,
+    ) -> Result<(), ShaderError> {
+        let module = self.modules.get(name);
+        let path = match module {
+            Some(_) => PathBuf::from(name),
+            None => parent.parent().unwrap_or(Path::new("")).join(name),
+        };
+
+        if state.include_stack.contains(&path) {
+            return Err(ShaderError {
+                path: path.clone(),
+                messages: vec![format!("{}: recursive #include", path.display())],
+            });
+        }
+        if state.composed.sources.contains(&path) {
+            return Ok(());
+        }
+
+        let source = match module {
+            Some(source) => source.to_string(),
+            None => {
+                let source = std::fs::read_to_string(&path).map_err(|err| ShaderError {
+                    path: path.clone(),
+                    messages: vec![format!("{}: {}", path.display(), err)],
+                })?;
+                state.composed.files.push(path.clone());
+                source
+            }
+        };
+
+        self.compose_source(&path, &source, state)
+    }
+}
+
+impl ComposedShader {
+    /// Maps a 1-based line of `code` back to its file and line.
+    pub fn source_location(&self, line: u32) -> Option<(&Path, u32)> {
+        let (source_index, source_line) = self.line_map.get(line.checked_sub(1)? as usize)?;
+        Some((self.sources[*source_index].as_path(), *source_line))
+    }
+}
+
+fn substitute(line: &str, defines: &HashMap<String, String>) -> String {
+    if defines.values().all(|value| value.is_empty()) {
+        return line.to_string();
+    }
+
+    let mut result = String::with_capacity(line.len());
+    let mut rest = line;
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    while let Some(start) = rest.find(is_word) {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest.find(|c: char| !is_word(c)).unwrap_or(rest.len());
+        let word = &rest[..end];
+
+        // Numeric literals like `4u` are not identifiers.
+        let is_number = word.starts_with(|c: char| c.is_ascii_digit());
+        match defines.get(word) {
+            Some(value) if !is_number && !value.is_empty() => result.push_str(value),
+            _ => result.push_str(word),
+        }
+        rest = &rest[end..];
+    }
+    result.push_str(rest);
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn composer() -> ShaderComposer {
+        ShaderComposer::new()
+            .add_module("a.wgsl", "#include \"c.wgsl\"\nconst A: u32 = C;")
+            .add_module("b.wgsl", "#include \"c.wgsl\"\nconst B: u32 = C;")
+            .add_module("c.wgsl", "const C: u32 = 1u;")
+    }
+
+    #[test]
+    fn nested_includes_are_composed_once() {
+        let source = "#include \"a.wgsl\"\n#include \"b.wgsl\"\nconst MAIN: u32 = A + B;";
+        let composed = composer().compose(Path::new("main.wgsl"), source).unwrap();
+
+        assert_eq!(
+            composed.code,
+            "const C: u32 = 1u;\nconst A: u32 = C;\nconst B: u32 = C;\nconst MAIN: u32 = A + B;"
+        );
+        assert_eq!(composed.files, vec![PathBuf::from("main.wgsl")]);
+    }
+
+    #[test]
+    fn diamond_includes_from_disk_are_watched_once() {
+        let dir =
+            std::env::temp_dir().join(format!("wgpu_app_preprocessor_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.wgsl"), "#include \"c.wgsl\"").unwrap();
+        std::fs::write(dir.join("b.wgsl"), "#include \"c.wgsl\"").unwrap();
+        std::fs::write(dir.join("c.wgsl"), "const C: u32 = 1u;").unwrap();
+
+        let main = dir.join("main.wgsl");
+        let composed = ShaderComposer::new()
+            .compose(&main, "#include \"a.wgsl\"\n#include \"b.wgsl\"")
+            .unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(composed.code, "const C: u32 = 1u;");
+        assert_eq!(
+            composed.files,
+            vec![
+                main,
+                dir.join("a.wgsl"),
+                dir.join("c.wgsl"),
+                dir.join("b.wgsl")
+            ]
+        );
+    }
+
+    #[test]
+    fn include_guards() {
+        let guarded = "#ifndef GUARD\n#define GUARD\nconst G: u32 = 1u;\n#endif";
+        let composed = ShaderComposer::new()
+            .add_module("first.wgsl", guarded)
+            .add_module("second.wgsl", guarded)
+            .compose(
+                Path::new("main.wgsl"),
+                "#include \"first.wgsl\"\n#include \"second.wgsl\"",
+            )
+            .unwrap();
+
+        assert_eq!(composed.code, "const G: u32 = 1u;");
+    }
+
+    #[test]
+    fn conditionals_and_defines() {
+        let source = "#ifdef FAST\nconst MODE = FAST;\n#else\nconst MODE = 0;\n#endif\n\
+                      #ifndef FAST\nconst SLOW = 1;\n#endif\n#define N 4u\nconst X = N + N4 + 4u;";
+        let composed = ShaderComposer::new()
+            .define("FAST", "2")
+            .compose(Path::new("main.wgsl"), source)
+            .unwrap();
+        assert_eq!(composed.code, "const MODE = 2;\nconst X = 4u + N4 + 4u;");
+
+        let composed = ShaderComposer::new()
+            .compose(Path::new("main.wgsl"), source)
+            .unwrap();
+        assert_eq!(
+            composed.code,
+            "const MODE = 0;\nconst SLOW = 1;\nconst X = 4u + N4 + 4u;"
+        );
+    }
+
+    #[test]
+    fn source_locations() {
+        let source =
+            "// main\n#include \"a.wgsl\"\n#ifdef MISSING\nskipped\n#endif\nconst MAIN = 0;";
+        let composed = composer().compose(Path::new("main.wgsl"), source).unwrap();
+
+        let locations: Vec<_> = (0..=5).map(|line| composed.source_location(line)).collect();
+        assert_eq!(
+            locations,
+            vec![
+                None,
+                Some((Path::new("main.wgsl"), 1)),
+                Some((Path::new("c.wgsl"), 1)),
+                Some((Path::new("a.wgsl"), 2)),
+                Some((Path::new("main.wgsl"), 6)),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn compile_errors_map_to_the_included_file() {
+        let composed = ShaderComposer::new()
+            .add_module("broken.wgsl", "const OK: u32 = 1u;\nconst BROKEN: u32 = ;")
+            .compose(
+                Path::new("main.wgsl"),
+                "#include \"broken.wgsl\"\nconst MAIN: u32 = 0u;",
+            )
+            .unwrap();
+
+        let error = naga::front::wgsl::parse_str(&composed.code).unwrap_err();
+        let location = error.location(&composed.code).unwrap();
+        assert_eq!(
+            composed.source_location(location.line_number),
+            Some((Path::new("broken.wgsl"), 2))
+        );
+    }
+
+    #[test]
+    fn directive_errors_have_file_and_line() {
+        let messages = |source: &str| {
+            composer()
+                .add_module("bad.wgsl", "\n#bogus")
+                .compose(Path::new("main.wgsl"), source)
+                .unwrap_err()
+                .messages
+        };
+
+        assert_eq!(
+            messages("\n\n#endif"),
+            vec!["main.wgsl:3: #endif without #ifdef"]
+        );
+        assert_eq!(
+            messages("#ifdef A\n#else\n#else\n#endif"),
+            vec!["main.wgsl:3: Unexpected #else"]
+        );
+        assert_eq!(
+            messages("\n#ifdef A"),
+            vec!["main.wgsl:2: #ifdef without #endif"]
+        );
+        assert_eq!(
+            messages("#include a.wgsl"),
+            vec!["main.wgsl:1: Malformed #include a.wgsl"]
+        );
+        assert_eq!(
+            messages("// bad\n#include \"bad.wgsl\""),
+            vec![
+                "main.wgsl:2: in file included from here",
+                "bad.wgsl:2: Unknown directive #bogus"
+            ]
+        );
+    }
+
+    #[test]
+    fn recursive_includes_are_errors() {
+        let error = ShaderComposer::new()
+            .add_module("loop.wgsl", "#include \"loop.wgsl\"")
+            .compose(Path::new("main.wgsl"), "#include \"loop.wgsl\"")
+            .unwrap_err();
+
+        assert_eq!(
+            error.messages,
+            vec![
+                "main.wgsl:1: in file included from here",
+                "loop.wgsl:1: in file included from here",
+                "loop.wgsl: recursive #include"
+            ]
+        );
+    }
+}
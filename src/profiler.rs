@@ -0,0 +1,526 @@
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+const MAX_SCOPES_PER_FRAME: u32 = 64;
+const READBACK_BUFFER_COUNT: usize = 4;
+const MAX_HISTORY: usize = 600;
+const TIMESTAMP_SIZE: wgpu::BufferAddress = std::mem::size_of::<u64>() as wgpu::BufferAddress;
+
+const MAP_PENDING: u8 = 0;
+const MAP_DONE: u8 = 1;
+const MAP_FAILED: u8 = 2;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ScopeId(usize);
+
+#[derive(Debug, Clone)]
+pub struct ScopeTiming {
+    pub name: String,
+    /// Seconds since the profiler was created.
+    pub cpu: Range<f64>,
+    /// Seconds since the frame started, measured on the GPU. `None` without timestamp queries.
+    pub gpu: Option<Range<f64>>,
+    pub children: Vec<ScopeTiming>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ProfilerFrame {
+    pub index: u64,
+    /// Seconds since the profiler was created.
+    pub cpu: Range<f64>,
+    pub scopes: Vec<ScopeTiming>,
+}
+
+/// Durations in seconds over the frames kept in the profiler history.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScopeStatistics {
+    /// Scope names from the root, joined by `/`.
+    pub path: String,
+    pub count: usize,
+    pub cpu_average: f64,
+    pub cpu_max: f64,
+    pub gpu_average: Option<f64>,
+    pub gpu_max: Option<f64>,
+}
+
+struct RecordedScope {
+    name: String,
+    parent: Option<usize>,
+    cpu: Range<f64>,
+    query: Option<u32>,
+}
+
+struct PendingFrame {
+    index: u64,
+    cpu: Range<f64>,
+    scopes: Vec<RecordedScope>,
+    readback: Option<usize>,
+}
+
+struct Readback {
+    buffer: wgpu::Buffer,
+    mapped_size: wgpu::BufferAddress,
+    map_state: Arc<AtomicU8>,
+    in_use: bool,
+}
+
+struct TimestampQueries {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    readbacks: Vec<Readback>,
+    timestamp_period: f64,
+    inside_encoders: bool,
+}
+
+/// Times named scopes around passes with timestamp queries, falling back to CPU timing
+/// when the device lacks `Features::TIMESTAMP_QUERY`. GPU results arrive a few frames later.
+pub struct GpuProfiler {
+    start_time: Instant,
+    queries: Option<TimestampQueries>,
+
+    frame_index: u64,
+    frame_start: f64,
+    scopes: Vec<RecordedScope>,
+    open_scopes: Vec<usize>,
+    next_query: u32,
+    resolved_readback: Option<usize>,
+
+    pending: VecDeque<PendingFrame>,
+    history: VecDeque<ProfilerFrame>,
+}
+
+impl GpuProfiler {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let features = device.features();
+        let queries = features
+            .contains(wgpu::Features::TIMESTAMP_QUERY)
+            .then(|| TimestampQueries::new(device, queue, features));
+
+        Self {
+            start_time: Instant::now(),
+            queries,
+            frame_index: 0,
+            frame_start: 0.0,
+            scopes: vec![],
+            open_scopes: vec![],
+            next_query: 0,
+            resolved_readback: None,
+            pending: VecDeque::new(),
+            history: VecDeque::new(),
+        }
+    }
+
+    pub fn has_gpu_timestamps(&self) -> bool {
+        self.queries.is_some()
+    }
+
+    /// Opens a scope nested in the currently open one. With an encoder, the GPU time is taken
+    /// around everything recorded until `end_scope`, which needs
+    /// `Features::TIMESTAMP_QUERY_INSIDE_ENCODERS`. Without one, pass the writes from
+    /// `render_pass_timestamp_writes` or `compute_pass_timestamp_writes` to a single pass.
+    pub fn begin_scope(
+        &mut self,
+        name: &str,
+        encoder: Option<&mut wgpu::CommandEncoder>,
+    ) -> ScopeId {
+        let mut query = None;
+        if let Some(queries) = &self.queries {
+            let can_write = encoder.is_none() || queries.inside_encoders;
+            if can_write && self.next_query + 2 <= MAX_SCOPES_PER_FRAME * 2 {
+                query = Some(self.next_query);
+                self.next_query += 2;
+            }
+        }
+
+        if let (Some(queries), Some(query), Some(encoder)) = (&self.queries, query, encoder) {
+            encoder.write_timestamp(&queries.query_set, query);
+        }
+
+        let now = self.now();
+        self.scopes.push(RecordedScope {
+            name: name.to_string(),
+            parent: self.open_scopes.last().copied(),
+            cpu: now..now,
+            query,
+        });
+        self.open_scopes.push(self.scopes.len() - 1);
+
+        ScopeId(self.scopes.len() - 1)
+    }
+
+    pub fn end_scope(&mut self, scope: ScopeId, encoder: Option<&mut wgpu::CommandEncoder>) {
+        assert_eq!(
+            self.open_scopes.last(),
+            Some(&scope.0),
+            "Profiler scopes must be closed in reverse order."
+        );
+        self.open_scopes.pop();
+
+        let now = self.now();
+        let recorded = &mut self.scopes[scope.0];
+        recorded.cpu.end = now;
+
+        if let (Some(queries), Some(query), Some(encoder)) =
+            (&self.queries, recorded.query, encoder)
+        {
+            encoder.write_timestamp(&queries.query_set, query + 1);
+        }
+    }
+
+    pub fn render_pass_timestamp_writes(
+        &self,
+        scope: ScopeId,
+    ) -> Option<wgpu::RenderPassTimestampWrites<'_>> {
+        let queries = self.queries.as_ref()?;
+        let query = self.scopes[scope.0].query?;
+
+        Some(wgpu::RenderPassTimestampWrites {
+            query_set: &queries.query_set,
+            beginning_of_pass_write_index: Some(query),
+            end_of_pass_write_index: Some(query + 1),
+        })
+    }
+
+    pub fn compute_pass_timestamp_writes(
+        &self,
+        scope: ScopeId,
+    ) -> Option<wgpu::ComputePassTimestampWrites<'_>> {
+        let queries = self.queries.as_ref()?;
+        let query = self.scopes[scope.0].query?;
+
+        Some(wgpu::ComputePassTimestampWrites {
+            query_set: &queries.query_set,
+            beginning_of_pass_write_index: Some(query),
+            end_of_pass_write_index: Some(query + 1),
+        })
+    }
+
+    /// Records the copy of this frame's timestamps. Call once, in the last encoder of the frame.
+    pub fn resolve(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        let Some(queries) = self.queries.as_mut() else {
+            return;
+        };
+        if self.next_query == 0 || self.resolved_readback.is_some() {
+            return;
+        }
+        let Some(readback_index) = queries.readbacks.iter().position(|r| !r.in_use) else {
+            tracing::debug!("All profiler readback buffers are busy, dropping GPU timings");
+            return;
+        };
+
+        let size = self.next_query as wgpu::BufferAddress * TIMESTAMP_SIZE;
+        let readback = &mut queries.readbacks[readback_index];
+        encoder.resolve_query_set(
+            &queries.query_set,
+            0..self.next_query,
+            &queries.resolve_buffer,
+            0,
+        );
+        encoder.copy_buffer_to_buffer(&queries.resolve_buffer, 0, &readback.buffer, 0, size);
+        readback.in_use = true;
+
+        self.resolved_readback = Some(readback_index);
+    }
+
+    /// Closes the frame. Call after the frame's command buffers are submitted.
+    pub fn end_frame(&mut self, device: &wgpu::Device) {
+        assert!(
+            self.open_scopes.is_empty(),
+            "All profiler scopes must be closed before the frame ends."
+        );
+
+        let now = self.now();
+        let frame = PendingFrame {
+            index: self.frame_index,
+            cpu: self.frame_start..now,
+            scopes: std::mem::take(&mut self.scopes),
+            readback: self.resolved_readback.take(),
+        };
+
+        if let (Some(queries), Some(readback_index)) = (&mut self.queries, frame.readback) {
+            let readback = &mut queries.readbacks[readback_index];
+            readback.mapped_size = self.next_query as wgpu::BufferAddress * TIMESTAMP_SIZE;
+            let map_state = readback.map_state.clone();
+            map_state.store(MAP_PENDING, Ordering::Release);
+            readback.buffer.slice(0..readback.mapped_size).map_async(
+                wgpu::MapMode::Read,
+                move |result| {
+                    let state = if result.is_ok() { MAP_DONE } else { MAP_FAILED };
+                    map_state.store(state, Ordering::Release);
+                },
+            );
+        }
+        self.pending.push_back(frame);
+
+        self.frame_index += 1;
+        self.frame_start = now;
+        self.next_query = 0;
+
+        let _ = device.poll(wgpu::PollType::Poll);
+        self.collect_finished();
+    }
+
+    pub fn latest_frame(&self) -> Option<&ProfilerFrame> {
+        self.history.back()
+    }
+
+    pub fn frames(&self) -> impl Iterator<Item = &ProfilerFrame> {
+        self.history.iter()
+    }
+
+    pub fn statistics(&self) -> Vec<ScopeStatistics> {
+        let mut durations: Vec<(String, Vec<f64>, Vec<f64>)> = vec![];
+        for frame in self.history.iter() {
+            let mut flat = vec![];
+            flatten_scopes(&frame.scopes, "", &mut flat);
+
+            for (path, scope) in flat {
+                let index = match durations.iter().position(|(p, _, _)| *p == path) {
+                    Some(index) => index,
+                    None => {
+                        durations.push((path, vec![], vec![]));
+                        durations.len() - 1
+                    }
+                };
+                durations[index].1.push(scope.cpu.end - scope.cpu.start);
+                if let Some(gpu) = &scope.gpu {
+                    durations[index].2.push(gpu.end - gpu.start);
+                }
+            }
+        }
+
+        durations
+            .into_iter()
+            .map(|(path, cpu, gpu)| ScopeStatistics {
+                path,
+                count: cpu.len(),
+                cpu_average: average(&cpu).unwrap_or(0.0),
+                cpu_max: cpu.iter().copied().fold(0.0, f64::max),
+                gpu_average: average(&gpu),
+                gpu_max: (!gpu.is_empty()).then(|| gpu.iter().copied().fold(0.0, f64::max)),
+            })
+            .collect()
+    }
+
+    /// Writes the kept frames in the Chrome trace event format, viewable in
+    /// `chrome://tracing` or Perfetto. CPU scopes go to thread 0 and GPU scopes to thread 1.
+    pub fn write_chrome_trace<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        let mut events = vec![];
+        for frame in self.history.iter() {
+            events.push(trace_event(
+                &format!("frame {}", frame.index),
+                0,
+                frame.cpu.start,
+                frame.cpu.end - frame.cpu.start,
+            ));
+
+            let mut flat = vec![];
+            flatten_scopes(&frame.scopes, "", &mut flat);
+            for (_path, scope) in flat {
+                events.push(trace_event(
+                    &scope.name,
+                    0,
+                    scope.cpu.start,
+                    scope.cpu.end - scope.cpu.start,
+                ));
+                if let Some(gpu) = &scope.gpu {
+                    events.push(trace_event(
+                        &scope.name,
+                        1,
+                        frame.cpu.start + gpu.start,
+                        gpu.end - gpu.start,
+                    ));
+                }
+            }
+        }
+
+        let json = format!("{{\"traceEvents\":[\n{}\n]}}\n", events.join(",\n"));
+        std::fs::write(path, json)
+    }
+
+    fn now(&self) -> f64 {
+        self.start_time.elapsed().as_secs_f64()
+    }
+
+    fn collect_finished(&mut self) {
+        while let Some(frame) = self.pending.front() {
+            let timestamps = match (&mut self.queries, frame.readback) {
+                (Some(queries), Some(readback_index)) => {
+                    match queries.readbacks[readback_index].take_timestamps() {
+                        Some(timestamps) => Some((timestamps, queries.timestamp_period)),
+                        None => break,
+                    }
+                }
+                _ => None,
+            };
+
+            let frame = self.pending.pop_front().unwrap();
+            self.history.push_back(build_frame(frame, timestamps));
+            if self.history.len() > MAX_HISTORY {
+                self.history.pop_front();
+            }
+        }
+    }
+}
+
+impl TimestampQueries {
+    fn new(device: &wgpu::Device, queue: &wgpu::Queue, features: wgpu::Features) -> Self {
+        let query_count = MAX_SCOPES_PER_FRAME * 2;
+        let size = query_count as wgpu::BufferAddress * TIMESTAMP_SIZE;
+
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("Profiler Query Set"),
+            ty: wgpu::QueryType::Timestamp,
+            count: query_count,
+        });
+        let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Profiler Resolve Buffer"),
+            size,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readbacks = (0..READBACK_BUFFER_COUNT)
+            .map(|_| Readback {
+                buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Profiler Readback Buffer"),
+                    size,
+                    usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }),
+                mapped_size: 0,
+                map_state: Arc::new(AtomicU8::new(MAP_PENDING)),
+                in_use: false,
+            })
+            .collect();
+
+        Self {
+            query_set,
+            resolve_buffer,
+            readbacks,
+            timestamp_period: queue.get_timestamp_period() as f64,
+            inside_encoders: features.contains(wgpu::Features::TIMESTAMP_QUERY_INSIDE_ENCODERS),
+        }
+    }
+}
+
+impl Readback {
+    /// Returns `None` while the mapping is still in flight, an empty list if it failed.
+    fn take_timestamps(&mut self) -> Option<Vec<u64>> {
+        let timestamps = match self.map_state.load(Ordering::Acquire) {
+            MAP_PENDING => return None,
+            MAP_DONE => {
+                let timestamps = {
+                    let data = self.buffer.slice(0..self.mapped_size).get_mapped_range();
+                    bytemuck::cast_slice::<u8, u64>(&data).to_vec()
+                };
+                self.buffer.unmap();
+                timestamps
+            }
+            _ => vec![],
+        };
+        self.in_use = false;
+
+        Some(timestamps)
+    }
+}
+
+fn build_frame(frame: PendingFrame, timestamps: Option<(Vec<u64>, f64)>) -> ProfilerFrame {
+    let gpu_range = |query: Option<u32>| -> Option<Range<u64>> {
+        let (timestamps, _) = timestamps.as_ref()?;
+        let query = query? as usize;
+        let start = *timestamps.get(query)?;
+        let end = *timestamps.get(query + 1)?;
+        (start != 0 && end >= start).then_some(start..end)
+    };
+    let gpu_origin = frame
+        .scopes
+        .iter()
+        .filter_map(|scope| gpu_range(scope.query))
+        .map(|range| range.start)
+        .min();
+    let period = timestamps.as_ref().map_or(1.0, |(_, period)| *period);
+
+    let mut timings: Vec<Option<ScopeTiming>> = frame
+        .scopes
+        .iter()
+        .map(|scope| {
+            let gpu = gpu_range(scope.query)
+                .zip(gpu_origin)
+                .map(|(range, origin)| {
+                    let to_seconds =
+                        |ticks: u64| (ticks - origin) as f64 * period / 1_000_000_000.0;
+                    to_seconds(range.start)..to_seconds(range.end)
+                });
+
+            Some(ScopeTiming {
+                name: scope.name.clone(),
+                cpu: scope.cpu.clone(),
+                gpu,
+                children: vec![],
+            })
+        })
+        .collect();
+
+    // Children always come after their parent, so attaching in reverse builds the whole tree.
+    let mut roots = vec![];
+    for index in (0..frame.scopes.len()).rev() {
+        let timing = timings[index].take().unwrap();
+        match frame.scopes[index].parent {
+            Some(parent) => timings[parent].as_mut().unwrap().children.insert(0, timing),
+            None => roots.insert(0, timing),
+        }
+    }
+
+    ProfilerFrame {
+        index: frame.index,
+        cpu: frame.cpu,
+        scopes: roots,
+    }
+}
+
+fn flatten_scopes<'a>(
+    scopes: &'a [ScopeTiming],
+    prefix: &str,
+    result: &mut Vec<(String, &'a ScopeTiming)>,
+) {
+    for scope in scopes {
+        let path = if prefix.is_empty() {
+            scope.name.clone()
+        } else {
+            format!("{}/{}", prefix, scope.name)
+        };
+        result.push((path.clone(), scope));
+        flatten_scopes(&scope.children, &path, result);
+    }
+}
+
+fn average(values: &[f64]) -> Option<f64> {
+    (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+}
+
+fn trace_event(name: &str, thread: u32, start: f64, duration: f64) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for c in name.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(escaped, "\\u{:04x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+
+    format!(
+        "{{\"name\":\"{}\",\"ph\":\"X\",\"pid\":0,\"tid\":{},\"ts\":{:.3},\"dur\":{:.3}}}",
+        escaped,
+        thread,
+        start * 1_000_000.0,
+        duration * 1_000_000.0
+    )
+}
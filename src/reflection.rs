@@ -0,0 +1,504 @@
+use std::collections::BTreeMap;
+
+use glam::UVec3;
+use naga::valid::{Capabilities, ValidationFlags, Validator};
+
+/// Bindings, vertex inputs and the immediate block of a WGSL module, used to build
+/// layouts that can't drift from the shader.
+#[derive(Debug)]
+pub struct ShaderReflection {
+    module: naga::Module,
+    bind_groups: BTreeMap<u32, Vec<wgpu::BindGroupLayoutEntry>>,
+    immediate_size: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReflectionError {
+    Parse(String),
+    Validation(String),
+    MissingEntryPoint(String),
+    UnsupportedType { name: String },
+    Mismatch(String),
+}
+
+/// Owned counterpart of `wgpu::VertexBufferLayout`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VertexLayout {
+    pub array_stride: wgpu::BufferAddress,
+    pub step_mode: wgpu::VertexStepMode,
+    pub attributes: Vec<wgpu::VertexAttribute>,
+}
+
+impl ShaderReflection {
+    pub fn from_wgsl(source: &str) -> Result<Self, ReflectionError> {
+        let module = naga::front::wgsl::parse_str(source)
+            .map_err(|err| ReflectionError::Parse(err.emit_to_string(source)))?;
+        let info = Validator::new(ValidationFlags::all(), Capabilities::all())
+            .validate(&module)
+            .map_err(|err| ReflectionError::Validation(err.emit_to_string(source)))?;
+
+        let mut bind_groups: BTreeMap<u32, Vec<wgpu::BindGroupLayoutEntry>> = BTreeMap::new();
+        let mut immediate_size = 0;
+
+        for (handle, global) in module.global_variables.iter() {
+            let mut visibility = wgpu::ShaderStages::NONE;
+            for (index, entry_point) in module.entry_points.iter().enumerate() {
+                if !info.get_entry_point(index)[handle].is_empty() {
+                    visibility |= shader_stage(entry_point.stage);
+                }
+            }
+
+            if global.space == naga::AddressSpace::Immediate {
+                immediate_size = module.types[global.ty].inner.size(module.to_ctx());
+                continue;
+            }
+
+            let Some(binding) = &global.binding else {
+                continue;
+            };
+            let (ty, count) = binding_type(&module, global)?;
+            bind_groups
+                .entry(binding.group)
+                .or_default()
+                .push(wgpu::BindGroupLayoutEntry {
+                    binding: binding.binding,
+                    visibility,
+                    ty,
+                    count,
+                });
+        }
+
+        for entries in bind_groups.values_mut() {
+            entries.sort_by_key(|entry| entry.binding);
+        }
+
+        Ok(Self {
+            module,
+            bind_groups,
+            immediate_size,
+        })
+    }
+
+    /// Size in bytes of the `var<immediate>` block, 0 when there is none.
+    pub fn immediate_size(&self) -> u32 {
+        self.immediate_size
+    }
+
+    /// Number of bind groups, including unused ones below the highest used index.
+    pub fn bind_group_count(&self) -> u32 {
+        self.bind_groups
+            .keys()
+            .next_back()
+            .map_or(0, |group| group + 1)
+    }
+
+    /// Entries of one bind group. Samplers are reflected as filtering and float textures as
+    /// filterable since WGSL doesn't say otherwise; adjust the entries before creating a
+    /// layout when that's not what the pipeline needs.
+    pub fn bind_group_layout_entries(&self, group: u32) -> Vec<wgpu::BindGroupLayoutEntry> {
+        self.bind_groups.get(&group).cloned().unwrap_or_default()
+    }
+
+    pub fn create_bind_group_layouts(&self, device: &wgpu::Device) -> Vec<wgpu::BindGroupLayout> {
+        (0..self.bind_group_count())
+            .map(|group| {
+                device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    entries: &self.bind_group_layout_entries(group),
+                    label: None,
+                })
+            })
+            .collect()
+    }
+
+    pub fn create_pipeline_layout(
+        &self,
+        device: &wgpu::Device,
+        bind_group_layouts: &[wgpu::BindGroupLayout],
+    ) -> wgpu::PipelineLayout {
+        device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            bind_group_layouts: &bind_group_layouts.iter().collect::<Vec<_>>(),
+            immediate_size: self.immediate_size,
+            label: None,
+        })
+    }
+
+    /// `@workgroup_size` of a compute entry point.
+    pub fn workgroup_size(&self, entry_point: &str) -> Result<UVec3, ReflectionError> {
+        self.module
+            .entry_points
+            .iter()
+            .find(|ep| ep.name == entry_point && ep.stage == naga::ShaderStage::Compute)
+            .map(|ep| UVec3::from_array(ep.workgroup_size))
+            .ok_or_else(|| ReflectionError::MissingEntryPoint(entry_point.to_string()))
+    }
+
+    /// Inputs of a vertex entry point as `(location, format)`, ordered by location.
+    pub fn vertex_inputs(
+        &self,
+        entry_point: &str,
+    ) -> Result<Vec<(u32, wgpu::VertexFormat)>, ReflectionError> {
+        let entry_point = self
+            .module
+            .entry_points
+            .iter()
+            .find(|ep| ep.name == entry_point && ep.stage == naga::ShaderStage::Vertex)
+            .ok_or_else(|| ReflectionError::MissingEntryPoint(entry_point.to_string()))?;
+
+        let mut inputs = vec![];
+        for argument in entry_point.function.arguments.iter() {
+            match &argument.binding {
+                Some(naga::Binding::Location { location, .. }) => {
+                    inputs.push((*location, self.vertex_format(argument.ty)?));
+                }
+                Some(naga::Binding::BuiltIn(_)) => {}
+                None => {
+                    if let naga::TypeInner::Struct { members, .. } =
+                        &self.module.types[argument.ty].inner
+                    {
+                        for member in members.iter() {
+                            if let Some(naga::Binding::Location { location, .. }) = member.binding {
+                                inputs.push((location, self.vertex_format(member.ty)?));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        inputs.sort_by_key(|(location, _)| *location);
+
+        Ok(inputs)
+    }
+
+    /// Packs the inputs of `entry_point` tightly in location order. `vertex_size` is the size of
+    /// the Rust vertex struct and must match the packed size.
+    pub fn vertex_layout(
+        &self,
+        entry_point: &str,
+        vertex_size: usize,
+    ) -> Result<VertexLayout, ReflectionError> {
+        let mut offset = 0;
+        let attributes = self
+            .vertex_inputs(entry_point)?
+            .into_iter()
+            .map(|(location, format)| {
+                let attribute = wgpu::VertexAttribute {
+                    format,
+                    offset,
+                    shader_location: location,
+                };
+                offset += format.size();
+                attribute
+            })
+            .collect::<Vec<_>>();
+
+        if offset != vertex_size as wgpu::BufferAddress {
+            return Err(ReflectionError::Mismatch(format!(
+                "Inputs of {} take {} bytes, the vertex struct has {}",
+                entry_point, offset, vertex_size
+            )));
+        }
+
+        Ok(VertexLayout {
+            array_stride: offset,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes,
+        })
+    }
+
+    /// Checks a hand-written layout against the inputs of `entry_point`.
+    pub fn validate_vertex_layout(
+        &self,
+        entry_point: &str,
+        layout: &wgpu::VertexBufferLayout,
+    ) -> Result<(), ReflectionError> {
+        for (location, format) in self.vertex_inputs(entry_point)? {
+            let attribute = layout
+                .attributes
+                .iter()
+                .find(|attribute| attribute.shader_location == location)
+                .ok_or_else(|| {
+                    ReflectionError::Mismatch(format!(
+                        "{} reads location {} which the vertex layout doesn't provide",
+                        entry_point, location
+                    ))
+                })?;
+
+            if !formats_compatible(attribute.format, format) {
+                return Err(ReflectionError::Mismatch(format!(
+                    "Location {} of {} is {:?} in the shader but {:?} in the vertex layout",
+                    location, entry_point, format, attribute.format
+                )));
+            }
+            if attribute.offset + attribute.format.size() > layout.array_stride {
+                return Err(ReflectionError::Mismatch(format!(
+                    "Location {} ends past the vertex stride of {} bytes",
+                    location, layout.array_stride
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn vertex_format(
+        &self,
+        ty: naga::Handle<naga::Type>,
+    ) -> Result<wgpu::VertexFormat, ReflectionError> {
+        let unsupported = || ReflectionError::UnsupportedType {
+            name: self.module.types[ty]
+                .name
+                .clone()
+                .unwrap_or_else(|| format!("{:?}", self.module.types[ty].inner)),
+        };
+
+        let (scalar, components) = match self.module.types[ty].inner {
+            naga::TypeInner::Scalar(scalar) => (scalar, 1),
+            naga::TypeInner::Vector { size, scalar } => (scalar, size as u32),
+            _ => return Err(unsupported()),
+        };
+
+        use wgpu::VertexFormat as F;
+        let format = match (scalar.kind, scalar.width, components) {
+            (naga::ScalarKind::Float, 4, 1) => F::Float32,
+            (naga::ScalarKind::Float, 4, 2) => F::Float32x2,
+            (naga::ScalarKind::Float, 4, 3) => F::Float32x3,
+            (naga::ScalarKind::Float, 4, 4) => F::Float32x4,
+            (naga::ScalarKind::Float, 2, 1) => F::Float16,
+            (naga::ScalarKind::Float, 2, 2) => F::Float16x2,
+            (naga::ScalarKind::Float, 2, 4) => F::Float16x4,
+            (naga::ScalarKind::Uint, 4, 1) => F::Uint32,
+            (naga::ScalarKind::Uint, 4, 2) => F::Uint32x2,
+            (naga::ScalarKind::Uint, 4, 3) => F::Uint32x3,
+            (naga::ScalarKind::Uint, 4, 4) => F::Uint32x4,
+            (naga::ScalarKind::Sint, 4, 1) => F::Sint32,
+            (naga::ScalarKind::Sint, 4, 2) => F::Sint32x2,
+            (naga::ScalarKind::Sint, 4, 3) => F::Sint32x3,
+            (naga::ScalarKind::Sint, 4, 4) => F::Sint32x4,
+            _ => return Err(unsupported()),
+        };
+
+        Ok(format)
+    }
+}
+
+impl VertexLayout {
+    pub fn as_wgpu(&self) -> wgpu::VertexBufferLayout<'_> {
+        wgpu::VertexBufferLayout {
+            array_stride: self.array_stride,
+            step_mode: self.step_mode,
+            attributes: &self.attributes,
+        }
+    }
+}
+
+fn shader_stage(stage: naga::ShaderStage) -> wgpu::ShaderStages {
+    match stage {
+        naga::ShaderStage::Vertex => wgpu::ShaderStages::VERTEX,
+        naga::ShaderStage::Fragment => wgpu::ShaderStages::FRAGMENT,
+        naga::ShaderStage::Compute => wgpu::ShaderStages::COMPUTE,
+        naga::ShaderStage::Task => wgpu::ShaderStages::TASK,
+        naga::ShaderStage::Mesh => wgpu::ShaderStages::MESH,
+    }
+}
+
+fn binding_type(
+    module: &naga::Module,
+    global: &naga::GlobalVariable,
+) -> Result<(wgpu::BindingType, Option<std::num::NonZeroU32>), ReflectionError> {
+    let unsupported = || ReflectionError::UnsupportedType {
+        name: global.name.clone().unwrap_or_default(),
+    };
+
+    let (ty, count) = match module.types[global.ty].inner {
+        naga::TypeInner::BindingArray {
+            base,
+            size: naga::ArraySize::Constant(size),
+        } => (base, Some(size)),
+        naga::TypeInner::BindingArray { .. } => return Err(unsupported()),
+        _ => (global.ty, None),
+    };
+
+    let binding_type = match (global.space, &module.types[ty].inner) {
+        (naga::AddressSpace::Uniform, inner) => wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: wgpu::BufferSize::new(inner.size(module.to_ctx()) as u64),
+        },
+        (naga::AddressSpace::Storage { access }, _) => wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage {
+                read_only: !access.contains(naga::StorageAccess::STORE),
+            },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        (naga::AddressSpace::Handle, naga::TypeInner::Sampler { comparison }) => {
+            wgpu::BindingType::Sampler(if *comparison {
+                wgpu::SamplerBindingType::Comparison
+            } else {
+                wgpu::SamplerBindingType::Filtering
+            })
+        }
+        (
+            naga::AddressSpace::Handle,
+            naga::TypeInner::Image {
+                dim,
+                arrayed,
+                class,
+            },
+        ) => {
+            let view_dimension = view_dimension(*dim, *arrayed);
+            match class {
+                naga::ImageClass::Sampled { kind, multi } => wgpu::BindingType::Texture {
+                    sample_type: match kind {
+                        naga::ScalarKind::Sint => wgpu::TextureSampleType::Sint,
+                        naga::ScalarKind::Uint => wgpu::TextureSampleType::Uint,
+                        _ => wgpu::TextureSampleType::Float { filterable: !multi },
+                    },
+                    view_dimension,
+                    multisampled: *multi,
+                },
+                naga::ImageClass::Depth { multi } => wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Depth,
+                    view_dimension,
+                    multisampled: *multi,
+                },
+                naga::ImageClass::Storage { format, access } => wgpu::BindingType::StorageTexture {
+                    access: match (
+                        access.contains(naga::StorageAccess::LOAD),
+                        access.contains(naga::StorageAccess::STORE),
+                    ) {
+                        (true, true) => wgpu::StorageTextureAccess::ReadWrite,
+                        (true, false) => wgpu::StorageTextureAccess::ReadOnly,
+                        _ => wgpu::StorageTextureAccess::WriteOnly,
+                    },
+                    format: storage_format(*format),
+                    view_dimension,
+                },
+                naga::ImageClass::External => return Err(unsupported()),
+            }
+        }
+        _ => return Err(unsupported()),
+    };
+
+    Ok((binding_type, count))
+}
+
+fn view_dimension(dim: naga::ImageDimension, arrayed: bool) -> wgpu::TextureViewDimension {
+    match (dim, arrayed) {
+        (naga::ImageDimension::D1, _) => wgpu::TextureViewDimension::D1,
+        (naga::ImageDimension::D2, false) => wgpu::TextureViewDimension::D2,
+        (naga::ImageDimension::D2, true) => wgpu::TextureViewDimension::D2Array,
+        (naga::ImageDimension::D3, _) => wgpu::TextureViewDimension::D3,
+        (naga::ImageDimension::Cube, false) => wgpu::TextureViewDimension::Cube,
+        (naga::ImageDimension::Cube, true) => wgpu::TextureViewDimension::CubeArray,
+    }
+}
+
+fn storage_format(format: naga::StorageFormat) -> wgpu::TextureFormat {
+    use naga::StorageFormat as S;
+    use wgpu::TextureFormat as T;
+
+    match format {
+        S::R8Unorm => T::R8Unorm,
+        S::R8Snorm => T::R8Snorm,
+        S::R8Uint => T::R8Uint,
+        S::R8Sint => T::R8Sint,
+        S::R16Uint => T::R16Uint,
+        S::R16Sint => T::R16Sint,
+        S::R16Float => T::R16Float,
+        S::Rg8Unorm => T::Rg8Unorm,
+        S::Rg8Snorm => T::Rg8Snorm,
+        S::Rg8Uint => T::Rg8Uint,
+        S::Rg8Sint => T::Rg8Sint,
+        S::R32Uint => T::R32Uint,
+        S::R32Sint => T::R32Sint,
+        S::R32Float => T::R32Float,
+        S::Rg16Uint => T::Rg16Uint,
+        S::Rg16Sint => T::Rg16Sint,
+        S::Rg16Float => T::Rg16Float,
+        S::Rgba8Unorm => T::Rgba8Unorm,
+        S::Rgba8Snorm => T::Rgba8Snorm,
+        S::Rgba8Uint => T::Rgba8Uint,
+        S::Rgba8Sint => T::Rgba8Sint,
+        S::Bgra8Unorm => T::Bgra8Unorm,
+        S::Rgb10a2Uint => T::Rgb10a2Uint,
+        S::Rgb10a2Unorm => T::Rgb10a2Unorm,
+        S::Rg11b10Ufloat => T::Rg11b10Ufloat,
+        S::R64Uint => T::R64Uint,
+        S::Rg32Uint => T::Rg32Uint,
+        S::Rg32Sint => T::Rg32Sint,
+        S::Rg32Float => T::Rg32Float,
+        S::Rgba16Uint => T::Rgba16Uint,
+        S::Rgba16Sint => T::Rgba16Sint,
+        S::Rgba16Float => T::Rgba16Float,
+        S::Rgba32Uint => T::Rgba32Uint,
+        S::Rgba32Sint => T::Rgba32Sint,
+        S::Rgba32Float => T::Rgba32Float,
+        S::R16Unorm => T::R16Unorm,
+        S::R16Snorm => T::R16Snorm,
+        S::Rg16Unorm => T::Rg16Unorm,
+        S::Rg16Snorm => T::Rg16Snorm,
+        S::Rgba16Unorm => T::Rgba16Unorm,
+        S::Rgba16Snorm => T::Rgba16Snorm,
+    }
+}
+
+/// Same component type and count; normalized integer formats feed float inputs.
+fn formats_compatible(layout: wgpu::VertexFormat, shader: wgpu::VertexFormat) -> bool {
+    use wgpu::VertexFormat as F;
+
+    let components = |format: F| -> (char, u32) {
+        match format {
+            F::Float32 | F::Float16 | F::Unorm8 | F::Snorm8 | F::Unorm16 | F::Snorm16 => ('f', 1),
+            F::Float32x2
+            | F::Float16x2
+            | F::Unorm8x2
+            | F::Snorm8x2
+            | F::Unorm16x2
+            | F::Snorm16x2 => ('f', 2),
+            F::Float32x3 => ('f', 3),
+            F::Float32x4
+            | F::Float16x4
+            | F::Unorm8x4
+            | F::Snorm8x4
+            | F::Unorm16x4
+            | F::Snorm16x4
+            | F::Unorm10_10_10_2
+            | F::Unorm8x4Bgra => ('f', 4),
+            F::Uint32 | F::Uint8 | F::Uint16 => ('u', 1),
+            F::Uint32x2 | F::Uint8x2 | F::Uint16x2 => ('u', 2),
+            F::Uint32x3 => ('u', 3),
+            F::Uint32x4 | F::Uint8x4 | F::Uint16x4 => ('u', 4),
+            F::Sint32 | F::Sint8 | F::Sint16 => ('i', 1),
+            F::Sint32x2 | F::Sint8x2 | F::Sint16x2 => ('i', 2),
+            F::Sint32x3 => ('i', 3),
+            F::Sint32x4 | F::Sint8x4 | F::Sint16x4 => ('i', 4),
+            F::Float64 => ('d', 1),
+            F::Float64x2 => ('d', 2),
+            F::Float64x3 => ('d', 3),
+            F::Float64x4 => ('d', 4),
+        }
+    };
+
+    components(layout) == components(shader)
+}
+
+impl std::fmt::Display for ReflectionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReflectionError::Parse(message) => write!(f, "Failed to parse WGSL:\n{}", message),
+            ReflectionError::Validation(message) => {
+                write!(f, "Failed to validate WGSL:\n{}", message)
+            }
+            ReflectionError::MissingEntryPoint(name) => {
+                write!(f, "No entry point of the expected stage named {}", name)
+            }
+            ReflectionError::UnsupportedType { name } => {
+                write!(f, "Type of {} can't be reflected", name)
+            }
+            ReflectionError::Mismatch(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for ReflectionError {}
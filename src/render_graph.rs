@@ -0,0 +1,1169 @@
+use std::collections::BTreeSet;
+
+use glam::UVec2;
+
+use crate::wgpu_app::Frame;
+
+/// Texture or buffer declared in a `RenderGraphBuilder`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ResourceId(usize);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TextureDesc {
+    pub size: UVec2,
+    pub format: wgpu::TextureFormat,
+    pub sample_count: u32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BufferDesc {
+    pub size: wgpu::BufferAddress,
+}
+
+/// How a pass uses a resource. Usages of transient resources are the union of their accesses.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Access {
+    /// Texture bound for sampling or loading.
+    Sampled,
+    /// Storage texture or buffer.
+    Storage,
+    /// Color or depth attachment, declared through `PassBuilder::color` and `PassBuilder::depth`.
+    Attachment,
+    Vertex,
+    Index,
+    Uniform,
+    Indirect,
+    CopySrc,
+    CopyDst,
+}
+
+/// The frame attachments imported by `RenderGraphBuilder::import_frame`.
+#[derive(Copy, Clone, Debug)]
+pub struct FrameResources {
+    pub output: ResourceId,
+    pub msaa: Option<ResourceId>,
+    pub depth: Option<ResourceId>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RenderGraphError {
+    /// A texture access on a buffer or the other way around.
+    InvalidAccess {
+        pass: String,
+        resource: String,
+        access: Access,
+    },
+    /// A pass reads a transient resource that no pass writes.
+    Unwritten { pass: String, resource: String },
+    /// Passes that depend on each other through their reads and writes, and passes waiting
+    /// on them.
+    Cycle(Vec<String>),
+    /// A pass recording to the encoder declared color or depth attachments.
+    AttachmentsOutsideRenderPass(String),
+}
+
+/// Passes and resources of one frame. Passes are ordered by their dependencies: a pass reading
+/// a resource it doesn't write runs after all passes writing it, and passes writing the same
+/// resource run in the order they were added. Passes that don't contribute to an imported
+/// resource are skipped.
+pub struct RenderGraphBuilder<'a> {
+    resources: Vec<ResourceNode<'a>>,
+    passes: Vec<PassNode<'a>>,
+}
+
+/// Declares the resources of one pass, finished by `render` or `record`.
+pub struct PassBuilder<'b, 'a> {
+    builder: &'b mut RenderGraphBuilder<'a>,
+    pass: PassNode<'a>,
+}
+
+/// Resources of the pass being recorded.
+pub struct PassContext<'r> {
+    pub device: &'r wgpu::Device,
+    resources: &'r [Option<Bound<'r>>],
+    snapshots: &'r [(ResourceId, ResourceId)],
+}
+
+/// Allocates the transient resources of a `RenderGraphBuilder` and records its passes. Keep it
+/// across frames: transient textures and buffers are reused while their descriptions match,
+/// and resources with disjoint lifetimes within a frame share the same memory.
+#[derive(Debug, Default)]
+pub struct RenderGraph {
+    textures: Vec<PooledTexture>,
+    buffers: Vec<PooledBuffer>,
+}
+
+type RenderFn<'a> = Box<dyn FnOnce(&PassContext, &mut wgpu::RenderPass<'_>) + 'a>;
+type RecordFn<'a> = Box<dyn FnOnce(&PassContext, &mut wgpu::CommandEncoder) + 'a>;
+
+enum Record<'a> {
+    Render(RenderFn<'a>),
+    Encoder(RecordFn<'a>),
+}
+
+struct PassNode<'a> {
+    name: String,
+    reads: Vec<(ResourceId, Access)>,
+    writes: Vec<(ResourceId, Access)>,
+    colors: Vec<(ResourceId, Option<ResourceId>, wgpu::LoadOp<wgpu::Color>)>,
+    depth: Option<(ResourceId, wgpu::Operations<f32>)>,
+    timestamp_writes: Option<wgpu::RenderPassTimestampWrites<'a>>,
+    record: Option<Record<'a>>,
+}
+
+struct ResourceNode<'a> {
+    name: String,
+    kind: ResourceKind<'a>,
+}
+
+enum ResourceKind<'a> {
+    Texture(TextureDesc),
+    Buffer(BufferDesc),
+    ImportedTexture(&'a wgpu::TextureView),
+    ImportedBuffer(&'a wgpu::Buffer),
+}
+
+#[derive(Copy, Clone)]
+enum Bound<'r> {
+    Texture(&'r wgpu::TextureView),
+    Buffer(&'r wgpu::Buffer),
+}
+
+#[derive(Debug)]
+struct PooledTexture {
+    desc: TextureDesc,
+    usage: wgpu::TextureUsages,
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    busy: bool,
+    used: bool,
+}
+
+#[derive(Debug)]
+struct PooledBuffer {
+    desc: BufferDesc,
+    usage: wgpu::BufferUsages,
+    buffer: wgpu::Buffer,
+    busy: bool,
+    used: bool,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Usage {
+    texture: wgpu::TextureUsages,
+    buffer: wgpu::BufferUsages,
+}
+
+impl Default for Usage {
+    fn default() -> Self {
+        Self {
+            texture: wgpu::TextureUsages::empty(),
+            buffer: wgpu::BufferUsages::empty(),
+        }
+    }
+}
+
+impl TextureDesc {
+    pub fn new(size: UVec2, format: wgpu::TextureFormat) -> Self {
+        Self {
+            size,
+            format,
+            sample_count: 1,
+        }
+    }
+}
+
+impl Access {
+    fn texture_usage(self) -> Option<wgpu::TextureUsages> {
+        match self {
+            Access::Sampled => Some(wgpu::TextureUsages::TEXTURE_BINDING),
+            Access::Storage => Some(wgpu::TextureUsages::STORAGE_BINDING),
+            Access::Attachment => Some(wgpu::TextureUsages::RENDER_ATTACHMENT),
+            Access::CopySrc => Some(wgpu::TextureUsages::COPY_SRC),
+            Access::CopyDst => Some(wgpu::TextureUsages::COPY_DST),
+            _ => None,
+        }
+    }
+
+    fn buffer_usage(self) -> Option<wgpu::BufferUsages> {
+        match self {
+            Access::Storage => Some(wgpu::BufferUsages::STORAGE),
+            Access::Vertex => Some(wgpu::BufferUsages::VERTEX),
+            Access::Index => Some(wgpu::BufferUsages::INDEX),
+            Access::Uniform => Some(wgpu::BufferUsages::UNIFORM),
+            Access::Indirect => Some(wgpu::BufferUsages::INDIRECT),
+            Access::CopySrc => Some(wgpu::BufferUsages::COPY_SRC),
+            Access::CopyDst => Some(wgpu::BufferUsages::COPY_DST),
+            _ => None,
+        }
+    }
+}
+
+impl ResourceKind<'_> {
+    fn is_texture(&self) -> bool {
+        matches!(
+            self,
+            ResourceKind::Texture(_) | ResourceKind::ImportedTexture(_)
+        )
+    }
+
+    fn is_imported(&self) -> bool {
+        matches!(
+            self,
+            ResourceKind::ImportedTexture(_) | ResourceKind::ImportedBuffer(_)
+        )
+    }
+}
+
+impl Default for RenderGraphBuilder<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> RenderGraphBuilder<'a> {
+    pub fn new() -> Self {
+        Self {
+            resources: vec![],
+            passes: vec![],
+        }
+    }
+
+    /// Imports the surface view passed to `WgpuApp::render` as the output of the graph.
+    pub fn output(&mut self, surface_view: &'a wgpu::TextureView) -> ResourceId {
+        self.import_texture("output", surface_view)
+    }
+
+    /// Imports the surface as the output, and the multisampled color and depth textures
+    /// when the runner creates them.
+    pub fn import_frame(&mut self, frame: &Frame<'a>) -> FrameResources {
+        FrameResources {
+            output: self.output(frame.surface_view),
+            msaa: frame
+                .msaa_view
+                .map(|view| self.import_texture("msaa", view)),
+            depth: frame
+                .depth_view
+                .map(|view| self.import_texture("depth", view)),
+        }
+    }
+
+    /// Textures created outside of the graph. Passes writing imported resources are never
+    /// skipped.
+    pub fn import_texture(&mut self, name: &str, view: &'a wgpu::TextureView) -> ResourceId {
+        self.add_resource(name, ResourceKind::ImportedTexture(view))
+    }
+
+    pub fn import_buffer(&mut self, name: &str, buffer: &'a wgpu::Buffer) -> ResourceId {
+        self.add_resource(name, ResourceKind::ImportedBuffer(buffer))
+    }
+
+    /// Texture that only lives during the frame. Its contents are undefined until a pass
+    /// writes it, so the first pass should clear it.
+    pub fn create_texture(&mut self, name: &str, desc: TextureDesc) -> ResourceId {
+        self.add_resource(name, ResourceKind::Texture(desc))
+    }
+
+    pub fn create_buffer(&mut self, name: &str, desc: BufferDesc) -> ResourceId {
+        self.add_resource(name, ResourceKind::Buffer(desc))
+    }
+
+    pub fn add_pass(&mut self, name: &str) -> PassBuilder<'_, 'a> {
+        PassBuilder {
+            builder: self,
+            pass: PassNode {
+                name: name.to_string(),
+                reads: vec![],
+                writes: vec![],
+                colors: vec![],
+                depth: None,
+                timestamp_writes: None,
+                record: None,
+            },
+        }
+    }
+
+    fn add_resource(&mut self, name: &str, kind: ResourceKind<'a>) -> ResourceId {
+        self.resources.push(ResourceNode {
+            name: name.to_string(),
+            kind,
+        });
+
+        ResourceId(self.resources.len() - 1)
+    }
+}
+
+impl<'a> PassBuilder<'_, 'a> {
+    /// Reading a resource the pass also writes gives it a copy of the previous contents, see
+    /// `PassContext::previous_texture_view`. Multisampled textures can't be copied.
+    pub fn read(mut self, resource: ResourceId, access: Access) -> Self {
+        self.pass.reads.push((resource, access));
+        self
+    }
+
+    pub fn write(mut self, resource: ResourceId, access: Access) -> Self {
+        self.pass.writes.push((resource, access));
+        self
+    }
+
+    /// Color attachment, stored at the end of the pass.
+    pub fn color(self, target: ResourceId, load: wgpu::LoadOp<wgpu::Color>) -> Self {
+        self.add_color(target, None, load)
+    }
+
+    /// Multisampled color attachment resolved to `resolve_target` and then discarded.
+    pub fn color_resolve(
+        self,
+        target: ResourceId,
+        resolve_target: ResourceId,
+        load: wgpu::LoadOp<wgpu::Color>,
+    ) -> Self {
+        self.add_color(target, Some(resolve_target), load)
+    }
+
+    /// Color attachment for the frame output, resolving multisampled color to it.
+    pub fn frame_color(self, frame: &FrameResources, load: wgpu::LoadOp<wgpu::Color>) -> Self {
+        match frame.msaa {
+            Some(msaa) => self.color_resolve(msaa, frame.output, load),
+            None => self.color(frame.output, load),
+        }
+    }
+
+    /// Depth attachment with `depth_ops`, and stencil cleared to 0 if the format has it.
+    pub fn depth(mut self, target: ResourceId, depth_ops: wgpu::Operations<f32>) -> Self {
+        self.pass.writes.push((target, Access::Attachment));
+        self.pass.depth = Some((target, depth_ops));
+        self
+    }
+
+    pub fn timestamp_writes(
+        mut self,
+        timestamp_writes: Option<wgpu::RenderPassTimestampWrites<'a>>,
+    ) -> Self {
+        self.pass.timestamp_writes = timestamp_writes;
+        self
+    }
+
+    /// Records the pass into a render pass begun with the declared attachments.
+    pub fn render(mut self, render: impl FnOnce(&PassContext, &mut wgpu::RenderPass<'_>) + 'a) {
+        self.pass.record = Some(Record::Render(Box::new(render)));
+        self.builder.passes.push(self.pass);
+    }
+
+    /// Records the pass directly into the encoder, e.g. for compute passes and copies.
+    pub fn record(mut self, record: impl FnOnce(&PassContext, &mut wgpu::CommandEncoder) + 'a) {
+        self.pass.record = Some(Record::Encoder(Box::new(record)));
+        self.builder.passes.push(self.pass);
+    }
+
+    fn add_color(
+        mut self,
+        target: ResourceId,
+        resolve_target: Option<ResourceId>,
+        load: wgpu::LoadOp<wgpu::Color>,
+    ) -> Self {
+        self.pass.writes.push((target, Access::Attachment));
+        if let Some(resolve_target) = resolve_target {
+            self.pass.writes.push((resolve_target, Access::Attachment));
+        }
+        self.pass.colors.push((target, resolve_target, load));
+        self
+    }
+}
+
+impl PassNode<'_> {
+    fn writes(&self, resource: ResourceId) -> bool {
+        self.writes.iter().any(|&(written, _)| written == resource)
+    }
+
+    fn resources(&self) -> impl Iterator<Item = (ResourceId, Access)> + '_ {
+        self.reads.iter().chain(self.writes.iter()).copied()
+    }
+}
+
+impl<'r> PassContext<'r> {
+    /// Panics if `resource` isn't a texture used by the graph.
+    pub fn texture_view(&self, resource: ResourceId) -> &'r wgpu::TextureView {
+        match self.resources[resource.0] {
+            Some(Bound::Texture(view)) => view,
+            _ => panic!("{:?} isn't a texture used by the graph", resource),
+        }
+    }
+
+    pub fn texture(&self, resource: ResourceId) -> &'r wgpu::Texture {
+        self.texture_view(resource).texture()
+    }
+
+    /// Panics if `resource` isn't a buffer used by the graph.
+    pub fn buffer(&self, resource: ResourceId) -> &'r wgpu::Buffer {
+        match self.resources[resource.0] {
+            Some(Bound::Buffer(buffer)) => buffer,
+            _ => panic!("{:?} isn't a buffer used by the graph", resource),
+        }
+    }
+
+    /// Copy of a texture the pass both reads and writes, taken before the pass.
+    pub fn previous_texture_view(&self, resource: ResourceId) -> &'r wgpu::TextureView {
+        self.texture_view(self.snapshot(resource))
+    }
+
+    /// Copy of a buffer the pass both reads and writes, taken before the pass.
+    pub fn previous_buffer(&self, resource: ResourceId) -> &'r wgpu::Buffer {
+        self.buffer(self.snapshot(resource))
+    }
+
+    fn snapshot(&self, resource: ResourceId) -> ResourceId {
+        self.snapshots
+            .iter()
+            .find(|&&(source, _)| source == resource)
+            .map(|&(_, snapshot)| snapshot)
+            .unwrap_or_else(|| panic!("{:?} isn't read and written by the pass", resource))
+    }
+}
+
+impl RenderGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Orders the passes, allocates transient resources and records the passes into
+    /// `encoder`. Pooled resources the frame didn't use are released.
+    pub fn execute(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        builder: RenderGraphBuilder,
+    ) -> Result<(), RenderGraphError> {
+        let RenderGraphBuilder {
+            mut resources,
+            mut passes,
+        } = builder;
+
+        validate(&resources, &passes)?;
+        let order = order_passes(&resources, &passes)?;
+
+        // Passes reading what they write get a copy of the previous contents.
+        let mut snapshots = vec![vec![]; passes.len()];
+        for &index in order.iter() {
+            let pass = &passes[index];
+            for &(resource, _) in pass.reads.iter() {
+                if !pass.writes(resource) || snapshots[index].iter().any(|&(r, _)| r == resource) {
+                    continue;
+                }
+                let name = format!("{} (previous)", resources[resource.0].name);
+                let kind = match resources[resource.0].kind {
+                    ResourceKind::Texture(desc) => ResourceKind::Texture(desc),
+                    ResourceKind::Buffer(desc) => ResourceKind::Buffer(desc),
+                    ResourceKind::ImportedTexture(view) => {
+                        let texture = view.texture();
+                        ResourceKind::Texture(TextureDesc {
+                            size: UVec2::new(texture.width(), texture.height()),
+                            format: texture.format(),
+                            sample_count: texture.sample_count(),
+                        })
+                    }
+                    ResourceKind::ImportedBuffer(buffer) => ResourceKind::Buffer(BufferDesc {
+                        size: buffer.size(),
+                    }),
+                };
+                resources.push(ResourceNode { name, kind });
+                snapshots[index].push((resource, ResourceId(resources.len() - 1)));
+            }
+        }
+
+        // Usages and the first and last position in `order` of each resource.
+        let mut usages = vec![Usage::default(); resources.len()];
+        let mut lifetimes: Vec<Option<(usize, usize)>> = vec![None; resources.len()];
+        for (position, &index) in order.iter().enumerate() {
+            let pass = &passes[index];
+            let snapshot_of = |resource: ResourceId| {
+                snapshots[index]
+                    .iter()
+                    .find(|&&(source, _)| source == resource)
+                    .map(|&(_, snapshot)| snapshot)
+            };
+            // Reads of resources the pass also writes go to their copies.
+            let mut uses = pass
+                .reads
+                .iter()
+                .map(|&(resource, access)| (snapshot_of(resource).unwrap_or(resource), access))
+                .chain(pass.writes.iter().copied())
+                .collect::<Vec<_>>();
+            for &(source, snapshot) in snapshots[index].iter() {
+                uses.push((source, Access::CopySrc));
+                uses.push((snapshot, Access::CopyDst));
+            }
+
+            for (resource, access) in uses {
+                let usage = &mut usages[resource.0];
+                usage.texture |= access
+                    .texture_usage()
+                    .unwrap_or(wgpu::TextureUsages::empty());
+                usage.buffer |= access.buffer_usage().unwrap_or(wgpu::BufferUsages::empty());
+                let lifetime = lifetimes[resource.0].get_or_insert((position, position));
+                lifetime.1 = position;
+            }
+        }
+
+        let physical = self.allocate(device, &resources, &usages, &lifetimes, order.len());
+
+        let bound = resources
+            .iter()
+            .enumerate()
+            .map(
+                |(index, resource)| match (&resource.kind, physical[index]) {
+                    (ResourceKind::ImportedTexture(view), _) => Some(Bound::Texture(view)),
+                    (ResourceKind::ImportedBuffer(buffer), _) => Some(Bound::Buffer(buffer)),
+                    (ResourceKind::Texture(_), Some(pooled)) => {
+                        Some(Bound::Texture(&self.textures[pooled].view))
+                    }
+                    (ResourceKind::Buffer(_), Some(pooled)) => {
+                        Some(Bound::Buffer(&self.buffers[pooled].buffer))
+                    }
+                    _ => None,
+                },
+            )
+            .collect::<Vec<_>>();
+
+        for &index in order.iter() {
+            let pass = &mut passes[index];
+            let _span = tracing::info_span!("pass", name = %pass.name).entered();
+
+            let context = PassContext {
+                device,
+                resources: &bound,
+                snapshots: &snapshots[index],
+            };
+            for &(source, snapshot) in snapshots[index].iter() {
+                match (bound[source.0], bound[snapshot.0]) {
+                    (Some(Bound::Texture(source)), Some(Bound::Texture(snapshot))) => {
+                        encoder.copy_texture_to_texture(
+                            source.texture().as_image_copy(),
+                            snapshot.texture().as_image_copy(),
+                            snapshot.texture().size(),
+                        );
+                    }
+                    (Some(Bound::Buffer(source)), Some(Bound::Buffer(snapshot))) => {
+                        encoder.copy_buffer_to_buffer(source, 0, snapshot, 0, snapshot.size());
+                    }
+                    _ => unreachable!(),
+                }
+            }
+
+            match pass.record.take() {
+                Some(Record::Render(render)) => {
+                    let color_attachments = pass
+                        .colors
+                        .iter()
+                        .map(|&(target, resolve_target, load)| {
+                            Some(wgpu::RenderPassColorAttachment {
+                                view: context.texture_view(target),
+                                resolve_target: resolve_target
+                                    .map(|resolve_target| context.texture_view(resolve_target)),
+                                ops: wgpu::Operations {
+                                    load,
+                                    store: if resolve_target.is_some() {
+                                        wgpu::StoreOp::Discard
+                                    } else {
+                                        wgpu::StoreOp::Store
+                                    },
+                                },
+                                depth_slice: None,
+                            })
+                        })
+                        .collect::<Vec<_>>();
+                    let depth_stencil_attachment = pass.depth.map(|(target, depth_ops)| {
+                        let view = context.texture_view(target);
+                        let has_stencil = view.texture().format().has_stencil_aspect();
+                        wgpu::RenderPassDepthStencilAttachment {
+                            view,
+                            depth_ops: Some(depth_ops),
+                            stencil_ops: has_stencil.then_some(wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(0),
+                                store: wgpu::StoreOp::Store,
+                            }),
+                        }
+                    });
+
+                    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                        label: Some(&pass.name),
+                        color_attachments: &color_attachments,
+                        depth_stencil_attachment,
+                        timestamp_writes: pass.timestamp_writes.take(),
+                        occlusion_query_set: None,
+                        multiview_mask: None,
+                    });
+                    render(&context, &mut render_pass);
+                }
+                Some(Record::Encoder(record)) => {
+                    encoder.push_debug_group(&pass.name);
+                    record(&context, encoder);
+                    encoder.pop_debug_group();
+                }
+                None => unreachable!(),
+            }
+        }
+
+        self.textures.retain(|pooled| pooled.used);
+        self.buffers.retain(|pooled| pooled.used);
+
+        Ok(())
+    }
+
+    /// Assigns pooled resources to transients, reusing ones whose lifetime ended earlier in
+    /// the frame. Returns the pool index of each transient resource that is used.
+    fn allocate(
+        &mut self,
+        device: &wgpu::Device,
+        resources: &[ResourceNode],
+        usages: &[Usage],
+        lifetimes: &[Option<(usize, usize)>],
+        pass_count: usize,
+    ) -> Vec<Option<usize>> {
+        for pooled in self.textures.iter_mut() {
+            pooled.busy = false;
+            pooled.used = false;
+        }
+        for pooled in self.buffers.iter_mut() {
+            pooled.busy = false;
+            pooled.used = false;
+        }
+
+        let mut physical = vec![None; resources.len()];
+        for position in 0..pass_count {
+            for (index, resource) in resources.iter().enumerate() {
+                if lifetimes[index].map(|(first, _)| first) != Some(position) {
+                    continue;
+                }
+                physical[index] = match resource.kind {
+                    ResourceKind::Texture(desc) => Some(self.allocate_texture(
+                        device,
+                        &resource.name,
+                        desc,
+                        usages[index].texture,
+                    )),
+                    ResourceKind::Buffer(desc) => Some(self.allocate_buffer(
+                        device,
+                        &resource.name,
+                        desc,
+                        usages[index].buffer,
+                    )),
+                    _ => None,
+                };
+            }
+
+            for (index, resource) in resources.iter().enumerate() {
+                if lifetimes[index].map(|(_, last)| last) != Some(position) {
+                    continue;
+                }
+                match (&resource.kind, physical[index]) {
+                    (ResourceKind::Texture(_), Some(pooled)) => self.textures[pooled].busy = false,
+                    (ResourceKind::Buffer(_), Some(pooled)) => self.buffers[pooled].busy = false,
+                    _ => {}
+                }
+            }
+        }
+
+        physical
+    }
+
+    fn allocate_texture(
+        &mut self,
+        device: &wgpu::Device,
+        name: &str,
+        desc: TextureDesc,
+        usage: wgpu::TextureUsages,
+    ) -> usize {
+        let free = self
+            .textures
+            .iter()
+            .position(|pooled| !pooled.busy && pooled.desc == desc && pooled.usage == usage);
+        let index = free.unwrap_or_else(|| {
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                size: wgpu::Extent3d {
+                    width: desc.size.x.max(1),
+                    height: desc.size.y.max(1),
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: desc.sample_count,
+                dimension: wgpu::TextureDimension::D2,
+                format: desc.format,
+                usage,
+                view_formats: &[],
+                label: Some(name),
+            });
+            let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+            self.textures.push(PooledTexture {
+                desc,
+                usage,
+                texture,
+                view,
+                busy: false,
+                used: false,
+            });
+            self.textures.len() - 1
+        });
+
+        let pooled = &mut self.textures[index];
+        pooled.busy = true;
+        pooled.used = true;
+
+        index
+    }
+
+    fn allocate_buffer(
+        &mut self,
+        device: &wgpu::Device,
+        name: &str,
+        desc: BufferDesc,
+        usage: wgpu::BufferUsages,
+    ) -> usize {
+        let free = self
+            .buffers
+            .iter()
+            .position(|pooled| !pooled.busy && pooled.desc == desc && pooled.usage == usage);
+        let index = free.unwrap_or_else(|| {
+            let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                size: desc.size,
+                usage,
+                mapped_at_creation: false,
+                label: Some(name),
+            });
+            self.buffers.push(PooledBuffer {
+                desc,
+                usage,
+                buffer,
+                busy: false,
+                used: false,
+            });
+            self.buffers.len() - 1
+        });
+
+        let pooled = &mut self.buffers[index];
+        pooled.busy = true;
+        pooled.used = true;
+
+        index
+    }
+}
+
+fn validate(resources: &[ResourceNode], passes: &[PassNode]) -> Result<(), RenderGraphError> {
+    for pass in passes.iter() {
+        if matches!(pass.record, Some(Record::Encoder(_)))
+            && (!pass.colors.is_empty() || pass.depth.is_some())
+        {
+            return Err(RenderGraphError::AttachmentsOutsideRenderPass(
+                pass.name.clone(),
+            ));
+        }
+
+        for (resource, access) in pass.resources() {
+            let node = &resources[resource.0];
+            let valid = if node.kind.is_texture() {
+                access.texture_usage().is_some()
+            } else {
+                access.buffer_usage().is_some()
+            };
+            if !valid {
+                return Err(RenderGraphError::InvalidAccess {
+                    pass: pass.name.clone(),
+                    resource: node.name.clone(),
+                    access,
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Indices of the passes contributing to imported resources, ordered by their dependencies
+/// and otherwise by the order they were added.
+fn order_passes(
+    resources: &[ResourceNode],
+    passes: &[PassNode],
+) -> Result<Vec<usize>, RenderGraphError> {
+    let mut writers = vec![vec![]; resources.len()];
+    for (index, pass) in passes.iter().enumerate() {
+        for &(resource, _) in pass.writes.iter() {
+            if writers[resource.0].last() != Some(&index) {
+                writers[resource.0].push(index);
+            }
+        }
+    }
+
+    let mut dependencies = vec![BTreeSet::new(); passes.len()];
+    for (index, pass) in passes.iter().enumerate() {
+        for &(resource, _) in pass.reads.iter() {
+            if pass.writes(resource) {
+                continue;
+            }
+            let resource_writers = &writers[resource.0];
+            if resource_writers.is_empty() && !resources[resource.0].kind.is_imported() {
+                return Err(RenderGraphError::Unwritten {
+                    pass: pass.name.clone(),
+                    resource: resources[resource.0].name.clone(),
+                });
+            }
+            dependencies[index].extend(resource_writers.iter().copied());
+        }
+        for &(resource, _) in pass.writes.iter() {
+            let resource_writers = &writers[resource.0];
+            let position = resource_writers.iter().position(|&w| w == index).unwrap();
+            if position > 0 {
+                dependencies[index].insert(resource_writers[position - 1]);
+            }
+        }
+    }
+
+    // Only passes that lead to an imported resource are recorded.
+    let mut live = vec![false; passes.len()];
+    let mut stack = passes
+        .iter()
+        .enumerate()
+        .filter(|(_, pass)| {
+            pass.writes
+                .iter()
+                .any(|&(resource, _)| resources[resource.0].kind.is_imported())
+        })
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+    while let Some(index) = stack.pop() {
+        if !std::mem::replace(&mut live[index], true) {
+            stack.extend(dependencies[index].iter().copied());
+        }
+    }
+
+    let mut remaining = vec![0; passes.len()];
+    let mut dependents = vec![vec![]; passes.len()];
+    for index in (0..passes.len()).filter(|&index| live[index]) {
+        remaining[index] = dependencies[index].len();
+        for &dependency in dependencies[index].iter() {
+            dependents[dependency].push(index);
+        }
+    }
+
+    let mut ready = (0..passes.len())
+        .filter(|&index| live[index] && remaining[index] == 0)
+        .collect::<BTreeSet<_>>();
+    let mut order = vec![];
+    while let Some(index) = ready.pop_first() {
+        order.push(index);
+        for &dependent in dependents[index].iter() {
+            remaining[dependent] -= 1;
+            if remaining[dependent] == 0 {
+                ready.insert(dependent);
+            }
+        }
+    }
+
+    let live_count = live.iter().filter(|&&live| live).count();
+    if order.len() != live_count {
+        let cycle = (0..passes.len())
+            .filter(|&index| live[index] && remaining[index] > 0)
+            .map(|index| passes[index].name.clone())
+            .collect();
+        return Err(RenderGraphError::Cycle(cycle));
+    }
+
+    Ok(order)
+}
+
+impl std::fmt::Debug for RenderGraphBuilder<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RenderGraphBuilder")
+            .field(
+                "resources",
+                &self.resources.iter().map(|r| &r.name).collect::<Vec<_>>(),
+            )
+            .field(
+                "passes",
+                &self.passes.iter().map(|p| &p.name).collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl std::fmt::Display for RenderGraphError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RenderGraphError::InvalidAccess {
+                pass,
+                resource,
+                access,
+            } => write!(f, "Pass {} can't access {} as {:?}", pass, resource, access),
+            RenderGraphError::Unwritten { pass, resource } => {
+                write!(f, "Pass {} reads {}, which no pass writes", pass, resource)
+            }
+            RenderGraphError::Cycle(passes) => {
+                write!(f, "Dependency cycle between passes: {}", passes.join(", "))
+            }
+            RenderGraphError::AttachmentsOutsideRenderPass(pass) => {
+                write!(
+                    f,
+                    "Pass {} declares attachments but isn't a render pass",
+                    pass
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for RenderGraphError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Device and queue of the noop backend, the queue must outlive command encoders.
+    fn device() -> (wgpu::Device, wgpu::Queue) {
+        wgpu::Device::noop(&wgpu::DeviceDescriptor::default())
+    }
+
+    fn buffer(device: &wgpu::Device) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            size: 16,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+            label: None,
+        })
+    }
+
+    fn texture_desc() -> TextureDesc {
+        TextureDesc::new(UVec2::splat(4), wgpu::TextureFormat::Rgba8Unorm)
+    }
+
+    /// Names of the passes in the order `execute` records them.
+    fn order(builder: &RenderGraphBuilder) -> Result<Vec<String>, RenderGraphError> {
+        validate(&builder.resources, &builder.passes)?;
+        let order = order_passes(&builder.resources, &builder.passes)?;
+        Ok(order
+            .into_iter()
+            .map(|index| builder.passes[index].name.clone())
+            .collect())
+    }
+
+    #[test]
+    fn reads_run_after_writes() {
+        let (device, _queue) = device();
+        let output = buffer(&device);
+        let mut builder = RenderGraphBuilder::new();
+        let output = builder.import_buffer("output", &output);
+        let scene = builder.create_texture("scene", texture_desc());
+        builder
+            .add_pass("composite")
+            .read(scene, Access::Sampled)
+            .write(output, Access::Storage)
+            .record(|_, _| {});
+        builder
+            .add_pass("scene")
+            .write(scene, Access::Storage)
+            .record(|_, _| {});
+
+        assert_eq!(order(&builder).unwrap(), ["scene", "composite"]);
+    }
+
+    #[test]
+    fn writers_run_in_order_before_readers() {
+        let (device, _queue) = device();
+        let output = buffer(&device);
+        let mut builder = RenderGraphBuilder::new();
+        let output = builder.import_buffer("output", &output);
+        let scene = builder.create_texture("scene", texture_desc());
+        builder
+            .add_pass("opaque")
+            .write(scene, Access::Storage)
+            .record(|_, _| {});
+        builder
+            .add_pass("composite")
+            .read(scene, Access::Sampled)
+            .write(output, Access::Storage)
+            .record(|_, _| {});
+        builder
+            .add_pass("transparent")
+            .write(scene, Access::Storage)
+            .record(|_, _| {});
+        builder
+            .add_pass("overlay")
+            .read(scene, Access::Storage)
+            .write(scene, Access::Storage)
+            .record(|_, _| {});
+
+        assert_eq!(
+            order(&builder).unwrap(),
+            ["opaque", "transparent", "overlay", "composite"]
+        );
+    }
+
+    #[test]
+    fn passes_not_reaching_imports_are_culled() {
+        let (device, _queue) = device();
+        let output = buffer(&device);
+        let mut builder = RenderGraphBuilder::new();
+        let output = builder.import_buffer("output", &output);
+        let scene = builder.create_texture("scene", texture_desc());
+        let debug = builder.create_texture("debug", texture_desc());
+        builder
+            .add_pass("scene")
+            .write(scene, Access::Storage)
+            .record(|_, _| {});
+        builder
+            .add_pass("debug")
+            .read(scene, Access::Sampled)
+            .write(debug, Access::Storage)
+            .record(|_, _| {});
+        builder
+            .add_pass("composite")
+            .read(scene, Access::Sampled)
+            .write(output, Access::Storage)
+            .record(|_, _| {});
+
+        assert_eq!(order(&builder).unwrap(), ["scene", "composite"]);
+    }
+
+    #[test]
+    fn cycle() {
+        let (device, _queue) = device();
+        let output = buffer(&device);
+        let mut builder = RenderGraphBuilder::new();
+        let output = builder.import_buffer("output", &output);
+        let a = builder.create_texture("a", texture_desc());
+        let b = builder.create_texture("b", texture_desc());
+        builder
+            .add_pass("first")
+            .read(b, Access::Sampled)
+            .write(a, Access::Storage)
+            .record(|_, _| {});
+        builder
+            .add_pass("second")
+            .read(a, Access::Sampled)
+            .write(b, Access::Storage)
+            .record(|_, _| {});
+        builder
+            .add_pass("composite")
+            .read(b, Access::Sampled)
+            .write(output, Access::Storage)
+            .record(|_, _| {});
+
+        assert_eq!(
+            order(&builder),
+            Err(RenderGraphError::Cycle(vec![
+                "first".to_string(),
+                "second".to_string(),
+                "composite".to_string(),
+            ]))
+        );
+    }
+
+    #[test]
+    fn unwritten() {
+        let (device, _queue) = device();
+        let output = buffer(&device);
+        let mut builder = RenderGraphBuilder::new();
+        let output = builder.import_buffer("output", &output);
+        let scene = builder.create_texture("scene", texture_desc());
+        builder
+            .add_pass("composite")
+            .read(scene, Access::Sampled)
+            .write(output, Access::Storage)
+            .record(|_, _| {});
+
+        assert_eq!(
+            order(&builder),
+            Err(RenderGraphError::Unwritten {
+                pass: "composite".to_string(),
+                resource: "scene".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn invalid_access() {
+        let (device, _queue) = device();
+        let output_buffer = buffer(&device);
+        let mut builder = RenderGraphBuilder::new();
+        let output = builder.import_buffer("output", &output_buffer);
+        let scene = builder.create_texture("scene", texture_desc());
+        builder
+            .add_pass("scene")
+            .write(scene, Access::Vertex)
+            .record(|_, _| {});
+        builder
+            .add_pass("composite")
+            .read(scene, Access::Sampled)
+            .write(output, Access::Storage)
+            .record(|_, _| {});
+        assert_eq!(
+            order(&builder),
+            Err(RenderGraphError::InvalidAccess {
+                pass: "scene".to_string(),
+                resource: "scene".to_string(),
+                access: Access::Vertex,
+            })
+        );
+
+        let mut builder = RenderGraphBuilder::new();
+        let output = builder.import_buffer("output", &output_buffer);
+        builder
+            .add_pass("composite")
+            .read(output, Access::Sampled)
+            .record(|_, _| {});
+        assert_eq!(
+            order(&builder),
+            Err(RenderGraphError::InvalidAccess {
+                pass: "composite".to_string(),
+                resource: "output".to_string(),
+                access: Access::Sampled,
+            })
+        );
+    }
+
+    #[test]
+    fn transients_with_disjoint_lifetimes_share_textures() {
+        let (device, _queue) = device();
+        let output_buffer = buffer(&device);
+        let mut graph = RenderGraph::new();
+        for _ in 0..2 {
+            // Each texture is written by one pass and read by the next, so the first and
+            // the last are never alive at the same time.
+            let mut builder = RenderGraphBuilder::new();
+            let output = builder.import_buffer("output", &output_buffer);
+            let textures = ["a", "b", "c"].map(|name| builder.create_texture(name, texture_desc()));
+            builder
+                .add_pass("a")
+                .write(textures[0], Access::Storage)
+                .record(|_, _| {});
+            builder
+                .add_pass("b")
+                .read(textures[0], Access::Storage)
+                .write(textures[1], Access::Storage)
+                .record(|_, _| {});
+            builder
+                .add_pass("c")
+                .read(textures[1], Access::Storage)
+                .write(textures[2], Access::Storage)
+                .record(|_, _| {});
+            builder
+                .add_pass("output")
+                .read(textures[2], Access::Storage)
+                .write(output, Access::Storage)
+                .record(|_, _| {});
+
+            let mut encoder = device.create_command_encoder(&Default::default());
+            graph.execute(&device, &mut encoder, builder).unwrap();
+            assert_eq!(graph.textures.len(), 2);
+        }
+
+        // The pool drops textures the frame didn't use.
+        let mut builder = RenderGraphBuilder::new();
+        let output = builder.import_buffer("output", &output_buffer);
+        builder
+            .add_pass("output")
+            .write(output, Access::Storage)
+            .record(|_, _| {});
+        let mut encoder = device.create_command_encoder(&Default::default());
+        graph.execute(&device, &mut encoder, builder).unwrap();
+        assert!(graph.textures.is_empty());
+    }
+}
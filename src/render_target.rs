@@ -0,0 +1,261 @@
+use glam::UVec2;
+
+use crate::color::ColorSpace;
+
+#[derive(Debug, Clone)]
+pub struct RenderTargetOptions {
+    /// `None` for depth-only targets. `Rgba16Float` keeps HDR colors, see `ColorSpace`.
+    pub color_format: Option<wgpu::TextureFormat>,
+    /// `None` for targets without depth.
+    pub depth_format: Option<wgpu::TextureFormat>,
+    /// Multisampled color is resolved to a single-sampled texture that can be sampled.
+    pub sample_count: u32,
+    /// Size relative to the window, e.g. 0.5 for half resolution.
+    pub scale: f32,
+    /// Usages of the sampled color texture on top of `RENDER_ATTACHMENT | TEXTURE_BINDING`.
+    pub usage: wgpu::TextureUsages,
+}
+
+#[derive(Debug)]
+pub struct Attachment {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+}
+
+/// Color and depth attachments sized relative to the window. The runner resizes targets
+/// returned by `WgpuApp::render_targets` before passing `WindowEvent::Resized` to the app,
+/// others need `resize` called with the window size.
+#[derive(Debug)]
+pub struct RenderTarget {
+    options: RenderTargetOptions,
+    window_size: UVec2,
+    size: UVec2,
+    color: Option<Attachment>,
+    /// Single-sampled texture the multisampled color resolves to.
+    resolve: Option<Attachment>,
+    depth: Option<Attachment>,
+}
+
+impl Default for RenderTargetOptions {
+    fn default() -> Self {
+        Self {
+            color_format: Some(wgpu::TextureFormat::Rgba8UnormSrgb),
+            depth_format: Some(wgpu::TextureFormat::Depth32Float),
+            sample_count: 1,
+            scale: 1.0,
+            usage: wgpu::TextureUsages::empty(),
+        }
+    }
+}
+
+impl RenderTarget {
+    pub fn new(device: &wgpu::Device, window_size: UVec2, options: RenderTargetOptions) -> Self {
+        let mut target = Self {
+            options,
+            window_size,
+            size: UVec2::ZERO,
+            color: None,
+            resolve: None,
+            depth: None,
+        };
+        target.create_attachments(device);
+
+        target
+    }
+
+    /// Recreates the attachments if the window size changed and returns whether it did, in
+    /// which case bind groups using them need to be recreated too.
+    pub fn resize(&mut self, device: &wgpu::Device, window_size: UVec2) -> bool {
+        if window_size == self.window_size {
+            return false;
+        }
+
+        self.window_size = window_size;
+        self.create_attachments(device);
+
+        true
+    }
+
+    pub fn options(&self) -> &RenderTargetOptions {
+        &self.options
+    }
+
+    pub fn size(&self) -> UVec2 {
+        self.size
+    }
+
+    /// Window size the attachments were last created for.
+    pub fn window_size(&self) -> UVec2 {
+        self.window_size
+    }
+
+    /// Color space of the color attachment, `None` for depth-only targets.
+    pub fn color_space(&self) -> Option<ColorSpace> {
+        self.options.color_format.map(ColorSpace::of)
+    }
+
+    /// The view to render color to, multisampled if the sample count is above 1.
+    pub fn color_view(&self) -> Option<&wgpu::TextureView> {
+        self.color.as_ref().map(|color| &color.view)
+    }
+
+    /// The single-sampled color texture to sample from.
+    pub fn sampled(&self) -> Option<&Attachment> {
+        self.resolve.as_ref().or(self.color.as_ref())
+    }
+
+    pub fn sampled_view(&self) -> Option<&wgpu::TextureView> {
+        self.sampled().map(|sampled| &sampled.view)
+    }
+
+    pub fn depth_view(&self) -> Option<&wgpu::TextureView> {
+        self.depth.as_ref().map(|depth| &depth.view)
+    }
+
+    /// Color attachment that resolves multisampled color and stores the result.
+    pub fn color_attachment(
+        &self,
+        load: wgpu::LoadOp<wgpu::Color>,
+    ) -> Option<wgpu::RenderPassColorAttachment<'_>> {
+        let color = self.color.as_ref()?;
+
+        Some(wgpu::RenderPassColorAttachment {
+            view: &color.view,
+            resolve_target: self.resolve.as_ref().map(|resolve| &resolve.view),
+            ops: wgpu::Operations {
+                load,
+                store: wgpu::StoreOp::Store,
+            },
+            depth_slice: None,
+        })
+    }
+
+    /// Depth attachment with `depth_ops`, and stencil cleared to 0 if the format has it.
+    pub fn depth_stencil_attachment(
+        &self,
+        depth_ops: wgpu::Operations<f32>,
+    ) -> Option<wgpu::RenderPassDepthStencilAttachment<'_>> {
+        let depth = self.depth.as_ref()?;
+        let has_stencil = depth.texture.format().has_stencil_aspect();
+
+        Some(wgpu::RenderPassDepthStencilAttachment {
+            view: &depth.view,
+            depth_ops: Some(depth_ops),
+            stencil_ops: has_stencil.then_some(wgpu::Operations {
+                load: wgpu::LoadOp::Clear(0),
+                store: wgpu::StoreOp::Store,
+            }),
+        })
+    }
+
+    /// Depth state for pipelines drawing to this target, or `None` without depth.
+    pub fn depth_stencil_state(
+        &self,
+        depth_write_enabled: bool,
+        depth_compare: wgpu::CompareFunction,
+    ) -> Option<wgpu::DepthStencilState> {
+        self.options
+            .depth_format
+            .map(|format| wgpu::DepthStencilState {
+                format,
+                depth_write_enabled,
+                depth_compare,
+                stencil: Default::default(),
+                bias: Default::default(),
+            })
+    }
+
+    pub fn multisample_state(&self) -> wgpu::MultisampleState {
+        wgpu::MultisampleState {
+            count: self.options.sample_count,
+            ..Default::default()
+        }
+    }
+
+    fn create_attachments(&mut self, device: &wgpu::Device) {
+        let options = &self.options;
+        self.size = (self.window_size.as_vec2() * options.scale)
+            .round()
+            .as_uvec2()
+            .max(UVec2::ONE);
+        let multisampled = options.sample_count > 1;
+
+        let sampled_usage = wgpu::TextureUsages::RENDER_ATTACHMENT
+            | wgpu::TextureUsages::TEXTURE_BINDING
+            | options.usage;
+        self.color = options.color_format.map(|format| {
+            let (sample_count, usage) = if multisampled {
+                (options.sample_count, wgpu::TextureUsages::RENDER_ATTACHMENT)
+            } else {
+                (1, sampled_usage)
+            };
+            create_attachment(device, self.size, format, sample_count, usage)
+        });
+        self.resolve = options
+            .color_format
+            .filter(|_| multisampled)
+            .map(|format| create_attachment(device, self.size, format, 1, sampled_usage));
+
+        // Multisampled depth can't be sampled as a regular depth texture.
+        let depth_usage = if multisampled {
+            wgpu::TextureUsages::RENDER_ATTACHMENT
+        } else {
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING
+        };
+        self.depth = options.depth_format.map(|format| {
+            create_attachment(device, self.size, format, options.sample_count, depth_usage)
+        });
+    }
+}
+
+pub(crate) fn create_attachment(
+    device: &wgpu::Device,
+    size: UVec2,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+    usage: wgpu::TextureUsages,
+) -> Attachment {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        size: wgpu::Extent3d {
+            width: size.x,
+            height: size.y,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage,
+        view_formats: &[],
+        label: None,
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    Attachment { texture, view }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resize() {
+        let (device, _queue) = wgpu::Device::noop(&wgpu::DeviceDescriptor::default());
+        let options = RenderTargetOptions {
+            sample_count: 4,
+            scale: 0.5,
+            ..Default::default()
+        };
+        let mut target = RenderTarget::new(&device, UVec2::new(101, 50), options);
+        assert_eq!(target.size(), UVec2::new(51, 25));
+        assert_eq!(target.color_view().unwrap().texture().sample_count(), 4);
+        assert_eq!(target.sampled().unwrap().texture.sample_count(), 1);
+
+        assert!(!target.resize(&device, UVec2::new(101, 50)));
+        assert!(target.resize(&device, UVec2::new(1, 1)));
+        assert_eq!(target.window_size(), UVec2::new(1, 1));
+        assert_eq!(target.size(), UVec2::ONE);
+        let depth = target.depth_view().unwrap().texture();
+        assert_eq!((depth.width(), depth.height()), (1, 1));
+    }
+}
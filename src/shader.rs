@@ -0,0 +1,257 @@
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use pollster::FutureExt;
+
+use crate::preprocessor::{ComposedShader, ShaderComposer};
+use crate::reflection::{ReflectionError, ShaderReflection};
+
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// WGSL shader file together with the source embedded at compile time.
+/// Use `shader_source!` to build one from a path relative to the crate root.
+#[derive(Debug, Clone)]
+pub struct ShaderSource {
+    pub path: PathBuf,
+    pub embedded: &'static str,
+}
+
+#[macro_export]
+macro_rules! shader_source {
+    ($path:literal) => {
+        $crate::shader::ShaderSource {
+            path: ::std::path::PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/", $path)),
+            embedded: include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/", $path)),
+        }
+    };
+}
+
+#[derive(Debug, Clone)]
+pub struct ShaderError {
+    pub path: PathBuf,
+    /// One `file:line:column: message` entry per compilation error.
+    pub messages: Vec<String>,
+}
+
+/// Shader module that follows its WGSL file and the files it includes on disk in debug builds.
+/// Release builds compile the embedded source once; their includes must then come from
+/// modules registered in the `ShaderComposer`.
+#[derive(Debug)]
+pub struct ShaderAsset {
+    source: ShaderSource,
+    composer: ShaderComposer,
+    code: String,
+    module: wgpu::ShaderModule,
+
+    watch: bool,
+    watched_files: Vec<(PathBuf, Option<SystemTime>)>,
+    last_poll: Instant,
+}
+
+impl ShaderSource {
+    fn read(&self) -> String {
+        std::fs::read_to_string(&self.path).unwrap_or_else(|err| {
+            tracing::warn!(
+                "Can't read {}, using embedded source: {}",
+                self.path.display(),
+                err
+            );
+            self.embedded.to_string()
+        })
+    }
+}
+
+impl ShaderAsset {
+    pub fn new(device: &wgpu::Device, source: ShaderSource) -> Result<Self, ShaderError> {
+        Self::with_composer(device, source, ShaderComposer::default())
+    }
+
+    /// Preprocesses the source with `composer`, e.g. to select a permutation with defines.
+    pub fn with_composer(
+        device: &wgpu::Device,
+        source: ShaderSource,
+        composer: ShaderComposer,
+    ) -> Result<Self, ShaderError> {
+        let watch = cfg!(debug_assertions);
+        let code = if watch {
+            source.read()
+        } else {
+            source.embedded.to_string()
+        };
+        let composed = composer.compose(&source.path, &code)?;
+        let module = compile(device, &composed)?;
+
+        Ok(Self {
+            watched_files: watched_files(&composed),
+            source,
+            composer,
+            code: composed.code,
+            module,
+            watch,
+            last_poll: Instant::now(),
+        })
+    }
+
+    pub fn module(&self) -> &wgpu::ShaderModule {
+        &self.module
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.source.path
+    }
+
+    /// Reflects the preprocessed source the current module was compiled from.
+    pub fn reflect(&self) -> Result<ShaderReflection, ReflectionError> {
+        ShaderReflection::from_wgsl(&self.code)
+    }
+
+    /// Checks the file for changes, at most every half a second. When it changed and compiles,
+    /// `rebuild` creates the dependent pipelines from the new module. The module and the
+    /// pipelines returned by `rebuild` replace the old ones only if neither raised a validation
+    /// error; otherwise the errors are logged with file and line and `None` is returned.
+    pub fn poll<P>(
+        &mut self,
+        device: &wgpu::Device,
+        rebuild: impl FnOnce(&wgpu::ShaderModule) -> P,
+    ) -> Option<P> {
+        if !self.watch || self.last_poll.elapsed() < POLL_INTERVAL {
+            return None;
+        }
+        self.last_poll = Instant::now();
+
+        let changed = self
+            .watched_files
+            .iter()
+            .any(|(path, modified)| modified_time(path) != *modified);
+        if !changed {
+            return None;
+        }
+        for (path, modified) in self.watched_files.iter_mut() {
+            *modified = modified_time(path);
+        }
+
+        let code = self.source.read();
+        let compiled = self
+            .composer
+            .compose(&self.source.path, &code)
+            .and_then(|composed| {
+                self.watched_files = watched_files(&composed);
+                compile(device, &composed).map(|module| (composed.code, module))
+            });
+        let (code, module) = match compiled {
+            Ok(compiled) => compiled,
+            Err(err) => {
+                tracing::error!("{}", err);
+                return None;
+            }
+        };
+
+        let error_scope = device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let rebuilt = rebuild(&module);
+        if let Some(err) = error_scope.pop().block_on() {
+            tracing::error!(
+                "Failed to rebuild pipelines for {}: {}",
+                self.source.path.display(),
+                err
+            );
+            return None;
+        }
+
+        tracing::info!("Reloaded {}", self.source.path.display());
+        self.code = code;
+        self.module = module;
+
+        Some(rebuilt)
+    }
+}
+
+pub(crate) fn compile(
+    device: &wgpu::Device,
+    composed: &ComposedShader,
+) -> Result<wgpu::ShaderModule, ShaderError> {
+    let path = composed.files[0].as_path();
+
+    let error_scope = device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: path.file_name().and_then(|name| name.to_str()),
+        source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(&composed.code)),
+    });
+    let error = error_scope.pop().block_on();
+
+    let messages = module
+        .get_compilation_info()
+        .block_on()
+        .messages
+        .into_iter()
+        .filter(|message| message.message_type == wgpu::CompilationMessageType::Error)
+        .map(|message| {
+            let location = message.location.and_then(|location| {
+                composed
+                    .source_location(location.line_number)
+                    .map(|(file, line)| (file, line, location.line_position))
+            });
+            match location {
+                Some((file, line, column)) => format!(
+                    "{}:{}:{}: {}",
+                    file.display(),
+                    line,
+                    column,
+                    message.message
+                ),
+                None => format!("{}: {}", path.display(), message.message),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    match (error, messages.is_empty()) {
+        (None, true) => Ok(module),
+        (Some(error), true) => Err(ShaderError {
+            path: path.to_path_buf(),
+            messages: vec![format!("{}: {}", path.display(), error)],
+        }),
+        (_, false) => Err(ShaderError {
+            path: path.to_path_buf(),
+            messages,
+        }),
+    }
+}
+
+/// Composes and compiles a shader shipped with the library, which is expected to be valid.
+pub(crate) fn compile_builtin(
+    device: &wgpu::Device,
+    name: &str,
+    source: &str,
+) -> wgpu::ShaderModule {
+    ShaderComposer::default()
+        .compose(Path::new(name), source)
+        .and_then(|composed| compile(device, &composed))
+        .unwrap_or_else(|err| panic!("Built-in shader failed to compile: {}", err))
+}
+
+fn watched_files(composed: &ComposedShader) -> Vec<(PathBuf, Option<SystemTime>)> {
+    composed
+        .files
+        .iter()
+        .map(|path| (path.clone(), modified_time(path)))
+        .collect()
+}
+
+pub(crate) fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+impl std::fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Failed to compile {}", self.path.display())?;
+        for message in self.messages.iter() {
+            write!(f, "\n{}", message)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for ShaderError {}
@@ -0,0 +1,21 @@
+struct PushConstant {
+    mvp: mat4x4<f32>,
+};
+// Uniform fallback group, set by `DrawConstants::shader_composer` and 1 otherwise.
+#ifndef WGPU_APP_DRAW_CONSTANTS_GROUP
+#define WGPU_APP_DRAW_CONSTANTS_GROUP 1
+#endif
+#ifdef WGPU_APP_IMMEDIATES
+var<immediate> pc: PushConstant;
+#else
+@group(WGPU_APP_DRAW_CONSTANTS_GROUP)
+@binding(0)
+var<uniform> pc: PushConstant;
+#endif
+
+@group(0)
+@binding(0)
+var the_sampler: sampler;
+@group(0)
+@binding(1)
+var color: texture_2d<f32>;
@@ -0,0 +1,17 @@
+struct FullscreenOutput {
+    @builtin(position) position: vec4<f32>,
+    // Top-left origin, like texture coordinates.
+    @location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_fullscreen(
+    @location(0) position: vec4<f32>,
+    @location(1) uv: vec2<f32>,
+) -> FullscreenOutput {
+    var result: FullscreenOutput;
+    result.position = position;
+    result.uv = uv;
+
+    return result;
+}
@@ -0,0 +1,14 @@
+#include "wgpu_app/fullscreen.wgsl"
+
+@group(0)
+@binding(0)
+var the_sampler: sampler;
+@group(0)
+@binding(1)
+var color: texture_2d<f32>;
+
+@fragment
+fn fs_main(vertex: FullscreenOutput) -> @location(0) vec4<f32> {
+    let rgb = textureSample(color, the_sampler, vertex.uv).rgb;
+    return vec4<f32>(rgb, 1.0);
+}
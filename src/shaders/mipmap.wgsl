@@ -0,0 +1,27 @@
+@group(0)
+@binding(0)
+var source: texture_2d<f32>;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    // Fullscreen triangle.
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// Box filter over the 2x2 source texels of every destination texel. textureLoad doesn't need a
+// filterable format and an sRGB source view decodes to linear, so the average is gamma-correct.
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let max_texel = vec2<i32>(textureDimensions(source)) - 1;
+    let texel = vec2<i32>(position.xy) * 2;
+
+    var sum = vec4<f32>(0.0);
+    for (var y = 0; y < 2; y++) {
+        for (var x = 0; x < 2; x++) {
+            sum += textureLoad(source, min(texel + vec2<i32>(x, y), max_texel), 0);
+        }
+    }
+
+    return sum * 0.25;
+}
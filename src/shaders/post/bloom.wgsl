@@ -0,0 +1,70 @@
+#include "wgpu_app/post_process.wgsl"
+
+struct BloomParams {
+    threshold: f32,
+    // Width of the soft transition below the threshold.
+    knee: f32,
+    intensity: f32,
+    _padding: f32,
+};
+
+@group(0)
+@binding(2)
+var<uniform> params: BloomParams;
+
+// Dual filter downsample: the center and four diagonal bilinear taps.
+fn downsample(uv: vec2<f32>) -> vec4<f32> {
+    let offset = texel_size();
+
+    return (sample_source(uv) * 4.0
+        + sample_source(uv + vec2<f32>(-offset.x, -offset.y))
+        + sample_source(uv + vec2<f32>(offset.x, -offset.y))
+        + sample_source(uv + vec2<f32>(-offset.x, offset.y))
+        + sample_source(uv + vec2<f32>(offset.x, offset.y))) / 8.0;
+}
+
+// Downsamples and keeps only the part of the color above the threshold.
+@fragment
+fn fs_prefilter(vertex: FullscreenOutput) -> @location(0) vec4<f32> {
+    let color = downsample(vertex.uv).rgb;
+    let brightness = max(color.r, max(color.g, color.b));
+
+    let knee = max(params.knee, 0.00001);
+    var soft = clamp(brightness - params.threshold + knee, 0.0, 2.0 * knee);
+    soft = soft * soft / (4.0 * knee);
+    let contribution = max(soft, brightness - params.threshold) / max(brightness, 0.00001);
+
+    return vec4<f32>(color * contribution, 1.0);
+}
+
+@fragment
+fn fs_downsample(vertex: FullscreenOutput) -> @location(0) vec4<f32> {
+    return downsample(vertex.uv);
+}
+
+// 3x3 tent filter, added to the larger level by the blend state.
+@fragment
+fn fs_upsample(vertex: FullscreenOutput) -> @location(0) vec4<f32> {
+    let offset = texel_size();
+
+    var sum = sample_source(vertex.uv) * 4.0;
+    sum += (sample_source(vertex.uv + vec2<f32>(-offset.x, 0.0))
+        + sample_source(vertex.uv + vec2<f32>(offset.x, 0.0))
+        + sample_source(vertex.uv + vec2<f32>(0.0, -offset.y))
+        + sample_source(vertex.uv + vec2<f32>(0.0, offset.y))) * 2.0;
+    sum += sample_source(vertex.uv + vec2<f32>(-offset.x, -offset.y))
+        + sample_source(vertex.uv + vec2<f32>(offset.x, -offset.y))
+        + sample_source(vertex.uv + vec2<f32>(-offset.x, offset.y))
+        + sample_source(vertex.uv + vec2<f32>(offset.x, offset.y));
+
+    return vec4<f32>(sum.rgb / 16.0, 1.0);
+}
+
+// Adds the first bloom level, in `source`, to the effect input.
+@fragment
+fn fs_composite(vertex: FullscreenOutput) -> @location(0) vec4<f32> {
+    let color = textureSampleLevel(original, source_sampler, vertex.uv, 0.0);
+    let bloom = sample_source(vertex.uv).rgb;
+
+    return vec4<f32>(color.rgb + bloom * params.intensity, color.a);
+}
@@ -0,0 +1,42 @@
+#include "wgpu_app/post_process.wgsl"
+
+struct BlurParams {
+    // Texel step of the Gaussian pass, (1, 0) or (0, 1).
+    direction: vec2<f32>,
+    radius: f32,
+    // Distance in texels of the Kawase taps from the center.
+    offset: f32,
+};
+
+@group(0)
+@binding(2)
+var<uniform> params: BlurParams;
+
+// One direction of a separable Gaussian, with the radius at three standard deviations.
+@fragment
+fn fs_gaussian(vertex: FullscreenOutput) -> @location(0) vec4<f32> {
+    let step = params.direction * texel_size();
+    let sigma = max(params.radius / 3.0, 0.001);
+    let taps = i32(ceil(params.radius));
+
+    var sum = vec4<f32>(0.0);
+    var weight_sum = 0.0;
+    for (var i = -taps; i <= taps; i++) {
+        let weight = exp(-f32(i * i) / (2.0 * sigma * sigma));
+        sum += sample_source(vertex.uv + step * f32(i)) * weight;
+        weight_sum += weight;
+    }
+
+    return sum / weight_sum;
+}
+
+// One Kawase iteration: four bilinear taps, each averaging four texels, around the center.
+@fragment
+fn fs_kawase(vertex: FullscreenOutput) -> @location(0) vec4<f32> {
+    let offset = params.offset * texel_size();
+
+    return (sample_source(vertex.uv + vec2<f32>(-offset.x, -offset.y))
+        + sample_source(vertex.uv + vec2<f32>(offset.x, -offset.y))
+        + sample_source(vertex.uv + vec2<f32>(-offset.x, offset.y))
+        + sample_source(vertex.uv + vec2<f32>(offset.x, offset.y))) * 0.25;
+}
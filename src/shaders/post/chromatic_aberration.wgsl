@@ -0,0 +1,23 @@
+#include "wgpu_app/post_process.wgsl"
+
+struct ChromaticAberrationParams {
+    // Offset in texels of the red and blue channels at the edges.
+    intensity: f32,
+    _padding0: f32,
+    _padding1: f32,
+    _padding2: f32,
+};
+
+@group(0)
+@binding(2)
+var<uniform> params: ChromaticAberrationParams;
+
+@fragment
+fn fs_main(vertex: FullscreenOutput) -> @location(0) vec4<f32> {
+    let offset = (vertex.uv - 0.5) * 2.0 * params.intensity * texel_size();
+    let color = sample_source(vertex.uv);
+    let red = sample_source(vertex.uv + offset).r;
+    let blue = sample_source(vertex.uv - offset).b;
+
+    return vec4<f32>(red, color.g, blue, color.a);
+}
@@ -0,0 +1,6 @@
+#include "wgpu_app/post_process.wgsl"
+
+@fragment
+fn fs_main(vertex: FullscreenOutput) -> @location(0) vec4<f32> {
+    return sample_source(vertex.uv);
+}
@@ -0,0 +1,126 @@
+#include "wgpu_app/post_process.wgsl"
+
+struct FxaaParams {
+    // Minimum local contrast, relative to the brightest neighbor, to be treated as an edge.
+    edge_threshold: f32,
+    // Contrast below which dark areas are skipped.
+    edge_threshold_min: f32,
+    // Amount of subpixel aliasing removal.
+    subpixel: f32,
+    _padding: f32,
+};
+
+@group(0)
+@binding(2)
+var<uniform> params: FxaaParams;
+
+const EDGE_SEARCH_STEPS: i32 = 10;
+
+// Perceptual luma, the source being linear.
+fn luma_at(uv: vec2<f32>) -> f32 {
+    return sqrt(dot(sample_source(uv).rgb, vec3<f32>(0.299, 0.587, 0.114)));
+}
+
+fn edge_step_size(step: i32) -> f32 {
+    if step < 4 {
+        return 1.0;
+    } else if step < 6 {
+        return 2.0;
+    }
+    return 4.0;
+}
+
+// FXAA 3.11 style: finds the edge orientation from a 3x3 neighborhood, searches along the edge
+// for its ends and shifts the sample towards the other side of the edge.
+@fragment
+fn fs_main(vertex: FullscreenOutput) -> @location(0) vec4<f32> {
+    let texel = texel_size();
+    let uv = vertex.uv;
+    let center = sample_source(uv);
+
+    let luma_m = sqrt(dot(center.rgb, vec3<f32>(0.299, 0.587, 0.114)));
+    let luma_n = luma_at(uv + vec2<f32>(0.0, -texel.y));
+    let luma_s = luma_at(uv + vec2<f32>(0.0, texel.y));
+    let luma_w = luma_at(uv + vec2<f32>(-texel.x, 0.0));
+    let luma_e = luma_at(uv + vec2<f32>(texel.x, 0.0));
+
+    let luma_min = min(luma_m, min(min(luma_n, luma_s), min(luma_w, luma_e)));
+    let luma_max = max(luma_m, max(max(luma_n, luma_s), max(luma_w, luma_e)));
+    let range = luma_max - luma_min;
+    if range < max(params.edge_threshold_min, luma_max * params.edge_threshold) {
+        return center;
+    }
+
+    let luma_nw = luma_at(uv + vec2<f32>(-texel.x, -texel.y));
+    let luma_ne = luma_at(uv + vec2<f32>(texel.x, -texel.y));
+    let luma_sw = luma_at(uv + vec2<f32>(-texel.x, texel.y));
+    let luma_se = luma_at(uv + vec2<f32>(texel.x, texel.y));
+
+    let average = (2.0 * (luma_n + luma_s + luma_w + luma_e) + luma_nw + luma_ne + luma_sw
+        + luma_se) / 12.0;
+    let subpixel = smoothstep(0.0, 1.0, clamp(abs(average - luma_m) / range, 0.0, 1.0));
+    let subpixel_blend = subpixel * subpixel * params.subpixel;
+
+    // A horizontal edge separates the rows above and below.
+    let horizontal = 2.0 * abs(luma_n + luma_s - 2.0 * luma_m)
+        + abs(luma_ne + luma_se - 2.0 * luma_e)
+        + abs(luma_nw + luma_sw - 2.0 * luma_w);
+    let vertical = 2.0 * abs(luma_e + luma_w - 2.0 * luma_m)
+        + abs(luma_ne + luma_nw - 2.0 * luma_n)
+        + abs(luma_se + luma_sw - 2.0 * luma_s);
+    let is_horizontal = horizontal >= vertical;
+
+    let luma_positive = select(luma_e, luma_s, is_horizontal);
+    let luma_negative = select(luma_w, luma_n, is_horizontal);
+    let gradient_positive = abs(luma_positive - luma_m);
+    let gradient_negative = abs(luma_negative - luma_m);
+
+    var pixel_step = select(texel.x, texel.y, is_horizontal);
+    var opposite_luma = luma_positive;
+    var gradient = gradient_positive;
+    if gradient_positive < gradient_negative {
+        pixel_step = -pixel_step;
+        opposite_luma = luma_negative;
+        gradient = gradient_negative;
+    }
+
+    let across = select(vec2<f32>(1.0, 0.0), vec2<f32>(0.0, 1.0), is_horizontal);
+    let along = select(vec2<f32>(0.0, texel.y), vec2<f32>(texel.x, 0.0), is_horizontal);
+    let edge_uv = uv + across * pixel_step * 0.5;
+    let edge_luma = (luma_m + opposite_luma) * 0.5;
+    let gradient_threshold = gradient * 0.25;
+
+    var positive_uv = edge_uv + along;
+    var positive_delta = luma_at(positive_uv) - edge_luma;
+    var negative_uv = edge_uv - along;
+    var negative_delta = luma_at(negative_uv) - edge_luma;
+    for (var i = 0; i < EDGE_SEARCH_STEPS; i++) {
+        let positive_done = abs(positive_delta) >= gradient_threshold;
+        let negative_done = abs(negative_delta) >= gradient_threshold;
+        if positive_done && negative_done {
+            break;
+        }
+        if !positive_done {
+            positive_uv += along * edge_step_size(i);
+            positive_delta = luma_at(positive_uv) - edge_luma;
+        }
+        if !negative_done {
+            negative_uv -= along * edge_step_size(i);
+            negative_delta = luma_at(negative_uv) - edge_luma;
+        }
+    }
+
+    let positive_distance = dot(positive_uv - uv, along) / dot(along, along);
+    let negative_distance = dot(uv - negative_uv, along) / dot(along, along);
+    let closest_delta = select(negative_delta, positive_delta, positive_distance <= negative_distance);
+    let shortest = min(positive_distance, negative_distance);
+
+    // Blend only on the side of the edge the search ended on.
+    var edge_blend = 0.0;
+    if (closest_delta >= 0.0) != (luma_m - edge_luma >= 0.0) {
+        edge_blend = 0.5 - shortest / (positive_distance + negative_distance);
+    }
+
+    let blend = max(subpixel_blend, edge_blend);
+    return sample_source(uv + across * pixel_step * blend);
+}
@@ -0,0 +1,22 @@
+#include "wgpu_app/post_process.wgsl"
+
+struct VignetteParams {
+    intensity: f32,
+    radius: f32,
+    smoothness: f32,
+    _padding: f32,
+};
+
+@group(0)
+@binding(2)
+var<uniform> params: VignetteParams;
+
+@fragment
+fn fs_main(vertex: FullscreenOutput) -> @location(0) vec4<f32> {
+    let color = sample_source(vertex.uv);
+    // 0 in the center, 1 in the corners.
+    let edge_distance = length(vertex.uv - 0.5) * sqrt(2.0);
+    let darkening = smoothstep(params.radius, params.radius + params.smoothness, edge_distance);
+
+    return vec4<f32>(color.rgb * (1.0 - params.intensity * darkening), color.a);
+}
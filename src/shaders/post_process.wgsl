@@ -0,0 +1,23 @@
+#include "wgpu_app/fullscreen.wgsl"
+
+// Bindings of every post-processing pass. Effects with parameters declare their uniform as
+// `@group(0) @binding(2) var<uniform> params: Params;`.
+@group(0)
+@binding(0)
+var source_sampler: sampler;
+// Output of the previous pass.
+@group(0)
+@binding(1)
+var source: texture_2d<f32>;
+// Input of the effect, the same as `source` unless the effect has several passes.
+@group(0)
+@binding(3)
+var original: texture_2d<f32>;
+
+fn texel_size() -> vec2<f32> {
+    return 1.0 / vec2<f32>(textureDimensions(source));
+}
+
+fn sample_source(uv: vec2<f32>) -> vec4<f32> {
+    return textureSampleLevel(source, source_sampler, uv, 0.0);
+}
@@ -0,0 +1,72 @@
+struct Projection {
+    matrix: mat4x4<f32>,
+};
+
+@group(0)
+@binding(0)
+var the_sampler: sampler;
+@group(0)
+@binding(1)
+var color: texture_2d<f32>;
+
+@group(1)
+@binding(0)
+var<uniform> projection: Projection;
+
+struct SpriteOutput {
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+    @builtin(position) position: vec4<f32>,
+};
+
+// One instance per sprite, drawn as a four vertex triangle strip around its center.
+@vertex
+fn vs_sprite(
+    @builtin(vertex_index) vertex_index: u32,
+    @location(0) center: vec2<f32>,
+    @location(1) size: vec2<f32>,
+    @location(2) uv_rect: vec4<f32>,
+    @location(3) sprite_color: vec4<f32>,
+    @location(4) rotation: f32,
+) -> SpriteOutput {
+    let corner = vec2<f32>(f32(vertex_index >> 1u), f32(vertex_index & 1u));
+    let offset = size * (corner - 0.5);
+    let c = cos(rotation);
+    let s = sin(rotation);
+    let position = center + vec2<f32>(c * offset.x - s * offset.y, s * offset.x + c * offset.y);
+
+    // The top of the image goes to the corner with the higher y, unless the projection
+    // flips y and the top is at the lower one.
+    let y_down = projection.matrix[1][1] < 0.0;
+    let v = select(1.0 - corner.y, corner.y, y_down);
+
+    var result: SpriteOutput;
+    result.position = projection.matrix * vec4<f32>(position, 0.0, 1.0);
+    result.uv = mix(uv_rect.xy, uv_rect.zw, vec2<f32>(corner.x, v));
+    result.color = sprite_color;
+
+    return result;
+}
+
+@fragment
+fn fs_sprite(vertex: SpriteOutput) -> @location(0) vec4<f32> {
+    return textureSample(color, the_sampler, vertex.uv) * vertex.color;
+}
+
+// Single-channel textures such as glyph atlases are masks: red is the coverage of the
+// vertex color, scaling alpha only since blending is straight alpha.
+@fragment
+fn fs_sprite_mask(vertex: SpriteOutput) -> @location(0) vec4<f32> {
+    let coverage = textureSample(color, the_sampler, vertex.uv).r;
+    return vec4<f32>(vertex.color.rgb, vertex.color.a * coverage);
+}
+
+// Signed distance fields hold 0.5 on the edge, antialiased over about a pixel. Like masks,
+// the coverage only scales alpha.
+@fragment
+fn fs_sprite_sdf(vertex: SpriteOutput) -> @location(0) vec4<f32> {
+    let distance = textureSample(color, the_sampler, vertex.uv).r;
+    let width = 0.5 * fwidth(distance);
+    let coverage = smoothstep(0.5 - width, 0.5 + width, distance);
+    return vec4<f32>(vertex.color.rgb, vertex.color.a * coverage);
+}
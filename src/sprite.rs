@@ -0,0 +1,501 @@
+use std::ops::Range;
+
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, UVec2, Vec2};
+
+use crate::buffer::{DynamicBuffer, UniformBuffer};
+use crate::layout::VertexLayout;
+use crate::shader;
+use crate::texture::Texture;
+
+const INITIAL_CAPACITY: usize = 256;
+
+/// Textured quad drawn by a `SpriteBatch`, stored as one instance at locations 0 to 4.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Pod, Zeroable)]
+pub struct Sprite {
+    pub center: Vec2,
+    pub size: Vec2,
+    /// Texture coordinates of the top-left and bottom-right corners of the image,
+    /// `[left, top, right, bottom]`. The image stays upright whichever way the projection
+    /// points y.
+    pub uv_rect: [f32; 4],
+    /// Multiplies the texture, straight alpha.
+    pub color: [f32; 4],
+    /// Counter-clockwise around the center, in radians.
+    pub rotation: f32,
+    /// Sprites are drawn in increasing depth. Only used for sorting.
+    pub depth: f32,
+}
+
+/// Draws many sprites with one instanced draw per run of the same texture.
+/// `clear` at the start of the frame, `push` sprites, `flush` into the frame's encoder,
+/// `render` in a pass after it and call `after_submit` once the encoder is submitted. `flush` sorts them by depth and then by texture, so sprites of equal depth
+/// may not keep their push order. Single-channel textures such as glyph atlases are masks:
+/// their red channel scales the sprite color. `push_sdf` draws signed distance fields.
+#[derive(Debug)]
+pub struct SpriteBatch {
+    pipeline: wgpu::RenderPipeline,
+    mask_pipeline: wgpu::RenderPipeline,
+    sdf_pipeline: wgpu::RenderPipeline,
+    texture_layout: wgpu::BindGroupLayout,
+    projection: UniformBuffer<Mat4>,
+
+    instances: DynamicBuffer<Sprite>,
+    sorted: Vec<Sprite>,
+
+    textures: Vec<BatchTexture>,
+    pending: Vec<(u32, Sprite)>,
+    bind_groups: Vec<(TextureKey, wgpu::BindGroup)>,
+    draws: Vec<SpriteDraw>,
+}
+
+type TextureKey = (wgpu::TextureView, wgpu::Sampler);
+
+/// How the fragment shader turns texels into colors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Shading {
+    Color,
+    Mask,
+    Sdf,
+}
+
+#[derive(Debug)]
+struct BatchTexture {
+    key: TextureKey,
+    shading: Shading,
+}
+
+impl BatchTexture {
+    fn is(&self, (view, sampler): (&wgpu::TextureView, &wgpu::Sampler), shading: Shading) -> bool {
+        self.key.0 == *view && self.key.1 == *sampler && self.shading == shading
+    }
+}
+
+#[derive(Debug)]
+struct SpriteDraw {
+    bind_group: wgpu::BindGroup,
+    shading: Shading,
+    instances: Range<u32>,
+}
+
+impl Default for Sprite {
+    fn default() -> Self {
+        Self {
+            center: Vec2::ZERO,
+            size: Vec2::ONE,
+            uv_rect: [0.0, 0.0, 1.0, 1.0],
+            color: [1.0; 4],
+            rotation: 0.0,
+            depth: 0.0,
+        }
+    }
+}
+
+impl VertexLayout for Sprite {
+    const ATTRIBUTES: &'static [wgpu::VertexAttribute] = &wgpu::vertex_attr_array![
+        0 => Float32x2, 1 => Float32x2, 2 => Float32x4, 3 => Float32x4, 4 => Float32
+    ];
+    const STEP_MODE: wgpu::VertexStepMode = wgpu::VertexStepMode::Instance;
+}
+
+/// Orthographic projection that fits the square from 0 to 1 in the middle of a viewport of
+/// `size`, y up, showing more of the longer axis.
+pub fn unit_square_projection(size: UVec2) -> Mat4 {
+    let (width, height) = (size.x as f32, size.y as f32);
+    if width > height {
+        let margin = (height / width - 1.0) / 2.0;
+        Mat4::orthographic_rh(0.0, 1.0, -margin, 1.0 + margin, 0.0, 1.0)
+    } else {
+        let margin = (width / height - 1.0) / 2.0;
+        Mat4::orthographic_rh(-margin, 1.0 + margin, 0.0, 1.0, 0.0, 1.0)
+    }
+}
+
+/// Orthographic projection in pixels of a viewport of `size`, from the top-left corner,
+/// y down.
+pub fn pixel_projection(size: UVec2) -> Mat4 {
+    Mat4::orthographic_rh(0.0, size.x as f32, size.y as f32, 0.0, 0.0, 1.0)
+}
+
+impl SpriteBatch {
+    /// Draws into color targets of `format`, blending with straight alpha. Textures must be
+    /// filterable.
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        depth_stencil: Option<wgpu::DepthStencilState>,
+        multisample: wgpu::MultisampleState,
+    ) -> Self {
+        let texture_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("Sprite Texture"),
+        });
+        let projection = UniformBuffer::new(device, &Mat4::IDENTITY, wgpu::ShaderStages::VERTEX);
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            bind_group_layouts: &[&texture_layout, projection.bind_group_layout()],
+            immediate_size: 0,
+            label: None,
+        });
+        let module = shader::compile_builtin(
+            device,
+            "wgpu_app/sprite.wgsl",
+            include_str!("shaders/sprite.wgsl"),
+        );
+        let create_pipeline = |fragment_entry| {
+            create_pipeline(
+                device,
+                &pipeline_layout,
+                &module,
+                fragment_entry,
+                format,
+                depth_stencil.clone(),
+                multisample,
+            )
+        };
+
+        Self {
+            pipeline: create_pipeline("fs_sprite"),
+            mask_pipeline: create_pipeline("fs_sprite_mask"),
+            sdf_pipeline: create_pipeline("fs_sprite_sdf"),
+            texture_layout,
+            projection,
+            instances: DynamicBuffer::new(device, wgpu::BufferUsages::VERTEX, INITIAL_CAPACITY),
+            sorted: vec![],
+            textures: vec![],
+            pending: vec![],
+            bind_groups: vec![],
+            draws: vec![],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.textures.clear();
+        self.pending.clear();
+    }
+
+    pub fn push(&mut self, texture: &Texture, sprite: Sprite) {
+        let shading = if texture.texture.format().components() == 1 {
+            Shading::Mask
+        } else {
+            Shading::Color
+        };
+        self.push_shaded(texture, sprite, shading);
+    }
+
+    /// Pushes a sprite whose texture holds a signed distance field in its red channel: 0.5 on
+    /// the edge and more inside, which stays sharp at any scale.
+    pub fn push_sdf(&mut self, texture: &Texture, sprite: Sprite) {
+        self.push_shaded(texture, sprite, Shading::Sdf);
+    }
+
+    fn push_shaded(&mut self, texture: &Texture, sprite: Sprite, shading: Shading) {
+        // Frames use few textures, usually pushing runs of the same one.
+        let key = (&texture.view, &texture.sampler);
+        let index = match self.pending.last() {
+            Some(&(index, _)) if self.textures[index as usize].is(key, shading) => index,
+            _ => match self
+                .textures
+                .iter()
+                .position(|batch| batch.is(key, shading))
+            {
+                Some(index) => index as u32,
+                None => {
+                    self.textures.push(BatchTexture {
+                        key: (texture.view.clone(), texture.sampler.clone()),
+                        shading,
+                    });
+                    self.textures.len() as u32 - 1
+                }
+            },
+        };
+        self.pending.push((index, sprite));
+    }
+
+    pub fn set_projection(&self, queue: &wgpu::Queue, projection: Mat4) {
+        self.projection.write(queue, &projection);
+    }
+
+    /// Sorts the sprites pushed since `clear` and records their upload into `encoder`, once
+    /// per submission, see `DynamicBuffer::write`. Bind groups of textures that weren't
+    /// pushed are dropped.
+    pub fn flush(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
+        self.pending.sort_by(|(a_texture, a), (b_texture, b)| {
+            a.depth.total_cmp(&b.depth).then(a_texture.cmp(b_texture))
+        });
+
+        let textures = &self.textures;
+        self.bind_groups
+            .retain(|(key, _)| textures.iter().any(|texture| texture.key == *key));
+        let bind_groups: Vec<wgpu::BindGroup> = self
+            .textures
+            .iter()
+            .map(
+                |texture| match self.bind_groups.iter().find(|(key, _)| texture.key == *key) {
+                    Some((_, bind_group)) => bind_group.clone(),
+                    None => {
+                        let bind_group =
+                            create_texture_bind_group(device, &self.texture_layout, &texture.key);
+                        self.bind_groups
+                            .push((texture.key.clone(), bind_group.clone()));
+                        bind_group
+                    }
+                },
+            )
+            .collect();
+
+        self.draws.clear();
+        for (index, (texture, _)) in self.pending.iter().enumerate() {
+            let index = index as u32;
+            match self.draws.last_mut() {
+                Some(draw) if self.pending[draw.instances.start as usize].0 == *texture => {
+                    draw.instances.end = index + 1;
+                }
+                _ => self.draws.push(SpriteDraw {
+                    bind_group: bind_groups[*texture as usize].clone(),
+                    shading: self.textures[*texture as usize].shading,
+                    instances: index..index + 1,
+                }),
+            }
+        }
+
+        self.sorted.clear();
+        self.sorted
+            .extend(self.pending.iter().map(|(_, sprite)| *sprite));
+        self.instances.write(device, encoder, &self.sorted);
+    }
+
+    /// See `DynamicBuffer::after_submit`.
+    pub fn after_submit(&mut self) {
+        self.instances.after_submit();
+    }
+
+    /// Draws the sprites uploaded by the last `flush`. Sets groups 0 and 1 and vertex
+    /// buffer 0.
+    pub fn render(&self, pass: &mut wgpu::RenderPass) {
+        if self.draws.is_empty() {
+            return;
+        }
+
+        pass.set_bind_group(1, self.projection.bind_group(), &[]);
+        pass.set_vertex_buffer(0, self.instances.slice());
+        let mut shading = None;
+        for draw in self.draws.iter() {
+            if shading != Some(draw.shading) {
+                shading = Some(draw.shading);
+                pass.set_pipeline(match draw.shading {
+                    Shading::Color => &self.pipeline,
+                    Shading::Mask => &self.mask_pipeline,
+                    Shading::Sdf => &self.sdf_pipeline,
+                });
+            }
+            pass.set_bind_group(0, &draw.bind_group, &[]);
+            pass.draw(0..4, draw.instances.clone());
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Instanced draws the last `flush` needs.
+    pub fn draw_count(&self) -> usize {
+        self.draws.len()
+    }
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    module: &wgpu::ShaderModule,
+    fragment_entry: &str,
+    format: wgpu::TextureFormat,
+    depth_stencil: Option<wgpu::DepthStencilState>,
+    multisample: wgpu::MultisampleState,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(fragment_entry),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module,
+            entry_point: Some("vs_sprite"),
+            buffers: &[Sprite::layout()],
+            compilation_options: Default::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module,
+            entry_point: Some(fragment_entry),
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState {
+                    color: wgpu::BlendComponent {
+                        operation: wgpu::BlendOperation::Add,
+                        src_factor: wgpu::BlendFactor::SrcAlpha,
+                        dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                    },
+                    alpha: wgpu::BlendComponent {
+                        operation: wgpu::BlendOperation::Add,
+                        src_factor: wgpu::BlendFactor::One,
+                        dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                    },
+                }),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: Default::default(),
+        }),
+        primitive: wgpu::PrimitiveState {
+            cull_mode: None,
+            topology: wgpu::PrimitiveTopology::TriangleStrip,
+
+            ..Default::default()
+        },
+        depth_stencil,
+        multisample,
+        multiview_mask: None,
+        cache: None,
+    })
+}
+
+fn create_texture_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    (view, sampler): &TextureKey,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(view),
+            },
+        ],
+        label: Some("Sprite Texture"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::texture::TextureOptions;
+
+    fn texture(device: &wgpu::Device, format: wgpu::TextureFormat) -> Texture {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: 4,
+                height: 4,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+            label: None,
+        });
+        Texture::new(device, texture, &TextureOptions::default())
+    }
+
+    fn sprite(depth: f32) -> Sprite {
+        Sprite {
+            depth,
+            ..Default::default()
+        }
+    }
+
+    /// Shading and instances of every draw after flushing `sprites`, pushed with the texture
+    /// at their index in `[color, mask]`.
+    fn draws(sprites: &[(usize, Sprite)]) -> Vec<(Shading, Range<u32>)> {
+        let (device, _queue) = wgpu::Device::noop(&wgpu::DeviceDescriptor::default());
+        let textures = [
+            texture(&device, wgpu::TextureFormat::Rgba8UnormSrgb),
+            texture(&device, wgpu::TextureFormat::R8Unorm),
+        ];
+        let mut batch = SpriteBatch::new(
+            &device,
+            wgpu::TextureFormat::Bgra8UnormSrgb,
+            None,
+            wgpu::MultisampleState::default(),
+        );
+
+        for (texture, sprite) in sprites {
+            batch.push(&textures[*texture], *sprite);
+        }
+        let mut encoder = device.create_command_encoder(&Default::default());
+        batch.flush(&device, &mut encoder);
+
+        assert_eq!(batch.len(), sprites.len());
+        assert_eq!(batch.draw_count(), batch.draws.len());
+        batch
+            .draws
+            .iter()
+            .map(|draw| (draw.shading, draw.instances.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn same_depth_merges_textures() {
+        let sprites = [
+            (0, sprite(0.0)),
+            (1, sprite(0.0)),
+            (0, sprite(0.0)),
+            (1, sprite(0.0)),
+            (0, sprite(0.0)),
+        ];
+
+        assert_eq!(
+            draws(&sprites),
+            [(Shading::Color, 0..3), (Shading::Mask, 3..5)]
+        );
+    }
+
+    #[test]
+    fn depths_split_draws_in_order() {
+        let sprites = [
+            (0, sprite(2.0)),
+            (1, sprite(1.0)),
+            (0, sprite(0.0)),
+            (1, sprite(2.0)),
+        ];
+
+        assert_eq!(
+            draws(&sprites),
+            [
+                (Shading::Color, 0..1),
+                (Shading::Mask, 1..2),
+                (Shading::Color, 2..3),
+                (Shading::Mask, 3..4),
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_flush_draws_nothing() {
+        assert_eq!(draws(&[]), []);
+    }
+}
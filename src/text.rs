@@ -0,0 +1,909 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use ab_glyph::{Font as _, FontArc, GlyphId, PxScale, ScaleFont};
+use glam::{Mat4, UVec2, Vec2};
+
+use crate::sprite::{Sprite, SpriteBatch};
+use crate::texture::{Texture, TextureOptions};
+
+const ATLAS_SIZE: u32 = 1024;
+/// Empty texels around each glyph, so that filtering doesn't pick up its neighbors.
+const GLYPH_PADDING: u32 = 1;
+
+/// TrueType or OpenType font, cheap to clone.
+#[derive(Debug, Clone)]
+pub struct Font {
+    inner: FontArc,
+}
+
+#[derive(Debug)]
+pub enum FontError {
+    Read { path: PathBuf, message: String },
+    Invalid,
+}
+
+/// How glyphs are rasterized into the atlas.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum GlyphMode {
+    /// Coverage bitmaps rasterized at every size drawn, rounded to whole pixels. Sharpest
+    /// for UI text at a few fixed sizes.
+    #[default]
+    Bitmap,
+    /// Signed distance fields rasterized once at `raster_size` pixels, scaled to any size.
+    /// `spread` is the distance in raster pixels the field covers around the outline.
+    Sdf { raster_size: f32, spread: f32 },
+}
+
+/// Coverage of a rasterized glyph, one byte per pixel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GlyphBitmap {
+    pub size: UVec2,
+    /// Top-left corner from the pen on the baseline, in pixels, y down.
+    pub offset: Vec2,
+    pub data: Vec<u8>,
+}
+
+/// Glyph of laid out text. `position` is the pen on the baseline, in pixels from the top-left
+/// corner of the text, y down.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PositionedGlyph {
+    /// Index of the font that has the glyph, in the list passed to `layout`.
+    pub font: usize,
+    pub glyph: u16,
+    pub position: Vec2,
+}
+
+/// Draws text from fonts rasterized on the fly into a glyph atlas, batched into one
+/// instanced draw. Works like `SpriteBatch`: `clear` at the start of the frame, `draw`
+/// strings, `flush` into the frame's encoder, `render` in any pass after it and
+/// `after_submit` once the encoder is submitted. Positions
+/// are in pixels, y down, with the projection set to `sprite::pixel_projection`.
+///
+/// The atlas is packed in rows of glyphs of similar height. When it is full, the row used
+/// least recently makes room, as long as it wasn't used in the current frame; glyphs that
+/// still don't fit are skipped with a warning.
+///
+/// Each character maps to one glyph through the fonts' character maps, kerned by their `kern`
+/// tables. There is no shaping, so no ligatures, `GPOS` kerning or complex scripts, and
+/// distance fields have a single channel, rounding sharp corners when scaled far up.
+#[derive(Debug)]
+pub struct TextRenderer {
+    fonts: Vec<Font>,
+    mode: GlyphMode,
+    atlas: GlyphAtlas,
+    sprites: SpriteBatch,
+    glyphs: Vec<PositionedGlyph>,
+}
+
+#[derive(Debug)]
+struct GlyphAtlas {
+    texture: Texture,
+    shelves: Vec<Shelf>,
+    glyphs: HashMap<GlyphKey, Option<AtlasGlyph>>,
+    uploads: Vec<GlyphUpload>,
+    frame: u64,
+    warned_frame: Option<u64>,
+}
+
+/// Size is 0 for distance fields, which serve all sizes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct GlyphKey {
+    font: usize,
+    glyph: u16,
+    size: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct AtlasGlyph {
+    shelf: usize,
+    origin: UVec2,
+    size: UVec2,
+    /// Top-left corner of the stored bitmap from the pen, in raster pixels.
+    offset: Vec2,
+}
+
+#[derive(Debug)]
+struct Shelf {
+    y: u32,
+    height: u32,
+    x: u32,
+    last_used: u64,
+}
+
+#[derive(Debug)]
+struct GlyphUpload {
+    origin: UVec2,
+    size: UVec2,
+    data: Vec<u8>,
+}
+
+impl Font {
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self, FontError> {
+        let inner = FontArc::try_from_vec(data).map_err(|_| FontError::Invalid)?;
+        Ok(Self { inner })
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, FontError> {
+        let path = path.as_ref();
+        let data = std::fs::read(path).map_err(|err| FontError::Read {
+            path: path.to_path_buf(),
+            message: err.to_string(),
+        })?;
+        Self::from_bytes(data)
+    }
+
+    pub fn has_glyph(&self, c: char) -> bool {
+        self.inner.glyph_id(c).0 != 0
+    }
+
+    /// Glyph of `c` at `size` pixels high, for building atlases by hand. None for characters
+    /// the font lacks and for those without an outline, like spaces.
+    pub fn rasterize(&self, c: char, size: f32) -> Option<GlyphBitmap> {
+        if !self.has_glyph(c) {
+            return None;
+        }
+
+        let (coverage, size, offset) = coverage(self, self.inner.glyph_id(c), size)?;
+        Some(GlyphBitmap {
+            size,
+            offset,
+            data: coverage.iter().map(|value| to_unorm8(*value)).collect(),
+        })
+    }
+
+    /// Distance between baselines of text `size` pixels high.
+    pub fn line_height(&self, size: f32) -> f32 {
+        let scaled = self.inner.as_scaled(size);
+        scaled.height() + scaled.line_gap()
+    }
+}
+
+impl GlyphMode {
+    /// Distance fields detailed enough for headings and labels at any size.
+    pub const SDF: Self = Self::Sdf {
+        raster_size: 48.0,
+        spread: 6.0,
+    };
+}
+
+/// Lays out `text` at `size` pixels high into `glyphs` and returns the size of its box.
+/// Each character comes from the first of `fonts` that has it, kerned against the previous
+/// one from the same font; `\n` starts a new line.
+pub fn layout(fonts: &[Font], text: &str, size: f32, glyphs: &mut Vec<PositionedGlyph>) -> Vec2 {
+    assert!(!fonts.is_empty(), "Text needs at least one font");
+
+    let primary = fonts[0].inner.as_scaled(size);
+    let line_height = fonts[0].line_height(size);
+    let mut pen = Vec2::new(0.0, primary.ascent());
+    let mut width: f32 = 0.0;
+    let mut previous: Option<(usize, GlyphId)> = None;
+
+    for c in text.chars() {
+        if c == '\n' {
+            width = width.max(pen.x);
+            pen = Vec2::new(0.0, pen.y + line_height);
+            previous = None;
+            continue;
+        }
+        if c.is_control() {
+            continue;
+        }
+
+        // Missing everywhere, the primary font's placeholder glyph is drawn.
+        let (font, id) = fonts
+            .iter()
+            .enumerate()
+            .find(|(_, font)| font.has_glyph(c))
+            .map(|(index, font)| (index, font.inner.glyph_id(c)))
+            .unwrap_or((0, GlyphId(0)));
+        let scaled = fonts[font].inner.as_scaled(size);
+        if let Some((previous_font, previous_id)) = previous {
+            if previous_font == font {
+                pen.x += scaled.kern(previous_id, id);
+            }
+        }
+
+        glyphs.push(PositionedGlyph {
+            font,
+            glyph: id.0,
+            position: pen,
+        });
+        pen.x += scaled.h_advance(id);
+        previous = Some((font, id));
+    }
+
+    width = width.max(pen.x);
+    Vec2::new(width, pen.y - primary.ascent() + primary.height())
+}
+
+impl TextRenderer {
+    /// `fonts` are tried in order for each character, the first one sets the line height.
+    /// Draws into color targets of `format`.
+    pub fn new(
+        device: &wgpu::Device,
+        fonts: Vec<Font>,
+        mode: GlyphMode,
+        format: wgpu::TextureFormat,
+        depth_stencil: Option<wgpu::DepthStencilState>,
+        multisample: wgpu::MultisampleState,
+    ) -> Self {
+        assert!(!fonts.is_empty(), "Text needs at least one font");
+
+        Self {
+            fonts,
+            mode,
+            atlas: GlyphAtlas::new(device),
+            sprites: SpriteBatch::new(device, format, depth_stencil, multisample),
+            glyphs: vec![],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.sprites.clear();
+        self.atlas.frame += 1;
+    }
+
+    /// Queues `text` with the top-left corner of its box at `position`, `size` pixels high,
+    /// and returns the size of the box.
+    pub fn draw(&mut self, text: &str, position: Vec2, size: f32, color: [f32; 4]) -> Vec2 {
+        let size = match self.mode {
+            GlyphMode::Bitmap => size.round(),
+            GlyphMode::Sdf { .. } => size,
+        };
+
+        let mut glyphs = std::mem::take(&mut self.glyphs);
+        glyphs.clear();
+        let extent = layout(&self.fonts, text, size, &mut glyphs);
+
+        for glyph in glyphs.iter() {
+            let (key, scale) = match self.mode {
+                GlyphMode::Bitmap => (
+                    GlyphKey {
+                        font: glyph.font,
+                        glyph: glyph.glyph,
+                        size: size as u32,
+                    },
+                    1.0,
+                ),
+                GlyphMode::Sdf { raster_size, .. } => (
+                    GlyphKey {
+                        font: glyph.font,
+                        glyph: glyph.glyph,
+                        size: 0,
+                    },
+                    size / raster_size,
+                ),
+            };
+            let Some(entry) = self.atlas.glyph(&self.fonts, key, self.mode) else {
+                continue;
+            };
+
+            let mut pen = position + glyph.position;
+            if self.mode == GlyphMode::Bitmap {
+                // Whole pixels keep bitmaps sharp.
+                pen = pen.round();
+            }
+            let glyph_size = entry.size.as_vec2() * scale;
+            let uv_min = entry.origin.as_vec2() / ATLAS_SIZE as f32;
+            let uv_max = (entry.origin + entry.size).as_vec2() / ATLAS_SIZE as f32;
+            let sprite = Sprite {
+                center: pen + entry.offset * scale + glyph_size / 2.0,
+                size: glyph_size,
+                uv_rect: [uv_min.x, uv_min.y, uv_max.x, uv_max.y],
+                color,
+                ..Default::default()
+            };
+
+            match self.mode {
+                GlyphMode::Bitmap => self.sprites.push(&self.atlas.texture, sprite),
+                GlyphMode::Sdf { .. } => self.sprites.push_sdf(&self.atlas.texture, sprite),
+            }
+        }
+
+        self.glyphs = glyphs;
+        extent
+    }
+
+    /// Size of the box `draw` would return.
+    pub fn measure(&self, text: &str, size: f32) -> Vec2 {
+        let size = match self.mode {
+            GlyphMode::Bitmap => size.round(),
+            GlyphMode::Sdf { .. } => size,
+        };
+        layout(&self.fonts, text, size, &mut vec![])
+    }
+
+    pub fn set_projection(&self, queue: &wgpu::Queue, projection: Mat4) {
+        self.sprites.set_projection(queue, projection);
+    }
+
+    /// Uploads glyphs rasterized since the last `flush` and the queued text, see
+    /// `SpriteBatch::flush`.
+    pub fn flush(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+    ) {
+        self.atlas.upload(queue);
+        self.sprites.flush(device, encoder);
+    }
+
+    pub fn render(&self, pass: &mut wgpu::RenderPass) {
+        self.sprites.render(pass);
+    }
+
+    pub fn after_submit(&mut self) {
+        self.sprites.after_submit();
+    }
+
+    pub fn fonts(&self) -> &[Font] {
+        &self.fonts
+    }
+
+    /// Single-channel atlas the glyphs are drawn from.
+    pub fn atlas(&self) -> &Texture {
+        &self.atlas.texture
+    }
+}
+
+impl GlyphAtlas {
+    fn new(device: &wgpu::Device) -> Self {
+        let options = TextureOptions {
+            format: wgpu::TextureFormat::R8Unorm,
+            filter: wgpu::FilterMode::Linear,
+            address_mode: wgpu::AddressMode::ClampToEdge,
+            ..Default::default()
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: ATLAS_SIZE,
+                height: ATLAS_SIZE,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: options.format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+            label: Some("Glyph Atlas"),
+        });
+
+        Self {
+            texture: Texture::new(device, texture, &options),
+            shelves: vec![],
+            glyphs: HashMap::new(),
+            uploads: vec![],
+            frame: 0,
+            warned_frame: None,
+        }
+    }
+
+    /// The glyph's place in the atlas, rasterizing it on first use. None for glyphs without
+    /// an outline, like spaces, and for those that don't fit.
+    fn glyph(&mut self, fonts: &[Font], key: GlyphKey, mode: GlyphMode) -> Option<AtlasGlyph> {
+        let entry = match self.glyphs.get(&key) {
+            Some(entry) => (*entry)?,
+            None => {
+                let Some((bitmap, size, offset)) = rasterize(&fonts[key.font], key, mode) else {
+                    self.glyphs.insert(key, None);
+                    return None;
+                };
+                // Not remembered when it doesn't fit, as evictions may make room later.
+                let entry = self.insert(bitmap, size, offset)?;
+                self.glyphs.insert(key, Some(entry));
+                entry
+            }
+        };
+
+        self.shelves[entry.shelf].last_used = self.frame;
+        Some(entry)
+    }
+
+    fn insert(&mut self, data: Vec<u8>, size: UVec2, offset: Vec2) -> Option<AtlasGlyph> {
+        let Some((shelf, origin)) = self.allocate(size) else {
+            if self.warned_frame != Some(self.frame) {
+                self.warned_frame = Some(self.frame);
+                tracing::warn!("Glyph atlas is full, skipping glyphs");
+            }
+            return None;
+        };
+
+        self.uploads.push(GlyphUpload { origin, size, data });
+        Some(AtlasGlyph {
+            shelf,
+            origin,
+            size,
+            offset,
+        })
+    }
+
+    fn allocate(&mut self, size: UVec2) -> Option<(usize, UVec2)> {
+        if size.x > ATLAS_SIZE || size.y > ATLAS_SIZE {
+            return None;
+        }
+
+        // Rows of a quarter more than the glyph at most, so that rows aren't wasted on
+        // much smaller glyphs.
+        let fits = |shelf: &Shelf| shelf.height >= size.y && shelf.height <= size.y + size.y / 4;
+        let index = self
+            .shelves
+            .iter()
+            .enumerate()
+            .filter(|(_, shelf)| fits(shelf) && shelf.x + size.x <= ATLAS_SIZE)
+            .min_by_key(|(_, shelf)| shelf.height)
+            .map(|(index, _)| index);
+
+        let index = match index {
+            Some(index) => index,
+            None => {
+                let y = self
+                    .shelves
+                    .last()
+                    .map_or(0, |shelf| shelf.y + shelf.height);
+                if y + size.y <= ATLAS_SIZE {
+                    self.shelves.push(Shelf {
+                        y,
+                        height: size.y,
+                        x: 0,
+                        last_used: self.frame,
+                    });
+                    self.shelves.len() - 1
+                } else {
+                    self.evict(size.y)?
+                }
+            }
+        };
+
+        let shelf = &mut self.shelves[index];
+        let origin = UVec2::new(shelf.x, shelf.y);
+        shelf.x += size.x;
+        Some((index, origin))
+    }
+
+    /// Empties the least recently used row at least `height` high that the current frame
+    /// doesn't use.
+    fn evict(&mut self, height: u32) -> Option<usize> {
+        let frame = self.frame;
+        let (index, _) = self
+            .shelves
+            .iter()
+            .enumerate()
+            .filter(|(_, shelf)| shelf.height >= height && shelf.last_used < frame)
+            .min_by_key(|(_, shelf)| shelf.last_used)?;
+
+        self.glyphs
+            .retain(|_, entry| entry.is_none_or(|entry| entry.shelf != index));
+        self.shelves[index].x = 0;
+        Some(index)
+    }
+
+    fn upload(&mut self, queue: &wgpu::Queue) {
+        for upload in self.uploads.drain(..) {
+            queue.write_texture(
+                wgpu::TexelCopyTextureInfo {
+                    texture: &self.texture.texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d {
+                        x: upload.origin.x,
+                        y: upload.origin.y,
+                        z: 0,
+                    },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                &upload.data,
+                wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(upload.size.x),
+                    rows_per_image: None,
+                },
+                wgpu::Extent3d {
+                    width: upload.size.x,
+                    height: upload.size.y,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+    }
+}
+
+/// Bitmap of the glyph with its padding, its size and its offset from the pen.
+fn rasterize(font: &Font, key: GlyphKey, mode: GlyphMode) -> Option<(Vec<u8>, UVec2, Vec2)> {
+    let raster_size = match mode {
+        GlyphMode::Bitmap => key.size as f32,
+        GlyphMode::Sdf { raster_size, .. } => raster_size,
+    };
+    let (coverage, raster, offset) = coverage(font, GlyphId(key.glyph), raster_size)?;
+    let (width, height) = (raster.x, raster.y);
+
+    let padding = match mode {
+        GlyphMode::Bitmap => GLYPH_PADDING,
+        GlyphMode::Sdf { spread, .. } => spread.ceil() as u32 + GLYPH_PADDING,
+    };
+    let size = raster + 2 * padding;
+    let mut padded = vec![0.0; (size.x * size.y) as usize];
+    for y in 0..height {
+        let row = ((y + padding) * size.x + padding) as usize;
+        padded[row..row + width as usize]
+            .copy_from_slice(&coverage[(y * width) as usize..((y + 1) * width) as usize]);
+    }
+
+    let data = match mode {
+        GlyphMode::Bitmap => padded.iter().map(|value| to_unorm8(*value)).collect(),
+        GlyphMode::Sdf { spread, .. } => signed_distance_field(&padded, size, spread),
+    };
+
+    Some((data, size, offset - padding as f32))
+}
+
+/// Coverage of the glyph's pixels, its size and its offset from the pen.
+fn coverage(font: &Font, glyph: GlyphId, size: f32) -> Option<(Vec<f32>, UVec2, Vec2)> {
+    let outline = font
+        .inner
+        .outline_glyph(glyph.with_scale(PxScale::from(size)))?;
+    let bounds = outline.px_bounds();
+    let width = bounds.width() as u32;
+    let height = bounds.height() as u32;
+    if width == 0 || height == 0 {
+        return None;
+    }
+
+    let mut coverage = vec![0.0; (width * height) as usize];
+    outline.draw(|x, y, value| {
+        if x < width && y < height {
+            coverage[(y * width + x) as usize] = value;
+        }
+    });
+
+    Some((
+        coverage,
+        UVec2::new(width, height),
+        Vec2::new(bounds.min.x, bounds.min.y),
+    ))
+}
+
+/// Distance to the outline mapped to 0..1, 0.5 on it and increasing inside, reaching 0 and
+/// 1 at `spread` texels.
+fn signed_distance_field(coverage: &[f32], size: UVec2, spread: f32) -> Vec<u8> {
+    let inside: Vec<bool> = coverage.iter().map(|value| *value >= 0.5).collect();
+    let to_inside = distance_transform(&inside, size);
+    let outside: Vec<bool> = inside.iter().map(|inside| !inside).collect();
+    let to_outside = distance_transform(&outside, size);
+
+    to_inside
+        .iter()
+        .zip(to_outside.iter())
+        .map(|(to_inside, to_outside)| {
+            let distance = to_outside.sqrt() - to_inside.sqrt();
+            to_unorm8(0.5 + distance / (2.0 * spread))
+        })
+        .collect()
+}
+
+/// Squared distance from each texel to the nearest set one, with the separable transform of
+/// Felzenszwalb and Huttenlocher.
+fn distance_transform(set: &[bool], size: UVec2) -> Vec<f32> {
+    const FAR: f32 = 1e20;
+
+    let (width, height) = (size.x as usize, size.y as usize);
+    let mut distances: Vec<f32> = set.iter().map(|set| if *set { 0.0 } else { FAR }).collect();
+
+    let length = width.max(height);
+    let mut line = vec![0.0; length];
+    let mut result = vec![0.0; length];
+    let mut parabolas = vec![0; length];
+    let mut bounds = vec![0.0; length + 1];
+
+    for x in 0..width {
+        for y in 0..height {
+            line[y] = distances[y * width + x];
+        }
+        transform_line(
+            &line[..height],
+            &mut result,
+            &mut parabolas,
+            &mut bounds,
+            FAR,
+        );
+        for y in 0..height {
+            distances[y * width + x] = result[y];
+        }
+    }
+    for y in 0..height {
+        line[..width].copy_from_slice(&distances[y * width..(y + 1) * width]);
+        transform_line(
+            &line[..width],
+            &mut result,
+            &mut parabolas,
+            &mut bounds,
+            FAR,
+        );
+        distances[y * width..(y + 1) * width].copy_from_slice(&result[..width]);
+    }
+
+    distances
+}
+
+/// One dimensional pass: the lower envelope of parabolas rooted at every texel.
+fn transform_line(
+    line: &[f32],
+    result: &mut [f32],
+    parabolas: &mut [usize],
+    bounds: &mut [f32],
+    far: f32,
+) {
+    let intersection = |q: usize, p: usize| {
+        ((line[q] + (q * q) as f32) - (line[p] + (p * p) as f32)) / (2 * q - 2 * p) as f32
+    };
+
+    let mut k = 0;
+    parabolas[0] = 0;
+    bounds[0] = -far;
+    bounds[1] = far;
+    for q in 1..line.len() {
+        let mut s = intersection(q, parabolas[k]);
+        while s <= bounds[k] {
+            k -= 1;
+            s = intersection(q, parabolas[k]);
+        }
+        k += 1;
+        parabolas[k] = q;
+        bounds[k] = s;
+        bounds[k + 1] = far;
+    }
+
+    k = 0;
+    for (q, result) in result.iter_mut().enumerate().take(line.len()) {
+        while bounds[k + 1] < q as f32 {
+            k += 1;
+        }
+        let offset = q as f32 - parabolas[k] as f32;
+        *result = offset * offset + line[parabolas[k]];
+    }
+}
+
+fn to_unorm8(value: f32) -> u8 {
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+impl std::fmt::Display for FontError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FontError::Read { path, message } => {
+                write!(f, "Failed to read {}: {}", path.display(), message)
+            }
+            FontError::Invalid => write!(f, "Not a TrueType or OpenType font"),
+        }
+    }
+}
+
+impl std::error::Error for FontError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: f32 = 125.0;
+
+    /// DejaVu Sans Mono, without katakana or kerning, then Matrix Katakana, whose units per
+    /// em make glyph metrics exact at `SIZE`: advances of 62.5 and `ﾚﾉ` kerned by -10.
+    fn fonts() -> Vec<Font> {
+        ["DejaVuSansMono.ttf", "MatrixKatakana.ttf"]
+            .iter()
+            .map(|name| {
+                let path = format!(
+                    "{}/Examples/the_matrix/assets/{}",
+                    env!("CARGO_MANIFEST_DIR"),
+                    name
+                );
+                Font::from_file(path).unwrap()
+            })
+            .collect()
+    }
+
+    fn laid_out(fonts: &[Font], text: &str) -> (Vec<PositionedGlyph>, Vec2) {
+        let mut glyphs = vec![];
+        let size = layout(fonts, text, SIZE, &mut glyphs);
+        (glyphs, size)
+    }
+
+    #[test]
+    fn kerning_within_a_font() {
+        let fonts = fonts();
+        let width = |text| laid_out(&fonts, text).1.x;
+        // `S` has the same glyph index in DejaVu Sans Mono as `ﾚ` in Matrix Katakana, so
+        // looking the pair up in the kerning of `ﾉ`'s font would pull `ﾉ` closer.
+        let cases = [
+            ("ﾚﾉ", width("ﾚ") - 10.0),
+            ("ﾄﾉ", width("ﾄ") - 7.5),
+            ("ﾉﾉ", width("ﾉ")),
+            ("Sﾉ", width("S")),
+        ];
+
+        for case in cases {
+            let (text, x) = case;
+            let (glyphs, _) = laid_out(&fonts, text);
+            assert_eq!(glyphs[1].position.x, x, "{:?}", case);
+        }
+    }
+
+    #[test]
+    fn missing_characters_fall_back() {
+        let fonts = fonts();
+        let katakana = fonts[1].inner.glyph_id('ﾉ').0;
+        let latin = fonts[0].inner.glyph_id('A').0;
+        // Font and glyph index of each character.
+        let cases = [
+            ("Aﾉ", vec![(0, latin), (1, katakana)]),
+            ("ﾉA", vec![(1, katakana), (0, latin)]),
+            // Missing from both, the placeholder of the first font.
+            ("\u{E000}", vec![(0, 0)]),
+        ];
+
+        for case in cases {
+            let (text, expected) = &case;
+            let (glyphs, _) = laid_out(&fonts, text);
+            let glyphs: Vec<(usize, u16)> = glyphs
+                .iter()
+                .map(|glyph| (glyph.font, glyph.glyph))
+                .collect();
+            assert_eq!(&glyphs, expected, "{:?}", case);
+        }
+    }
+
+    #[test]
+    fn newlines_reset_the_pen() {
+        let fonts = &fonts()[1..];
+        // Ascent of 100 and lines 125 apart.
+        let cases = [
+            ("ﾉﾉ", vec![(0.0, 100.0), (62.5, 100.0)], (125.0, 125.0)),
+            (
+                "ﾉﾉ\nﾉ",
+                vec![(0.0, 100.0), (62.5, 100.0), (0.0, 225.0)],
+                (125.0, 250.0),
+            ),
+            (
+                "ﾉ\nﾉﾉﾉ",
+                vec![(0.0, 100.0), (0.0, 225.0), (62.5, 225.0), (125.0, 225.0)],
+                (187.5, 250.0),
+            ),
+            ("ﾉ\n\nﾉ", vec![(0.0, 100.0), (0.0, 350.0)], (62.5, 375.0)),
+        ];
+
+        for case in cases {
+            let (text, positions, size) = &case;
+            let (glyphs, box_size) = laid_out(fonts, text);
+            let glyphs: Vec<Vec2> = glyphs.iter().map(|glyph| glyph.position).collect();
+            let positions: Vec<Vec2> = positions.iter().map(|(x, y)| Vec2::new(*x, *y)).collect();
+            assert_eq!(glyphs, positions, "{:?}", case);
+            assert_eq!(box_size, Vec2::new(size.0, size.1), "{:?}", case);
+        }
+    }
+
+    #[test]
+    fn shelves_fit_similar_heights() {
+        let (device, _queue) = wgpu::Device::noop(&wgpu::DeviceDescriptor::default());
+        let mut atlas = GlyphAtlas::new(&device);
+        // Glyph size, then its row and origin. Rows hold glyphs down to four fifths of their
+        // height, taking the lowest row with room left.
+        let cases = [
+            ((10, 20), 0, (0, 0)),
+            ((10, 16), 0, (10, 0)),
+            ((10, 15), 1, (0, 20)),
+            ((10, 21), 2, (0, 35)),
+            ((10, 20), 0, (20, 0)),
+            ((10, 21), 2, (10, 35)),
+            ((1000, 20), 2, (20, 35)),
+            ((1000, 20), 3, (0, 56)),
+        ];
+
+        for case in cases {
+            let (size, shelf, origin) = case;
+            let allocation = atlas.allocate(UVec2::new(size.0, size.1));
+            assert_eq!(
+                allocation,
+                Some((shelf, UVec2::new(origin.0, origin.1))),
+                "{:?}",
+                case
+            );
+        }
+    }
+
+    /// Atlas filled by four rows a quarter of its height, last used in `frames`.
+    fn full_atlas(device: &wgpu::Device, frames: [u64; 4]) -> GlyphAtlas {
+        let mut atlas = GlyphAtlas::new(device);
+        for (index, frame) in frames.iter().enumerate() {
+            let allocation = atlas.allocate(UVec2::new(ATLAS_SIZE, ATLAS_SIZE / 4));
+            assert_eq!(allocation.map(|(shelf, _)| shelf), Some(index));
+            atlas.shelves[index].last_used = *frame;
+        }
+        atlas.frame = 4;
+        atlas
+    }
+
+    #[test]
+    fn full_atlas_evicts_least_recently_used_row() {
+        let (device, _queue) = wgpu::Device::noop(&wgpu::DeviceDescriptor::default());
+        // Frames the rows were last used in, then the row a glyph gets. Rows used in the
+        // current frame, 4, stay.
+        let cases = [
+            ([2, 0, 3, 1], Some(1)),
+            ([3, 1, 2, 1], Some(1)),
+            ([4, 4, 0, 4], Some(2)),
+            ([4, 4, 4, 4], None),
+        ];
+
+        for case in cases {
+            let (frames, evicted) = case;
+            let mut atlas = full_atlas(&device, frames);
+            let allocation = atlas.allocate(UVec2::new(8, 200));
+            assert_eq!(allocation.map(|(shelf, _)| shelf), evicted, "{:?}", case);
+            if let Some((shelf, origin)) = allocation {
+                assert_eq!(origin, UVec2::new(0, shelf as u32 * 256), "{:?}", case);
+                assert_eq!(atlas.shelves[shelf].x, 8, "{:?}", case);
+            }
+        }
+    }
+
+    #[test]
+    fn eviction_forgets_the_row_glyphs() {
+        let (device, _queue) = wgpu::Device::noop(&wgpu::DeviceDescriptor::default());
+        let mut atlas = full_atlas(&device, [2, 0, 3, 1]);
+        let key = |glyph| GlyphKey {
+            font: 0,
+            glyph,
+            size: 16,
+        };
+        for shelf in 0..4 {
+            atlas.glyphs.insert(
+                key(shelf as u16),
+                Some(AtlasGlyph {
+                    shelf,
+                    origin: UVec2::new(0, shelf as u32 * 256),
+                    size: UVec2::splat(16),
+                    offset: Vec2::ZERO,
+                }),
+            );
+        }
+        // Glyphs without an outline have no row and are kept.
+        atlas.glyphs.insert(key(4), None);
+
+        assert_eq!(atlas.evict(256), Some(1));
+
+        let mut glyphs: Vec<u16> = atlas.glyphs.keys().map(|key| key.glyph).collect();
+        glyphs.sort();
+        assert_eq!(glyphs, [0, 2, 3, 4]);
+    }
+
+    #[test]
+    fn distance_transform() {
+        // Set texels, the grid size and the squared distances to the nearest one.
+        let cases = [
+            (
+                "...\n.#.\n...",
+                (3, 3),
+                vec![2.0, 1.0, 2.0, 1.0, 0.0, 1.0, 2.0, 1.0, 2.0],
+            ),
+            ("#...", (4, 1), vec![0.0, 1.0, 4.0, 9.0]),
+            ("#..#", (4, 1), vec![0.0, 1.0, 1.0, 0.0]),
+            (
+                "#...\n....\n...#",
+                (4, 3),
+                vec![0.0, 1.0, 4.0, 4.0, 1.0, 2.0, 2.0, 1.0, 4.0, 4.0, 1.0, 0.0],
+            ),
+        ];
+
+        for case in &cases {
+            let (grid, size, expected) = case;
+            let set: Vec<bool> = grid
+                .chars()
+                .filter(|c| *c != '\n')
+                .map(|c| c == '#')
+                .collect();
+            let distances = super::distance_transform(&set, UVec2::new(size.0, size.1));
+            assert_eq!(&distances, expected, "{:?}", case);
+        }
+    }
+}
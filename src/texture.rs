@@ -0,0 +1,806 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use imaginarium::color_format::ColorFormat;
+use imaginarium::image::Image;
+
+use crate::block_decode;
+use crate::shader;
+use crate::texture_container::TextureContainer;
+
+#[derive(Debug, Clone)]
+pub struct TextureOptions {
+    /// Format of the created texture. Decoded images are converted to it; float formats
+    /// keep the precision of 16-bit and float images. Image bytes are taken as sRGB-encoded for `*Srgb`
+    /// formats, see `ColorSpace`, and as linear values otherwise.
+    /// Ignored for KTX2 and DDS files, which specify their format.
+    pub format: wgpu::TextureFormat,
+    /// Generate the full mip chain on the GPU. Containers with their own mip levels keep them.
+    pub mipmaps: bool,
+    pub address_mode: wgpu::AddressMode,
+    pub filter: wgpu::FilterMode,
+    /// Usages on top of `TEXTURE_BINDING | COPY_DST`.
+    pub usage: wgpu::TextureUsages,
+}
+
+/// Formats images are decoded to.
+pub const DECODED_FORMATS: [wgpu::TextureFormat; 7] = [
+    wgpu::TextureFormat::Rgba8UnormSrgb,
+    wgpu::TextureFormat::Rgba8Unorm,
+    wgpu::TextureFormat::R8Unorm,
+    wgpu::TextureFormat::Rgba16Float,
+    wgpu::TextureFormat::R16Float,
+    wgpu::TextureFormat::Rgba32Float,
+    wgpu::TextureFormat::R32Float,
+];
+
+/// Texture with a view of all its mip levels and a sampler built from `TextureOptions`.
+#[derive(Debug)]
+pub struct Texture {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub sampler: wgpu::Sampler,
+}
+
+/// Texture decoded on the CPU, ready for `upload` on the render thread.
+#[derive(Debug)]
+pub(crate) enum TextureData {
+    Pixels {
+        width: u32,
+        height: u32,
+        bytes: Vec<u8>,
+    },
+    Container(TextureContainer),
+}
+
+#[derive(Debug)]
+pub enum TextureError {
+    Read {
+        path: PathBuf,
+        message: String,
+    },
+    UnsupportedFormat(wgpu::TextureFormat),
+    SizeMismatch {
+        expected: usize,
+        actual: usize,
+    },
+    Container(String),
+    /// Bytes that aren't a KTX2 or DDS file, passed to `load_bytes`.
+    UnsupportedImage,
+}
+
+impl Default for TextureOptions {
+    fn default() -> Self {
+        Self {
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            mipmaps: true,
+            address_mode: wgpu::AddressMode::ClampToEdge,
+            filter: wgpu::FilterMode::Linear,
+            usage: wgpu::TextureUsages::empty(),
+        }
+    }
+}
+
+/// Decodes an image file and uploads it as `options.format`, which must be one of
+/// `DECODED_FORMATS`. `.ktx2` and `.dds` files are loaded with `from_container`. Mip levels
+/// come from `mipmap_generator`, shared between loads to reuse its pipelines.
+pub fn load(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    mipmap_generator: &mut MipmapGenerator,
+    path: impl AsRef<Path>,
+    options: &TextureOptions,
+) -> Result<Texture, TextureError> {
+    decode(path.as_ref(), options, device.features())?.upload(
+        device,
+        queue,
+        mipmap_generator,
+        options,
+    )
+}
+
+/// Same as `load` for a KTX2 or DDS file already in memory. Other images can only be
+/// loaded from files, anything else is `TextureError::UnsupportedImage`.
+pub fn load_bytes(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    mipmap_generator: &mut MipmapGenerator,
+    bytes: &[u8],
+    options: &TextureOptions,
+) -> Result<Texture, TextureError> {
+    if !TextureContainer::is_container(bytes) {
+        return Err(TextureError::UnsupportedImage);
+    }
+
+    from_container(device, queue, mipmap_generator, bytes, options)
+}
+
+/// The CPU part of `load`. `features` are the device features, deciding whether
+/// compressed containers need to be decompressed.
+pub(crate) fn decode(
+    path: &Path,
+    options: &TextureOptions,
+    features: wgpu::Features,
+) -> Result<TextureData, TextureError> {
+    let extension = path.extension().and_then(|extension| extension.to_str());
+    if matches!(extension, Some("ktx2" | "dds")) {
+        let bytes = std::fs::read(path).map_err(|err| read_error(path, err))?;
+        return decode_container(&bytes, features).map(TextureData::Container);
+    }
+
+    decode_image(path, options)
+}
+
+/// Decodes 8-bit formats through 8-bit pixels and float formats through 32-bit float ones,
+/// keeping the precision of 16-bit and float images.
+fn decode_image(path: &Path, options: &TextureOptions) -> Result<TextureData, TextureError> {
+    let channels = channel_count(options.format)?;
+    let float = !matches!(
+        options.format,
+        wgpu::TextureFormat::Rgba8UnormSrgb
+            | wgpu::TextureFormat::Rgba8Unorm
+            | wgpu::TextureFormat::R8Unorm
+    );
+    let (color_format, component_size) = match (channels, float) {
+        (1, false) => (ColorFormat::GRAY_U8, 1),
+        (_, false) => (ColorFormat::RGBA_U8, 1),
+        (1, true) => (ColorFormat::GRAY_F32, 4),
+        (_, true) => (ColorFormat::RGBA_F32, 4),
+    };
+    let image = Image::read_file(path)
+        .map_err(|err| read_error(path, err))?
+        .convert(color_format)
+        .map_err(|err| read_error(path, err))?;
+
+    let width = image.desc.width();
+    let height = image.desc.height();
+    let row_size = (width * channels * component_size) as usize;
+    let pixels = image
+        .bytes
+        .chunks(image.desc.stride() as usize)
+        .take(height as usize)
+        .flat_map(|row| &row[..row_size])
+        .copied()
+        .collect::<Vec<u8>>();
+
+    Ok(TextureData::Pixels {
+        width,
+        height,
+        bytes: narrow(&pixels, options.format).into_owned(),
+    })
+}
+
+/// Uploads tightly packed pixels that are already in `options.format`.
+pub fn from_bytes(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    mipmap_generator: &mut MipmapGenerator,
+    width: u32,
+    height: u32,
+    bytes: &[u8],
+    options: &TextureOptions,
+) -> Result<Texture, TextureError> {
+    let format = options.format;
+    let block_size = format
+        .block_copy_size(None)
+        .filter(|_| !format.is_compressed())
+        .ok_or(TextureError::UnsupportedFormat(format))?;
+    // Too large to address is a mismatch with any slice.
+    let expected = (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|size| size.checked_mul(block_size as usize))
+        .unwrap_or(usize::MAX);
+    if bytes.len() != expected {
+        return Err(TextureError::SizeMismatch {
+            expected,
+            actual: bytes.len(),
+        });
+    }
+
+    let size = wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    };
+    let mip_level_count = if options.mipmaps {
+        size.max_mips(wgpu::TextureDimension::D2)
+    } else {
+        1
+    };
+    let mut usage =
+        wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST | options.usage;
+    if mip_level_count > 1 {
+        usage |= wgpu::TextureUsages::RENDER_ATTACHMENT;
+    }
+
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        size,
+        mip_level_count,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage,
+        view_formats: &[],
+        label: None,
+    });
+
+    queue.write_texture(
+        wgpu::TexelCopyTextureInfo {
+            texture: &texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: Default::default(),
+        },
+        bytes,
+        wgpu::TexelCopyBufferLayout {
+            offset: 0,
+            bytes_per_row: Some(width * block_size),
+            rows_per_image: Some(height),
+        },
+        size,
+    );
+
+    if mip_level_count > 1 {
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        mipmap_generator.generate(device, &mut encoder, &texture);
+        queue.submit([encoder.finish()]);
+    }
+
+    Ok(Texture::new(device, texture, options))
+}
+
+/// Uploads a KTX2 or DDS file with all its mip levels, array layers and cubemap faces.
+/// Compressed formats are uploaded as is when the device has the feature they need and
+/// otherwise decompressed on the CPU: BC1-BC5, BC7, ETC2/EAC and ASTC LDR to
+/// `Rgba8Unorm` or `Rgba8UnormSrgb`, their signed variants to `Rgba8Snorm` and BC6H to
+/// `Rgba16Float`. ASTC HDR needs the device feature. Cubemaps get a `Cube` or `CubeArray`
+/// view.
+pub fn from_container(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    mipmap_generator: &mut MipmapGenerator,
+    bytes: &[u8],
+    options: &TextureOptions,
+) -> Result<Texture, TextureError> {
+    let container = decode_container(bytes, device.features())?;
+    upload_container(device, queue, mipmap_generator, &container, options)
+}
+
+fn decode_container(
+    bytes: &[u8],
+    features: wgpu::Features,
+) -> Result<TextureContainer, TextureError> {
+    let mut container = TextureContainer::parse(bytes)?;
+    if !features.contains(container.format.required_features()) {
+        decompress_container(&mut container)?;
+    }
+
+    Ok(container)
+}
+
+fn upload_container(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    mipmap_generator: &mut MipmapGenerator,
+    container: &TextureContainer,
+    options: &TextureOptions,
+) -> Result<Texture, TextureError> {
+    let format = container.format;
+    let size = container.size();
+    // Decompressed snorm textures can't be rendered to for mipmaps.
+    let generate_mips = container.levels.len() == 1
+        && options.mipmaps
+        && format
+            .guaranteed_format_features(device.features())
+            .allowed_usages
+            .contains(wgpu::TextureUsages::RENDER_ATTACHMENT);
+    let mip_level_count = if generate_mips {
+        size.max_mips(wgpu::TextureDimension::D2)
+    } else {
+        container.levels.len() as u32
+    };
+    let mut usage =
+        wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST | options.usage;
+    if generate_mips {
+        usage |= wgpu::TextureUsages::RENDER_ATTACHMENT;
+    }
+
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        size,
+        mip_level_count,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage,
+        view_formats: &[],
+        label: None,
+    });
+
+    let (block_width, block_height) = format.block_dimensions();
+    let block_size = format
+        .block_copy_size(None)
+        .ok_or(TextureError::UnsupportedFormat(format))?;
+    for (level, data) in container.levels.iter().enumerate() {
+        let level_size = size
+            .mip_level_size(level as u32, wgpu::TextureDimension::D2)
+            .physical_size(format);
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &texture,
+                mip_level: level as u32,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: Default::default(),
+            },
+            data,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(level_size.width / block_width * block_size),
+                rows_per_image: Some(level_size.height / block_height),
+            },
+            level_size,
+        );
+    }
+
+    if generate_mips && mip_level_count > 1 {
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        mipmap_generator.generate(device, &mut encoder, &texture);
+        queue.submit([encoder.finish()]);
+    }
+
+    let mut texture = Texture::new(device, texture, options);
+    if container.cube {
+        texture.view = texture.texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(if container.layers > 1 {
+                wgpu::TextureViewDimension::CubeArray
+            } else {
+                wgpu::TextureViewDimension::Cube
+            }),
+            ..Default::default()
+        });
+    }
+
+    Ok(texture)
+}
+
+/// Fills mip chains by downsampling each level from the previous one, with a pipeline per
+/// target format created on first use.
+#[derive(Debug)]
+pub struct MipmapGenerator {
+    module: wgpu::ShaderModule,
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline_layout: wgpu::PipelineLayout,
+    pipelines: HashMap<wgpu::TextureFormat, wgpu::RenderPipeline>,
+}
+
+impl MipmapGenerator {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let module = shader::compile_builtin(
+            device,
+            "wgpu_app/mipmap.wgsl",
+            include_str!("shaders/mipmap.wgsl"),
+        );
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                },
+                count: None,
+            }],
+            label: None,
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            bind_group_layouts: &[&bind_group_layout],
+            immediate_size: 0,
+            label: None,
+        });
+
+        Self {
+            module,
+            bind_group_layout,
+            pipeline_layout,
+            pipelines: HashMap::new(),
+        }
+    }
+
+    /// Fills mip levels 1.. of every array layer. The texture needs
+    /// `TEXTURE_BINDING | RENDER_ATTACHMENT` and a renderable float format.
+    pub fn generate(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+    ) {
+        let pipeline = self.pipelines.entry(texture.format()).or_insert_with(|| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("mipmap"),
+                layout: Some(&self.pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &self.module,
+                    entry_point: Some("vs_main"),
+                    buffers: &[],
+                    compilation_options: Default::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &self.module,
+                    entry_point: Some("fs_main"),
+                    targets: &[Some(texture.format().into())],
+                    compilation_options: Default::default(),
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview_mask: None,
+                cache: None,
+            })
+        });
+
+        let level_view = |layer: u32, level: u32| {
+            texture.create_view(&wgpu::TextureViewDescriptor {
+                dimension: Some(wgpu::TextureViewDimension::D2),
+                base_mip_level: level,
+                mip_level_count: Some(1),
+                base_array_layer: layer,
+                array_layer_count: Some(1),
+                ..Default::default()
+            })
+        };
+
+        for layer in 0..texture.depth_or_array_layers() {
+            for level in 1..texture.mip_level_count() {
+                let source = level_view(layer, level - 1);
+                let target = level_view(layer, level);
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    layout: &self.bind_group_layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&source),
+                    }],
+                    label: None,
+                });
+
+                let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("mipmap"),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: &target,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                            store: wgpu::StoreOp::Store,
+                        },
+                        resolve_target: None,
+                        depth_slice: None,
+                    })],
+                    ..Default::default()
+                });
+                render_pass.set_pipeline(pipeline);
+                render_pass.set_bind_group(0, &bind_group, &[]);
+                render_pass.draw(0..3, 0..1);
+            }
+        }
+    }
+}
+
+impl TextureData {
+    pub(crate) fn upload(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        mipmap_generator: &mut MipmapGenerator,
+        options: &TextureOptions,
+    ) -> Result<Texture, TextureError> {
+        match self {
+            TextureData::Pixels {
+                width,
+                height,
+                bytes,
+            } => from_bytes(
+                device,
+                queue,
+                mipmap_generator,
+                *width,
+                *height,
+                bytes,
+                options,
+            ),
+            TextureData::Container(container) => {
+                upload_container(device, queue, mipmap_generator, container, options)
+            }
+        }
+    }
+}
+
+impl Texture {
+    /// Wraps an existing texture, creating the view and sampler.
+    pub fn new(device: &wgpu::Device, texture: wgpu::Texture, options: &TextureOptions) -> Self {
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: options.address_mode,
+            address_mode_v: options.address_mode,
+            address_mode_w: options.address_mode,
+            mag_filter: options.filter,
+            min_filter: options.filter,
+            mipmap_filter: match options.filter {
+                wgpu::FilterMode::Nearest => wgpu::MipmapFilterMode::Nearest,
+                wgpu::FilterMode::Linear => wgpu::MipmapFilterMode::Linear,
+            },
+            ..Default::default()
+        });
+
+        Self {
+            texture,
+            view,
+            sampler,
+        }
+    }
+
+    pub fn size(&self) -> wgpu::Extent3d {
+        self.texture.size()
+    }
+}
+
+/// Replaces the compressed data of every level and layer with
+/// `block_decode::decompressed_format`.
+fn decompress_container(container: &mut TextureContainer) -> Result<(), TextureError> {
+    let format = container.format;
+    let decompressed =
+        block_decode::decompressed_format(format).ok_or(TextureError::UnsupportedFormat(format))?;
+    let array_layers = container.array_layers() as usize;
+    for (level, data) in container.levels.iter_mut().enumerate() {
+        let level_size = wgpu::Extent3d {
+            width: container.width,
+            height: container.height,
+            depth_or_array_layers: 1,
+        }
+        .mip_level_size(level as u32, wgpu::TextureDimension::D2);
+        let layer_size = data.len() / array_layers;
+
+        *data = data
+            .chunks_exact(layer_size)
+            .map(|layer| {
+                block_decode::decompress(format, level_size.width, level_size.height, layer)
+                    .ok_or(TextureError::UnsupportedFormat(format))
+            })
+            .collect::<Result<Vec<_>, _>>()?
+            .concat();
+    }
+
+    tracing::info!(
+        "{:?} is not supported, decompressed to {:?}",
+        format,
+        decompressed
+    );
+    container.format = decompressed;
+
+    Ok(())
+}
+
+/// Converts RGBA8 pixels to `format`, one of those `load` decodes to, keeping the first
+/// channel for single channel formats.
+pub(crate) fn convert_rgba8(
+    pixels: &[u8],
+    format: wgpu::TextureFormat,
+) -> Result<Vec<u8>, TextureError> {
+    let channels = channel_count(format)? as usize;
+    let values = pixels
+        .chunks_exact(4)
+        .flat_map(|pixel| pixel[..channels].iter().copied());
+    if matches!(
+        format,
+        wgpu::TextureFormat::Rgba8UnormSrgb
+            | wgpu::TextureFormat::Rgba8Unorm
+            | wgpu::TextureFormat::R8Unorm
+    ) {
+        return Ok(values.collect());
+    }
+
+    let floats: Vec<u8> = values
+        .flat_map(|value| (value as f32 / 255.0).to_ne_bytes())
+        .collect();
+    Ok(narrow(&floats, format).into_owned())
+}
+
+fn channel_count(format: wgpu::TextureFormat) -> Result<u32, TextureError> {
+    match format {
+        wgpu::TextureFormat::R8Unorm
+        | wgpu::TextureFormat::R16Float
+        | wgpu::TextureFormat::R32Float => Ok(1),
+        wgpu::TextureFormat::Rgba8UnormSrgb
+        | wgpu::TextureFormat::Rgba8Unorm
+        | wgpu::TextureFormat::Rgba16Float
+        | wgpu::TextureFormat::Rgba32Float => Ok(4),
+        _ => Err(TextureError::UnsupportedFormat(format)),
+    }
+}
+
+/// Converts 32-bit float pixels to half floats for 16-bit float formats.
+fn narrow(pixels: &[u8], format: wgpu::TextureFormat) -> Cow<'_, [u8]> {
+    match format {
+        wgpu::TextureFormat::Rgba16Float | wgpu::TextureFormat::R16Float => Cow::Owned(
+            pixels
+                .chunks_exact(4)
+                .map(|value| f32::from_ne_bytes(value.try_into().unwrap()))
+                .flat_map(|value| half::f16::from_f32(value).to_bits().to_ne_bytes())
+                .collect(),
+        ),
+        _ => Cow::Borrowed(pixels),
+    }
+}
+
+fn read_error(path: &Path, err: impl ToString) -> TextureError {
+    TextureError::Read {
+        path: path.to_path_buf(),
+        message: err.to_string(),
+    }
+}
+
+impl std::fmt::Display for TextureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TextureError::Read { path, message } => {
+                write!(f, "Failed to read {}: {}", path.display(), message)
+            }
+            TextureError::UnsupportedFormat(format) => {
+                write!(f, "Unsupported texture format {:?}", format)
+            }
+            TextureError::SizeMismatch { expected, actual } => write!(
+                f,
+                "Expected {} bytes of pixel data, got {}",
+                expected, actual
+            ),
+            TextureError::Container(message) => {
+                write!(f, "Invalid texture container: {}", message)
+            }
+            TextureError::UnsupportedImage => {
+                f.write_str("Only KTX2 and DDS textures can be loaded from memory")
+            }
+        }
+    }
+}
+
+impl std::error::Error for TextureError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_bytes_rejects_images() {
+        let (device, queue) = wgpu::Device::noop(&wgpu::DeviceDescriptor::default());
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+        let result = load_bytes(
+            &device,
+            &queue,
+            &mut MipmapGenerator::new(&device),
+            png,
+            &TextureOptions::default(),
+        );
+
+        assert!(
+            matches!(result, Err(TextureError::UnsupportedImage)),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn from_bytes_size_mismatch() {
+        let (device, queue) = wgpu::Device::noop(&wgpu::DeviceDescriptor::default());
+        let mut mipmap_generator = MipmapGenerator::new(&device);
+        let cases = [
+            (wgpu::TextureFormat::Rgba8Unorm, 4, 2, 4 * 2 * 4),
+            (wgpu::TextureFormat::R8Unorm, 3, 3, 9),
+            // 4 GiB, past what u32 arithmetic can hold.
+            (wgpu::TextureFormat::Rgba32Float, 16384, 16384, 1 << 32),
+        ];
+
+        for case in cases {
+            let (format, width, height, expected) = case;
+            let options = TextureOptions {
+                format,
+                mipmaps: false,
+                ..Default::default()
+            };
+
+            let result = from_bytes(
+                &device,
+                &queue,
+                &mut mipmap_generator,
+                width,
+                height,
+                &[0; 5],
+                &options,
+            );
+
+            assert!(
+                matches!(
+                    result,
+                    Err(TextureError::SizeMismatch { expected: e, actual: 5 }) if e == expected
+                ),
+                "{:?} {:?}",
+                case,
+                result
+            );
+        }
+    }
+
+    #[test]
+    fn mipmap_pipelines_are_cached_per_format() {
+        let (device, _queue) = wgpu::Device::noop(&wgpu::DeviceDescriptor::default());
+        let mut mipmap_generator = MipmapGenerator::new(&device);
+        let mut encoder = device.create_command_encoder(&Default::default());
+        let formats = [
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+            wgpu::TextureFormat::R8Unorm,
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+        ];
+
+        for format in formats {
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                size: wgpu::Extent3d {
+                    width: 4,
+                    height: 4,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 3,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[],
+                label: None,
+            });
+            mipmap_generator.generate(&device, &mut encoder, &texture);
+        }
+
+        assert_eq!(mipmap_generator.pipelines.len(), 2);
+    }
+
+    #[test]
+    fn convert_rgba8() {
+        let pixels = [255, 0, 51, 255, 0, 255, 0, 128];
+        let floats = |values: &[f32]| -> Vec<u8> {
+            values
+                .iter()
+                .flat_map(|value| value.to_ne_bytes())
+                .collect()
+        };
+        let halves = |values: &[f32]| -> Vec<u8> {
+            values
+                .iter()
+                .flat_map(|value| half::f16::from_f32(*value).to_bits().to_ne_bytes())
+                .collect()
+        };
+        let cases = [
+            (wgpu::TextureFormat::Rgba8UnormSrgb, pixels.to_vec()),
+            (wgpu::TextureFormat::R8Unorm, vec![255, 0]),
+            (
+                wgpu::TextureFormat::Rgba32Float,
+                floats(&[1.0, 0.0, 0.2, 1.0, 0.0, 1.0, 0.0, 128.0 / 255.0]),
+            ),
+            (wgpu::TextureFormat::R32Float, floats(&[1.0, 0.0])),
+            (wgpu::TextureFormat::R16Float, halves(&[1.0, 0.0])),
+        ];
+
+        for case in cases {
+            let (format, expected) = &case;
+            assert_eq!(
+                super::convert_rgba8(&pixels, *format).unwrap(),
+                *expected,
+                "{:?}",
+                case
+            );
+        }
+        assert!(matches!(
+            super::convert_rgba8(&pixels, wgpu::TextureFormat::Bc1RgbaUnorm),
+            Err(TextureError::UnsupportedFormat(_))
+        ));
+    }
+}
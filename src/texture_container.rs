@@ -0,0 +1,549 @@
+use wgpu::{AstcBlock, AstcChannel, TextureFormat};
+
+use crate::texture::TextureError;
+
+const KTX2_IDENTIFIER: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+const DDS_MAGIC: &[u8; 4] = b"DDS ";
+
+const DDS_PF_FOURCC: u32 = 0x4;
+const DDS_PF_RGB: u32 = 0x40;
+const DDS_PF_LUMINANCE: u32 = 0x20000;
+const DDS_CAPS2_CUBEMAP: u32 = 0x200;
+const DDS_CAPS2_VOLUME: u32 = 0x200000;
+const DDS_DX10_MISC_CUBE: u32 = 0x4;
+const DDS_DX10_DIMENSION_3D: u32 = 4;
+
+/// Texture data parsed from a KTX2 or DDS file. Every level holds the data of all array
+/// layers in wgpu order, cubemap faces of a layer being consecutive layers.
+#[derive(Debug)]
+pub(crate) struct TextureContainer {
+    pub format: TextureFormat,
+    pub width: u32,
+    pub height: u32,
+    /// Array elements, each with six faces for cubemaps.
+    pub layers: u32,
+    pub cube: bool,
+    pub levels: Vec<Vec<u8>>,
+}
+
+impl TextureContainer {
+    pub fn parse(bytes: &[u8]) -> Result<Self, TextureError> {
+        if bytes.starts_with(&KTX2_IDENTIFIER) {
+            parse_ktx2(bytes)
+        } else if bytes.starts_with(DDS_MAGIC) {
+            parse_dds(bytes)
+        } else {
+            Err(container_error("Unknown file identifier"))
+        }
+    }
+
+    /// Whether `bytes` start with a KTX2 or DDS identifier.
+    pub fn is_container(bytes: &[u8]) -> bool {
+        bytes.starts_with(&KTX2_IDENTIFIER) || bytes.starts_with(DDS_MAGIC)
+    }
+
+    pub fn array_layers(&self) -> u32 {
+        if self.cube {
+            self.layers * 6
+        } else {
+            self.layers
+        }
+    }
+
+    pub fn size(&self) -> wgpu::Extent3d {
+        wgpu::Extent3d {
+            width: self.width,
+            height: self.height,
+            depth_or_array_layers: self.array_layers(),
+        }
+    }
+}
+
+/// Size in bytes of a single layer of mip `level`, `None` if it overflows.
+fn layer_size(format: TextureFormat, width: u32, height: u32, level: u32) -> Option<usize> {
+    let (block_width, block_height) = format.block_dimensions();
+    let blocks_x = (width >> level).max(1).div_ceil(block_width) as usize;
+    let blocks_y = (height >> level).max(1).div_ceil(block_height) as usize;
+    let block_size = format.block_copy_size(None)? as usize;
+
+    blocks_x.checked_mul(blocks_y)?.checked_mul(block_size)
+}
+
+/// Rejects empty sizes and more mip levels than the size has, which also keeps levels
+/// below 32 for the shifts in `layer_size`.
+fn validate_size(width: u32, height: u32, level_count: u32) -> Result<(), TextureError> {
+    if width == 0 || height == 0 {
+        return Err(container_error(format!(
+            "Invalid size {}x{}",
+            width, height
+        )));
+    }
+    let max_levels = 32 - width.max(height).leading_zeros();
+    if level_count > max_levels {
+        return Err(container_error(format!(
+            "{} mip levels for a {}x{} texture",
+            level_count, width, height
+        )));
+    }
+
+    Ok(())
+}
+
+fn overflow_error() -> TextureError {
+    container_error("Texture size overflows")
+}
+
+fn parse_ktx2(bytes: &[u8]) -> Result<TextureContainer, TextureError> {
+    let vk_format = read_u32(bytes, 12)?;
+    let width = read_u32(bytes, 20)?;
+    let height = read_u32(bytes, 24)?.max(1);
+    let depth = read_u32(bytes, 28)?;
+    let layers = read_u32(bytes, 32)?.max(1);
+    let faces = read_u32(bytes, 36)?;
+    let level_count = read_u32(bytes, 40)?.max(1);
+    let supercompression = read_u32(bytes, 44)?;
+
+    if supercompression != 0 {
+        return Err(container_error(format!(
+            "KTX2 supercompression scheme {} is not supported",
+            supercompression
+        )));
+    }
+    if depth > 1 {
+        return Err(container_error("3D textures are not supported"));
+    }
+    if faces != 1 && faces != 6 {
+        return Err(container_error(format!(
+            "Invalid KTX2 face count {}",
+            faces
+        )));
+    }
+    let format = ktx2_format(vk_format)
+        .ok_or_else(|| container_error(format!("Unsupported KTX2 Vulkan format {}", vk_format)))?;
+    validate_size(width, height, level_count)?;
+
+    let array_layers = layers.checked_mul(faces).ok_or_else(overflow_error)? as usize;
+    let levels = (0..level_count)
+        .map(|level| {
+            let index = 80 + level as usize * 24;
+            let offset = usize::try_from(read_u64(bytes, index)?).map_err(|_| overflow_error())?;
+            let end = layer_size(format, width, height, level)
+                .and_then(|size| size.checked_mul(array_layers))
+                .and_then(|size| size.checked_add(offset))
+                .ok_or_else(overflow_error)?;
+            bytes
+                .get(offset..end)
+                .map(<[u8]>::to_vec)
+                .ok_or_else(|| container_error(format!("KTX2 level {} is truncated", level)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(TextureContainer {
+        format,
+        width,
+        height,
+        layers,
+        cube: faces == 6,
+        levels,
+    })
+}
+
+fn parse_dds(bytes: &[u8]) -> Result<TextureContainer, TextureError> {
+    let height = read_u32(bytes, 12)?;
+    let width = read_u32(bytes, 16)?;
+    let level_count = read_u32(bytes, 28)?.max(1);
+    let pixel_flags = read_u32(bytes, 80)?;
+    let four_cc = read_u32(bytes, 84)?;
+    let caps2 = read_u32(bytes, 112)?;
+
+    if caps2 & DDS_CAPS2_VOLUME != 0 {
+        return Err(container_error("3D textures are not supported"));
+    }
+
+    let (format, layers, cube, data_offset) = if pixel_flags & DDS_PF_FOURCC != 0
+        && four_cc.to_le_bytes() == *b"DX10"
+    {
+        let dxgi_format = read_u32(bytes, 128)?;
+        if read_u32(bytes, 132)? == DDS_DX10_DIMENSION_3D {
+            return Err(container_error("3D textures are not supported"));
+        }
+        let cube = read_u32(bytes, 136)? & DDS_DX10_MISC_CUBE != 0;
+        let layers = read_u32(bytes, 140)?.max(1);
+        let format = dxgi_format_to_wgpu(dxgi_format)
+            .ok_or_else(|| container_error(format!("Unsupported DXGI format {}", dxgi_format)))?;
+        (format, layers, cube, 148)
+    } else {
+        let format = dds_legacy_format(bytes, pixel_flags, four_cc)?;
+        (format, 1, caps2 & DDS_CAPS2_CUBEMAP != 0, 128)
+    };
+    validate_size(width, height, level_count)?;
+
+    // DDS stores the full mip chain of every layer one after another.
+    let array_layers = if cube {
+        layers.checked_mul(6).ok_or_else(overflow_error)?
+    } else {
+        layers
+    };
+    let mut levels = vec![vec![]; level_count as usize];
+    let mut offset = data_offset;
+    for _ in 0..array_layers {
+        for (level, data) in levels.iter_mut().enumerate() {
+            let end = layer_size(format, width, height, level as u32)
+                .and_then(|size| size.checked_add(offset))
+                .ok_or_else(overflow_error)?;
+            let layer = bytes
+                .get(offset..end)
+                .ok_or_else(|| container_error("DDS data is truncated"))?;
+            data.extend_from_slice(layer);
+            offset = end;
+        }
+    }
+
+    Ok(TextureContainer {
+        format,
+        width,
+        height,
+        layers,
+        cube,
+        levels,
+    })
+}
+
+fn dds_legacy_format(
+    bytes: &[u8],
+    pixel_flags: u32,
+    four_cc: u32,
+) -> Result<TextureFormat, TextureError> {
+    if pixel_flags & DDS_PF_FOURCC != 0 {
+        return match &four_cc.to_le_bytes() {
+            b"DXT1" => Ok(TextureFormat::Bc1RgbaUnorm),
+            b"DXT2" | b"DXT3" => Ok(TextureFormat::Bc2RgbaUnorm),
+            b"DXT4" | b"DXT5" => Ok(TextureFormat::Bc3RgbaUnorm),
+            b"ATI1" | b"BC4U" => Ok(TextureFormat::Bc4RUnorm),
+            b"BC4S" => Ok(TextureFormat::Bc4RSnorm),
+            b"ATI2" | b"BC5U" => Ok(TextureFormat::Bc5RgUnorm),
+            b"BC5S" => Ok(TextureFormat::Bc5RgSnorm),
+            // D3DFORMAT values stored in place of a FourCC.
+            _ => match four_cc {
+                111 => Ok(TextureFormat::R16Float),
+                113 => Ok(TextureFormat::Rgba16Float),
+                114 => Ok(TextureFormat::R32Float),
+                116 => Ok(TextureFormat::Rgba32Float),
+                _ => Err(container_error(format!(
+                    "Unsupported DDS FourCC {:?}",
+                    String::from_utf8_lossy(&four_cc.to_le_bytes())
+                ))),
+            },
+        };
+    }
+
+    let bit_count = read_u32(bytes, 88)?;
+    let red_mask = read_u32(bytes, 92)?;
+    match (
+        pixel_flags & (DDS_PF_RGB | DDS_PF_LUMINANCE),
+        bit_count,
+        red_mask,
+    ) {
+        (DDS_PF_RGB, 32, 0xFF) => Ok(TextureFormat::Rgba8Unorm),
+        (DDS_PF_RGB, 32, 0xFF0000) => Ok(TextureFormat::Bgra8Unorm),
+        (DDS_PF_LUMINANCE, 8, _) => Ok(TextureFormat::R8Unorm),
+        _ => Err(container_error(format!(
+            "Unsupported DDS pixel format with {} bits per pixel",
+            bit_count
+        ))),
+    }
+}
+
+fn ktx2_format(vk_format: u32) -> Option<TextureFormat> {
+    let format = match vk_format {
+        9 => TextureFormat::R8Unorm,
+        16 => TextureFormat::Rg8Unorm,
+        37 => TextureFormat::Rgba8Unorm,
+        43 => TextureFormat::Rgba8UnormSrgb,
+        44 => TextureFormat::Bgra8Unorm,
+        50 => TextureFormat::Bgra8UnormSrgb,
+        76 => TextureFormat::R16Float,
+        83 => TextureFormat::Rg16Float,
+        97 => TextureFormat::Rgba16Float,
+        100 => TextureFormat::R32Float,
+        109 => TextureFormat::Rgba32Float,
+        // BC1 without alpha decodes the same as BC1 with alpha.
+        131 | 133 => TextureFormat::Bc1RgbaUnorm,
+        132 | 134 => TextureFormat::Bc1RgbaUnormSrgb,
+        135 => TextureFormat::Bc2RgbaUnorm,
+        136 => TextureFormat::Bc2RgbaUnormSrgb,
+        137 => TextureFormat::Bc3RgbaUnorm,
+        138 => TextureFormat::Bc3RgbaUnormSrgb,
+        139 => TextureFormat::Bc4RUnorm,
+        140 => TextureFormat::Bc4RSnorm,
+        141 => TextureFormat::Bc5RgUnorm,
+        142 => TextureFormat::Bc5RgSnorm,
+        143 => TextureFormat::Bc6hRgbUfloat,
+        144 => TextureFormat::Bc6hRgbFloat,
+        145 => TextureFormat::Bc7RgbaUnorm,
+        146 => TextureFormat::Bc7RgbaUnormSrgb,
+        147 => TextureFormat::Etc2Rgb8Unorm,
+        148 => TextureFormat::Etc2Rgb8UnormSrgb,
+        149 => TextureFormat::Etc2Rgb8A1Unorm,
+        150 => TextureFormat::Etc2Rgb8A1UnormSrgb,
+        151 => TextureFormat::Etc2Rgba8Unorm,
+        152 => TextureFormat::Etc2Rgba8UnormSrgb,
+        153 => TextureFormat::EacR11Unorm,
+        154 => TextureFormat::EacR11Snorm,
+        155 => TextureFormat::EacRg11Unorm,
+        156 => TextureFormat::EacRg11Snorm,
+        157..=184 => {
+            let block = [
+                AstcBlock::B4x4,
+                AstcBlock::B5x4,
+                AstcBlock::B5x5,
+                AstcBlock::B6x5,
+                AstcBlock::B6x6,
+                AstcBlock::B8x5,
+                AstcBlock::B8x6,
+                AstcBlock::B8x8,
+                AstcBlock::B10x5,
+                AstcBlock::B10x6,
+                AstcBlock::B10x8,
+                AstcBlock::B10x10,
+                AstcBlock::B12x10,
+                AstcBlock::B12x12,
+            ][(vk_format - 157) as usize / 2];
+            let channel = if vk_format % 2 == 1 {
+                AstcChannel::Unorm
+            } else {
+                AstcChannel::UnormSrgb
+            };
+            TextureFormat::Astc { block, channel }
+        }
+        _ => return None,
+    };
+
+    Some(format)
+}
+
+fn dxgi_format_to_wgpu(dxgi_format: u32) -> Option<TextureFormat> {
+    let format = match dxgi_format {
+        2 => TextureFormat::Rgba32Float,
+        10 => TextureFormat::Rgba16Float,
+        28 => TextureFormat::Rgba8Unorm,
+        29 => TextureFormat::Rgba8UnormSrgb,
+        34 => TextureFormat::Rg16Float,
+        41 => TextureFormat::R32Float,
+        49 => TextureFormat::Rg8Unorm,
+        54 => TextureFormat::R16Float,
+        61 => TextureFormat::R8Unorm,
+        71 => TextureFormat::Bc1RgbaUnorm,
+        72 => TextureFormat::Bc1RgbaUnormSrgb,
+        74 => TextureFormat::Bc2RgbaUnorm,
+        75 => TextureFormat::Bc2RgbaUnormSrgb,
+        77 => TextureFormat::Bc3RgbaUnorm,
+        78 => TextureFormat::Bc3RgbaUnormSrgb,
+        80 => TextureFormat::Bc4RUnorm,
+        81 => TextureFormat::Bc4RSnorm,
+        83 => TextureFormat::Bc5RgUnorm,
+        84 => TextureFormat::Bc5RgSnorm,
+        87 => TextureFormat::Bgra8Unorm,
+        91 => TextureFormat::Bgra8UnormSrgb,
+        95 => TextureFormat::Bc6hRgbUfloat,
+        96 => TextureFormat::Bc6hRgbFloat,
+        98 => TextureFormat::Bc7RgbaUnorm,
+        99 => TextureFormat::Bc7RgbaUnormSrgb,
+        _ => return None,
+    };
+
+    Some(format)
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, TextureError> {
+    bytes
+        .get(offset..offset + 4)
+        .map(|value| u32::from_le_bytes(value.try_into().unwrap()))
+        .ok_or_else(|| container_error("Header is truncated"))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Result<u64, TextureError> {
+    bytes
+        .get(offset..offset + 8)
+        .map(|value| u64::from_le_bytes(value.try_into().unwrap()))
+        .ok_or_else(|| container_error("Header is truncated"))
+}
+
+fn container_error(message: impl Into<String>) -> TextureError {
+    TextureError::Container(message.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ktx2(vk_format: u32, size: [u32; 2], layers: u32, faces: u32, levels: &[&[u8]]) -> Vec<u8> {
+        let mut header = KTX2_IDENTIFIER.to_vec();
+        for value in [
+            vk_format,
+            1,
+            size[0],
+            size[1],
+            0,
+            layers,
+            faces,
+            levels.len() as u32,
+        ] {
+            header.extend_from_slice(&value.to_le_bytes());
+        }
+        header.resize(80, 0);
+
+        let mut offset = 80 + levels.len() * 24;
+        for level in levels {
+            for value in [offset, level.len(), level.len()] {
+                header.extend_from_slice(&(value as u64).to_le_bytes());
+            }
+            offset += level.len();
+        }
+        header.extend(levels.concat());
+        header
+    }
+
+    /// DDS file with a FourCC, and a DX10 header when it's `DX10`.
+    fn dds(four_cc: &[u8; 4], size: [u32; 2], levels: u32, dx10: [u32; 3], data: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0u8; 148];
+        bytes[..4].copy_from_slice(DDS_MAGIC);
+        let mut write = |offset: usize, value: u32| {
+            bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        };
+        write(12, size[1]);
+        write(16, size[0]);
+        write(28, levels);
+        write(80, DDS_PF_FOURCC);
+        write(84, u32::from_le_bytes(*four_cc));
+        if four_cc == b"DX10" {
+            let [dxgi_format, misc, array_size] = dx10;
+            write(128, dxgi_format);
+            write(136, misc);
+            write(140, array_size);
+        } else {
+            bytes.truncate(128);
+        }
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    fn error(result: Result<TextureContainer, TextureError>) -> String {
+        match result {
+            Err(TextureError::Container(message)) => message,
+            other => panic!("Expected a container error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn ktx2_levels() {
+        let level0 = [1u8; 4 * 4 * 3];
+        let level1 = [2u8; 2 * 4];
+        let container =
+            TextureContainer::parse(&ktx2(37, [4, 3], 0, 1, &[&level0, &level1])).unwrap();
+
+        assert_eq!(container.format, TextureFormat::Rgba8Unorm);
+        assert_eq!((container.width, container.height), (4, 3));
+        assert_eq!((container.layers, container.cube), (1, false));
+        assert_eq!(container.levels, [level0.to_vec(), level1.to_vec()]);
+    }
+
+    #[test]
+    fn ktx2_cubemap() {
+        let faces = (0..6u8).flat_map(|face| [face; 8]).collect::<Vec<_>>();
+        let container = TextureContainer::parse(&ktx2(133, [4, 4], 0, 6, &[&faces])).unwrap();
+
+        assert_eq!(container.format, TextureFormat::Bc1RgbaUnorm);
+        assert!(container.cube);
+        assert_eq!(container.array_layers(), 6);
+        assert_eq!(container.levels, [faces]);
+    }
+
+    #[test]
+    fn ktx2_truncated() {
+        assert!(error(TextureContainer::parse(&KTX2_IDENTIFIER)).contains("Header"));
+
+        let mut bytes = ktx2(37, [2, 2], 0, 1, &[&[0; 16]]);
+        bytes.pop();
+        assert!(error(TextureContainer::parse(&bytes)).contains("truncated"));
+
+        let bytes = ktx2(37, [2, 2], 0, 1, &[&[0; 16]]);
+        assert!(error(TextureContainer::parse(&bytes[..100])).contains("truncated"));
+    }
+
+    #[test]
+    fn ktx2_oversized() {
+        let mut bytes = ktx2(37, [2, 2], 0, 1, &[&[0; 16]]);
+        bytes[80..88].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(TextureContainer::parse(&bytes).is_err());
+
+        let bytes = ktx2(109, [u32::MAX, u32::MAX], 0, 1, &[&[0; 16]]);
+        assert!(error(TextureContainer::parse(&bytes)).contains("overflows"));
+
+        let bytes = ktx2(37, [2, 2], u32::MAX, 6, &[&[0; 16]]);
+        assert!(error(TextureContainer::parse(&bytes)).contains("overflows"));
+
+        let levels = [&[0u8; 4][..]; 3];
+        assert!(
+            error(TextureContainer::parse(&ktx2(37, [2, 2], 0, 1, &levels))).contains("levels")
+        );
+
+        let bytes = ktx2(37, [0, 2], 0, 1, &[&[0; 16]]);
+        assert!(error(TextureContainer::parse(&bytes)).contains("Invalid size"));
+    }
+
+    #[test]
+    fn dds_legacy() {
+        let data = (0..16 + 8).collect::<Vec<u8>>();
+        let container = TextureContainer::parse(&dds(b"DXT1", [8, 4], 2, [0; 3], &data)).unwrap();
+
+        assert_eq!(container.format, TextureFormat::Bc1RgbaUnorm);
+        assert_eq!((container.width, container.height), (8, 4));
+        assert_eq!(container.levels, [data[..16].to_vec(), data[16..].to_vec()]);
+    }
+
+    #[test]
+    fn dds_cube_array_layers_are_interleaved() {
+        // Two cubes of 1x1 RGBA8 with two levels, stored layer by layer.
+        let data = (0..12u8).flat_map(|layer| [layer; 12]).collect::<Vec<_>>();
+        let bytes = dds(b"DX10", [2, 1], 2, [28, DDS_DX10_MISC_CUBE, 2], &data);
+        let container = TextureContainer::parse(&bytes).unwrap();
+
+        assert_eq!(container.format, TextureFormat::Rgba8Unorm);
+        assert_eq!((container.layers, container.cube), (2, true));
+        assert_eq!(container.array_layers(), 12);
+        let level0 = (0..12u8).flat_map(|layer| [layer; 8]).collect::<Vec<_>>();
+        let level1 = (0..12u8).flat_map(|layer| [layer; 4]).collect::<Vec<_>>();
+        assert_eq!(container.levels, [level0, level1]);
+    }
+
+    #[test]
+    fn dds_truncated() {
+        let bytes = dds(b"DXT5", [4, 4], 1, [0; 3], &[0; 16]);
+        assert!(error(TextureContainer::parse(&bytes[..100])).contains("Header"));
+        assert!(error(TextureContainer::parse(&bytes[..140])).contains("truncated"));
+
+        let bytes = dds(b"DX10", [4, 4], 1, [28, 0, 1], &[0; 16]);
+        assert!(error(TextureContainer::parse(&bytes[..140])).contains("Header"));
+    }
+
+    #[test]
+    fn dds_oversized() {
+        let bytes = dds(b"DX10", [u32::MAX, u32::MAX], 1, [2, 0, 1], &[0; 16]);
+        assert!(error(TextureContainer::parse(&bytes)).contains("overflows"));
+
+        let bytes = dds(
+            b"DX10",
+            [1, 1],
+            1,
+            [28, DDS_DX10_MISC_CUBE, u32::MAX],
+            &[0; 4],
+        );
+        assert!(error(TextureContainer::parse(&bytes)).contains("overflows"));
+
+        let bytes = dds(b"DXT1", [4, 4], 4, [0; 3], &[0; 64]);
+        assert!(error(TextureContainer::parse(&bytes)).contains("levels"));
+    }
+}
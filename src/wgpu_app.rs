@@ -1,17 +1,21 @@
 use std::fmt::Debug;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use glam::UVec2;
 use pollster::FutureExt;
 use wgpu::Features;
 use winit::application::ApplicationHandler;
-use winit::event::{DeviceEvent, DeviceId};
+use winit::event::{DeviceEvent, DeviceId, StartCause};
 use winit::event_loop::{ActiveEventLoop, EventLoop, EventLoopProxy};
 use winit::monitor::VideoModeHandle;
 use winit::window::{Window, WindowId};
 
+use crate::adapter::{self, AdapterSelector};
+use crate::color::ColorSpace;
 use crate::events::{EventResult, WindowEvent};
+use crate::layout::MAX_IMMEDIATE_SIZE;
+use crate::render_target::{self, Attachment, RenderTarget};
 
 #[derive(Debug)]
 pub struct AppContext<'window> {
@@ -19,28 +23,116 @@ pub struct AppContext<'window> {
 
     pub surface: wgpu::Surface<'window>,
     pub surface_config: wgpu::SurfaceConfiguration,
+    /// Color space of the surface format.
+    pub color_space: ColorSpace,
+    pub adapter: wgpu::Adapter,
+    pub adapter_info: wgpu::AdapterInfo,
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
+    /// Wakes the event loop from other threads, see `WgpuApp::user_event`.
+    pub event_loop_proxy: EventLoopProxy<UserEventType>,
 
     pub window_size: UVec2,
     pub mouse_position: Option<UVec2>,
 
+    /// `AppConfig::sample_count` lowered to what the surface and depth formats support.
+    pub sample_count: u32,
+    pub depth_format: Option<wgpu::TextureFormat>,
+    msaa_target: Option<Attachment>,
+    depth_target: Option<Attachment>,
+
     pub start_time: Instant,
+    /// Number of frames rendered so far.
+    pub frame_index: u64,
 
     redraw_requested: bool,
     is_redrawing: bool,
     is_resizing: bool,
 }
 
-pub struct UserEventType {}
+/// Attachments of the frame being rendered. With multisampling, color is drawn to `msaa_view`
+/// and resolved to `surface_view`.
+#[derive(Debug)]
+pub struct Frame<'a> {
+    pub surface_view: &'a wgpu::TextureView,
+    pub msaa_view: Option<&'a wgpu::TextureView>,
+    pub depth_view: Option<&'a wgpu::TextureView>,
+}
+
+/// Events sent to the event loop through `AppContext::event_loop_proxy`.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum UserEventType {
+    /// Background loads finished, `AssetManager::update` uploads them.
+    AssetsReady,
+}
+
+/// Decides when the runner draws a new frame.
+#[derive(PartialEq, Debug, Copy, Clone, Default)]
+pub enum ControlFlow {
+    /// Redraw as fast as possible, whatever `render` returns.
+    Continuous,
+    /// Redraw only on keyboard, mouse or touch input, when the app returns `EventResult::Redraw`
+    /// or when the window system asks for it.
+    #[default]
+    Reactive,
+    /// Same as `Reactive`, but also redraw once the given time has passed since the last frame.
+    WaitUntil(Duration),
+}
+
+#[derive(Debug, Clone)]
+pub struct AppConfig {
+    pub control_flow: ControlFlow,
+    pub backends: wgpu::Backends,
+    /// Overridden by the `WGPU_APP_ADAPTER` environment variable.
+    pub adapter: AdapterSelector,
+    /// Depth/stencil texture the runner creates for the surface, see `Frame::depth_view`.
+    pub depth_format: Option<wgpu::TextureFormat>,
+    /// MSAA sample count of the surface, lowered to the highest count the surface and depth
+    /// formats support.
+    pub sample_count: u32,
+    /// Present to an `Rgba16Float` surface in `ColorSpace::ExtendedLinearSrgb` when the surface
+    /// supports it, instead of an sRGB one.
+    pub hdr: bool,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            control_flow: ControlFlow::default(),
+            backends: wgpu::Backends::PRIMARY,
+            adapter: AdapterSelector::default(),
+            depth_format: None,
+            sample_count: 1,
+            hdr: false,
+        }
+    }
+}
 
 pub trait WgpuApp {
     fn window_event(&mut self, app_context: &AppContext, event: WindowEvent) -> EventResult;
-    fn render(
-        &mut self,
-        app_context: &AppContext,
-        surface_texture_view: &wgpu::TextureView,
-    ) -> EventResult;
+    fn render(&mut self, app_context: &AppContext, frame: &Frame) -> EventResult;
+
+    /// Called before `render` each frame to record compute work, e.g. simulations or textures
+    /// `render` draws. Returns whether anything was recorded, in which case the runner submits
+    /// `encoder` before calling `render`.
+    fn compute(&mut self, app_context: &AppContext, encoder: &mut wgpu::CommandEncoder) -> bool {
+        let _ = (app_context, encoder);
+        false
+    }
+
+    /// Render targets sized relative to the window. The runner resizes them when the window
+    /// is resized, before passing `WindowEvent::Resized` to `window_event`, where bind groups
+    /// using them can be recreated.
+    fn render_targets(&mut self) -> Vec<&mut RenderTarget> {
+        Vec::new()
+    }
+
+    /// Called for events sent through `AppContext::event_loop_proxy`. Redraws by default, so
+    /// apps that update their assets in `render` don't need to handle it.
+    fn user_event(&mut self, app_context: &AppContext, event: UserEventType) -> EventResult {
+        let _ = (app_context, event);
+        EventResult::Redraw
+    }
 }
 
 struct AppState<'window> {
@@ -48,6 +140,7 @@ struct AppState<'window> {
 
     main_window_context: Option<AppContext<'window>>,
 
+    config: AppConfig,
     start_time: Instant,
 
     app: Option<Box<dyn WgpuApp>>,
@@ -60,8 +153,10 @@ impl<'window> ApplicationHandler<UserEventType> for AppState<'window> {
             panic!("Resumed called twice");
         }
 
+        let _span = tracing::info_span!("init").entered();
+
         let monitors = event_loop.available_monitors().collect::<Vec<_>>();
-        println!("Available Monitors: {:?}", monitors);
+        tracing::debug!("Available Monitors: {:?}", monitors);
         let primary_monitor = event_loop
             .primary_monitor()
             .unwrap_or_else(move || monitors.first().cloned().unwrap());
@@ -83,34 +178,46 @@ impl<'window> ApplicationHandler<UserEventType> for AppState<'window> {
 
         let size = window.inner_size();
 
-        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::PRIMARY,
-            ..Default::default()
-        });
+        let instance = adapter::create_instance(self.config.backends);
 
         let surface = instance.create_surface(window.clone()).unwrap();
 
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::LowPower,
-                force_fallback_adapter: false,
-                compatible_surface: Some(&surface),
-            })
-            .block_on()
-            .expect("No suitable GPU adapters found on the system.");
+        let adapter_selector = AdapterSelector::from_env().unwrap_or(self.config.adapter.clone());
+        let adapter = adapter::select_adapter(&instance, &surface, &adapter_selector);
+        let adapter_info = adapter.get_info();
+        tracing::info!(
+            name = %adapter_info.name,
+            backend = %adapter_info.backend,
+            device_type = ?adapter_info.device_type,
+            driver = %adapter_info.driver,
+            "Selected adapter"
+        );
 
         // Make sure we use the texture resolution limits from the adapter, so we can support images the size of the surface.
         let required_limits = wgpu::Limits {
-            max_immediate_size: 256,
+            max_immediate_size: MAX_IMMEDIATE_SIZE.min(adapter.limits().max_immediate_size),
             ..Default::default()
         }
         .using_resolution(adapter.limits());
 
+        // Used when present: `DrawConstants` falls back to uniform buffers without immediates
+        // and the profiler to CPU timings without timestamp queries. Compressed textures
+        // without native support are decompressed on the CPU. Adapter specific format features
+        // allow sample counts beyond the guaranteed ones.
+        let optional_features = adapter.features()
+            & (Features::IMMEDIATES
+                | Features::TIMESTAMP_QUERY
+                | Features::TIMESTAMP_QUERY_INSIDE_ENCODERS
+                | Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
+                | Features::TEXTURE_COMPRESSION_BC
+                | Features::TEXTURE_COMPRESSION_ETC2
+                | Features::TEXTURE_COMPRESSION_ASTC);
+
         let (device, queue) = adapter
             .request_device(&wgpu::DeviceDescriptor {
                 label: None,
                 required_limits,
-                required_features: Features::IMMEDIATES,
+                required_features: optional_features,
                 ..Default::default()
             })
             .block_on()
@@ -119,24 +226,58 @@ impl<'window> ApplicationHandler<UserEventType> for AppState<'window> {
         let mut surface_config = surface
             .get_default_config(&adapter, size.width, size.height)
             .expect("Surface isn't supported by the adapter.");
-        surface_config.format = surface_config.format.add_srgb_suffix();
+        let hdr_supported = surface
+            .get_capabilities(&adapter)
+            .formats
+            .contains(&wgpu::TextureFormat::Rgba16Float);
+        surface_config.format = if self.config.hdr && hdr_supported {
+            wgpu::TextureFormat::Rgba16Float
+        } else {
+            if self.config.hdr {
+                tracing::warn!("Surface doesn't support HDR, presenting in sRGB");
+            }
+            surface_config.format.add_srgb_suffix()
+        };
         surface_config.view_formats.push(surface_config.format);
         surface_config.present_mode = wgpu::PresentMode::AutoNoVsync;
         surface.configure(&device, &surface_config);
-
-        self.main_window_context = Some(AppContext {
+        tracing::debug!(
+            format = ?surface_config.format,
+            present_mode = ?surface_config.present_mode,
+            "Surface configured"
+        );
+
+        let sample_count = supported_sample_count(
+            &adapter,
+            &device,
+            &[Some(surface_config.format), self.config.depth_format],
+            self.config.sample_count,
+        );
+
+        let mut app_context = AppContext {
             window: window.clone(),
             surface,
+            color_space: ColorSpace::of(surface_config.format),
             surface_config,
+            adapter,
+            adapter_info,
             device,
             queue,
+            event_loop_proxy: self.event_loop_proxy.clone(),
             mouse_position: None,
             window_size: UVec2::new(size.width, size.height),
+            sample_count,
+            depth_format: self.config.depth_format,
+            msaa_target: None,
+            depth_target: None,
             is_redrawing: false,
             is_resizing: false,
             start_time: self.start_time,
+            frame_index: 0,
             redraw_requested: true,
-        });
+        };
+        app_context.create_frame_targets();
+        self.main_window_context = Some(app_context);
 
         let app = (self.app_ctor)(self.main_window_context.as_ref().unwrap());
         self.app = Some(app);
@@ -144,12 +285,32 @@ impl<'window> ApplicationHandler<UserEventType> for AppState<'window> {
         window.request_redraw();
     }
 
+    fn new_events(&mut self, _event_loop: &ActiveEventLoop, cause: StartCause) {
+        if self.main_window_context.is_none() {
+            return;
+        }
+
+        if let StartCause::ResumeTimeReached { .. } = cause {
+            self.main_window_context
+                .as_ref()
+                .unwrap()
+                .window
+                .request_redraw();
+        }
+    }
+
     fn user_event(&mut self, event_loop: &ActiveEventLoop, user_event: UserEventType) {
         if self.main_window_context.is_none() {
             return;
         }
 
-        let _ = (event_loop, user_event);
+        let window_context = self.main_window_context.as_mut().unwrap();
+        let event_result = self
+            .app
+            .as_mut()
+            .unwrap()
+            .user_event(window_context, user_event);
+        Self::process_event_result(event_loop, window_context, event_result);
     }
 
     fn window_event(
@@ -176,13 +337,21 @@ impl<'window> ApplicationHandler<UserEventType> for AppState<'window> {
 
             _ => {
                 let window_context = self.main_window_context.as_mut().unwrap();
+                let input = is_input(&event);
                 let event = WindowEvent::convert_event(&event, &mut window_context.mouse_position);
-                if !matches!(event, WindowEvent::Unknown) {
+                let mut event_result = if matches!(event, WindowEvent::Unknown) {
+                    EventResult::Continue
+                } else {
                     self.app
                         .as_mut()
                         .unwrap()
-                        .window_event(window_context, event);
+                        .window_event(window_context, event)
+                };
+                // Input may change what the app draws even when it doesn't ask for a frame.
+                if input && event_result == EventResult::Continue {
+                    event_result = EventResult::Redraw;
                 }
+                Self::process_event_result(event_loop, window_context, event_result);
             }
         }
     }
@@ -212,12 +381,20 @@ impl<'window> ApplicationHandler<UserEventType> for AppState<'window> {
 
             let window_size = physical_size_to_vec2u32(window_context.window.inner_size());
             if window_size != window_context.window_size {
+                let _span =
+                    tracing::info_span!("resize", width = window_size.x, height = window_size.y)
+                        .entered();
+
                 window_context.window_size = window_size;
                 window_context.surface_config.width = window_size.x;
                 window_context.surface_config.height = window_size.y;
                 window_context
                     .surface
                     .configure(&window_context.device, &window_context.surface_config);
+                window_context.create_frame_targets();
+                for target in self.app.as_mut().unwrap().render_targets() {
+                    target.resize(&window_context.device, window_size);
+                }
 
                 let resize_result = self
                     .app
@@ -237,13 +414,98 @@ impl<'window> ApplicationHandler<UserEventType> for AppState<'window> {
     }
 }
 
+impl AppContext<'_> {
+    /// Multisample state for pipelines drawing to the surface.
+    pub fn multisample_state(&self) -> wgpu::MultisampleState {
+        wgpu::MultisampleState {
+            count: self.sample_count,
+            ..Default::default()
+        }
+    }
+
+    /// Depth state for pipelines drawing to the surface, or `None` without a depth format.
+    pub fn depth_stencil_state(
+        &self,
+        depth_write_enabled: bool,
+        depth_compare: wgpu::CompareFunction,
+    ) -> Option<wgpu::DepthStencilState> {
+        self.depth_format.map(|format| wgpu::DepthStencilState {
+            format,
+            depth_write_enabled,
+            depth_compare,
+            stencil: Default::default(),
+            bias: Default::default(),
+        })
+    }
+
+    fn create_frame_targets(&mut self) {
+        let usage = wgpu::TextureUsages::RENDER_ATTACHMENT;
+        self.msaa_target = (self.sample_count > 1).then(|| {
+            render_target::create_attachment(
+                &self.device,
+                self.window_size,
+                self.surface_config.format,
+                self.sample_count,
+                usage,
+            )
+        });
+        self.depth_target = self.depth_format.map(|format| {
+            render_target::create_attachment(
+                &self.device,
+                self.window_size,
+                format,
+                self.sample_count,
+                usage,
+            )
+        });
+    }
+}
+
+impl Frame<'_> {
+    /// Color attachment for the surface, resolving multisampled color to it.
+    pub fn color_attachment(
+        &self,
+        load: wgpu::LoadOp<wgpu::Color>,
+    ) -> wgpu::RenderPassColorAttachment<'_> {
+        let (view, resolve_target, store) = match self.msaa_view {
+            Some(msaa_view) => (msaa_view, Some(self.surface_view), wgpu::StoreOp::Discard),
+            None => (self.surface_view, None, wgpu::StoreOp::Store),
+        };
+
+        wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target,
+            ops: wgpu::Operations { load, store },
+            depth_slice: None,
+        }
+    }
+
+    /// Depth attachment with `depth_ops`, and stencil cleared to 0 if the format has it.
+    pub fn depth_stencil_attachment(
+        &self,
+        depth_ops: wgpu::Operations<f32>,
+    ) -> Option<wgpu::RenderPassDepthStencilAttachment<'_>> {
+        let depth_view = self.depth_view?;
+        let has_stencil = depth_view.texture().format().has_stencil_aspect();
+
+        Some(wgpu::RenderPassDepthStencilAttachment {
+            view: depth_view,
+            depth_ops: Some(depth_ops),
+            stencil_ops: has_stencil.then_some(wgpu::Operations {
+                load: wgpu::LoadOp::Clear(0),
+                store: wgpu::StoreOp::Store,
+            }),
+        })
+    }
+}
+
 impl<'window> AppState<'window> {
     fn process_event_result(
         event_loop: &ActiveEventLoop,
         window_context: &mut AppContext,
-        resize_result: EventResult,
+        event_result: EventResult,
     ) {
-        match resize_result {
+        match event_result {
             EventResult::Exit => {
                 window_context.redraw_requested = false;
                 event_loop.exit();
@@ -267,13 +529,22 @@ impl<'window> AppState<'window> {
         window_context.redraw_requested = false;
         window_context.is_redrawing = true;
 
+        let _span = tracing::info_span!(
+            "frame",
+            index = window_context.frame_index,
+            width = window_context.window_size.x,
+            height = window_context.window_size.y
+        )
+        .entered();
+
         let surface = &window_context.surface;
 
         let error_scope = window_context
             .device
             .push_error_scope(wgpu::ErrorFilter::Validation);
 
-        let surface_texture = surface.get_current_texture().unwrap_or_else(|_| {
+        let surface_texture = surface.get_current_texture().unwrap_or_else(|error| {
+            tracing::warn!("Reconfiguring surface after acquire failure: {}", error);
             surface.configure(&window_context.device, &window_context.surface_config);
             surface
                 .get_current_texture()
@@ -287,30 +558,76 @@ impl<'window> AppState<'window> {
                     ..wgpu::TextureViewDescriptor::default()
                 });
 
-        let event_result = self
-            .app
-            .as_mut()
-            .unwrap()
-            .render(window_context, &surface_texture_view);
+        let frame = Frame {
+            surface_view: &surface_texture_view,
+            msaa_view: window_context.msaa_target.as_ref().map(|msaa| &msaa.view),
+            depth_view: window_context
+                .depth_target
+                .as_ref()
+                .map(|depth| &depth.view),
+        };
+        tracing::info_span!("compute").in_scope(|| {
+            let mut encoder =
+                window_context
+                    .device
+                    .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                        label: Some("Compute"),
+                    });
+            if self
+                .app
+                .as_mut()
+                .unwrap()
+                .compute(window_context, &mut encoder)
+            {
+                window_context.queue.submit([encoder.finish()]);
+            }
+        });
+        let mut event_result = tracing::info_span!("render")
+            .in_scope(|| self.app.as_mut().unwrap().render(window_context, &frame));
 
-        surface_texture.present();
+        tracing::info_span!("present").in_scope(|| surface_texture.present());
+        window_context.frame_index += 1;
+
+        match self.config.control_flow {
+            ControlFlow::Continuous => {
+                if event_result == EventResult::Continue {
+                    event_result = EventResult::Redraw;
+                }
+            }
+            ControlFlow::Reactive => {}
+            ControlFlow::WaitUntil(interval) => {
+                event_loop.set_control_flow(winit::event_loop::ControlFlow::WaitUntil(
+                    Instant::now() + interval,
+                ));
+            }
+        }
 
         Self::process_event_result(event_loop, window_context, event_result);
 
         let error = error_scope.pop().block_on();
-        if let Some(_error) = error {
-            // log::error!("Error: {}", error);
+        if let Some(error) = error {
+            tracing::error!("Validation error: {}", error);
         }
     }
 }
 
 pub fn run(app_ctor: fn(&AppContext) -> Box<dyn WgpuApp>) {
+    run_with_config(AppConfig::default(), app_ctor);
+}
+
+pub fn run_with_config(config: AppConfig, app_ctor: fn(&AppContext) -> Box<dyn WgpuApp>) {
+    if std::env::args().any(|arg| arg == adapter::LIST_ADAPTERS_ARG) {
+        adapter::print_adapters(config.backends);
+        return;
+    }
+
     let event_loop: EventLoop<UserEventType> = EventLoop::<UserEventType>::with_user_event()
         .build()
         .unwrap();
     let mut app_state = AppState {
         event_loop_proxy: event_loop.create_proxy(),
         main_window_context: None,
+        config,
         start_time: Instant::now(),
         app: None,
         app_ctor,
@@ -318,6 +635,62 @@ pub fn run(app_ctor: fn(&AppContext) -> Box<dyn WgpuApp>) {
     event_loop.run_app(&mut app_state).unwrap();
 }
 
+/// Highest sample count up to `requested` that all `formats` support as render attachments.
+fn supported_sample_count(
+    adapter: &wgpu::Adapter,
+    device: &wgpu::Device,
+    formats: &[Option<wgpu::TextureFormat>],
+    requested: u32,
+) -> u32 {
+    let adapter_specific = device
+        .features()
+        .contains(Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES);
+    let supported = |count: u32| {
+        formats.iter().flatten().all(|&format| {
+            let features = if adapter_specific {
+                adapter.get_texture_format_features(format)
+            } else {
+                format.guaranteed_format_features(device.features())
+            };
+            features.flags.sample_count_supported(count)
+        })
+    };
+
+    let sample_count = [16, 8, 4, 2]
+        .into_iter()
+        .find(|&count| count <= requested && supported(count))
+        .unwrap_or(1);
+    if sample_count != requested {
+        tracing::warn!(
+            requested,
+            sample_count,
+            "Sample count isn't supported by the surface and depth formats"
+        );
+    }
+
+    sample_count
+}
+
+/// Keyboard, mouse and touch events, which redraw in every `ControlFlow`.
+fn is_input(event: &winit::event::WindowEvent) -> bool {
+    use winit::event::WindowEvent::*;
+
+    matches!(
+        event,
+        KeyboardInput { .. }
+            | ModifiersChanged(_)
+            | Ime(_)
+            | CursorMoved { .. }
+            | MouseWheel { .. }
+            | MouseInput { .. }
+            | Touch(_)
+            | PinchGesture { .. }
+            | PanGesture { .. }
+            | RotationGesture { .. }
+            | DoubleTapGesture { .. }
+    )
+}
+
 fn physical_size_to_vec2u32(size: winit::dpi::PhysicalSize<u32>) -> UVec2 {
     UVec2::new(size.width, size.height)
 }
@@ -0,0 +1,185 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Data, DeriveInput, Fields};
+
+#[derive(Clone, Copy, PartialEq)]
+enum AddressSpace {
+    Uniform,
+    Storage,
+    Immediate,
+}
+
+pub fn derive(input: DeriveInput) -> syn::Result<TokenStream> {
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input.generics,
+            "GpuBlock can't be derived for generic structs",
+        ));
+    }
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "GpuBlock needs named fields to match the WGSL struct",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "GpuBlock can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut address_spaces = vec![];
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("gpu_block"))
+    {
+        attr.parse_nested_meta(|meta| {
+            let address_space = if meta.path.is_ident("uniform") {
+                AddressSpace::Uniform
+            } else if meta.path.is_ident("storage") {
+                AddressSpace::Storage
+            } else if meta.path.is_ident("immediate") {
+                AddressSpace::Immediate
+            } else {
+                return Err(meta.error("expected `uniform`, `storage` or `immediate`"));
+            };
+            address_spaces.push(address_space);
+            Ok(())
+        })?;
+    }
+    if address_spaces.is_empty() {
+        address_spaces.push(AddressSpace::Storage);
+    }
+
+    let name = &input.ident;
+    let layout = quote!(::wgpu_app::layout);
+    let wgsl_fields = fields
+        .iter()
+        .filter(|field| {
+            let ident = field.ident.as_ref().unwrap().to_string();
+            !ident.starts_with('_')
+        })
+        .collect::<Vec<_>>();
+    let types = wgsl_fields
+        .iter()
+        .map(|field| &field.ty)
+        .collect::<Vec<_>>();
+
+    let mut checks = vec![];
+    for field in wgsl_fields.iter() {
+        let ty = &field.ty;
+        let message = format!(
+            "`{}::{}` has a different size in Rust than in WGSL",
+            name,
+            field.ident.as_ref().unwrap()
+        );
+        checks.push(quote! {
+            ::core::assert!(
+                ::core::mem::size_of::<#ty>() == <#ty as #layout::WgslType>::SIZE,
+                #message
+            );
+        });
+    }
+
+    for address_space in address_spaces {
+        let space_name = match address_space {
+            AddressSpace::Uniform => "uniform",
+            AddressSpace::Storage => "storage",
+            AddressSpace::Immediate => "immediate",
+        };
+        let mut field_checks = vec![];
+        for field in wgsl_fields.iter() {
+            let ty = &field.ty;
+            let ident = field.ident.as_ref().unwrap();
+            let message = format!(
+                "`{}::{}` is not where WGSL places it in the {} address space; add or remove padding before it",
+                name, ident, space_name
+            );
+            let (align, size) = if address_space == AddressSpace::Uniform {
+                (
+                    quote!(<#ty as #layout::WgslType>::UNIFORM_ALIGN),
+                    quote!(<#ty as #layout::WgslType>::UNIFORM_SIZE),
+                )
+            } else {
+                (
+                    quote!(<#ty as #layout::WgslType>::ALIGN),
+                    quote!(<#ty as #layout::WgslType>::SIZE),
+                )
+            };
+            field_checks.push(quote! {
+                offset = #layout::round_up(offset, #align);
+                ::core::assert!(offset == ::core::mem::offset_of!(#name, #ident), #message);
+                end = offset + <#ty as #layout::WgslType>::SIZE;
+                offset += #size;
+            });
+            if address_space == AddressSpace::Uniform {
+                let message = format!(
+                    "`{}::{}` has an array stride that is not a multiple of 16, which uniform buffers require",
+                    name, ident
+                );
+                field_checks.push(quote! {
+                    ::core::assert!(<#ty as #layout::WgslType>::UNIFORM_COMPATIBLE, #message);
+                });
+            }
+        }
+
+        let size_message = format!(
+            "`{}` has a different size in Rust than in the WGSL {} address space; adjust trailing padding",
+            name, space_name
+        );
+        let immediate_check = (address_space == AddressSpace::Immediate).then(|| {
+            let message = format!(
+                "`{}` is larger than the immediate data the runner requests",
+                name
+            );
+            quote! {
+                ::core::assert!(
+                    ::core::mem::size_of::<#name>() <= #layout::MAX_IMMEDIATE_SIZE as usize,
+                    #message
+                );
+            }
+        });
+
+        checks.push(quote! {
+            {
+                let mut offset = 0usize;
+                let mut end = 0usize;
+                #(#field_checks)*
+                ::core::assert!(
+                    #layout::round_up(end, <#name as #layout::WgslType>::ALIGN)
+                        == ::core::mem::size_of::<#name>(),
+                    #size_message
+                );
+                #immediate_check
+            }
+        });
+    }
+
+    Ok(quote! {
+        impl #layout::WgslType for #name {
+            const ALIGN: usize = #layout::max_align(&[#(<#types as #layout::WgslType>::ALIGN),*]);
+            const SIZE: usize = ::core::mem::size_of::<#name>();
+            const UNIFORM_ALIGN: usize = #layout::round_up(
+                #layout::max_align(&[#(<#types as #layout::WgslType>::UNIFORM_ALIGN),*]),
+                16,
+            );
+            const UNIFORM_SIZE: usize = #layout::round_up(::core::mem::size_of::<#name>(), 16);
+            const UNIFORM_COMPATIBLE: bool =
+                true #(&& <#types as #layout::WgslType>::UNIFORM_COMPATIBLE)*;
+        }
+
+        impl #layout::GpuBlock for #name {}
+
+        #[allow(unused_mut, unused_assignments)]
+        const _: () = {
+            #(#checks)*
+        };
+    })
+}
@@ -0,0 +1,32 @@
+use proc_macro::TokenStream;
+use syn::{parse_macro_input, DeriveInput};
+
+mod gpu_block;
+mod vertex_layout;
+
+/// Implements `wgpu_app::layout::VertexLayout`. Every field becomes an attribute at its
+/// `offset_of!` offset, with the format taken from `VertexAttributeType` and locations
+/// counting up from 0 in field order.
+///
+/// Struct attribute: `#[vertex(instance)]` for per-instance data.
+/// Field attributes: `#[vertex(location = 3)]` to continue numbering from 3,
+/// `#[vertex(format = Unorm8x4)]` to override the format and `#[vertex(skip)]` for padding.
+#[proc_macro_derive(VertexLayout, attributes(vertex))]
+pub fn derive_vertex_layout(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    vertex_layout::derive(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Implements `wgpu_app::layout::GpuBlock` and checks at compile time that the Rust layout
+/// matches the WGSL layout of the same struct in the address spaces named by
+/// `#[gpu_block(uniform, storage, immediate)]`, `storage` by default.
+/// Fields whose name starts with `_` are padding and don't exist on the WGSL side.
+#[proc_macro_derive(GpuBlock, attributes(gpu_block))]
+pub fn derive_gpu_block(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    gpu_block::derive(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
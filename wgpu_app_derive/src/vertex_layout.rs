@@ -0,0 +1,115 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Data, DeriveInput, Ident, LitInt};
+
+pub fn derive(input: DeriveInput) -> syn::Result<TokenStream> {
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input.generics,
+            "VertexLayout can't be derived for generic structs",
+        ));
+    }
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "VertexLayout can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut instance = false;
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("vertex"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("instance") {
+                instance = true;
+                Ok(())
+            } else {
+                Err(meta.error("expected `instance`"))
+            }
+        })?;
+    }
+
+    let name = &input.ident;
+    let mut location = 0u32;
+    let mut attributes = vec![];
+    let mut checks = vec![];
+
+    for (field, member) in fields.iter().zip(fields.members()) {
+        let mut skip = false;
+        let mut format: Option<Ident> = None;
+        for attr in field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("vertex"))
+        {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("skip") {
+                    skip = true;
+                } else if meta.path.is_ident("location") {
+                    location = meta.value()?.parse::<LitInt>()?.base10_parse()?;
+                } else if meta.path.is_ident("format") {
+                    format = Some(meta.value()?.parse()?);
+                } else {
+                    return Err(meta.error("expected `skip`, `location` or `format`"));
+                }
+                Ok(())
+            })?;
+        }
+        if skip {
+            continue;
+        }
+
+        let ty = &field.ty;
+        let format = match format {
+            Some(format) => {
+                let message = format!(
+                    "`{}::{}` is not the size of `{}`",
+                    name,
+                    quote!(#member),
+                    format
+                );
+                checks.push(quote! {
+                    ::core::assert!(
+                        ::wgpu_app::wgpu::VertexFormat::#format.size() as usize
+                            == ::core::mem::size_of::<#ty>(),
+                        #message
+                    );
+                });
+                quote!(::wgpu_app::wgpu::VertexFormat::#format)
+            }
+            None => quote!(<#ty as ::wgpu_app::layout::VertexAttributeType>::FORMAT),
+        };
+
+        attributes.push(quote! {
+            ::wgpu_app::wgpu::VertexAttribute {
+                format: #format,
+                offset: ::core::mem::offset_of!(#name, #member) as ::wgpu_app::wgpu::BufferAddress,
+                shader_location: #location,
+            }
+        });
+        location += 1;
+    }
+
+    let step_mode = if instance {
+        quote!(::wgpu_app::wgpu::VertexStepMode::Instance)
+    } else {
+        quote!(::wgpu_app::wgpu::VertexStepMode::Vertex)
+    };
+
+    Ok(quote! {
+        impl ::wgpu_app::layout::VertexLayout for #name {
+            const ATTRIBUTES: &'static [::wgpu_app::wgpu::VertexAttribute] = &[#(#attributes),*];
+            const STEP_MODE: ::wgpu_app::wgpu::VertexStepMode = #step_mode;
+        }
+
+        const _: () = {
+            #(#checks)*
+        };
+    })
+}